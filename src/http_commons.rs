@@ -1,4 +1,17 @@
+pub mod accept;
+pub mod cache;
+pub mod mime;
+pub mod parser;
+pub mod status;
+
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::time::SystemTime;
+
+use crate::http_commons::cache::{CacheControl, ETag, format_http_date};
+use crate::http_commons::mime::Mime;
+use crate::http_commons::status::{InvalidStatusCode, StatusCode};
 
 pub type HttpUri = String;
 pub type HttpHeaders = HashMap<String, String>;
@@ -6,21 +19,53 @@ pub type HttpParams = HashMap<String, String>;
 pub type HttpBody = Option<String>;
 
 pub struct HttpResponse {
-    pub code: u16,
+    pub code: StatusCode,
     pub headers: Vec<(String, String)>,
     pub payload: Option<Vec<u8>>
 }
 
 impl HttpResponse {
+    /// # Panics
+    ///
+    /// Panics if `code` is not a valid status code
     pub fn new(code: u16, headers: Vec<(String, String)>, payload: Option<String>) -> Self {
         Self {
-            code,
+            code: valid_code(code),
             headers,
             payload: payload.map(String::into_bytes)
         }
     }
 
+    /// # Panics
+    ///
+    /// Panics if `code` is not a valid status code
     pub fn new_raw(code: u16, headers: Vec<(String, String)>, payload: Option<Vec<u8>>) -> Self {
+        Self::with_status(valid_code(code), headers, payload)
+    }
+
+    /// Like `new`, but fails instead of panicking if `code` is not a valid status code
+    pub fn try_new(
+        code: u16,
+        headers: Vec<(String, String)>,
+        payload: Option<String>
+    ) -> Result<Self, InvalidStatusCode> {
+        Self::try_new_raw(code, headers, payload.map(String::into_bytes))
+    }
+
+    /// Like `new_raw`, but fails instead of panicking if `code` is not a valid status code
+    pub fn try_new_raw(
+        code: u16,
+        headers: Vec<(String, String)>,
+        payload: Option<Vec<u8>>
+    ) -> Result<Self, InvalidStatusCode> {
+        Ok(Self::with_status(StatusCode::from_u16(code)?, headers, payload))
+    }
+
+    pub fn with_status(
+        code: StatusCode,
+        headers: Vec<(String, String)>,
+        payload: Option<Vec<u8>>
+    ) -> Self {
         Self {
             code,
             headers,
@@ -30,9 +75,10 @@ impl HttpResponse {
 
     pub fn builder() -> HttpResponseBuilder {
         HttpResponseBuilder {
-            code: 200,
+            code: StatusCode::OK,
             headers: Vec::new(),
-            payload: None
+            payload: None,
+            auto_etag: None
         }
     }
 
@@ -43,16 +89,36 @@ impl HttpResponse {
     pub fn has_header(&self, header: &str) -> bool {
         self.headers.iter().any(|(h, _)| h.eq_ignore_ascii_case(header))
     }
+
+    /// Parsed `Content-Type` header, `None` if absent or malformed
+    pub fn content_type(&self) -> Option<Mime> {
+        header_value(&self.headers, "Content-Type")?.parse().ok()
+    }
 }
 
 pub struct HttpResponseBuilder {
-    code: u16,
+    code: StatusCode,
     headers: Vec<(String, String)>,
-    payload: Option<Vec<u8>>
+    payload: Option<Vec<u8>>,
+    auto_etag: Option<bool>
 }
 
 impl HttpResponseBuilder {
+    /// # Panics
+    ///
+    /// Panics if `code` is not a valid status code
     pub fn set_code(mut self, code: u16) -> Self {
+        self.code = valid_code(code);
+        self
+    }
+
+    /// Like `set_code`, but fails instead of panicking if `code` is not a valid status code
+    pub fn try_set_code(mut self, code: u16) -> Result<Self, InvalidStatusCode> {
+        self.code = StatusCode::from_u16(code)?;
+        Ok(self)
+    }
+
+    pub fn set_status(mut self, code: StatusCode) -> Self {
         self.code = code;
         self
     }
@@ -62,6 +128,10 @@ impl HttpResponseBuilder {
         self
     }
 
+    pub fn set_content_type(self, mime: &Mime) -> Self {
+        self.add_header("Content-Type", mime.to_string())
+    }
+
     pub fn set_payload(mut self, payload: impl Into<String>) -> Self {
         self.payload = Some(payload.into().into_bytes());
         self
@@ -72,53 +142,266 @@ impl HttpResponseBuilder {
         self
     }
 
-    pub fn build(self) -> HttpResponse {
-        HttpResponse::new_raw(self.code, self.headers, self.payload)
+    pub fn set_etag(self, etag: ETag) -> Self {
+        self.add_header("ETag", etag.to_string())
+    }
+
+    /// Computes `ETag` from the payload when building the response
+    pub fn auto_etag(mut self, weak: bool) -> Self {
+        self.auto_etag = Some(weak);
+        self
+    }
+
+    pub fn set_last_modified(self, time: SystemTime) -> Self {
+        self.add_header("Last-Modified", format_http_date(time))
+    }
+
+    pub fn set_cache_control(self, cache_control: CacheControl) -> Self {
+        self.add_header("Cache-Control", cache_control.to_string())
+    }
+
+    pub fn build(mut self) -> HttpResponse {
+        if let Some(weak) = self.auto_etag {
+            let payload: &[u8] = self.payload.as_deref().unwrap_or_default();
+            let etag: ETag = ETag::from_payload(payload, weak);
+            self.headers.push(("ETag".to_string(), etag.to_string()));
+        }
+        HttpResponse::with_status(self.code, self.headers, self.payload)
+    }
+}
+
+fn valid_code(code: u16) -> StatusCode {
+    StatusCode::from_u16(code).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// An error carrying the HTTP status code it should be answered with
+///
+/// When a handler returns an `HttpError`, the server responds with its code and message instead
+/// of `500 Internal Server Error`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HttpError {
+    pub code: StatusCode,
+    pub message: String
+}
+
+impl HttpError {
+    /// # Panics
+    ///
+    /// Panics if `code` is not a valid status code
+    pub fn new(code: u16, message: impl Into<String>) -> Self {
+        Self { code: valid_code(code), message: message.into() }
+    }
+
+    /// Like `new`, but fails instead of panicking if `code` is not a valid status code
+    pub fn try_new(code: u16, message: impl Into<String>) -> Result<Self, InvalidStatusCode> {
+        Ok(Self { code: StatusCode::from_u16(code)?, message: message.into() })
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(400, message)
+    }
+
+    pub fn into_response(self) -> HttpResponse {
+        HttpResponse::builder()
+            .set_status(self.code)
+            .add_header("Content-Type", "text/plain; charset=utf-8")
+            .set_payload(self.message)
+            .build()
     }
 }
 
+impl Display for HttpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl Error for HttpError {}
+
+/// Value of the first header named `name` in `headers`, compared case-insensitively
+pub fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter()
+        .find(|(h, _)| h.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Decodes `%XX` escapes, and `+` as space if `plus_as_space` is set. Returns `None` on malformed
+/// escapes or if the result is not valid UTF-8.
+pub fn percent_decode(input: &str, plus_as_space: bool) -> Option<String> {
+    let input: &[u8] = input.as_bytes();
+    let mut ret: Vec<u8> = Vec::with_capacity(input.len());
+    let mut i: usize = 0;
+    while i < input.len() {
+        match input[i] {
+            b'%' => {
+                let hex: &str = std::str::from_utf8(input.get(i + 1..i + 3)?).ok()?;
+                if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return None;
+                }
+                ret.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            },
+            b'+' if plus_as_space => {
+                ret.push(b' ');
+                i += 1;
+            },
+            b => {
+                ret.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(ret).ok()
+}
+
+/// Decodes standard (RFC 4648 section 4) base64, padding is optional
+pub fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    base64_decode_impl(input, b'+', b'/')
+}
+
+/// Decodes URL and filename safe (RFC 4648 section 5) base64, padding is optional
+#[cfg(feature = "minhttpd-h2c")]
+pub fn base64url_decode(input: &str) -> Option<Vec<u8>> {
+    base64_decode_impl(input, b'-', b'_')
+}
+
+fn base64_decode_impl(input: &str, c62: u8, c63: u8) -> Option<Vec<u8>> {
+    let sextet = |c: u8| -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a') as u32 + 26),
+            b'0'..=b'9' => Some((c - b'0') as u32 + 52),
+            _ if c == c62 => Some(62),
+            _ if c == c63 => Some(63),
+            _ => None
+        }
+    };
+
+    let input: &[u8] = input.as_bytes();
+    let input: &[u8] = input.strip_suffix(b"==")
+        .or_else(|| input.strip_suffix(b"="))
+        .unwrap_or(input);
+    if input.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut ret: Vec<u8> = Vec::with_capacity(input.len() / 4 * 3 + 2);
+    for group in input.chunks(4) {
+        let mut bits: u32 = 0;
+        for c in group {
+            bits = (bits << 6) | sextet(*c)?;
+        }
+        bits <<= 6 * (4 - group.len()) as u32;
+        let bytes: [u8; 4] = bits.to_be_bytes();
+        ret.extend_from_slice(&bytes[1..group.len()]);
+    }
+    Some(ret)
+}
+
+pub(crate) fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Removes quotes and backslash escapes of a quoted string, returns other strings unchanged
+pub(crate) fn unquote(s: &str) -> String {
+    match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(quoted) => {
+            let mut ret: String = String::with_capacity(quoted.len());
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => ret.extend(chars.next()),
+                    c => ret.push(c)
+                }
+            }
+            ret
+        },
+        None => s.to_string()
+    }
+}
+
+/// Splits `s` at `delim`, ignoring delimiters inside quoted strings
+pub(crate) fn split_unquoted(s: &str, delim: u8) -> Vec<&str> {
+    let mut ret: Vec<&str> = Vec::new();
+    let mut start: usize = 0;
+    let mut quoted: bool = false;
+    let mut escaped: bool = false;
+    for (i, b) in s.bytes().enumerate() {
+        match b {
+            _ if escaped => escaped = false,
+            b'\\' if quoted => escaped = true,
+            b'"' => quoted = !quoted,
+            _ if b == delim && !quoted => {
+                ret.push(&s[start..i]);
+                start = i + 1;
+            },
+            _ => {}
+        }
+    }
+    ret.push(&s[start..]);
+    ret
+}
+
+/// Reason phrase of `code`, or `"Unknown"` for unassigned or invalid codes
 pub fn http_code_describe(code: u16) -> &'static str {
-    match code {
-        100 => "Continue",
-        101 => "Switching Protocols",
-        200 => "OK",
-        201 => "Created",
-        202 => "Accepted",
-        203 => "Non-Authoritative Information",
-        204 => "No Content",
-        205 => "Reset Content",
-        206 => "Partial Content",
-        300 => "Multiple Choices",
-        301 => "Moved Permanently",
-        302 => "Found",
-        303 => "See Other",
-        304 => "Not Modified",
-        305 => "Use Proxy",
-        307 => "Temporary Redirect",
-        400 => "Bad Request",
-        401 => "Unauthorized",
-        402 => "Payment Required",
-        403 => "Forbidden",
-        404 => "Not Found",
-        405 => "Method Not Allowed",
-        406 => "Not Acceptable",
-        407 => "Proxy Authentication Required",
-        408 => "Request Timeout",
-        409 => "Conflict",
-        410 => "Gone",
-        411 => "Length Required",
-        412 => "Precondition Failed",
-        413 => "Request Entity Too Large",
-        414 => "Request-URI Too Long",
-        415 => "Unsupported Media Type",
-        416 => "Requested Range Not Satisfiable",
-        417 => "Expectation Failed",
-        500 => "Internal Server Error",
-        501 => "Not Implemented",
-        502 => "Bad Gateway",
-        503 => "Service Unavailable",
-        504 => "Gateway Timeout",
-        505 => "HTTP Version Not Supported",
-        _ => "Unknown"
+    StatusCode::from_u16(code).ok()
+        .and_then(StatusCode::canonical_reason)
+        .unwrap_or("Unknown")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http_commons::{
+        HttpError,
+        HttpResponse,
+        base64_decode,
+        percent_decode
+    };
+    #[cfg(feature = "minhttpd-h2c")] use crate::http_commons::base64url_decode;
+    use crate::http_commons::status::InvalidStatusCode;
+
+    #[test]
+    fn test_try_new() {
+        assert_eq!(HttpResponse::try_new(204, vec![], None).unwrap().code, 204);
+        assert_eq!(HttpResponse::try_new(1000, vec![], None).err(), Some(InvalidStatusCode(1000)));
+        assert_eq!(HttpResponse::try_new_raw(99, vec![], None).err(), Some(InvalidStatusCode(99)));
+        assert_eq!(HttpResponse::builder().try_set_code(451).unwrap().build().code, 451);
+        assert!(HttpResponse::builder().try_set_code(600).is_err());
+        assert_eq!(HttpError::try_new(418, "teapot"), Ok(HttpError::new(418, "teapot")));
+        assert_eq!(HttpError::try_new(0, "zero"), Err(InvalidStatusCode(0)));
+    }
+
+    #[test]
+    fn test_base64_decode() {
+        assert_eq!(base64_decode("").unwrap(), b"");
+        assert_eq!(base64_decode("Zg==").unwrap(), b"f");
+        assert_eq!(base64_decode("Zm8=").unwrap(), b"fo");
+        assert_eq!(base64_decode("Zm9v").unwrap(), b"foo");
+        assert_eq!(base64_decode("Zm9vYg").unwrap(), b"foob");
+        assert_eq!(base64_decode("Zm9vYmE=").unwrap(), b"fooba");
+        assert_eq!(base64_decode("+/+/").unwrap(), &[0xfb, 0xff, 0xbf]);
+        assert!(base64_decode("Zm9vY").is_none());
+        assert!(base64_decode("Zm=9v").is_none());
+        assert!(base64_decode("Zm9v!").is_none());
+        assert!(base64_decode("-_-_").is_none());
+    }
+
+    #[cfg(feature = "minhttpd-h2c")]
+    #[test]
+    fn test_base64url_decode() {
+        assert_eq!(base64url_decode("-_-_").unwrap(), &[0xfb, 0xff, 0xbf]);
+        assert_eq!(base64url_decode("AAMAAABkAAQAoAAAAAIAAAAA").unwrap().len(), 18);
+        assert!(base64url_decode("+/+/").is_none());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b+c", false).unwrap(), "a b+c");
+        assert_eq!(percent_decode("a%20b+c", true).unwrap(), "a b c");
+        assert_eq!(percent_decode("%E4%BD%A0%e5%a5%bd", false).unwrap(), "你好");
+        assert!(percent_decode("%", false).is_none());
+        assert!(percent_decode("%4", false).is_none());
+        assert!(percent_decode("%+1", false).is_none());
+        assert!(percent_decode("%FF", false).is_none());
     }
 }
@@ -0,0 +1,323 @@
+//! `Accept`, `Accept-Language` and `Accept-Charset` parsing and content negotiation (RFC 7231
+//! section 5.3)
+
+use crate::http_commons::{HttpError, HttpHeaders, is_token, split_unquoted, unquote};
+
+/// A media range of an `Accept` header, like `text/*;q=0.8`
+#[derive(Clone, Debug, PartialEq)]
+pub struct MediaRange {
+    pub main_type: String,
+    pub sub_type: String,
+    pub params: Vec<(String, String)>,
+    pub q: f32
+}
+
+impl MediaRange {
+    /// Parses a single media range, or a media type when used for available types
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = split_unquoted(s, b';').into_iter();
+        let (main_type, sub_type) = parts.next()?.trim().split_once('/')?;
+        if !is_token(main_type) || !is_token(sub_type) || (main_type == "*" && sub_type != "*") {
+            return None;
+        }
+
+        let mut params: Vec<(String, String)> = Vec::new();
+        let mut q: f32 = 1.0;
+        for param in parts {
+            let (name, value) = param.trim().split_once('=')?;
+            let name: String = name.trim().to_ascii_lowercase();
+            if name == "q" {
+                // parameters following `q` are accept extensions, which are ignored
+                q = parse_q(value.trim())?;
+                break;
+            }
+            params.push((name, unquote(value.trim())));
+        }
+
+        Some(Self {
+            main_type: main_type.to_ascii_lowercase(),
+            sub_type: sub_type.to_ascii_lowercase(),
+            params,
+            q
+        })
+    }
+
+    /// Whether this range matches `media_type`, returning the specificity of the match
+    fn matches(&self, media_type: &MediaRange) -> Option<usize> {
+        if self.main_type == "*" {
+            return Some(0);
+        }
+        if self.main_type != media_type.main_type {
+            return None;
+        }
+        if self.sub_type == "*" {
+            return Some(1);
+        }
+        if self.sub_type != media_type.sub_type {
+            return None;
+        }
+
+        let params_match: bool = self.params.iter().all(|(name, value)| {
+            media_type.params.iter().any(|(n, v)| n == name && v.eq_ignore_ascii_case(value))
+        });
+        if params_match { Some(2 + self.params.len()) } else { None }
+    }
+}
+
+/// An item of `Accept-Language`, `Accept-Charset` or `Accept-Encoding`, like `en-US;q=0.5`
+#[derive(Clone, Debug, PartialEq)]
+pub struct QualityItem {
+    pub value: String,
+    pub q: f32
+}
+
+/// Parses an `Accept` header. Malformed ranges are skipped, and the result is sorted by
+/// descending quality, in a stable way.
+pub fn parse_accept(header: &str) -> Vec<MediaRange> {
+    let mut ranges: Vec<MediaRange> = split_unquoted(header, b',')
+        .into_iter()
+        .filter(|range| !range.trim().is_empty())
+        .filter_map(MediaRange::parse)
+        .collect();
+    ranges.sort_by(|a, b| b.q.partial_cmp(&a.q).unwrap());
+    ranges
+}
+
+/// Parses a header made of tokens with optional q-values, like `Accept-Language`. Malformed items
+/// are skipped, and the result is sorted by descending quality, in a stable way.
+pub fn parse_quality_list(header: &str) -> Vec<QualityItem> {
+    let mut items: Vec<QualityItem> = header.split(',')
+        .filter(|item| !item.trim().is_empty())
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let value: &str = parts.next()?.trim();
+            if value.is_empty() || value.bytes().any(|b| b.is_ascii_whitespace()) {
+                return None;
+            }
+
+            let mut q: f32 = 1.0;
+            for param in parts {
+                let (name, q_value) = param.trim().split_once('=')?;
+                if name.trim().eq_ignore_ascii_case("q") {
+                    q = parse_q(q_value.trim())?;
+                }
+            }
+            Some(QualityItem { value: value.to_string(), q })
+        })
+        .collect();
+    items.sort_by(|a, b| b.q.partial_cmp(&a.q).unwrap());
+    items
+}
+
+/// Picks the best of `available` media types for the `Accept` header in `headers`. Ties are
+/// broken by the order of `available`, so the preferred type should go first. Fails with
+/// `406 Not Acceptable` if the client accepts none of them.
+///
+/// ```
+/// # use std::collections::HashMap;
+/// # use xjbutil::minhttpd::{HttpHeaders, negotiate};
+/// let mut headers: HttpHeaders = HashMap::new();
+/// headers.insert("accept".to_string(), "text/html, application/*;q=0.9, */*;q=0.1".to_string());
+/// assert_eq!(negotiate(&headers, &["application/json", "text/html"]), Ok("text/html"));
+/// assert_eq!(negotiate(&headers, &["application/json", "text/plain"]), Ok("application/json"));
+/// ```
+pub fn negotiate<'a>(headers: &HttpHeaders, available: &[&'a str]) -> Result<&'a str, HttpError> {
+    let not_acceptable = || HttpError::new(
+        406,
+        format!("no acceptable representation, available: {}", available.join(", "))
+    );
+
+    let header: &str = match headers.get("accept") {
+        Some(header) if !header.trim().is_empty() => header,
+        _ => return available.first().copied().ok_or_else(not_acceptable)
+    };
+
+    let ranges: Vec<MediaRange> = parse_accept(header);
+    best_of(available, |candidate| {
+        let media_type: MediaRange = MediaRange::parse(candidate)?;
+        ranges.iter()
+            .filter_map(|range| range.matches(&media_type).map(|specificity| (specificity, range.q)))
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, q)| q)
+    }).ok_or_else(not_acceptable)
+}
+
+/// Picks the best of `available` language tags for the `Accept-Language` header in `headers`,
+/// using basic filtering (RFC 4647 section 3.3.1): range `en` matches `en` and `en-US`. Returns
+/// `None` if the client accepts none of them, in which case a default language is usually used.
+pub fn negotiate_language<'a>(headers: &HttpHeaders, available: &[&'a str]) -> Option<&'a str> {
+    negotiate_quality_list(headers.get("accept-language"), available, |range, tag| {
+        if range == "*" {
+            return Some(0);
+        }
+        let matches: bool = tag.len() >= range.len()
+            && tag.as_bytes()[..range.len()].eq_ignore_ascii_case(range.as_bytes())
+            && (tag.len() == range.len() || tag.as_bytes()[range.len()] == b'-');
+        if matches { Some(range.len()) } else { None }
+    })
+}
+
+/// Picks the best of `available` charsets for the `Accept-Charset` header in `headers`. Returns
+/// `None` if the client accepts none of them.
+pub fn negotiate_charset<'a>(headers: &HttpHeaders, available: &[&'a str]) -> Option<&'a str> {
+    negotiate_quality_list(headers.get("accept-charset"), available, |range, charset| {
+        match range {
+            "*" => Some(0),
+            _ if range.eq_ignore_ascii_case(charset) => Some(1),
+            _ => None
+        }
+    })
+}
+
+fn negotiate_quality_list<'a>(
+    header: Option<&String>,
+    available: &[&'a str],
+    matches: impl Fn(&str, &str) -> Option<usize>
+) -> Option<&'a str> {
+    let header: &str = match header {
+        Some(header) if !header.trim().is_empty() => header,
+        _ => return available.first().copied()
+    };
+
+    let items: Vec<QualityItem> = parse_quality_list(header);
+    best_of(available, |candidate| {
+        items.iter()
+            .filter_map(|item| matches(&item.value, candidate).map(|specificity| (specificity, item.q)))
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, q)| q)
+    })
+}
+
+/// Picks the first candidate with the highest positive quality
+fn best_of<'a>(available: &[&'a str], quality: impl Fn(&str) -> Option<f32>) -> Option<&'a str> {
+    let mut best: Option<(&'a str, f32)> = None;
+    for candidate in available {
+        if let Some(q) = quality(candidate) {
+            let better: bool = match best {
+                Some((_, best_q)) => q > best_q,
+                None => true
+            };
+            if q > 0.0 && better {
+                best = Some((candidate, q));
+            }
+        }
+    }
+    best.map(|(candidate, _)| candidate)
+}
+
+fn parse_q(s: &str) -> Option<f32> {
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    match int {
+        "0" => Some(format!("0.{}", frac).parse().unwrap_or(0.0)),
+        "1" if frac.bytes().all(|b| b == b'0') => Some(1.0),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::http_commons::HttpHeaders;
+    use crate::http_commons::accept::{
+        MediaRange,
+        QualityItem,
+        negotiate,
+        negotiate_charset,
+        negotiate_language,
+        parse_accept,
+        parse_quality_list
+    };
+
+    fn headers(name: &str, value: &str) -> HttpHeaders {
+        let mut headers: HttpHeaders = HashMap::new();
+        headers.insert(name.to_string(), value.to_string());
+        headers
+    }
+
+    #[test]
+    fn test_parse_accept() {
+        let ranges: Vec<MediaRange> = parse_accept(
+            "text/*;q=0.3, text/HTML;q=0.7, text/html;level=1, text/html;level=\"2\";q=0.4, */*;q=0.5, bad, */html"
+        );
+        let summary: Vec<(String, usize, f32)> = ranges.iter()
+            .map(|r| (format!("{}/{}", r.main_type, r.sub_type), r.params.len(), r.q))
+            .collect();
+        assert_eq!(summary, vec![
+            ("text/html".to_string(), 1, 1.0),
+            ("text/html".to_string(), 0, 0.7),
+            ("*/*".to_string(), 0, 0.5),
+            ("text/html".to_string(), 1, 0.4),
+            ("text/*".to_string(), 0, 0.3)
+        ]);
+        assert_eq!(ranges[3].params, vec![("level".to_string(), "2".to_string())]);
+
+        assert!(parse_accept("text/html;q=1.5").is_empty());
+        assert!(parse_accept("text/html;q=0.1234").is_empty());
+        assert_eq!(parse_accept("text/html;q=0.001")[0].q, 0.001);
+        assert_eq!(parse_accept("a/b;x=\"1,2\", c/d").len(), 2);
+    }
+
+    #[test]
+    fn test_parse_quality_list() {
+        assert_eq!(parse_quality_list("da, en-gb;q=0.8, en;q=0.7, bad;q=2"), vec![
+            QualityItem { value: "da".to_string(), q: 1.0 },
+            QualityItem { value: "en-gb".to_string(), q: 0.8 },
+            QualityItem { value: "en".to_string(), q: 0.7 }
+        ]);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let available: [&str; 3] = ["text/html", "application/json", "text/plain"];
+        assert_eq!(negotiate(&HashMap::new(), &available), Ok("text/html"));
+        assert_eq!(negotiate(&headers("accept", "application/json"), &available), Ok("application/json"));
+        assert_eq!(negotiate(&headers("accept", "text/*"), &available), Ok("text/html"));
+        assert_eq!(
+            negotiate(&headers("accept", "text/*, text/html;q=0.1"), &available),
+            Ok("text/plain")
+        );
+        assert_eq!(
+            negotiate(&headers("accept", "*/*;q=0.1, application/json;q=0.5"), &available),
+            Ok("application/json")
+        );
+        assert_eq!(
+            negotiate(&headers("accept", "*/*, text/html;q=0"), &available),
+            Ok("application/json")
+        );
+        assert_eq!(
+            negotiate(&headers("accept", "text/html;level=1"), &["text/html", "text/html;level=1"]),
+            Ok("text/html;level=1")
+        );
+
+        let error = negotiate(&headers("accept", "image/png"), &available).unwrap_err();
+        assert_eq!(error.code, 406);
+        assert_eq!(
+            error.message,
+            "no acceptable representation, available: text/html, application/json, text/plain"
+        );
+    }
+
+    #[test]
+    fn test_negotiate_language_and_charset() {
+        let available: [&str; 3] = ["en-US", "zh-CN", "zh-TW"];
+        assert_eq!(negotiate_language(&HashMap::new(), &available), Some("en-US"));
+        let language = |header: &str| negotiate_language(&headers("accept-language", header), &available);
+        assert_eq!(language("zh"), Some("zh-CN"));
+        assert_eq!(language("zh-tw, zh;q=0.9"), Some("zh-TW"));
+        assert_eq!(language("z, en;q=0.5"), Some("en-US"));
+        assert_eq!(language("fr, *;q=0.1"), Some("en-US"));
+        assert_eq!(language("fr, zh-CN;q=0"), None);
+        assert_eq!(language("*, en;q=0"), Some("zh-CN"));
+
+        let charset = |header: &str| {
+            negotiate_charset(&headers("accept-charset", header), &["utf-8", "iso-8859-1"])
+        };
+        assert_eq!(charset("ISO-8859-1, utf-8;q=0.7"), Some("iso-8859-1"));
+        assert_eq!(charset("*;q=0.5, utf-8"), Some("utf-8"));
+        assert_eq!(charset("gbk"), None);
+    }
+}
@@ -0,0 +1,577 @@
+//! Caching headers and conditional request evaluation (RFC 7232, RFC 7234)
+
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::http_commons::{HttpHeaders, HttpResponse, header_value};
+
+/// An entity tag (RFC 7232 section 2.3)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ETag {
+    pub weak: bool,
+    pub tag: String
+}
+
+impl ETag {
+    pub fn strong(tag: impl Into<String>) -> Self {
+        Self { weak: false, tag: tag.into() }
+    }
+
+    pub fn weak(tag: impl Into<String>) -> Self {
+        Self { weak: true, tag: tag.into() }
+    }
+
+    /// Computes an entity tag from the 64-bit FNV-1a hash of `payload`
+    pub fn from_payload(payload: &[u8], weak: bool) -> Self {
+        let hash: u64 = payload.iter().fold(0xcbf29ce484222325, |hash, b| {
+            (hash ^ (*b as u64)).wrapping_mul(0x100000001b3)
+        });
+        Self { weak, tag: format!("{:016x}", hash) }
+    }
+
+    /// Parses a single entity tag, in `"tag"` or `W/"tag"` form
+    pub fn parse(s: &str) -> Option<Self> {
+        let s: &str = s.trim();
+        let (weak, quoted): (bool, &str) = match s.strip_prefix("W/") {
+            Some(quoted) => (true, quoted),
+            None => (false, s)
+        };
+        let tag: &str = quoted.strip_prefix('"')?.strip_suffix('"')?;
+        if tag.bytes().any(|b| b == b'"' || b < 0x21 || b == 0x7f) {
+            return None;
+        }
+        Some(Self { weak, tag: tag.to_string() })
+    }
+
+    /// Strong comparison: both tags are strong and identical
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Weak comparison: tags are identical, regardless of weakness
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl Display for ETag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.weak {
+            write!(f, "W/\"{}\"", self.tag)
+        } else {
+            write!(f, "\"{}\"", self.tag)
+        }
+    }
+}
+
+/// Directives of a `Cache-Control` response header
+///
+/// ```
+/// # use xjbutil::minhttpd::CacheControl;
+/// let cache_control: CacheControl = CacheControl::new().public().max_age(3600).immutable();
+/// assert_eq!(cache_control.to_string(), "public, max-age=3600, immutable");
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CacheControl {
+    public: bool,
+    private: bool,
+    no_cache: bool,
+    no_store: bool,
+    no_transform: bool,
+    must_revalidate: bool,
+    proxy_revalidate: bool,
+    immutable: bool,
+    max_age: Option<u64>,
+    s_maxage: Option<u64>,
+    stale_while_revalidate: Option<u64>
+}
+
+impl CacheControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    pub fn private(mut self) -> Self {
+        self.private = true;
+        self
+    }
+
+    pub fn no_cache(mut self) -> Self {
+        self.no_cache = true;
+        self
+    }
+
+    pub fn no_store(mut self) -> Self {
+        self.no_store = true;
+        self
+    }
+
+    pub fn no_transform(mut self) -> Self {
+        self.no_transform = true;
+        self
+    }
+
+    pub fn must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+        self
+    }
+
+    pub fn proxy_revalidate(mut self) -> Self {
+        self.proxy_revalidate = true;
+        self
+    }
+
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    pub fn max_age(mut self, seconds: u64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    pub fn s_maxage(mut self, seconds: u64) -> Self {
+        self.s_maxage = Some(seconds);
+        self
+    }
+
+    pub fn stale_while_revalidate(mut self, seconds: u64) -> Self {
+        self.stale_while_revalidate = Some(seconds);
+        self
+    }
+}
+
+impl Display for CacheControl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut directives: Vec<String> = Vec::new();
+        let flags: [(bool, &str); 5] = [
+            (self.public, "public"),
+            (self.private, "private"),
+            (self.no_cache, "no-cache"),
+            (self.no_store, "no-store"),
+            (self.no_transform, "no-transform")
+        ];
+        directives.extend(flags.iter().filter(|(set, _)| *set).map(|(_, d)| d.to_string()));
+
+        let values: [(Option<u64>, &str); 3] = [
+            (self.max_age, "max-age"),
+            (self.s_maxage, "s-maxage"),
+            (self.stale_while_revalidate, "stale-while-revalidate")
+        ];
+        directives.extend(values.iter().filter_map(|(v, d)| v.map(|v| format!("{}={}", d, v))));
+
+        let flags: [(bool, &str); 3] = [
+            (self.must_revalidate, "must-revalidate"),
+            (self.proxy_revalidate, "proxy-revalidate"),
+            (self.immutable, "immutable")
+        ];
+        directives.extend(flags.iter().filter(|(set, _)| *set).map(|(_, d)| d.to_string()));
+
+        write!(f, "{}", directives.join(", "))
+    }
+}
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"
+];
+
+// http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z: i64 = days + 719468;
+    let era: i64 = z.div_euclid(146097);
+    let doe: i64 = z.rem_euclid(146097);
+    let yoe: i64 = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy: i64 = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp: i64 = (5 * doy + 2) / 153;
+    let day: u32 = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month: u32 = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (yoe + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year: i64 = if month <= 2 { year - 1 } else { year };
+    let era: i64 = year.div_euclid(400);
+    let yoe: i64 = year.rem_euclid(400);
+    let mp: i64 = if month > 2 { month as i64 - 3 } else { month as i64 + 9 };
+    let doy: i64 = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe: i64 = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Formats `time` as IMF-fixdate, like `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn format_http_date(time: SystemTime) -> String {
+    let secs: i64 = match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs_f64().ceil() as i64)
+    };
+    let days: i64 = secs.div_euclid(86400);
+    let secs_of_day: i64 = secs.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days + 4).rem_euclid(7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parses an HTTP-date (RFC 7231 section 7.1.1.1), accepting IMF-fixdate as well as the obsolete
+/// RFC 850 and asctime formats
+pub fn parse_http_date(s: &str) -> Option<SystemTime> {
+    fn month(s: &str) -> Option<u32> {
+        MONTHS.iter().position(|m| m.eq_ignore_ascii_case(s)).map(|m| m as u32 + 1)
+    }
+
+    fn number(s: &str, digits: std::ops::RangeInclusive<usize>) -> Option<u32> {
+        if !digits.contains(&s.len()) || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok()
+    }
+
+    fn time_of_day(s: &str) -> Option<(u32, u32, u32)> {
+        let parts: Vec<&str> = s.split(':').collect::<Vec<_>>();
+        if parts.len() != 3 {
+            return None;
+        }
+        Some((number(parts[0], 2..=2)?, number(parts[1], 2..=2)?, number(parts[2], 2..=2)?))
+    }
+
+    let tokens: Vec<&str> = s.split([' ', ','])
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>();
+    let (year, month, day, (hour, minute, second)) = match tokens.as_slice() {
+        [_, day, month_name, year, time, gmt] if gmt.eq_ignore_ascii_case("GMT") => (
+            number(year, 4..=4)? as i64,
+            month(month_name)?,
+            number(day, 2..=2)?,
+            time_of_day(time)?
+        ),
+        [_, date, time, gmt] if gmt.eq_ignore_ascii_case("GMT") => {
+            let parts: Vec<&str> = date.split('-').collect::<Vec<_>>();
+            if parts.len() != 3 {
+                return None;
+            }
+            let year: i64 = number(parts[2], 2..=2)? as i64;
+            (
+                if year < 70 { 2000 + year } else { 1900 + year },
+                month(parts[1])?,
+                number(parts[0], 2..=2)?,
+                time_of_day(time)?
+            )
+        },
+        [_, month_name, day, time, year] => (
+            number(year, 4..=4)? as i64,
+            month(month_name)?,
+            number(day, 1..=2)?,
+            time_of_day(time)?
+        ),
+        _ => return None
+    };
+
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let days: i64 = days_from_civil(year, month, day);
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+
+    let secs: i64 = days * 86400 + (hour * 3600 + minute * 60 + second) as i64;
+    if secs >= 0 {
+        Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    } else {
+        Some(UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()))
+    }
+}
+
+/// Validators of the current representation of a resource
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Validators {
+    pub etag: Option<ETag>,
+    pub last_modified: Option<SystemTime>
+}
+
+/// Conditional headers of a request, evaluated against the response produced for it
+#[derive(Clone, Debug, Default)]
+pub struct Preconditions {
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+    if_unmodified_since: Option<String>
+}
+
+impl Preconditions {
+    /// Collects conditional headers from request headers with lowercase names
+    pub fn from_headers(headers: &HttpHeaders) -> Self {
+        Self {
+            if_match: headers.get("if-match").cloned(),
+            if_none_match: headers.get("if-none-match").cloned(),
+            if_modified_since: headers.get("if-modified-since").cloned(),
+            if_unmodified_since: headers.get("if-unmodified-since").cloned()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.if_match.is_none()
+            && self.if_none_match.is_none()
+            && self.if_modified_since.is_none()
+            && self.if_unmodified_since.is_none()
+    }
+
+    /// Evaluates preconditions in the order given by RFC 7232 section 6, based on `ETag` and
+    /// `Last-Modified` of `response`. Returns `Some(304)` or `Some(412)` if the response should be
+    /// replaced, or `None` if the response should be sent as is.
+    pub fn evaluate(&self, method: &str, response: &HttpResponse) -> Option<u16> {
+        if !response.code.is_success() || self.is_empty() {
+            return None;
+        }
+
+        let validators: Validators = Validators {
+            etag: header_value(&response.headers, "ETag").and_then(ETag::parse),
+            last_modified: header_value(&response.headers, "Last-Modified").and_then(parse_http_date)
+        };
+        self.evaluate_validators(method, Some(&validators))
+    }
+
+    /// Evaluates preconditions against the validators of the current representation of a
+    /// resource, or `None` if the resource has no current representation, so that requests with
+    /// unsafe methods can be rejected before they take effect. A missing `ETag` fails every
+    /// `If-Match` other than `*`, and `*` only matches existing resources.
+    pub fn evaluate_validators(&self, method: &str, current: Option<&Validators>) -> Option<u16> {
+        let safe: bool = method.eq_ignore_ascii_case("get") || method.eq_ignore_ascii_case("head");
+        let etag: Option<&ETag> = current.and_then(|current| current.etag.as_ref());
+        let last_modified: Option<SystemTime> = current.and_then(|current| current.last_modified);
+
+        if let Some(if_match) = &self.if_match {
+            if !etag_list_matches(if_match, current.is_some(), etag, ETag::strong_eq) {
+                return Some(412);
+            }
+        } else if let (Some(since), Some(last_modified)) = (
+            self.if_unmodified_since.as_deref().and_then(parse_http_date),
+            last_modified
+        ) {
+            if last_modified > since {
+                return Some(412);
+            }
+        }
+
+        if let Some(if_none_match) = &self.if_none_match {
+            if etag_list_matches(if_none_match, current.is_some(), etag, ETag::weak_eq) {
+                return Some(if safe { 304 } else { 412 });
+            }
+        } else if let (true, Some(since), Some(last_modified)) = (
+            safe,
+            self.if_modified_since.as_deref().and_then(parse_http_date),
+            last_modified
+        ) {
+            if last_modified <= since {
+                return Some(304);
+            }
+        }
+
+        None
+    }
+
+    /// Evaluates preconditions, replacing `response` with `304 Not Modified` or
+    /// `412 Precondition Failed` when appropriate
+    pub fn apply(&self, method: &str, response: HttpResponse) -> HttpResponse {
+        match self.evaluate(method, &response) {
+            Some(304) => {
+                // RFC 7232 section 4.1: only these headers describe the not-modified response
+                const KEPT: [&str; 7] = [
+                    "cache-control",
+                    "content-location",
+                    "date",
+                    "etag",
+                    "expires",
+                    "last-modified",
+                    "vary"
+                ];
+                let headers: Vec<(String, String)> = response.headers.into_iter()
+                    .filter(|(h, _)| KEPT.iter().any(|kept| h.eq_ignore_ascii_case(kept)))
+                    .collect();
+                HttpResponse::new_raw(304, headers, None)
+            },
+            Some(code) => HttpResponse::new_raw(code, vec![], None),
+            None => response
+        }
+    }
+}
+
+/// Whether `list` of an `If-Match` or `If-None-Match` header matches the current representation,
+/// if it `exists`, with entity tag `etag`
+fn etag_list_matches(
+    list: &str,
+    exists: bool,
+    etag: Option<&ETag>,
+    eq: fn(&ETag, &ETag) -> bool
+) -> bool {
+    if list.trim() == "*" {
+        return exists;
+    }
+
+    match etag {
+        Some(etag) => list.split(',').filter_map(ETag::parse).any(|candidate| eq(&candidate, etag)),
+        None => false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::http_commons::{HttpHeaders, HttpResponse, header_value};
+    use crate::http_commons::cache::{
+        CacheControl,
+        ETag,
+        Preconditions,
+        Validators,
+        format_http_date,
+        parse_http_date
+    };
+
+    #[test]
+    fn test_etag() {
+        assert_eq!(ETag::parse("\"abc\""), Some(ETag::strong("abc")));
+        assert_eq!(ETag::parse(" W/\"abc\" "), Some(ETag::weak("abc")));
+        assert_eq!(ETag::parse("abc"), None);
+        assert_eq!(ETag::parse("\"a\"b\""), None);
+        assert_eq!(ETag::weak("x").to_string(), "W/\"x\"");
+
+        assert!(ETag::strong("a").strong_eq(&ETag::strong("a")));
+        assert!(!ETag::strong("a").strong_eq(&ETag::weak("a")));
+        assert!(ETag::strong("a").weak_eq(&ETag::weak("a")));
+
+        assert_eq!(ETag::from_payload(b"", false).tag, "cbf29ce484222325");
+        assert_eq!(ETag::from_payload(b"a", true).to_string(), "W/\"af63dc4c8601ec8c\"");
+    }
+
+    #[test]
+    fn test_cache_control() {
+        assert_eq!(CacheControl::new().to_string(), "");
+        assert_eq!(CacheControl::new().no_store().to_string(), "no-store");
+        assert_eq!(
+            CacheControl::new().private().no_cache().max_age(0).must_revalidate().to_string(),
+            "private, no-cache, max-age=0, must-revalidate"
+        );
+    }
+
+    #[test]
+    fn test_http_date() {
+        let time: SystemTime = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(format_http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(
+            format_http_date(UNIX_EPOCH + Duration::from_secs(951782400)),
+            "Tue, 29 Feb 2000 00:00:00 GMT"
+        );
+
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), Some(time));
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Some(time));
+        assert_eq!(parse_http_date("Tue, 29 Feb 2000 00:00:00 GMT"), Some(UNIX_EPOCH + Duration::from_secs(951782400)));
+        assert_eq!(parse_http_date("Wed, 29 Feb 2001 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 CST"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 24:49:37 GMT"), None);
+        assert_eq!(parse_http_date("yesterday"), None);
+
+        let now: SystemTime = SystemTime::now();
+        let truncated: SystemTime = parse_http_date(&format_http_date(now)).unwrap();
+        assert!(now.duration_since(truncated).unwrap() < Duration::from_secs(1));
+    }
+
+    fn conditional(method: &str, headers: &[(&str, &str)], response: &HttpResponse) -> Option<u16> {
+        let headers: HttpHeaders = headers.iter()
+            .map(|(h, v)| (h.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        Preconditions::from_headers(&headers).evaluate(method, response)
+    }
+
+    #[test]
+    fn test_preconditions() {
+        let response: HttpResponse = HttpResponse::builder()
+            .set_etag(ETag::strong("v2"))
+            .set_last_modified(UNIX_EPOCH + Duration::from_secs(784111777))
+            .build();
+        let before: &str = "Sun, 06 Nov 1994 08:49:36 GMT";
+        let at: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+        assert_eq!(conditional("GET", &[], &response), None);
+        assert_eq!(conditional("GET", &[("if-none-match", "\"v1\", W/\"v2\"")], &response), Some(304));
+        assert_eq!(conditional("GET", &[("if-none-match", "\"v1\"")], &response), None);
+        assert_eq!(conditional("POST", &[("if-none-match", "*")], &response), Some(412));
+        assert_eq!(conditional("GET", &[("if-modified-since", at)], &response), Some(304));
+        assert_eq!(conditional("GET", &[("if-modified-since", before)], &response), None);
+        assert_eq!(conditional("POST", &[("if-modified-since", at)], &response), None);
+        assert_eq!(
+            conditional("GET", &[("if-none-match", "\"v1\""), ("if-modified-since", at)], &response),
+            None
+        );
+        assert_eq!(conditional("POST", &[("if-match", "\"v2\"")], &response), None);
+        assert_eq!(conditional("POST", &[("if-match", "W/\"v2\"")], &response), Some(412));
+        assert_eq!(conditional("POST", &[("if-match", "*")], &response), None);
+        assert_eq!(conditional("POST", &[("if-unmodified-since", before)], &response), Some(412));
+        assert_eq!(conditional("POST", &[("if-unmodified-since", at)], &response), None);
+
+        let not_found: HttpResponse = HttpResponse::builder().set_code(404).build();
+        assert_eq!(conditional("GET", &[("if-match", "\"v2\"")], &not_found), None);
+
+        let headers: HttpHeaders = vec![("if-match".to_string(), "\"v2\"".to_string())]
+            .into_iter()
+            .collect();
+        let preconditions: Preconditions = Preconditions::from_headers(&headers);
+        let current = |etag: Option<&str>| Validators { etag: etag.map(ETag::strong), last_modified: None };
+        assert_eq!(preconditions.evaluate_validators("POST", Some(&current(Some("v2")))), None);
+        assert_eq!(preconditions.evaluate_validators("POST", Some(&current(Some("v1")))), Some(412));
+        assert_eq!(preconditions.evaluate_validators("POST", Some(&current(None))), Some(412));
+        assert_eq!(preconditions.evaluate_validators("POST", None), Some(412));
+
+        let headers: HttpHeaders = vec![("if-none-match".to_string(), "*".to_string())]
+            .into_iter()
+            .collect();
+        let preconditions: Preconditions = Preconditions::from_headers(&headers);
+        assert_eq!(preconditions.evaluate_validators("PUT", None), None);
+        assert_eq!(preconditions.evaluate_validators("PUT", Some(&current(None))), Some(412));
+        let headers: HttpHeaders = vec![("if-match".to_string(), "*".to_string())]
+            .into_iter()
+            .collect();
+        let preconditions: Preconditions = Preconditions::from_headers(&headers);
+        assert_eq!(preconditions.evaluate_validators("PUT", None), Some(412));
+        assert_eq!(preconditions.evaluate_validators("PUT", Some(&current(None))), None);
+
+        let preconditions: Preconditions = Preconditions::from_headers(
+            &vec![("if-none-match".to_string(), "\"v2\"".to_string())].into_iter().collect()
+        );
+        let response: HttpResponse = preconditions.apply("GET", HttpResponse::builder()
+            .add_header("Content-Type", "text/plain")
+            .set_cache_control(CacheControl::new().max_age(60))
+            .set_payload("hello")
+            .auto_etag(false)
+            .build());
+        assert_eq!(response.code, 200);
+        let etag: String = header_value(&response.headers, "ETag").unwrap().to_string();
+
+        let preconditions: Preconditions = Preconditions::from_headers(
+            &vec![("if-none-match".to_string(), etag)].into_iter().collect()
+        );
+        let response: HttpResponse = preconditions.apply("GET", response);
+        assert_eq!(response.code, 304);
+        assert!(response.payload.is_none());
+        assert!(!response.has_header("Content-Type"));
+        assert_eq!(header_value(&response.headers, "Cache-Control"), Some("max-age=60"));
+    }
+}
@@ -0,0 +1,366 @@
+//! MIME types: `Content-Type` parsing, file extension table and content sniffing
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::http_commons::{is_token, split_unquoted, unquote};
+
+/// A MIME type with parameters, like `text/html; charset=utf-8`
+///
+/// Type, subtype and parameter names are case-insensitive, and stored in lowercase.
+///
+/// ```
+/// # use xjbutil::minhttpd::Mime;
+/// let mime: Mime = "multipart/form-data; boundary=\"--abc\"".parse().unwrap();
+/// assert_eq!(mime.essence(), "multipart/form-data");
+/// assert_eq!(mime.boundary(), Some("--abc"));
+///
+/// let mime: Mime = Mime::new("text", "plain").with_param("charset", "utf-8");
+/// assert_eq!(mime.to_string(), "text/plain; charset=utf-8");
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mime {
+    main_type: String,
+    sub_type: String,
+    params: Vec<(String, String)>
+}
+
+/// Error of parsing a malformed MIME type
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvalidMime(pub String);
+
+impl Display for InvalidMime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid MIME type {:?}", self.0)
+    }
+}
+
+impl Error for InvalidMime {}
+
+impl Mime {
+    /// # Panics
+    ///
+    /// Panics if `main_type` or `sub_type` is not a valid token
+    pub fn new(main_type: &str, sub_type: &str) -> Self {
+        assert!(
+            is_token(main_type) && is_token(sub_type),
+            "invalid MIME type {}/{}", main_type, sub_type
+        );
+        Self {
+            main_type: main_type.to_ascii_lowercase(),
+            sub_type: sub_type.to_ascii_lowercase(),
+            params: Vec::new()
+        }
+    }
+
+    /// Sets parameter `name`, replacing existing values
+    pub fn with_param(mut self, name: &str, value: impl Into<String>) -> Self {
+        let name: String = name.to_ascii_lowercase();
+        self.params.retain(|(n, _)| *n != name);
+        self.params.push((name, value.into()));
+        self
+    }
+
+    pub fn main_type(&self) -> &str {
+        &self.main_type
+    }
+
+    pub fn sub_type(&self) -> &str {
+        &self.sub_type
+    }
+
+    /// Structured syntax suffix of the subtype, like `json` in `application/ld+json`
+    pub fn suffix(&self) -> Option<&str> {
+        self.sub_type.rsplit_once('+').map(|(_, suffix)| suffix)
+    }
+
+    /// `type/subtype` without parameters
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.main_type, self.sub_type)
+    }
+
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+
+    pub fn boundary(&self) -> Option<&str> {
+        self.param("boundary")
+    }
+
+    /// Whether content of this type is text, including JSON, XML and JavaScript
+    pub fn is_text(&self) -> bool {
+        self.main_type == "text"
+            || matches!(self.suffix(), Some("json") | Some("xml"))
+            || matches!(
+                self.essence().as_str(),
+                "application/json" | "application/xml" | "application/javascript"
+                    | "application/x-www-form-urlencoded" | "image/svg+xml"
+            )
+    }
+
+    /// Guesses MIME type from file extension, case-insensitively. Text types get a UTF-8 charset.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let essence: &str = mime_for_extension(extension)?;
+        let mime: Mime = essence.parse().unwrap();
+        if mime.is_text() {
+            Some(mime.with_param("charset", "utf-8"))
+        } else {
+            Some(mime)
+        }
+    }
+
+    /// Guesses MIME type from the extension of the last component of `path`
+    pub fn from_path(path: &str) -> Option<Self> {
+        let file_name: &str = path.rsplit(['/', '\\']).next()?;
+        let (_, extension) = file_name.rsplit_once('.')?;
+        Self::from_extension(extension)
+    }
+}
+
+impl FromStr for Mime {
+    type Err = InvalidMime;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidMime(s.to_string());
+
+        let mut parts = split_unquoted(s, b';').into_iter();
+        let essence: &str = parts.next().unwrap().trim();
+        let (main_type, sub_type) = essence.split_once('/').ok_or_else(invalid)?;
+        if !is_token(main_type) || !is_token(sub_type) {
+            return Err(invalid());
+        }
+
+        let mut mime: Mime = Mime::new(main_type, sub_type);
+        for param in parts {
+            let param: &str = param.trim();
+            if param.is_empty() {
+                continue;
+            }
+            let (name, value) = param.split_once('=').ok_or_else(invalid)?;
+            let value: &str = value.trim();
+            let quoted: bool = value.len() >= 2 && value.starts_with('"') && value.ends_with('"');
+            if !is_token(name.trim()) || !(is_token(value) || quoted) {
+                return Err(invalid());
+            }
+            mime.params.push((name.trim().to_ascii_lowercase(), unquote(value)));
+        }
+        Ok(mime)
+    }
+}
+
+impl Display for Mime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.main_type, self.sub_type)?;
+        for (name, value) in self.params.iter() {
+            if is_token(value) {
+                write!(f, "; {}={}", name, value)?;
+            } else {
+                write!(f, "; {}=\"{}\"", name, value.replace('\\', "\\\\").replace('"', "\\\""))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// MIME type of file extension `extension`, without leading dot, compared case-insensitively
+pub fn mime_for_extension(extension: &str) -> Option<&'static str> {
+    let extension: String = extension.to_ascii_lowercase();
+    let mime: &str = match extension.as_str() {
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "txt" | "text" | "log" => "text/plain",
+        "csv" => "text/csv",
+        "md" | "markdown" => "text/markdown",
+        "xml" => "application/xml",
+        "json" | "map" => "application/json",
+        "jsonld" => "application/ld+json",
+        "yaml" | "yml" => "application/yaml",
+        "toml" => "application/toml",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "bz2" => "application/x-bzip2",
+        "xz" => "application/x-xz",
+        "7z" => "application/x-7z-compressed",
+        "rar" => "application/vnd.rar",
+        "bin" | "exe" | "dll" | "so" => "application/octet-stream",
+        "rtf" => "application/rtf",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xls" => "application/vnd.ms-excel",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "ppt" => "application/vnd.ms-powerpoint",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "odt" => "application/vnd.oasis.opendocument.text",
+        "epub" => "application/epub+zip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "bmp" => "image/bmp",
+        "ico" => "image/vnd.microsoft.icon",
+        "svg" => "image/svg+xml",
+        "tif" | "tiff" => "image/tiff",
+        "mp3" => "audio/mpeg",
+        "ogg" | "oga" => "audio/ogg",
+        "opus" => "audio/opus",
+        "flac" => "audio/flac",
+        "wav" => "audio/wav",
+        "aac" => "audio/aac",
+        "mid" | "midi" => "audio/midi",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "ogv" => "video/ogg",
+        "avi" => "video/x-msvideo",
+        "mov" => "video/quicktime",
+        "mkv" => "video/x-matroska",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        _ => return None
+    };
+    Some(mime)
+}
+
+/// Guesses MIME type from the leading bytes of `content`, recognizing common image, audio, video,
+/// font and archive formats, as well as HTML and XML documents
+pub fn sniff(content: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: [(&[u8], &str); 21] = [
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"BM", "image/bmp"),
+        (b"\x00\x00\x01\x00", "image/vnd.microsoft.icon"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b\x08", "application/gzip"),
+        (b"BZh", "application/x-bzip2"),
+        (b"\xfd7zXZ\x00", "application/x-xz"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"Rar!\x1a\x07", "application/vnd.rar"),
+        (b"\x00asm", "application/wasm"),
+        (b"ID3", "audio/mpeg"),
+        (b"OggS\x00", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+        (b"wOFF", "font/woff"),
+        (b"wOF2", "font/woff2"),
+        (b"\x00\x01\x00\x00\x00", "font/ttf")
+    ];
+
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(magic, _)| content.starts_with(magic)) {
+        return Some(mime);
+    }
+
+    if content.len() >= 12 && content.starts_with(b"RIFF") {
+        match &content[8..12] {
+            b"WEBP" => return Some("image/webp"),
+            b"WAVE" => return Some("audio/wav"),
+            b"AVI " => return Some("video/x-msvideo"),
+            _ => {}
+        }
+    }
+    if content.len() >= 12 && &content[4..8] == b"ftyp" {
+        return match &content[8..12] {
+            b"avif" => Some("image/avif"),
+            b"qt  " => Some("video/quicktime"),
+            _ => Some("video/mp4")
+        };
+    }
+    if content.starts_with(b"OTTO") {
+        return Some("font/otf");
+    }
+
+    let text: &[u8] = content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(content);
+    let start: usize = text.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(text.len());
+    let text: &[u8] = &text[start..];
+    let starts_with_ignore_case = |prefix: &[u8]| {
+        text.len() >= prefix.len() && text[..prefix.len()].eq_ignore_ascii_case(prefix)
+    };
+    let html_tags: [&str; 4] = ["<!doctype html", "<html", "<head", "<body"];
+    if html_tags.iter().any(|tag| starts_with_ignore_case(tag.as_bytes())) {
+        return Some("text/html");
+    }
+    if starts_with_ignore_case(b"<svg") {
+        return Some("image/svg+xml");
+    }
+    if starts_with_ignore_case(b"<?xml") {
+        return Some("application/xml");
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http_commons::mime::{InvalidMime, Mime, mime_for_extension, sniff};
+
+    #[test]
+    fn test_parse_mime() {
+        let mime: Mime = "Text/HTML ; Charset=\"UTF-8\"; level=1".parse().unwrap();
+        assert_eq!(mime.main_type(), "text");
+        assert_eq!(mime.sub_type(), "html");
+        assert_eq!(mime.charset(), Some("UTF-8"));
+        assert_eq!(mime.param("LEVEL"), Some("1"));
+        assert_eq!(mime.to_string(), "text/html; charset=UTF-8; level=1");
+
+        let mime: Mime = "multipart/form-data; boundary=\"a b\\\"c\";".parse().unwrap();
+        assert_eq!(mime.boundary(), Some("a b\"c"));
+        assert_eq!(mime.to_string(), "multipart/form-data; boundary=\"a b\\\"c\"");
+        assert_eq!(mime.to_string().parse::<Mime>().unwrap(), mime);
+
+        let mime: Mime = "application/vnd.api+json".parse().unwrap();
+        assert_eq!(mime.suffix(), Some("json"));
+        assert!(mime.is_text());
+        assert!(!"image/png".parse::<Mime>().unwrap().is_text());
+
+        let invalid_mimes: [&str; 7] = [
+            "", "text", "text/", "/html", "text/html; charset", "te xt/html", "text/html; a=b c"
+        ];
+        for invalid in invalid_mimes.iter() {
+            assert_eq!(invalid.parse::<Mime>(), Err(InvalidMime(invalid.to_string())));
+        }
+    }
+
+    #[test]
+    fn test_extension() {
+        assert_eq!(mime_for_extension("PNG"), Some("image/png"));
+        assert_eq!(mime_for_extension("unknown"), None);
+        assert_eq!(
+            Mime::from_path("/static/app.min.js").unwrap().to_string(),
+            "text/javascript; charset=utf-8"
+        );
+        assert_eq!(Mime::from_path("C:\\data\\photo.JPG").unwrap().to_string(), "image/jpeg");
+        assert_eq!(Mime::from_path("/etc.d/config").map(|m| m.to_string()), None);
+        assert_eq!(Mime::from_path("Makefile"), None);
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some("image/png"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WAVEfmt "), Some("audio/wav"));
+        assert_eq!(sniff(b"\0\0\0\x20ftypisom"), Some("video/mp4"));
+        assert_eq!(sniff(b"\xef\xbb\xbf  \n<!DOCTYPE HTML><html>"), Some("text/html"));
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?>"), Some("application/xml"));
+        assert_eq!(sniff(b"<svg xmlns=\"http://www.w3.org/2000/svg\">"), Some("image/svg+xml"));
+        assert_eq!(sniff(b"hello"), None);
+        assert_eq!(sniff(b""), None);
+    }
+}
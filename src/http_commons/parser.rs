@@ -0,0 +1,1003 @@
+//! Incremental HTTP/1.x message parser
+//!
+//! Both parsers consume arbitrary byte slices, so the input may be split at any position. Each
+//! call to `feed` reports whether the message is `Complete`, whether the parser `NeedMore` input,
+//! or the precise reason why the input is not a valid HTTP/1.x message.
+//!
+//! ```
+//! # use xjbutil::minhttpd::{HttpRequestParser, ParseStatus};
+//! let mut parser: HttpRequestParser = HttpRequestParser::new();
+//! assert!(matches!(parser.feed(b"POST /api HTTP/1.1\r\nContent-Le"), ParseStatus::NeedMore));
+//! assert!(matches!(parser.feed(b"ngth: 5\r\n\r\nhel"), ParseStatus::NeedMore));
+//! if let ParseStatus::Complete(request) = parser.feed(b"lo") {
+//!     assert_eq!(request.head.method, "POST");
+//!     assert_eq!(request.body.unwrap(), b"hello");
+//! } else {
+//!     unreachable!()
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::http_commons::{HttpResponse, header_value};
+use crate::http_commons::status::StatusCode;
+
+/// Result of feeding input to a parser
+#[derive(Debug)]
+pub enum ParseStatus<T> {
+    /// The message is not complete yet, more input is required
+    NeedMore,
+    /// The message has been completely parsed
+    Complete(T),
+    /// The input is not a valid HTTP/1.x message
+    Error(ParseErrorKind)
+}
+
+/// Reasons why an input cannot be parsed as HTTP/1.x message
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    /// The request line is not in `method SP request-target SP version` form
+    InvalidRequestLine,
+    /// The method contains non-token characters
+    InvalidMethod,
+    /// The request target is empty or contains invalid characters
+    InvalidUri,
+    /// The version is not in `HTTP/x.y` form
+    InvalidVersion,
+    /// The version is well-formed, but not `HTTP/1.0` or `HTTP/1.1`
+    UnsupportedVersion,
+    /// The status line is not in `version SP status-code SP reason-phrase` form
+    InvalidStatusLine,
+    /// The status code is not a three-digit number in range `100..=599`
+    InvalidStatusCode,
+    /// A header line does not contain a colon
+    InvalidHeaderLine,
+    /// A header name is empty or contains non-token characters
+    InvalidHeaderName,
+    /// A header value contains control characters
+    InvalidHeaderValue,
+    /// A header line starts with whitespace (RFC 7230 obsolete line folding)
+    ObsoleteLineFolding,
+    /// The `Content-Length` header is not a non-negative decimal number
+    InvalidContentLength,
+    /// Multiple `Content-Length` headers with different values
+    ConflictingContentLength,
+    /// Both `Transfer-Encoding` and `Content-Length` are present
+    ConflictingFraming,
+    /// The `Transfer-Encoding` of a request is something other than `chunked`
+    UnsupportedTransferEncoding,
+    /// A chunk size line is not a hexadecimal number
+    InvalidChunkSize,
+    /// Chunk data is not followed by a line break
+    InvalidChunkTerminator,
+    /// The message head exceeds `ParserLimits::max_head_size`
+    HeadTooLarge,
+    /// The message head has more headers than `ParserLimits::max_headers`
+    TooManyHeaders,
+    /// The message body exceeds `ParserLimits::max_body_size`
+    BodyTooLarge,
+    /// The input ended before the message was complete
+    UnexpectedEof
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let description: &'static str = match self {
+            ParseErrorKind::InvalidRequestLine => "invalid request line",
+            ParseErrorKind::InvalidMethod => "invalid method",
+            ParseErrorKind::InvalidUri => "invalid request target",
+            ParseErrorKind::InvalidVersion => "invalid HTTP version",
+            ParseErrorKind::UnsupportedVersion => "unsupported HTTP version",
+            ParseErrorKind::InvalidStatusLine => "invalid status line",
+            ParseErrorKind::InvalidStatusCode => "invalid status code",
+            ParseErrorKind::InvalidHeaderLine => "invalid header line",
+            ParseErrorKind::InvalidHeaderName => "invalid header name",
+            ParseErrorKind::InvalidHeaderValue => "invalid header value",
+            ParseErrorKind::ObsoleteLineFolding => "obsolete line folding",
+            ParseErrorKind::InvalidContentLength => "invalid content length",
+            ParseErrorKind::ConflictingContentLength => "conflicting content lengths",
+            ParseErrorKind::ConflictingFraming => "both transfer encoding and content length present",
+            ParseErrorKind::UnsupportedTransferEncoding => "unsupported transfer encoding",
+            ParseErrorKind::InvalidChunkSize => "invalid chunk size",
+            ParseErrorKind::InvalidChunkTerminator => "invalid chunk terminator",
+            ParseErrorKind::HeadTooLarge => "message head too large",
+            ParseErrorKind::TooManyHeaders => "too many headers",
+            ParseErrorKind::BodyTooLarge => "message body too large",
+            ParseErrorKind::UnexpectedEof => "unexpected end of input"
+        };
+        write!(f, "{}", description)
+    }
+}
+
+impl Error for ParseErrorKind {}
+
+/// Resource limits applied while parsing
+#[derive(Clone, Copy, Debug)]
+pub struct ParserLimits {
+    /// Maximum size of start line plus headers, in bytes
+    pub max_head_size: usize,
+    /// Maximum count of headers
+    pub max_headers: usize,
+    /// Maximum size of decoded body, in bytes
+    pub max_body_size: usize
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        Self {
+            max_head_size: 16 * 1024,
+            max_headers: 128,
+            max_body_size: usize::MAX
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HttpVersion {
+    Http10,
+    Http11,
+    /// Requests received over HTTP/2 streams, never produced by the parsers
+    Http2
+}
+
+impl Display for HttpVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpVersion::Http10 => write!(f, "HTTP/1.0"),
+            HttpVersion::Http11 => write!(f, "HTTP/1.1"),
+            HttpVersion::Http2 => write!(f, "HTTP/2")
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpRequestHead {
+    pub method: String,
+    pub uri: String,
+    pub version: HttpVersion,
+    pub headers: Vec<(String, String)>
+}
+
+impl HttpRequestHead {
+    /// Value of the first header named `name`, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header_value(&self.headers, name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpRequest {
+    pub head: HttpRequestHead,
+    pub body: Option<Vec<u8>>
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpResponseHead {
+    pub version: HttpVersion,
+    pub code: StatusCode,
+    pub reason: String,
+    pub headers: Vec<(String, String)>
+}
+
+impl HttpResponseHead {
+    /// Value of the first header named `name`, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header_value(&self.headers, name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpResponseMessage {
+    pub head: HttpResponseHead,
+    pub body: Option<Vec<u8>>
+}
+
+impl From<HttpResponseMessage> for HttpResponse {
+    fn from(message: HttpResponseMessage) -> Self {
+        HttpResponse::with_status(message.head.code, message.head.headers, message.body)
+    }
+}
+
+/// Incremental parser for HTTP/1.x requests
+///
+/// After a request completes, bytes following it are kept, so pipelined requests can be parsed by
+/// calling `feed` again (possibly with an empty slice). Errors are sticky.
+pub struct HttpRequestParser {
+    engine: Engine,
+    head: Option<HttpRequestHead>
+}
+
+impl HttpRequestParser {
+    pub fn new() -> Self {
+        Self::with_limits(ParserLimits::default())
+    }
+
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self {
+            engine: Engine::new(limits),
+            head: None
+        }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) -> ParseStatus<HttpRequest> {
+        let current_head: &mut Option<HttpRequestHead> = &mut self.head;
+        self.engine.feed(data, |engine| {
+            if current_head.is_none() {
+                let (start_line, headers) = match engine.parse_head()? {
+                    Some(head) => head,
+                    None => return Ok(None)
+                };
+                let (method, uri, version) = parse_request_line(&start_line)?;
+                engine.begin_body(request_framing(&headers)?)?;
+                *current_head = Some(HttpRequestHead { method, uri, version, headers });
+            }
+
+            Ok(engine.parse_body()?.map(|body| HttpRequest {
+                head: current_head.take().unwrap(),
+                body
+            }))
+        })
+    }
+
+    /// Head of the request currently being parsed, available once all headers have arrived
+    pub fn head(&self) -> Option<&HttpRequestHead> {
+        self.head.as_ref()
+    }
+
+    /// Bytes received but not yet consumed by a message
+    pub fn remaining(&self) -> &[u8] {
+        &self.engine.buffer
+    }
+}
+
+impl Default for HttpRequestParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Incremental parser for HTTP/1.x responses
+///
+/// Responses without `Content-Length` or chunked encoding are delimited by closing the connection,
+/// call `feed_eof` to complete them.
+pub struct HttpResponseParser {
+    engine: Engine,
+    head: Option<HttpResponseHead>,
+    head_request: bool
+}
+
+impl HttpResponseParser {
+    pub fn new() -> Self {
+        Self::with_limits(ParserLimits::default())
+    }
+
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self {
+            engine: Engine::new(limits),
+            head: None,
+            head_request: false
+        }
+    }
+
+    /// Creates a parser for the response to a request with given method. Responses to `HEAD`
+    /// requests never have a body, regardless of their headers.
+    pub fn for_method(method: &str, limits: ParserLimits) -> Self {
+        let mut ret: Self = Self::with_limits(limits);
+        ret.head_request = method.eq_ignore_ascii_case("head");
+        ret
+    }
+
+    pub fn feed(&mut self, data: &[u8]) -> ParseStatus<HttpResponseMessage> {
+        let head_request: bool = self.head_request;
+        let current_head: &mut Option<HttpResponseHead> = &mut self.head;
+        self.engine.feed(data, |engine| {
+            if current_head.is_none() {
+                let (start_line, headers) = match engine.parse_head()? {
+                    Some(head) => head,
+                    None => return Ok(None)
+                };
+                let (version, code, reason) = parse_status_line(&start_line)?;
+                engine.begin_body(response_framing(code, head_request, &headers)?)?;
+                *current_head = Some(HttpResponseHead { version, code, reason, headers });
+            }
+
+            Ok(engine.parse_body()?.map(|body| HttpResponseMessage {
+                head: current_head.take().unwrap(),
+                body
+            }))
+        })
+    }
+
+    /// Signals that the connection has been closed by peer
+    pub fn feed_eof(&mut self) -> ParseStatus<HttpResponseMessage> {
+        let status: ParseStatus<HttpResponseMessage> = self.feed(&[]);
+        if !matches!(status, ParseStatus::NeedMore) {
+            return status;
+        }
+
+        match (&self.engine.state, self.head.take()) {
+            (EngineState::Body(BodyState::UntilEof), Some(head)) => {
+                let body: Vec<u8> = std::mem::take(&mut self.engine.body);
+                self.engine.state = EngineState::Head;
+                ParseStatus::Complete(HttpResponseMessage { head, body: Some(body) })
+            },
+            _ => {
+                self.engine.failed = Some(ParseErrorKind::UnexpectedEof);
+                ParseStatus::Error(ParseErrorKind::UnexpectedEof)
+            }
+        }
+    }
+
+    /// Head of the response currently being parsed, available once all headers have arrived
+    pub fn head(&self) -> Option<&HttpResponseHead> {
+        self.head.as_ref()
+    }
+
+    /// Bytes received but not yet consumed by a message
+    pub fn remaining(&self) -> &[u8] {
+        &self.engine.buffer
+    }
+}
+
+impl Default for HttpResponseParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type ParseResult<T> = Result<Option<T>, ParseErrorKind>;
+
+const MAX_CHUNK_SIZE_LINE: usize = 1024;
+
+enum Framing {
+    None,
+    Length(usize),
+    Chunked,
+    UntilEof
+}
+
+enum BodyState {
+    None,
+    Length(usize),
+    ChunkSize,
+    ChunkData(usize),
+    ChunkDataEnd,
+    Trailer,
+    UntilEof
+}
+
+enum EngineState {
+    Head,
+    Body(BodyState)
+}
+
+struct Engine {
+    limits: ParserLimits,
+    buffer: Vec<u8>,
+    body: Vec<u8>,
+    state: EngineState,
+    trailer_size: usize,
+    failed: Option<ParseErrorKind>
+}
+
+impl Engine {
+    fn new(limits: ParserLimits) -> Self {
+        Self {
+            limits,
+            buffer: Vec::new(),
+            body: Vec::new(),
+            state: EngineState::Head,
+            trailer_size: 0,
+            failed: None
+        }
+    }
+
+    fn feed<T>(
+        &mut self,
+        data: &[u8],
+        advance: impl FnOnce(&mut Self) -> ParseResult<T>
+    ) -> ParseStatus<T> {
+        if let Some(kind) = self.failed {
+            return ParseStatus::Error(kind);
+        }
+
+        self.buffer.extend_from_slice(data);
+        match advance(self) {
+            Ok(Some(message)) => ParseStatus::Complete(message),
+            Ok(None) => ParseStatus::NeedMore,
+            Err(kind) => {
+                self.failed = Some(kind);
+                ParseStatus::Error(kind)
+            }
+        }
+    }
+
+    fn parse_head(&mut self) -> ParseResult<(String, Vec<(String, String)>)> {
+        // RFC 7230 section 3.5: ignore empty lines received prior to the start line
+        let leading: usize = self.buffer.iter().take_while(|b| **b == b'\r' || **b == b'\n').count();
+        self.buffer.drain(..leading);
+
+        let mut pos: usize = 0;
+        let mut lines: Vec<&[u8]> = Vec::new();
+        loop {
+            let (line, next): (&[u8], usize) = match take_line(&self.buffer, pos) {
+                Some(line) => line,
+                None => {
+                    return if self.buffer.len() > self.limits.max_head_size {
+                        Err(ParseErrorKind::HeadTooLarge)
+                    } else {
+                        Ok(None)
+                    };
+                }
+            };
+            pos = next;
+            if pos > self.limits.max_head_size {
+                return Err(ParseErrorKind::HeadTooLarge);
+            }
+            if line.is_empty() {
+                break;
+            }
+            lines.push(line);
+        }
+
+        let start_line: String = String::from_utf8_lossy(lines[0]).into_owned();
+        if lines.len() - 1 > self.limits.max_headers {
+            return Err(ParseErrorKind::TooManyHeaders);
+        }
+        let headers: Vec<(String, String)> = lines[1..].iter()
+            .map(|line| parse_header_line(line))
+            .collect::<Result<_, _>>()?;
+
+        self.buffer.drain(..pos);
+        Ok(Some((start_line, headers)))
+    }
+
+    fn begin_body(&mut self, framing: Framing) -> Result<(), ParseErrorKind> {
+        self.body.clear();
+        self.trailer_size = 0;
+        self.state = EngineState::Body(match framing {
+            Framing::None => BodyState::None,
+            Framing::Length(length) => {
+                if length > self.limits.max_body_size {
+                    return Err(ParseErrorKind::BodyTooLarge);
+                }
+                BodyState::Length(length)
+            },
+            Framing::Chunked => BodyState::ChunkSize,
+            Framing::UntilEof => BodyState::UntilEof
+        });
+        Ok(())
+    }
+
+    fn parse_body(&mut self) -> ParseResult<Option<Vec<u8>>> {
+        loop {
+            let state: &mut BodyState = match &mut self.state {
+                EngineState::Body(state) => state,
+                EngineState::Head => unreachable!()
+            };
+
+            match state {
+                BodyState::None => {
+                    self.state = EngineState::Head;
+                    return Ok(Some(None));
+                },
+                BodyState::Length(length) => {
+                    let length: usize = *length;
+                    if self.buffer.len() < length {
+                        return Ok(None);
+                    }
+                    let body: Vec<u8> = self.buffer.drain(..length).collect();
+                    self.state = EngineState::Head;
+                    return Ok(Some(Some(body)));
+                },
+                BodyState::ChunkSize => {
+                    let (line, next): (&[u8], usize) = match take_line(&self.buffer, 0) {
+                        Some(line) => line,
+                        None => {
+                            return if self.buffer.len() > MAX_CHUNK_SIZE_LINE {
+                                Err(ParseErrorKind::InvalidChunkSize)
+                            } else {
+                                Ok(None)
+                            };
+                        }
+                    };
+                    let size: usize = parse_chunk_size(line)?;
+                    self.buffer.drain(..next);
+                    if size == 0 {
+                        *state = BodyState::Trailer;
+                    } else {
+                        if self.limits.max_body_size - self.body.len() < size {
+                            return Err(ParseErrorKind::BodyTooLarge);
+                        }
+                        *state = BodyState::ChunkData(size);
+                    }
+                },
+                BodyState::ChunkData(remaining) => {
+                    let taken: usize = usize::min(*remaining, self.buffer.len());
+                    self.body.extend(self.buffer.drain(..taken));
+                    *remaining -= taken;
+                    if *remaining != 0 {
+                        return Ok(None);
+                    }
+                    *state = BodyState::ChunkDataEnd;
+                },
+                BodyState::ChunkDataEnd => {
+                    match self.buffer.as_slice() {
+                        [b'\n', ..] => { self.buffer.drain(..1); },
+                        [b'\r', b'\n', ..] => { self.buffer.drain(..2); },
+                        [] | [b'\r'] => return Ok(None),
+                        _ => return Err(ParseErrorKind::InvalidChunkTerminator)
+                    }
+                    *state = BodyState::ChunkSize;
+                },
+                BodyState::Trailer => {
+                    let (line, next): (&[u8], usize) = match take_line(&self.buffer, 0) {
+                        Some(line) => line,
+                        None => {
+                            return if self.trailer_size + self.buffer.len() > self.limits.max_head_size {
+                                Err(ParseErrorKind::HeadTooLarge)
+                            } else {
+                                Ok(None)
+                            };
+                        }
+                    };
+                    if !line.is_empty() {
+                        parse_header_line(line)?;
+                    }
+                    let finished: bool = line.is_empty();
+                    self.trailer_size += next;
+                    if self.trailer_size > self.limits.max_head_size {
+                        return Err(ParseErrorKind::HeadTooLarge);
+                    }
+                    self.buffer.drain(..next);
+                    if finished {
+                        self.state = EngineState::Head;
+                        return Ok(Some(Some(std::mem::take(&mut self.body))));
+                    }
+                },
+                BodyState::UntilEof => {
+                    if self.limits.max_body_size - self.body.len() < self.buffer.len() {
+                        return Err(ParseErrorKind::BodyTooLarge);
+                    }
+                    self.body.append(&mut self.buffer);
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+fn take_line(buffer: &[u8], pos: usize) -> Option<(&[u8], usize)> {
+    let offset: usize = buffer[pos..].iter().position(|b| *b == b'\n')?;
+    let line: &[u8] = &buffer[pos..pos + offset];
+    let line: &[u8] = line.strip_suffix(b"\r").unwrap_or(line);
+    Some((line, pos + offset + 1))
+}
+
+fn is_token_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+pub(crate) fn is_token(s: &[u8]) -> bool {
+    !s.is_empty() && s.iter().all(|b| is_token_char(*b))
+}
+
+fn parse_version(version: &str, error: ParseErrorKind) -> Result<HttpVersion, ParseErrorKind> {
+    let version: &[u8] = version.as_bytes();
+    if version.len() != 8
+        || !version[..5].eq_ignore_ascii_case(b"http/")
+        || !version[5].is_ascii_digit()
+        || version[6] != b'.'
+        || !version[7].is_ascii_digit()
+    {
+        return Err(error);
+    }
+
+    match (version[5], version[7]) {
+        (b'1', b'0') => Ok(HttpVersion::Http10),
+        (b'1', b'1') => Ok(HttpVersion::Http11),
+        _ => Err(ParseErrorKind::UnsupportedVersion)
+    }
+}
+
+fn parse_request_line(line: &str) -> Result<(String, String, HttpVersion), ParseErrorKind> {
+    let parts: Vec<&str> = line.split(' ').collect::<Vec<_>>();
+    if parts.len() != 3 {
+        return Err(ParseErrorKind::InvalidRequestLine);
+    }
+
+    if !is_token(parts[0].as_bytes()) {
+        return Err(ParseErrorKind::InvalidMethod);
+    }
+    if parts[1].is_empty() || !parts[1].bytes().all(|b| (0x21..=0x7e).contains(&b)) {
+        return Err(ParseErrorKind::InvalidUri);
+    }
+    let version: HttpVersion = parse_version(parts[2], ParseErrorKind::InvalidVersion)?;
+
+    Ok((parts[0].to_string(), parts[1].to_string(), version))
+}
+
+fn parse_status_line(line: &str) -> Result<(HttpVersion, StatusCode, String), ParseErrorKind> {
+    let parts: Vec<&str> = line.splitn(3, ' ').collect::<Vec<_>>();
+    if parts.len() < 2 {
+        return Err(ParseErrorKind::InvalidStatusLine);
+    }
+
+    let version: HttpVersion = parse_version(parts[0], ParseErrorKind::InvalidStatusLine)?;
+    if parts[1].len() != 3 || !parts[1].bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseErrorKind::InvalidStatusCode);
+    }
+    let code: StatusCode = parts[1].parse().ok()
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .ok_or(ParseErrorKind::InvalidStatusCode)?;
+    let reason: &str = parts.get(2).copied().unwrap_or("");
+    if reason.bytes().any(|b| b.is_ascii_control() && b != b'\t') {
+        return Err(ParseErrorKind::InvalidStatusLine);
+    }
+
+    Ok((version, code, reason.to_string()))
+}
+
+fn parse_header_line(line: &[u8]) -> Result<(String, String), ParseErrorKind> {
+    if line[0] == b' ' || line[0] == b'\t' {
+        return Err(ParseErrorKind::ObsoleteLineFolding);
+    }
+
+    let colon: usize = line.iter()
+        .position(|b| *b == b':')
+        .ok_or(ParseErrorKind::InvalidHeaderLine)?;
+    let name: &[u8] = &line[..colon];
+    if !is_token(name) {
+        return Err(ParseErrorKind::InvalidHeaderName);
+    }
+
+    let value: &[u8] = &line[colon + 1..];
+    if value.iter().any(|b| (*b < 0x20 && *b != b'\t') || *b == 0x7f) {
+        return Err(ParseErrorKind::InvalidHeaderValue);
+    }
+    let start: usize = value.iter()
+        .position(|b| *b != b' ' && *b != b'\t')
+        .unwrap_or(value.len());
+    let end: usize = value.iter()
+        .rposition(|b| *b != b' ' && *b != b'\t')
+        .map_or(start, |pos| pos + 1);
+
+    Ok((
+        String::from_utf8_lossy(name).into_owned(),
+        String::from_utf8_lossy(&value[start..end]).into_owned()
+    ))
+}
+
+fn parse_chunk_size(line: &[u8]) -> Result<usize, ParseErrorKind> {
+    let size: &[u8] = match line.iter().position(|b| *b == b';') {
+        Some(pos) => &line[..pos],
+        None => line
+    };
+    let size: &[u8] = match size.iter().rposition(|b| *b != b' ' && *b != b'\t') {
+        Some(pos) => &size[..=pos],
+        None => return Err(ParseErrorKind::InvalidChunkSize)
+    };
+
+    let mut ret: usize = 0;
+    for b in size {
+        let digit: usize = (*b as char).to_digit(16).ok_or(ParseErrorKind::InvalidChunkSize)? as usize;
+        ret = ret.checked_mul(16)
+            .and_then(|ret| ret.checked_add(digit))
+            .ok_or(ParseErrorKind::InvalidChunkSize)?;
+    }
+    Ok(ret)
+}
+
+fn content_length(headers: &[(String, String)]) -> Result<Option<usize>, ParseErrorKind> {
+    let mut ret: Option<usize> = None;
+    for (_, value) in headers.iter().filter(|(h, _)| h.eq_ignore_ascii_case("content-length")) {
+        for item in value.split(',') {
+            let item: &str = item.trim();
+            if item.is_empty() || !item.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(ParseErrorKind::InvalidContentLength);
+            }
+            let length: usize = item.parse().map_err(|_| ParseErrorKind::InvalidContentLength)?;
+            match ret {
+                Some(previous) if previous != length => {
+                    return Err(ParseErrorKind::ConflictingContentLength);
+                },
+                _ => ret = Some(length)
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// Returns `Some(true)` if the last transfer coding is `chunked`, `Some(false)` if it is not, and
+/// `None` if there's no `Transfer-Encoding` header at all.
+fn transfer_chunked(headers: &[(String, String)]) -> Option<bool> {
+    let codings: Vec<String> = headers.iter()
+        .filter(|(h, _)| h.eq_ignore_ascii_case("transfer-encoding"))
+        .flat_map(|(_, v)| v.split(','))
+        .map(|coding| coding.trim().to_ascii_lowercase())
+        .filter(|coding| !coding.is_empty())
+        .collect::<Vec<_>>();
+    if codings.is_empty() {
+        None
+    } else {
+        Some(codings.len() == 1 && codings[0] == "chunked")
+    }
+}
+
+fn request_framing(headers: &[(String, String)]) -> Result<Framing, ParseErrorKind> {
+    let length: Option<usize> = content_length(headers)?;
+    match (transfer_chunked(headers), length) {
+        (Some(_), Some(_)) => Err(ParseErrorKind::ConflictingFraming),
+        (Some(true), None) => Ok(Framing::Chunked),
+        (Some(false), None) => Err(ParseErrorKind::UnsupportedTransferEncoding),
+        (None, Some(length)) => Ok(Framing::Length(length)),
+        (None, None) => Ok(Framing::None)
+    }
+}
+
+fn response_framing(
+    code: StatusCode,
+    head_request: bool,
+    headers: &[(String, String)]
+) -> Result<Framing, ParseErrorKind> {
+    if head_request || code.is_bodiless() {
+        return Ok(Framing::None);
+    }
+
+    let length: Option<usize> = content_length(headers)?;
+    match (transfer_chunked(headers), length) {
+        (Some(_), Some(_)) => Err(ParseErrorKind::ConflictingFraming),
+        (Some(true), None) => Ok(Framing::Chunked),
+        (Some(false), None) => Ok(Framing::UntilEof),
+        (None, Some(length)) => Ok(Framing::Length(length)),
+        (None, None) => Ok(Framing::UntilEof)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http_commons::parser::{
+        HttpRequest,
+        HttpRequestParser,
+        HttpResponseMessage,
+        HttpResponseParser,
+        HttpVersion,
+        ParseErrorKind,
+        ParserLimits,
+        ParseStatus
+    };
+    use crate::rand_intern::random;
+
+    fn parse_request(input: &[u8]) -> ParseStatus<HttpRequest> {
+        HttpRequestParser::new().feed(input)
+    }
+
+    fn parse_request_error(input: &[u8]) -> ParseErrorKind {
+        match parse_request(input) {
+            ParseStatus::Error(kind) => kind,
+            status => panic!("expected error, got {:?}", status)
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let input: &[u8] =
+            b"\r\nPOST /api/v1?name=chuigda HTTP/1.1\r\nHost: example.com\r\n\
+              X-Empty:\r\nContent-Length: 11\r\n\r\nhello world";
+        let request: HttpRequest = match parse_request(input) {
+            ParseStatus::Complete(request) => request,
+            status => panic!("{:?}", status)
+        };
+        assert_eq!(request.head.method, "POST");
+        assert_eq!(request.head.uri, "/api/v1?name=chuigda");
+        assert_eq!(request.head.version, HttpVersion::Http11);
+        assert_eq!(request.head.header("host"), Some("example.com"));
+        assert_eq!(request.head.header("x-empty"), Some(""));
+        assert_eq!(request.body.unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn test_parse_request_byte_by_byte() {
+        let input: &[u8] =
+            b"PUT /upload HTTP/1.0\nTransfer-Encoding: chunked\n\n\
+              5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Trailer: 1\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+        let mut parser: HttpRequestParser = HttpRequestParser::new();
+        let mut completed: Vec<HttpRequest> = Vec::new();
+        for b in input {
+            match parser.feed(&[*b]) {
+                ParseStatus::NeedMore => {},
+                ParseStatus::Complete(request) => completed.push(request),
+                ParseStatus::Error(kind) => panic!("{}", kind)
+            }
+        }
+
+        assert_eq!(completed.len(), 2);
+        assert_eq!(completed[0].head.method, "PUT");
+        assert_eq!(completed[0].head.version, HttpVersion::Http10);
+        assert_eq!(completed[0].body.as_ref().unwrap(), b"hello world");
+        assert_eq!(completed[1].head.method, "GET");
+        assert!(completed[1].body.is_none());
+        assert!(parser.remaining().is_empty());
+    }
+
+    #[test]
+    fn test_parse_request_pipelined() {
+        let mut parser: HttpRequestParser = HttpRequestParser::new();
+        let input: &[u8] = b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n";
+        assert!(matches!(parser.feed(input), ParseStatus::Complete(r) if r.head.uri == "/a"));
+        assert!(matches!(parser.feed(&[]), ParseStatus::Complete(r) if r.head.uri == "/b"));
+        assert!(matches!(parser.feed(&[]), ParseStatus::NeedMore));
+    }
+
+    #[test]
+    fn test_parse_request_head_first() {
+        let mut parser: HttpRequestParser = HttpRequestParser::new();
+        let status = parser.feed(b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nab");
+        assert!(matches!(status, ParseStatus::NeedMore));
+        assert_eq!(parser.head().unwrap().header("Content-Length"), Some("3"));
+    }
+
+    #[test]
+    fn test_parse_request_errors() {
+        assert_eq!(parse_request_error(b"GET /\r\n\r\n"), ParseErrorKind::InvalidRequestLine);
+        assert_eq!(parse_request_error(b"GET  / HTTP/1.1\r\n\r\n"), ParseErrorKind::InvalidRequestLine);
+        assert_eq!(parse_request_error(b"G(T / HTTP/1.1\r\n\r\n"), ParseErrorKind::InvalidMethod);
+        assert_eq!(parse_request_error(b"GET /\x01 HTTP/1.1\r\n\r\n"), ParseErrorKind::InvalidUri);
+        assert_eq!(parse_request_error(b"GET / HTTP/11\r\n\r\n"), ParseErrorKind::InvalidVersion);
+        assert_eq!(parse_request_error(b"GET / HTTP/2.0\r\n\r\n"), ParseErrorKind::UnsupportedVersion);
+        assert_eq!(
+            parse_request_error(b"GET / HTTP/1.1\r\nHost\r\n\r\n"),
+            ParseErrorKind::InvalidHeaderLine
+        );
+        assert_eq!(
+            parse_request_error(b"GET / HTTP/1.1\r\nHost : a\r\n\r\n"),
+            ParseErrorKind::InvalidHeaderName
+        );
+        assert_eq!(
+            parse_request_error(b"GET / HTTP/1.1\r\nHost: a\x00b\r\n\r\n"),
+            ParseErrorKind::InvalidHeaderValue
+        );
+        assert_eq!(
+            parse_request_error(b"GET / HTTP/1.1\r\nHost: a\r\n b\r\n\r\n"),
+            ParseErrorKind::ObsoleteLineFolding
+        );
+        assert_eq!(
+            parse_request_error(b"GET / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"),
+            ParseErrorKind::InvalidContentLength
+        );
+        assert_eq!(
+            parse_request_error(b"GET / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n"),
+            ParseErrorKind::ConflictingContentLength
+        );
+        assert_eq!(
+            parse_request_error(
+                b"GET / HTTP/1.1\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n"
+            ),
+            ParseErrorKind::ConflictingFraming
+        );
+        assert_eq!(
+            parse_request_error(b"GET / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n"),
+            ParseErrorKind::UnsupportedTransferEncoding
+        );
+        assert_eq!(
+            parse_request_error(b"GET / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nxyz\r\n"),
+            ParseErrorKind::InvalidChunkSize
+        );
+        assert_eq!(
+            parse_request_error(
+                b"GET / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\nab\r\n"
+            ),
+            ParseErrorKind::InvalidChunkTerminator
+        );
+    }
+
+    #[test]
+    fn test_parse_request_limits() {
+        let limits: ParserLimits = ParserLimits {
+            max_head_size: 64,
+            max_headers: 2,
+            max_body_size: 4
+        };
+
+        let mut parser: HttpRequestParser = HttpRequestParser::with_limits(limits);
+        assert!(matches!(parser.feed(&[b'a'; 65]), ParseStatus::Error(ParseErrorKind::HeadTooLarge)));
+        assert!(matches!(parser.feed(b"\r\n"), ParseStatus::Error(ParseErrorKind::HeadTooLarge)));
+
+        let mut parser: HttpRequestParser = HttpRequestParser::with_limits(limits);
+        assert!(matches!(
+            parser.feed(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"),
+            ParseStatus::Error(ParseErrorKind::TooManyHeaders)
+        ));
+
+        let mut parser: HttpRequestParser = HttpRequestParser::with_limits(limits);
+        assert!(matches!(
+            parser.feed(b"GET / HTTP/1.1\r\nContent-Length: 5\r\n\r\n"),
+            ParseStatus::Error(ParseErrorKind::BodyTooLarge)
+        ));
+
+        let mut parser: HttpRequestParser = HttpRequestParser::with_limits(limits);
+        assert!(matches!(
+            parser.feed(b"GET / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\n"),
+            ParseStatus::Error(ParseErrorKind::BodyTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let mut parser: HttpResponseParser = HttpResponseParser::new();
+        let status = parser.feed(b"HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\n\r\nnope");
+        let response: HttpResponseMessage = match status {
+            ParseStatus::Complete(response) => response,
+            status => panic!("{:?}", status)
+        };
+        assert_eq!(response.head.code, 404);
+        assert_eq!(response.head.reason, "Not Found");
+        assert_eq!(response.body.unwrap(), b"nope");
+
+        let mut parser: HttpResponseParser = HttpResponseParser::new();
+        assert!(matches!(parser.feed(b"HTTP/1.0 200\r\n\r\nabc"), ParseStatus::NeedMore));
+        assert!(matches!(parser.feed(b"def"), ParseStatus::NeedMore));
+        match parser.feed_eof() {
+            ParseStatus::Complete(response) => {
+                assert_eq!(response.head.reason, "");
+                assert_eq!(response.body.unwrap(), b"abcdef");
+            },
+            status => panic!("{:?}", status)
+        }
+
+        let mut parser: HttpResponseParser = HttpResponseParser::for_method(
+            "HEAD",
+            ParserLimits::default()
+        );
+        assert!(matches!(
+            parser.feed(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n"),
+            ParseStatus::Complete(HttpResponseMessage { body: None, .. })
+        ));
+
+        let mut parser: HttpResponseParser = HttpResponseParser::new();
+        assert!(matches!(parser.feed(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nab"), ParseStatus::NeedMore));
+        assert!(matches!(parser.feed_eof(), ParseStatus::Error(ParseErrorKind::UnexpectedEof)));
+
+        assert!(matches!(
+            HttpResponseParser::new().feed(b"HTTP/1.1 2000 OK\r\n\r\n"),
+            ParseStatus::Error(ParseErrorKind::InvalidStatusCode)
+        ));
+        assert!(matches!(
+            HttpResponseParser::new().feed(b"HTTP/1.1 600 Weird\r\n\r\n"),
+            ParseStatus::Error(ParseErrorKind::InvalidStatusCode)
+        ));
+        assert!(matches!(
+            HttpResponseParser::new().feed(b"ICY 200 OK\r\n\r\n"),
+            ParseStatus::Error(ParseErrorKind::InvalidStatusLine)
+        ));
+    }
+
+    #[test]
+    fn test_parse_garbage() {
+        let samples: [&[u8]; 3] = [
+            b"POST /a?b=c HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\nabc",
+            b"GET / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nab\r\n0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n1;a\r\nx\r\n0\r\n\r\n"
+        ];
+
+        for _ in 0..2000 {
+            let mut input: Vec<u8> = samples[(random() % 3) as usize].to_vec();
+            for _ in 0..random() % 4 {
+                let pos: usize = (random() as usize) % input.len();
+                input[pos] = random() as u8;
+            }
+
+            let mut request_parser: HttpRequestParser = HttpRequestParser::new();
+            let mut response_parser: HttpResponseParser = HttpResponseParser::new();
+            let mut pos: usize = 0;
+            while pos < input.len() {
+                let step: usize = usize::min((random() % 8 + 1) as usize, input.len() - pos);
+                let _ = request_parser.feed(&input[pos..pos + step]);
+                let _ = response_parser.feed(&input[pos..pos + step]);
+                pos += step;
+            }
+            let _ = response_parser.feed_eof();
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! HTTP status codes, as registered in the IANA HTTP Status Code Registry
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// A valid HTTP status code, in range `100..=599`
+///
+/// ```
+/// # use xjbutil::minhttpd::StatusCode;
+/// let code: StatusCode = StatusCode::from_u16(429).unwrap();
+/// assert_eq!(code, StatusCode::TOO_MANY_REQUESTS);
+/// assert_eq!(code, 429);
+/// assert!(code.is_client_error());
+/// assert_eq!(code.to_string(), "429 Too Many Requests");
+/// assert!(StatusCode::from_u16(1000).is_err());
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct StatusCode(u16);
+
+/// Error of converting a number outside `100..=599` into `StatusCode`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InvalidStatusCode(pub u16);
+
+impl Display for InvalidStatusCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid HTTP status code {}, expected 100..=599", self.0)
+    }
+}
+
+impl Error for InvalidStatusCode {}
+
+impl StatusCode {
+    pub fn from_u16(code: u16) -> Result<Self, InvalidStatusCode> {
+        if (100..=599).contains(&code) {
+            Ok(Self(code))
+        } else {
+            Err(InvalidStatusCode(code))
+        }
+    }
+
+    pub fn as_u16(self) -> u16 {
+        self.0
+    }
+
+    /// Reason phrase of the code in the registry, `None` for unassigned codes
+    pub fn canonical_reason(self) -> Option<&'static str> {
+        canonical_reason(self.0)
+    }
+
+    /// `1xx`
+    pub fn is_informational(self) -> bool {
+        (100..200).contains(&self.0)
+    }
+
+    /// `2xx`
+    pub fn is_success(self) -> bool {
+        (200..300).contains(&self.0)
+    }
+
+    /// `3xx`
+    pub fn is_redirection(self) -> bool {
+        (300..400).contains(&self.0)
+    }
+
+    /// `4xx`
+    pub fn is_client_error(self) -> bool {
+        (400..500).contains(&self.0)
+    }
+
+    /// `5xx`
+    pub fn is_server_error(self) -> bool {
+        (500..600).contains(&self.0)
+    }
+
+    /// Whether responses with this code never carry a body
+    pub fn is_bodiless(self) -> bool {
+        self.is_informational() || self.0 == 204 || self.0 == 304
+    }
+}
+
+impl Default for StatusCode {
+    fn default() -> Self {
+        Self::OK
+    }
+}
+
+impl Display for StatusCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.canonical_reason() {
+            Some(reason) => write!(f, "{} {}", self.0, reason),
+            None => write!(f, "{}", self.0)
+        }
+    }
+}
+
+impl TryFrom<u16> for StatusCode {
+    type Error = InvalidStatusCode;
+
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        Self::from_u16(code)
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(code: StatusCode) -> Self {
+        code.0
+    }
+}
+
+impl PartialEq<u16> for StatusCode {
+    fn eq(&self, other: &u16) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<StatusCode> for u16 {
+    fn eq(&self, other: &StatusCode) -> bool {
+        *self == other.0
+    }
+}
+
+macro_rules! status_codes {
+    ($($code:literal $name:ident $reason:literal;)*) => {
+        impl StatusCode {
+            $(
+                #[doc = concat!("`", stringify!($code), " ", $reason, "`")]
+                pub const $name: StatusCode = StatusCode($code);
+            )*
+        }
+
+        fn canonical_reason(code: u16) -> Option<&'static str> {
+            match code {
+                $($code => Some($reason),)*
+                _ => None
+            }
+        }
+    }
+}
+
+status_codes! {
+    100 CONTINUE "Continue";
+    101 SWITCHING_PROTOCOLS "Switching Protocols";
+    102 PROCESSING "Processing";
+    103 EARLY_HINTS "Early Hints";
+
+    200 OK "OK";
+    201 CREATED "Created";
+    202 ACCEPTED "Accepted";
+    203 NON_AUTHORITATIVE_INFORMATION "Non-Authoritative Information";
+    204 NO_CONTENT "No Content";
+    205 RESET_CONTENT "Reset Content";
+    206 PARTIAL_CONTENT "Partial Content";
+    207 MULTI_STATUS "Multi-Status";
+    208 ALREADY_REPORTED "Already Reported";
+    226 IM_USED "IM Used";
+
+    300 MULTIPLE_CHOICES "Multiple Choices";
+    301 MOVED_PERMANENTLY "Moved Permanently";
+    302 FOUND "Found";
+    303 SEE_OTHER "See Other";
+    304 NOT_MODIFIED "Not Modified";
+    305 USE_PROXY "Use Proxy";
+    307 TEMPORARY_REDIRECT "Temporary Redirect";
+    308 PERMANENT_REDIRECT "Permanent Redirect";
+
+    400 BAD_REQUEST "Bad Request";
+    401 UNAUTHORIZED "Unauthorized";
+    402 PAYMENT_REQUIRED "Payment Required";
+    403 FORBIDDEN "Forbidden";
+    404 NOT_FOUND "Not Found";
+    405 METHOD_NOT_ALLOWED "Method Not Allowed";
+    406 NOT_ACCEPTABLE "Not Acceptable";
+    407 PROXY_AUTHENTICATION_REQUIRED "Proxy Authentication Required";
+    408 REQUEST_TIMEOUT "Request Timeout";
+    409 CONFLICT "Conflict";
+    410 GONE "Gone";
+    411 LENGTH_REQUIRED "Length Required";
+    412 PRECONDITION_FAILED "Precondition Failed";
+    413 CONTENT_TOO_LARGE "Content Too Large";
+    414 URI_TOO_LONG "URI Too Long";
+    415 UNSUPPORTED_MEDIA_TYPE "Unsupported Media Type";
+    416 RANGE_NOT_SATISFIABLE "Range Not Satisfiable";
+    417 EXPECTATION_FAILED "Expectation Failed";
+    418 IM_A_TEAPOT "I'm a teapot";
+    421 MISDIRECTED_REQUEST "Misdirected Request";
+    422 UNPROCESSABLE_CONTENT "Unprocessable Content";
+    423 LOCKED "Locked";
+    424 FAILED_DEPENDENCY "Failed Dependency";
+    425 TOO_EARLY "Too Early";
+    426 UPGRADE_REQUIRED "Upgrade Required";
+    428 PRECONDITION_REQUIRED "Precondition Required";
+    429 TOO_MANY_REQUESTS "Too Many Requests";
+    431 REQUEST_HEADER_FIELDS_TOO_LARGE "Request Header Fields Too Large";
+    451 UNAVAILABLE_FOR_LEGAL_REASONS "Unavailable For Legal Reasons";
+
+    500 INTERNAL_SERVER_ERROR "Internal Server Error";
+    501 NOT_IMPLEMENTED "Not Implemented";
+    502 BAD_GATEWAY "Bad Gateway";
+    503 SERVICE_UNAVAILABLE "Service Unavailable";
+    504 GATEWAY_TIMEOUT "Gateway Timeout";
+    505 HTTP_VERSION_NOT_SUPPORTED "HTTP Version Not Supported";
+    506 VARIANT_ALSO_NEGOTIATES "Variant Also Negotiates";
+    507 INSUFFICIENT_STORAGE "Insufficient Storage";
+    508 LOOP_DETECTED "Loop Detected";
+    510 NOT_EXTENDED "Not Extended";
+    511 NETWORK_AUTHENTICATION_REQUIRED "Network Authentication Required";
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryFrom;
+
+    use crate::http_commons::status::{InvalidStatusCode, StatusCode};
+
+    #[test]
+    fn test_status_code() {
+        assert_eq!(StatusCode::from_u16(99), Err(InvalidStatusCode(99)));
+        assert_eq!(StatusCode::try_from(600), Err(InvalidStatusCode(600)));
+        assert_eq!(u16::from(StatusCode::try_from(599).unwrap()), 599);
+
+        let unassigned: StatusCode = StatusCode::from_u16(599).unwrap();
+        assert!(unassigned.is_server_error());
+        assert_eq!(unassigned.canonical_reason(), None);
+        assert_eq!(unassigned.to_string(), "599");
+
+        for (code, reason) in [
+            (308, "Permanent Redirect"),
+            (418, "I'm a teapot"),
+            (422, "Unprocessable Content"),
+            (425, "Too Early"),
+            (426, "Upgrade Required"),
+            (428, "Precondition Required"),
+            (431, "Request Header Fields Too Large"),
+            (451, "Unavailable For Legal Reasons"),
+            (511, "Network Authentication Required")
+        ].iter() {
+            assert_eq!(StatusCode::from_u16(*code).unwrap().canonical_reason(), Some(*reason));
+        }
+
+        assert!(StatusCode::CONTINUE.is_informational() && StatusCode::CONTINUE.is_bodiless());
+        assert!(StatusCode::NO_CONTENT.is_success() && StatusCode::NO_CONTENT.is_bodiless());
+        assert!(StatusCode::NOT_MODIFIED.is_redirection() && StatusCode::NOT_MODIFIED.is_bodiless());
+        assert!(StatusCode::NOT_FOUND.is_client_error() && !StatusCode::NOT_FOUND.is_bodiless());
+        assert!(StatusCode::BAD_GATEWAY.is_server_error());
+        assert_eq!(StatusCode::default(), 200);
+        assert!(StatusCode::OK < StatusCode::NOT_FOUND);
+    }
+}
@@ -1,14 +1,82 @@
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::error::Error;
-use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::io::{BufWriter, Cursor, Read, Write};
 use std::net::{SocketAddr, SocketAddrV4, TcpListener, TcpStream};
+use std::sync::Arc;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering::SeqCst;
 use std::thread;
-
-pub use crate::http_commons::{HttpBody, HttpHeaders, HttpParams, HttpResponse, HttpUri};
+use std::time::{Duration, Instant, SystemTime};
+
+mod auth;
+mod cors;
+pub mod extract;
+#[cfg(feature = "minhttpd-h2c")] mod h2;
+mod metrics;
+mod proxy;
+mod rate_limit;
+mod sse;
+mod vhost;
+
+pub use auth::{
+    AUTH_PRINCIPAL_HEADER,
+    AuthResult,
+    BasicAuth,
+    BasicAuthChecker,
+    BearerAuth,
+    BearerValidator,
+    StaticCredentials,
+    constant_time_eq
+};
+pub use cors::CorsPolicy;
+pub use metrics::{HttpMetrics, LATENCY_BUCKETS, RouteMetrics, UNMATCHED_ROUTE};
+pub use proxy::ReverseProxy;
+pub use rate_limit::{RateLimitKeyFn, RateLimiter};
+pub use sse::{EventSink, SseEvent};
+pub use vhost::VirtualHost;
+pub use crate::http_commons::{HttpBody, HttpError, HttpHeaders, HttpParams, HttpResponse, HttpUri};
+pub use crate::http_commons::accept::{
+    MediaRange,
+    QualityItem,
+    negotiate,
+    negotiate_charset,
+    negotiate_language,
+    parse_accept,
+    parse_quality_list
+};
+pub use crate::http_commons::cache::{
+    CacheControl,
+    ETag,
+    Preconditions,
+    Validators,
+    format_http_date,
+    parse_http_date
+};
 pub use crate::http_commons::http_code_describe;
+pub use crate::http_commons::mime::{InvalidMime, Mime, mime_for_extension, sniff};
+pub use crate::http_commons::status::{InvalidStatusCode, StatusCode};
+pub use crate::http_commons::parser::{
+    HttpRequest,
+    HttpRequestHead,
+    HttpRequestParser,
+    HttpResponseHead,
+    HttpResponseMessage,
+    HttpResponseParser,
+    HttpVersion,
+    ParseErrorKind,
+    ParserLimits,
+    ParseStatus
+};
+
+use metrics::Exchange;
+use vhost::{HostTable, RouteCaptures};
+
+/// Name of the header through which the server tells handlers the address of the client
+pub const REMOTE_ADDR_HEADER: &str = "X-47-Remote-Addr";
+
+/// Value of the `Server` header of responses, unless set by handlers
+const SERVER_NAME: &str = "xjbutil/0.9 rhttpd";
 
 const HTTP_404_STRING: &'static str = include_str!("../resc/http_404.html");
 
@@ -19,6 +87,32 @@ pub type HttpHandler = Box<
         + 'static
 >;
 
+/// Handler of Server-Sent Events routes, which pushes events to the client through `EventSink`.
+/// The connection gets closed as soon as the handler returns.
+pub type SseHandler = Box<
+    dyn Fn(HttpUri, HttpHeaders, HttpParams, EventSink) -> Result<(), Box<dyn Error>>
+        + Send
+        + Sync
+        + 'static
+>;
+
+/// Check of a request head, run before the request body is read. See `VirtualHost::upload_check`.
+pub type UploadCheck = Box<
+    dyn Fn(&HttpRequestHead) -> Result<(), HttpError>
+        + Send
+        + Sync
+        + 'static
+>;
+
+/// Validators of the current representation of a resource, given its path, or `None` if the
+/// resource does not exist. See `VirtualHost::validator`.
+pub type ResourceValidator = Box<
+    dyn Fn(&str) -> Option<Validators>
+        + Send
+        + Sync
+        + 'static
+>;
+
 type HttpHandlerFn = fn(HttpUri, HttpHeaders, HttpParams, HttpBody) -> Result<HttpResponse, Box<dyn Error>>;
 
 #[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd)]
@@ -28,49 +122,136 @@ pub enum HttpLogLevel {
 
 pub type HttpLogger = fn(level: HttpLogLevel, info: &str) -> ();
 
+pub(crate) enum Route {
+    Handler(HttpHandler),
+    EventStream(SseHandler),
+    Proxy(ReverseProxy)
+}
+
+/// Result of routing a request
+enum Dispatch<'a> {
+    /// Response to send back
+    Response(HttpResponse),
+    /// Head of an event stream, after which events produced by `handler` follow
+    EventStream {
+        response: HttpResponse,
+        handler: &'a SseHandler,
+        uri: HttpUri,
+        headers: HttpHeaders,
+        params: HttpParams
+    },
+    /// The request cannot be answered, and the connection gets closed
+    Abort
+}
+
 pub struct MinHttpd {
-    handlers: Vec<(HttpUri, HttpHandler)>,
+    hosts: HostTable,
     logger: Option<HttpLogger>,
-    request_counter: AtomicU64
+    cors: Option<CorsPolicy>,
+    sse_heartbeat: Option<Duration>,
+    max_body_size: usize,
+    request_counter: AtomicU64,
+    metrics: Arc<HttpMetrics>
 }
 
 impl MinHttpd {
     pub fn new() -> Self {
         Self {
-            handlers: Vec::new(),
+            hosts: HostTable::new(),
             logger: None,
-            request_counter: AtomicU64::new(0)
+            cors: None,
+            sse_heartbeat: Some(Duration::from_secs(15)),
+            max_body_size: usize::MAX,
+            request_counter: AtomicU64::new(0),
+            metrics: Arc::new(HttpMetrics::new())
         }
     }
 
     pub fn with_logger(logger: HttpLogger) -> Self {
         Self {
-            handlers: Vec::new(),
+            hosts: HostTable::new(),
             logger: Some(logger),
-            request_counter: AtomicU64::new(0)
+            cors: None,
+            sse_heartbeat: Some(Duration::from_secs(15)),
+            max_body_size: usize::MAX,
+            request_counter: AtomicU64::new(0),
+            metrics: Arc::new(HttpMetrics::new())
         }
     }
 
+    /// Enables CORS: preflight `OPTIONS` requests are answered automatically, and responses to
+    /// requests from allowed origins get decorated with CORS headers.
+    pub fn set_cors(&mut self, policy: CorsPolicy) {
+        self.cors = Some(policy);
+    }
+
+    /// Sets the interval of heartbeat comments sent on Server-Sent Events routes, `None` disables
+    /// heartbeats. Defaults to 15 seconds.
+    pub fn set_sse_heartbeat(&mut self, interval: Option<Duration>) {
+        self.sse_heartbeat = interval;
+    }
+
+    /// Sets the maximum size of request bodies, in bytes. Requests declaring a larger
+    /// `Content-Length` are rejected with `413` before their body is read. Unlimited by default.
+    pub fn set_max_body_size(&mut self, size: usize) {
+        self.max_body_size = size;
+    }
+
+    /// Metrics of requests handled so far, updated live as the server runs
+    pub fn metrics(&self) -> Arc<HttpMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Route table of requests whose `Host` matches `pattern`, created on first use. Patterns
+    /// are either exact host names, or `*.` followed by a domain, matching all its subdomains.
+    /// Requests matching no virtual host are dispatched to routes registered on `MinHttpd`.
+    pub fn virtual_host(&mut self, pattern: &str) -> &mut VirtualHost {
+        self.hosts.get_or_insert(pattern)
+    }
+
     pub fn route(&mut self, uri: &str, handler: HttpHandler) {
-        self.handlers.push((uri.to_string(), handler));
+        self.hosts.default_host.route(uri, handler);
     }
 
     pub fn route_fn(&mut self, uri: &str, handler_fn: HttpHandlerFn) {
-        self.handlers.push((uri.to_string(), Box::new(handler_fn)));
+        self.hosts.default_host.route_fn(uri, handler_fn);
     }
 
     pub fn route_static(&mut self, uri: &str, content_type: &str, content: String) {
-        let content_type: String = content_type.to_string();
-        self.handlers.push((
-            uri.to_string(),
-            Box::new(move |_, _, _, _| {
-                Ok(HttpResponse::new(
-                    200,
-                    vec![("Content-Type".to_string(), content_type.clone())],
-                    Some(content.clone()))
-                )
-            })
-        ));
+        self.hosts.default_host.route_static(uri, content_type, content);
+    }
+
+    /// Registers a Server-Sent Events route, which holds the connection open and streams
+    /// `text/event-stream` to the client
+    pub fn route_sse(&mut self, uri: &str, handler: SseHandler) {
+        self.hosts.default_host.route_sse(uri, handler);
+    }
+
+    /// Forwards all requests under `uri`, regardless of their method, to an upstream server
+    pub fn route_proxy(&mut self, uri: &str, proxy: ReverseProxy) {
+        self.hosts.default_host.route_proxy(uri, proxy);
+    }
+
+    /// Registers a check run on requests under `uri` before their body is read
+    pub fn upload_check(&mut self, uri: &str, check: UploadCheck) {
+        self.hosts.default_host.upload_check(uri, check);
+    }
+
+    /// Registers the validators of resources under `uri`, see `VirtualHost::validator`
+    pub fn validator(&mut self, uri: &str, validator: ResourceValidator) {
+        self.hosts.default_host.validator(uri, validator);
+    }
+
+    /// Registers a route exposing metrics in Prometheus text exposition format
+    pub fn route_metrics(&mut self, uri: &str) {
+        let metrics: Arc<HttpMetrics> = self.metrics.clone();
+        self.route(uri, Box::new(move |_, _, _, _| {
+            Ok(HttpResponse::builder()
+                .add_header("Content-Type", "text/plain; version=0.0.4")
+                .add_header("Cache-Control", "no-store")
+                .set_payload(metrics.render_prometheus())
+                .build())
+        }));
     }
 
     pub fn serve(&self, addr: SocketAddrV4) -> Result<Infallible, Box<dyn Error>> {
@@ -90,13 +271,23 @@ impl MinHttpd {
     fn handle_connection(&self, stream: TcpStream, remote_addr: String, request_id: u64) {
         let unsafe_self: &'static Self = unsafe { std::mem::transmute::<&'_ _, &'static _>(self) };
         thread::spawn(move || {
-            match Self::handle_connection_impl(unsafe_self, stream, remote_addr, request_id) {
+            let _in_flight = unsafe_self.metrics.enter();
+            let start: Instant = Instant::now();
+            let mut exchange: Exchange = Exchange::default();
+            match Self::handle_connection_impl(
+                unsafe_self,
+                stream,
+                remote_addr,
+                request_id,
+                &mut exchange
+            ) {
                 Ok(_) => {},
                 Err(e) => unsafe_self.log(
                     HttpLogLevel::Error,
                     &format!("[MIN-HTTPD/{}] Error handling connection: {}", request_id, e)
                 )
             }
+            unsafe_self.metrics.record(&exchange, start.elapsed());
         });
     }
 
@@ -104,49 +295,104 @@ impl MinHttpd {
         &self,
         stream: TcpStream,
         remote_addr: String,
-        request_id: u64
+        request_id: u64,
+        exchange: &mut Exchange
     ) -> Result<(), Box<dyn Error>> {
-        let mut reader: BufReader<&TcpStream> = BufReader::new(&stream);
-        let mut writer: BufWriter<&TcpStream> = BufWriter::new(&stream);
+        #[cfg(feature = "minhttpd-h2c")]
+        let received: Vec<u8> = match h2::sniff_preface(&stream)? {
+            h2::Sniffed::Http2(rest) => {
+                let start: h2::Start = h2::Start::PriorKnowledge(rest);
+                return h2::serve(self, stream, remote_addr, request_id, start);
+            },
+            h2::Sniffed::Http1(received) => received
+        };
+        #[cfg(not(feature = "minhttpd-h2c"))]
+        let received: Vec<u8> = Vec::new();
+
+        let mut reader = Cursor::new(received).chain(&stream);
+        let request: HttpRequest =
+            match self.read_request(&mut reader, &stream, request_id, exchange)? {
+                Some(request) => request,
+                None => return Ok(())
+            };
 
-        let mut line: String = String::new();
-        reader.read_line(&mut line)?;
+        #[cfg(feature = "minhttpd-h2c")]
+        if let Some(settings) = h2::upgrade_settings(&request.head) {
+            let start: h2::Start = h2::Start::Upgrade(request, settings);
+            return h2::serve(self, stream, remote_addr, request_id, start);
+        }
 
-        let parts: Vec<&str> = line.trim().split_whitespace().collect::<Vec<_>>();
-        if parts.len() != 3 {
-            self.log(
-                HttpLogLevel::Error,
-                &format!("[MIN-HTTPD/{}] Invalid HTTP request: {}", request_id, line)
-            );
-            return Ok(());
+        match self.dispatch(request, remote_addr, request_id, exchange) {
+            Dispatch::Response(response) => Self::write_response(&stream, response, exchange),
+            Dispatch::EventStream { response, handler, uri, headers, params } => {
+                exchange.event_stream = true;
+                stream.set_nodelay(true)?;
+                Self::write_response(&stream, response, exchange)?;
+                self.serve_event_stream(stream, request_id, handler, uri, headers, params)
+            },
+            Dispatch::Abort => Ok(())
         }
-        let method: String = parts[0].to_lowercase();
-        let version: String = parts[2].to_lowercase();
+    }
 
-        if method != "get" && method != "post" {
+    /// Routes a request and runs its handler
+    fn dispatch(
+        &self,
+        request: HttpRequest,
+        remote_addr: String,
+        request_id: u64,
+        exchange: &mut Exchange
+    ) -> Dispatch<'_> {
+        let origin: Option<String> = request.head.header("Origin").map(str::to_string);
+        let method: String = request.head.method.to_lowercase();
+        if let (Some(cors), Some(origin), "options") = (&self.cors, &origin, method.as_str()) {
+            if let Some(request_method) = request.head.header("Access-Control-Request-Method") {
+                self.log(
+                    HttpLogLevel::Info,
+                    &format!("[MIN-HTTPD/{}] CORS preflight from: {}", request_id, origin)
+                );
+                return Dispatch::Response(cors.preflight(
+                    origin,
+                    request_method,
+                    request.head.header("Access-Control-Request-Headers")
+                ));
+            }
+        }
+
+        let uri_parts: Vec<&str> = request.head.uri.split("?").collect::<Vec<_>>();
+        let uri: String = request_path(&request.head.uri);
+
+        let (host, vhost): (Option<&str>, &VirtualHost) =
+            self.hosts.select(request.head.header("Host"));
+        let (route, captures): (Option<&(HttpUri, Route)>, RouteCaptures) =
+            match vhost.find_route(&uri) {
+                Some((route, captures)) => (Some(route), captures),
+                None => (None, Vec::new())
+            };
+        exchange.route = route.map(|(prefix, _)| match host {
+            Some(host) => format!("{}{}", host, prefix),
+            None => prefix.clone()
+        });
+        if let Some((prefix, Route::Proxy(proxy))) = route {
             self.log(
-                HttpLogLevel::Error,
-                &format!("[MIN-HTTPD/{}] Invalid HTTP method: {}", request_id, parts[0])
+                HttpLogLevel::Info,
+                &format!("[MIN-HTTPD/{}] Forwarding to upstream: {}", request_id, request.head.uri)
             );
-            return Ok(());
+            let mut response: HttpResponse = proxy.forward(&request, prefix, &remote_addr);
+            if let (Some(cors), Some(origin)) = (&self.cors, &origin) {
+                cors.decorate(origin, &mut response);
+            }
+            return Dispatch::Response(response);
         }
-        if version != "http/1.1" && version != "http/1.0" {
+
+        if method != "get" && method != "post" {
             self.log(
                 HttpLogLevel::Error,
-                &format!("[MIN-HTTPD/{}] Invalid HTTP version: {}", request_id, parts[2])
+                &format!("[MIN-HTTPD/{}] Invalid HTTP method: {}", request_id, request.head.method)
             );
-            return Ok(());
+            return Dispatch::Abort;
         }
 
-        let uri: String = parts[1].to_string();
-        let uri_parts: Vec<&str> = uri.split("?").collect::<Vec<_>>();
-        let mut uri: String = uri_parts[0].to_string();
-
-        if uri.ends_with("/") {
-            uri.pop();
-        }
-
-        let params: HashMap<String, String> = if uri_parts.len() > 1 {
+        let mut params: HashMap<String, String> = if uri_parts.len() > 1 {
             let mut params: HashMap<String, String> = HashMap::new();
             for param in uri_parts[1].split("&") {
                 let param_parts: Vec<&str> = param.split("=").collect::<Vec<_>>();
@@ -165,57 +411,81 @@ impl MinHttpd {
                         HttpLogLevel::Error,
                         &format!("[MIN-HTTPD/{}] Invalid HTTP parameter: {}", request_id, param)
                     );
-                    return Ok(());
+                    return Dispatch::Abort;
                 }
             }
             params
         } else {
             HashMap::new()
         };
+        for (name, value) in captures {
+            params.insert(format!("{}{}", extract::CAPTURE_PREFIX, name), value);
+        }
 
         let mut headers: HashMap<String, String> = HashMap::new();
-        loop {
-            line.clear();
-            reader.read_line(&mut line)?;
-            if line.trim().is_empty() {
-                break;
-            }
-            let parts: Vec<&str> = line.trim().split(": ").collect::<Vec<_>>();
-            if parts.len() != 2 {
+        for (key, value) in request.head.headers {
+            headers.insert(key.to_lowercase(), value);
+        }
+        headers.insert(REMOTE_ADDR_HEADER.to_string(), remote_addr);
+        let preconditions: Preconditions = Preconditions::from_headers(&headers);
+        let safe: bool = method == "get";
+        if !safe && route.is_some() && !preconditions.is_empty() {
+            let current: Option<Validators> = match vhost.find_validator(&uri) {
+                Some(validator) => validator(&uri),
+                None => Some(Validators::default())
+            };
+            let code: Option<u16> = preconditions.evaluate_validators(&method, current.as_ref());
+            if let Some(code) = code {
                 self.log(
-                    HttpLogLevel::Error,
-                    &format!("[MIN-HTTPD/{}] Invalid HTTP header: {}", request_id, line)
+                    HttpLogLevel::Warn,
+                    &format!("[MIN-HTTPD/{}] Precondition failed: {}", request_id, uri)
                 );
-                return Ok(());
+                let mut response: HttpResponse = HttpResponse::new_raw(code, vec![], None);
+                if let (Some(cors), Some(origin)) = (&self.cors, &origin) {
+                    cors.decorate(origin, &mut response);
+                }
+                return Dispatch::Response(response);
             }
-            headers.insert(
-                parts[0].to_lowercase().to_string(),
-                parts[1].to_lowercase().to_string()
-            );
         }
-        headers.insert("X-47-Remote-Addr".to_string(), remote_addr);
 
-        let body: Option<Vec<u8>> = if headers.contains_key("content-length") {
-            let content_length: usize = headers["content-length"].parse()?;
-            let mut buffer: Vec<u8> = vec![0; content_length];
-            reader.read_exact(&mut buffer)?;
-            Some(buffer)
-        } else {
-            None
-        };
+        let response: HttpResponse = if let Some((_, route)) = route {
+            let handler: &HttpHandler = match route {
+                Route::Handler(handler) => handler,
+                Route::Proxy(_) => unreachable!(),
+                Route::EventStream(sse_handler) => {
+                    let mut response: HttpResponse = HttpResponse::builder()
+                        .add_header("Content-Type", "text/event-stream")
+                        .add_header("Cache-Control", "no-cache")
+                        .build();
+                    if let (Some(cors), Some(origin)) = (&self.cors, &origin) {
+                        cors.decorate(origin, &mut response);
+                    }
+                    return Dispatch::EventStream {
+                        response,
+                        handler: sse_handler,
+                        uri,
+                        headers,
+                        params
+                    };
+                }
+            };
 
-        let handler: Option<&(HttpUri, HttpHandler)> =
-            self.handlers.iter().find(|h| uri.starts_with(&h.0));
-        if let Some(handler) = handler {
-            let result: Result<HttpResponse, Box<dyn Error>> = (handler.1)(
+            let result: Result<HttpResponse, Box<dyn Error>> = handler(
                 uri.to_string(),
                 headers,
                 params,
-                body.map(|b| String::from_utf8_lossy(b.as_ref()).to_string()),
+                request.body.map(|b| String::from_utf8_lossy(b.as_ref()).to_string()),
             );
-            let mut response: HttpResponse = match result {
+            match result.map_err(|e| e.downcast::<HttpError>()) {
                 Ok(result) => result,
-                Err(e) => {
+                Err(Ok(http_error)) => {
+                    self.log(
+                        HttpLogLevel::Warn,
+                        &format!("[MIN-HTTPD/{}] Request rejected: {}", request_id, http_error)
+                    );
+                    http_error.into_response()
+                },
+                Err(Err(e)) => {
                     self.log(
                         HttpLogLevel::Error,
                         &format!("[MIN-HTTPD/{}] Error handling request: {}", request_id, e)
@@ -226,58 +496,212 @@ impl MinHttpd {
                         Some(format!(include_str!("../resc/http_500.html"), e)),
                     )
                 }
-            };
-
-            if response.has_header("Content-Length") {
-                self.log(
-                    HttpLogLevel::Error,
-                    &format!("[MIN-HTTPD/{}] Setting `Content-Length` is not allowed", request_id)
-                );
-                return Ok(());
             }
+        } else {
+            self.log(
+                HttpLogLevel::Warn,
+                &format!("[MIN-HTTPD/{}] No handler for URI: {}", request_id, uri),
+            );
 
-            if response.has_header("Connection") {
+            HttpResponse::new(
+                404,
+                vec![("Content-Type".to_string(), "text/html".to_string())],
+                Some(HTTP_404_STRING.to_string())
+            )
+        };
+
+        if response.has_header("Content-Length") {
+            self.log(
+                HttpLogLevel::Error,
+                &format!("[MIN-HTTPD/{}] Setting `Content-Length` is not allowed", request_id)
+            );
+            return Dispatch::Abort;
+        }
+
+        if response.has_header("Connection") {
+            self.log(
+                HttpLogLevel::Error,
+                &format!("[MIN-HTTPD/{}] Setting `Connection` is not allowed", request_id)
+            );
+            return Dispatch::Abort;
+        }
+
+        // preconditions of unsafe requests have been checked before running the handler
+        let mut response: HttpResponse = if safe {
+            preconditions.apply(&method, response)
+        } else {
+            response
+        };
+        if let (Some(cors), Some(origin)) = (&self.cors, &origin) {
+            cors.decorate(origin, &mut response);
+        }
+
+        Dispatch::Response(response)
+    }
+
+    fn serve_event_stream(
+        &self,
+        stream: TcpStream,
+        request_id: u64,
+        sse_handler: &SseHandler,
+        uri: HttpUri,
+        headers: HttpHeaders,
+        params: HttpParams
+    ) -> Result<(), Box<dyn Error>> {
+        self.log(
+            HttpLogLevel::Info,
+            &format!("[MIN-HTTPD/{}] Event stream opened: {}", request_id, uri)
+        );
+        let sink: EventSink = EventSink::new(stream, self.sse_heartbeat)?;
+        if let Err(e) = sse_handler(uri, headers, params, sink) {
+            self.log(
+                HttpLogLevel::Error,
+                &format!("[MIN-HTTPD/{}] Error handling event stream: {}", request_id, e)
+            );
+        }
+        self.log(HttpLogLevel::Info, &format!("[MIN-HTTPD/{}] Event stream closed", request_id));
+        Ok(())
+    }
+
+    fn read_request(
+        &self,
+        reader: &mut impl Read,
+        mut stream: &TcpStream,
+        request_id: u64,
+        exchange: &mut Exchange
+    ) -> Result<Option<HttpRequest>, Box<dyn Error>> {
+        let mut parser: HttpRequestParser = HttpRequestParser::with_limits(ParserLimits {
+            max_body_size: self.max_body_size,
+            ..ParserLimits::default()
+        });
+        let mut buffer: [u8; 4096] = [0; 4096];
+        let mut head_checked: bool = false;
+        loop {
+            let size: usize = reader.read(&mut buffer)?;
+            if size == 0 {
                 self.log(
                     HttpLogLevel::Error,
-                    &format!("[MIN-HTTPD/{}] Setting `Connection` is not allowed", request_id)
+                    &format!("[MIN-HTTPD/{}] Connection closed before request completed", request_id)
                 );
-                return Ok(());
+                return Ok(None);
             }
-
-            response.add_header("Connection", "close");
-            if !response.has_header("Server") {
-                response.add_header("Server", "xjbutil/0.9 rhttpd");
+            exchange.bytes_in += size as u64;
+
+            let status: ParseStatus<HttpRequest> = parser.feed(&buffer[..size]);
+            if !head_checked {
+                let head: Option<&HttpRequestHead> = match &status {
+                    ParseStatus::Complete(request) => Some(&request.head),
+                    _ => parser.head()
+                };
+                if let Some(head) = head {
+                    head_checked = true;
+                    match self.check_head(head, request_id) {
+                        Ok(true) if matches!(status, ParseStatus::NeedMore) => {
+                            let interim: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+                            stream.write_all(interim)?;
+                            exchange.bytes_out += interim.len() as u64;
+                        },
+                        Ok(_) => {},
+                        Err(response) => {
+                            Self::write_response(stream, response, exchange)?;
+                            return Ok(None);
+                        }
+                    }
+                }
             }
 
-            write!(
-                writer,
-                "HTTP/1.1 {} {}\r\n",
-                response.code,
-                http_code_describe(response.code)
-            )?;
-            for (key /*: String*/, value /*: String*/) in response.headers {
-                write!(writer, "{}: {}\r\n", key, value)?;
-            }
-            if let Some(payload) = response.payload {
-                write!(writer, "Content-Length: {}\r\n", payload.len())?;
-                write!(writer, "\r\n")?;
-                writer.write_all(&payload)?;
-            } else {
-                write!(writer, "\r\n")?;
+            match status {
+                ParseStatus::NeedMore => {},
+                ParseStatus::Complete(request) => return Ok(Some(request)),
+                ParseStatus::Error(ParseErrorKind::BodyTooLarge) => {
+                    self.log(
+                        HttpLogLevel::Warn,
+                        &format!("[MIN-HTTPD/{}] Request body too large", request_id)
+                    );
+                    let response: HttpResponse = HttpError::new(
+                        413,
+                        format!("request body exceeds {} bytes", self.max_body_size)
+                    ).into_response();
+                    Self::write_response(stream, response, exchange)?;
+                    return Ok(None);
+                },
+                ParseStatus::Error(kind) => {
+                    self.log(
+                        HttpLogLevel::Error,
+                        &format!("[MIN-HTTPD/{}] Invalid HTTP request: {}", request_id, kind)
+                    );
+                    return Ok(None);
+                }
             }
-        } else {
-            self.log(
-                HttpLogLevel::Warn,
-                &format!("[MIN-HTTPD/{}] No handler for URI: {}", request_id, uri),
-            );
+        }
+    }
 
-            write!(writer, "HTTP/1.1 404 Not Found\r\n")?;
-            write!(writer, "Connection: close\r\n")?;
-            write!(writer, "Content-Type: text/html\r\n")?;
-            write!(writer, "Content-Length: {}\r\n", HTTP_404_STRING.len())?;
-            write!(writer, "\r\n")?;
-            writer.write_all(HTTP_404_STRING.as_bytes())?;
+    /// Handles the `Expect` header and upload checks of a request whose head has arrived.
+    /// Returns whether the client waits for `100 Continue`, or the response rejecting the request.
+    fn check_head(&self, head: &HttpRequestHead, request_id: u64) -> Result<bool, HttpResponse> {
+        // HTTP/1.0 clients do not know about expectations, so they are ignored (RFC 7231 5.1.1)
+        let expects_continue: bool = match head.header("Expect") {
+            Some(expect) if head.version != HttpVersion::Http10 => {
+                if !expect.trim().eq_ignore_ascii_case("100-continue") {
+                    self.log(
+                        HttpLogLevel::Warn,
+                        &format!("[MIN-HTTPD/{}] Unsupported expectation: {}", request_id, expect)
+                    );
+                    let message: String = format!("unsupported expectation `{}`", expect);
+                    return Err(HttpError::new(417, message).into_response());
+                }
+                true
+            },
+            _ => false
         };
+
+        let (_, vhost): (Option<&str>, &VirtualHost) = self.hosts.select(head.header("Host"));
+        if let Some(check) = vhost.find_upload_check(&request_path(&head.uri)) {
+            if let Err(http_error) = check(head) {
+                self.log(
+                    HttpLogLevel::Warn,
+                    &format!("[MIN-HTTPD/{}] Upload rejected: {}", request_id, http_error)
+                );
+                return Err(http_error.into_response());
+            }
+        }
+        Ok(expects_continue)
+    }
+
+    fn write_response(
+        stream: &TcpStream,
+        mut response: HttpResponse,
+        exchange: &mut Exchange
+    ) -> Result<(), Box<dyn Error>> {
+        response.add_header("Connection", "close");
+        if !response.has_header("Server") {
+            response.add_header("Server", SERVER_NAME);
+        }
+        if !response.has_header("Date") {
+            response.add_header("Date", &format_http_date(SystemTime::now()));
+        }
+
+        exchange.code = Some(response.code.as_u16());
+        let mut head: String = format!(
+            "HTTP/1.1 {} {}\r\n",
+            response.code.as_u16(),
+            response.code.canonical_reason().unwrap_or("")
+        );
+        for (key /*: String*/, value /*: String*/) in response.headers {
+            head.push_str(&format!("{}: {}\r\n", key, value));
+        }
+        if let Some(payload) = &response.payload {
+            head.push_str(&format!("Content-Length: {}\r\n", payload.len()));
+        }
+        head.push_str("\r\n");
+
+        let mut writer: BufWriter<&TcpStream> = BufWriter::new(stream);
+        writer.write_all(head.as_bytes())?;
+        exchange.bytes_out += head.len() as u64;
+        if let Some(payload) = response.payload {
+            writer.write_all(&payload)?;
+            exchange.bytes_out += payload.len() as u64;
+        }
         writer.flush()?;
 
         Ok(())
@@ -296,13 +720,128 @@ impl Default for MinHttpd {
     }
 }
 
+/// Path part of request target `uri`, without query and trailing slash
+fn request_path(uri: &str) -> String {
+    let mut path: String = uri.split('?').next().unwrap_or("").to_string();
+    if path.ends_with('/') {
+        path.pop();
+    }
+    path
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::HashMap;
     use std::error::Error;
     use std::net::{Ipv4Addr, SocketAddrV4};
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::SeqCst;
+
+    use crate::minhttpd::{
+        Dispatch,
+        ETag,
+        EventSink,
+        Exchange,
+        HttpError,
+        HttpRequest,
+        HttpRequestHead,
+        HttpResponse,
+        HttpUri,
+        HttpVersion,
+        MinHttpd,
+        ReverseProxy,
+        SseEvent,
+        Validators
+    };
 
-    use crate::minhttpd::{HttpUri, HttpResponse, MinHttpd};
+    #[test]
+    fn test_check_head() {
+        fn head(version: HttpVersion, uri: &str, headers: &[(&str, &str)]) -> HttpRequestHead {
+            HttpRequestHead {
+                method: "POST".to_string(),
+                uri: uri.to_string(),
+                version,
+                headers: headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+            }
+        }
+
+        let mut min_httpd: MinHttpd = MinHttpd::new();
+        min_httpd.upload_check("/upload", Box::new(|head| {
+            match head.header("Content-Type") {
+                Some("image/png") => Ok(()),
+                _ => Err(HttpError::new(415, "only PNG images are accepted"))
+            }
+        }));
+
+        let check = |head: HttpRequestHead| {
+            min_httpd.check_head(&head, 0).map_err(|response| response.code.as_u16())
+        };
+        assert_eq!(check(head(HttpVersion::Http11, "/hello", &[])), Ok(false));
+        assert_eq!(check(head(HttpVersion::Http11, "/hello", &[("Expect", "100-Continue")])), Ok(true));
+        assert_eq!(check(head(HttpVersion::Http11, "/hello", &[("Expect", "party")])), Err(417));
+        assert_eq!(check(head(HttpVersion::Http10, "/hello", &[("Expect", "party")])), Ok(false));
+        assert_eq!(
+            check(head(HttpVersion::Http11, "/upload/?x=1", &[("Expect", "100-continue")])),
+            Err(415)
+        );
+        assert_eq!(
+            check(head(HttpVersion::Http11, "/upload/a", &[
+                ("Expect", "100-continue"),
+                ("Content-Type", "image/png")
+            ])),
+            Ok(true)
+        );
+        assert_eq!(check(head(HttpVersion::Http11, "/uploads", &[])), Err(415));
+    }
+
+    #[test]
+    fn test_unsafe_preconditions() {
+        fn post(uri: &str, headers: &[(&str, &str)]) -> HttpRequest {
+            HttpRequest {
+                head: HttpRequestHead {
+                    method: "POST".to_string(),
+                    uri: uri.to_string(),
+                    version: HttpVersion::Http11,
+                    headers: headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+                },
+                body: Some(b"x".to_vec())
+            }
+        }
+
+        let calls: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+        let mut min_httpd: MinHttpd = MinHttpd::new();
+        let handler_calls: Arc<AtomicUsize> = calls.clone();
+        min_httpd.route("/doc", Box::new(move |_, _, _, _| {
+            handler_calls.fetch_add(1, SeqCst);
+            Ok(HttpResponse::builder().set_etag(ETag::strong("v2")).build())
+        }));
+        min_httpd.validator("/doc", Box::new(|uri| match uri {
+            "/doc/new" => None,
+            _ => Some(Validators { etag: Some(ETag::strong("v1")), last_modified: None })
+        }));
+
+        let dispatch = |request: HttpRequest| -> u16 {
+            match min_httpd.dispatch(request, "127.0.0.1".to_string(), 0, &mut Exchange::default()) {
+                Dispatch::Response(response) => response.code.as_u16(),
+                _ => unreachable!()
+            }
+        };
+        assert_eq!(dispatch(post("/doc", &[("If-Match", "\"v2\"")])), 412);
+        assert_eq!(calls.load(SeqCst), 0);
+        assert_eq!(dispatch(post("/doc", &[("If-None-Match", "*")])), 412);
+        assert_eq!(calls.load(SeqCst), 0);
+        assert_eq!(dispatch(post("/doc", &[("If-Match", "\"v1\"")])), 200);
+        assert_eq!(calls.load(SeqCst), 1);
+        assert_eq!(dispatch(post("/doc", &[])), 200);
+        assert_eq!(calls.load(SeqCst), 2);
+
+        // create-if-absent succeeds only while the resource does not exist
+        assert_eq!(dispatch(post("/doc/new", &[("If-None-Match", "*")])), 200);
+        assert_eq!(calls.load(SeqCst), 3);
+        assert_eq!(dispatch(post("/doc/new", &[("If-Match", "*")])), 412);
+        assert_eq!(calls.load(SeqCst), 3);
+    }
 
     #[test]
     #[ignore]
@@ -332,9 +871,34 @@ mod test {
             Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Example error")))
         }
 
+        fn example_sse_handler(
+            _uri: HttpUri,
+            _headers: HashMap<String, String>,
+            _params: HashMap<String, String>,
+            sink: EventSink
+        ) -> Result<(), Box<dyn Error>> {
+            for i in 0..5 {
+                sink.send(&SseEvent::new(format!("tick {}", i)).event("tick").id(i.to_string()))?;
+                std::thread::sleep(std::time::Duration::from_secs(1));
+            }
+            Ok(())
+        }
+
         let mut min_httpd = MinHttpd::with_logger(|_, content| { dbg!(content); });
         min_httpd.route_fn("/hello", example_handler);
         min_httpd.route_fn("/error", example_500_handler);
+        min_httpd.route_sse("/events", Box::new(example_sse_handler));
+        min_httpd.route_proxy("/proxy", ReverseProxy::new("127.0.0.1:3081"));
+        min_httpd.route_metrics("/metrics");
+        min_httpd.set_max_body_size(1024 * 1024);
+        min_httpd.upload_check("/hello", Box::new(|head| {
+            match head.header("Content-Length") {
+                Some(length) if length != "0" => Err(HttpError::new(403, "uploads not allowed")),
+                _ => Ok(())
+            }
+        }));
+        min_httpd.virtual_host("*.localhost")
+            .route_static("/hello", "text/plain", "Hello, virtual host!".to_string());
         if let Err(e) = min_httpd.serve(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 3080)) {
             panic!("{}", e);
         }
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use crate::http_commons::{HttpHeaders, HttpResponse, base64_decode};
+use crate::minhttpd::HttpHandler;
+
+/// Name of the header through which authentication guards expose the authenticated principal to
+/// the guarded handler
+pub const AUTH_PRINCIPAL_HEADER: &str = "X-47-Auth-Principal";
+
+/// Decision of a credential checker or token validator
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AuthResult {
+    /// Credentials are valid, carrying the authenticated principal
+    Granted(String),
+    /// Credentials are invalid, client should retry with other credentials (`401`)
+    Unauthorized,
+    /// Credentials are valid but not sufficient for this route (`403`)
+    Forbidden
+}
+
+pub type BasicAuthChecker = Box<dyn Fn(&str, &str) -> AuthResult + Send + Sync + 'static>;
+
+pub type BearerValidator = Box<dyn Fn(&str) -> AuthResult + Send + Sync + 'static>;
+
+/// Compares two byte strings in time depending only on their lengths
+pub fn constant_time_eq(lhs: &[u8], rhs: &[u8]) -> bool {
+    if lhs.len() != rhs.len() {
+        return false;
+    }
+
+    let diff: u8 = lhs.iter().zip(rhs.iter()).fold(0, |diff, (l, r)| diff | (l ^ r));
+    // prevents the compiler from turning the fold into an early-exit comparison
+    std::hint::black_box(diff) == 0
+}
+
+/// A fixed username-password table usable as `BasicAuthChecker`
+#[derive(Clone, Default)]
+pub struct StaticCredentials {
+    users: HashMap<String, String>
+}
+
+impl StaticCredentials {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_user(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.users.insert(username.into(), password.into());
+        self
+    }
+
+    pub fn check(&self, username: &str, password: &str) -> AuthResult {
+        let mut granted: bool = false;
+        for (user, expected) in self.users.iter() {
+            // every entry is compared so that timing does not tell which username exists
+            let user_matches: bool = constant_time_eq(user.as_bytes(), username.as_bytes());
+            let password_matches: bool = constant_time_eq(expected.as_bytes(), password.as_bytes());
+            granted |= user_matches & password_matches;
+        }
+
+        if granted {
+            AuthResult::Granted(username.to_string())
+        } else {
+            AuthResult::Unauthorized
+        }
+    }
+
+    pub fn into_checker(self) -> BasicAuthChecker {
+        Box::new(move |username, password| self.check(username, password))
+    }
+}
+
+/// HTTP Basic authentication (RFC 7617) guard
+///
+/// ```
+/// # use xjbutil::minhttpd::{AUTH_PRINCIPAL_HEADER, BasicAuth, HttpResponse, MinHttpd, StaticCredentials};
+/// let mut min_httpd: MinHttpd = MinHttpd::new();
+/// let credentials: StaticCredentials = StaticCredentials::new().add_user("admin", "p4ssw0rd");
+/// min_httpd.route("/admin", BasicAuth::new("admin area", credentials.into_checker()).guard(
+///     Box::new(|_uri, headers, _params, _body| {
+///         Ok(HttpResponse::builder()
+///             .set_payload(format!("Hello, {}!", headers[AUTH_PRINCIPAL_HEADER]))
+///             .build())
+///     })
+/// ));
+/// ```
+pub struct BasicAuth {
+    realm: String,
+    checker: BasicAuthChecker
+}
+
+impl BasicAuth {
+    pub fn new(realm: impl Into<String>, checker: BasicAuthChecker) -> Self {
+        Self {
+            realm: realm.into(),
+            checker
+        }
+    }
+
+    /// Authenticates the request, producing either the principal or a rejection response
+    pub fn authenticate(&self, headers: &HttpHeaders) -> Result<String, HttpResponse> {
+        let credentials: Option<(String, String)> = authorization(headers, "basic")
+            .and_then(base64_decode)
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .and_then(|decoded| {
+                let (username, password) = decoded.split_once(':')?;
+                Some((username.to_string(), password.to_string()))
+            });
+
+        let result: AuthResult = match credentials {
+            Some((username, password)) => (self.checker)(&username, &password),
+            None => AuthResult::Unauthorized
+        };
+        match result {
+            AuthResult::Granted(principal) => Ok(principal),
+            AuthResult::Unauthorized => Err(rejection(
+                401,
+                format!("Basic realm=\"{}\", charset=\"UTF-8\"", escape_quoted(&self.realm))
+            )),
+            AuthResult::Forbidden => Err(HttpResponse::new(403, vec![], None))
+        }
+    }
+
+    /// Wraps `handler`, so that it only gets called for authenticated requests
+    pub fn guard(self, handler: HttpHandler) -> HttpHandler {
+        Box::new(move |uri, headers, params, body| {
+            match self.authenticate(&headers) {
+                Ok(principal) => handler(uri, with_principal(headers, principal), params, body),
+                Err(response) => Ok(response)
+            }
+        })
+    }
+}
+
+/// Bearer token (RFC 6750) guard
+pub struct BearerAuth {
+    realm: String,
+    validator: BearerValidator
+}
+
+impl BearerAuth {
+    pub fn new(realm: impl Into<String>, validator: BearerValidator) -> Self {
+        Self {
+            realm: realm.into(),
+            validator
+        }
+    }
+
+    /// Authenticates the request, producing either the principal or a rejection response
+    pub fn authenticate(&self, headers: &HttpHeaders) -> Result<String, HttpResponse> {
+        let realm: String = escape_quoted(&self.realm);
+        let token: &str = match authorization(headers, "bearer") {
+            Some(token) if !token.is_empty() => token,
+            _ => return Err(rejection(401, format!("Bearer realm=\"{}\"", realm)))
+        };
+
+        match (self.validator)(token) {
+            AuthResult::Granted(principal) => Ok(principal),
+            AuthResult::Unauthorized => Err(rejection(
+                401,
+                format!("Bearer realm=\"{}\", error=\"invalid_token\"", realm)
+            )),
+            AuthResult::Forbidden => Err(rejection(
+                403,
+                format!("Bearer realm=\"{}\", error=\"insufficient_scope\"", realm)
+            ))
+        }
+    }
+
+    /// Wraps `handler`, so that it only gets called for authenticated requests
+    pub fn guard(self, handler: HttpHandler) -> HttpHandler {
+        Box::new(move |uri, headers, params, body| {
+            match self.authenticate(&headers) {
+                Ok(principal) => handler(uri, with_principal(headers, principal), params, body),
+                Err(response) => Ok(response)
+            }
+        })
+    }
+}
+
+fn authorization<'a>(headers: &'a HttpHeaders, scheme: &str) -> Option<&'a str> {
+    let (request_scheme, credentials) = headers.get("authorization")?.trim().split_once(' ')?;
+    if request_scheme.eq_ignore_ascii_case(scheme) {
+        Some(credentials.trim())
+    } else {
+        None
+    }
+}
+
+fn with_principal(mut headers: HttpHeaders, principal: String) -> HttpHeaders {
+    headers.remove(&AUTH_PRINCIPAL_HEADER.to_lowercase());
+    headers.insert(AUTH_PRINCIPAL_HEADER.to_string(), principal);
+    headers
+}
+
+fn escape_quoted(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn rejection(code: u16, challenge: String) -> HttpResponse {
+    HttpResponse::builder()
+        .set_code(code)
+        .add_header("WWW-Authenticate", challenge)
+        .build()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::http_commons::{HttpHeaders, HttpResponse, header_value};
+    use crate::minhttpd::{
+        AUTH_PRINCIPAL_HEADER,
+        AuthResult,
+        BasicAuth,
+        BearerAuth,
+        HttpHandler,
+        StaticCredentials,
+        constant_time_eq
+    };
+
+    fn echo_principal() -> HttpHandler {
+        Box::new(|_, headers, _, _| {
+            Ok(HttpResponse::builder().set_payload(headers[AUTH_PRINCIPAL_HEADER].clone()).build())
+        })
+    }
+
+    fn call(handler: &HttpHandler, authorization: Option<&str>) -> HttpResponse {
+        let mut headers: HttpHeaders = HashMap::new();
+        if let Some(authorization) = authorization {
+            headers.insert("authorization".to_string(), authorization.to_string());
+        }
+        headers.insert("x-47-auth-principal".to_string(), "spoofed".to_string());
+        handler("/".to_string(), headers, HashMap::new(), None).unwrap()
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+
+    #[test]
+    fn test_basic_auth() {
+        let credentials: StaticCredentials = StaticCredentials::new()
+            .add_user("Aladdin", "open sesame")
+            .add_user("chuigda", "");
+        let handler: HttpHandler =
+            BasicAuth::new("test", credentials.into_checker()).guard(echo_principal());
+
+        let response: HttpResponse = call(&handler, Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="));
+        assert_eq!(response.code, 200);
+        assert_eq!(response.payload.unwrap(), b"Aladdin");
+
+        let response: HttpResponse = call(&handler, Some("basic Y2h1aWdkYTo="));
+        assert_eq!(response.payload.unwrap(), b"chuigda");
+
+        let response: HttpResponse = call(&handler, Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtRQ=="));
+        assert_eq!(response.code, 401);
+        assert_eq!(
+            header_value(&response.headers, "WWW-Authenticate"),
+            Some("Basic realm=\"test\", charset=\"UTF-8\"")
+        );
+        assert_eq!(call(&handler, Some("Basic !!!")).code, 401);
+        assert_eq!(call(&handler, Some("Bearer QWxhZGRpbjpvcGVuIHNlc2FtZQ==")).code, 401);
+        assert_eq!(call(&handler, None).code, 401);
+
+        let handler: HttpHandler = BasicAuth::new("test", Box::new(|_, _| AuthResult::Forbidden))
+            .guard(echo_principal());
+        assert_eq!(call(&handler, Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==")).code, 403);
+    }
+
+    #[test]
+    fn test_bearer_auth() {
+        let handler: HttpHandler = BearerAuth::new("api", Box::new(|token| match token {
+            "AbC.dEf" => AuthResult::Granted("service".to_string()),
+            "readonly" => AuthResult::Forbidden,
+            _ => AuthResult::Unauthorized
+        })).guard(echo_principal());
+
+        let response: HttpResponse = call(&handler, Some("Bearer AbC.dEf"));
+        assert_eq!(response.code, 200);
+        assert_eq!(response.payload.unwrap(), b"service");
+
+        let response: HttpResponse = call(&handler, None);
+        assert_eq!(response.code, 401);
+        assert_eq!(header_value(&response.headers, "WWW-Authenticate"), Some("Bearer realm=\"api\""));
+
+        let response: HttpResponse = call(&handler, Some("Bearer abc.def"));
+        assert_eq!(response.code, 401);
+        assert_eq!(
+            header_value(&response.headers, "WWW-Authenticate"),
+            Some("Bearer realm=\"api\", error=\"invalid_token\"")
+        );
+
+        let response: HttpResponse = call(&handler, Some("Bearer readonly"));
+        assert_eq!(response.code, 403);
+        assert_eq!(
+            header_value(&response.headers, "WWW-Authenticate"),
+            Some("Bearer realm=\"api\", error=\"insufficient_scope\"")
+        );
+    }
+}
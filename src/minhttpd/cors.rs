@@ -0,0 +1,299 @@
+use crate::http_commons::HttpResponse;
+
+/// Cross-origin resource sharing policy of a `MinHttpd`
+///
+/// Origins are matched case-insensitively against patterns. A pattern `*` allows every origin,
+/// and a `*` inside a pattern matches one or more host characters, so `https://*.example.com`
+/// allows `https://api.example.com` but not `https://evil.com/.example.com`.
+///
+/// Credentialed CORS requires explicit origin patterns: combining `*` with
+/// `allow_credentials(true)` would let every site make credentialed reads, and panics.
+///
+/// ```
+/// # use xjbutil::minhttpd::CorsPolicy;
+/// let policy: CorsPolicy = CorsPolicy::new()
+///     .allow_origin("https://*.example.com")
+///     .allow_methods(&["GET", "POST", "PUT"])
+///     .allow_headers(&["Content-Type", "Authorization"])
+///     .allow_credentials(true)
+///     .max_age(600);
+/// assert!(policy.is_origin_allowed("https://app.example.com"));
+/// assert!(!policy.is_origin_allowed("https://example.org"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct CorsPolicy {
+    origins: Vec<String>,
+    methods: Vec<String>,
+    headers: Vec<String>,
+    expose_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<u32>
+}
+
+impl CorsPolicy {
+    /// Creates a policy allowing no origins, `GET` and `POST` methods and no extra headers
+    pub fn new() -> Self {
+        Self {
+            origins: Vec::new(),
+            methods: vec!["GET".to_string(), "POST".to_string()],
+            headers: Vec::new(),
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: None
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `pattern` is `*` and credentials are allowed
+    pub fn allow_origin(mut self, pattern: impl Into<String>) -> Self {
+        let pattern: String = pattern.into().to_ascii_lowercase();
+        assert!(
+            pattern != "*" || !self.credentials,
+            "allowing any origin together with credentials is forbidden"
+        );
+        self.origins.push(pattern);
+        self
+    }
+
+    pub fn allow_methods(mut self, methods: &[&str]) -> Self {
+        self.methods = methods.iter().map(|m| m.to_ascii_uppercase()).collect();
+        self
+    }
+
+    /// Sets request headers allowed in actual requests, `*` allows any header
+    pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = headers.iter().map(|h| h.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Sets response headers exposed to the script of the requesting origin
+    pub fn expose_headers(mut self, headers: &[&str]) -> Self {
+        self.expose_headers = headers.iter().map(|h| h.to_string()).collect();
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `credentials` is true and the policy allows any origin with `*`
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        assert!(
+            !credentials || !self.allows_any_origin(),
+            "allowing credentials together with any origin is forbidden"
+        );
+        self.credentials = credentials;
+        self
+    }
+
+    /// Sets how long, in seconds, preflight results may be cached by the client
+    pub fn max_age(mut self, seconds: u32) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    pub fn is_origin_allowed(&self, origin: &str) -> bool {
+        let origin: String = origin.to_ascii_lowercase();
+        self.origins.iter().any(|pattern| {
+            pattern == "*" || origin_matches(pattern.as_bytes(), origin.as_bytes())
+        })
+    }
+
+    /// Answers a preflight `OPTIONS` request
+    ///
+    /// `request_headers` is the value of the `Access-Control-Request-Headers` header. Disallowed
+    /// preflights are answered with `403` without any CORS headers.
+    pub fn preflight(
+        &self,
+        origin: &str,
+        request_method: &str,
+        request_headers: Option<&str>
+    ) -> HttpResponse {
+        let requested_headers: Vec<&str> = request_headers
+            .map(|headers| headers.split(',').map(str::trim).filter(|h| !h.is_empty()).collect())
+            .unwrap_or_default();
+
+        let method_allowed: bool = self.methods.iter().any(|m| m.eq_ignore_ascii_case(request_method));
+        let any_header: bool = self.headers.iter().any(|h| h == "*");
+        let headers_allowed: bool = any_header || requested_headers.iter().all(|requested| {
+            self.headers.iter().any(|h| h.eq_ignore_ascii_case(requested))
+        });
+        if !self.is_origin_allowed(origin) || !method_allowed || !headers_allowed {
+            return HttpResponse::new(403, vec![], None);
+        }
+
+        let mut response: HttpResponse = HttpResponse::new(204, vec![], None);
+        self.add_origin_headers(origin, &mut response);
+        response.add_header("Access-Control-Allow-Methods", &self.methods.join(", "));
+        if !requested_headers.is_empty() {
+            if any_header {
+                response.add_header("Access-Control-Allow-Headers", &requested_headers.join(", "));
+            } else {
+                response.add_header("Access-Control-Allow-Headers", &self.headers.join(", "));
+            }
+        }
+        if let Some(max_age) = self.max_age {
+            response.add_header("Access-Control-Max-Age", &max_age.to_string());
+        }
+        response.add_header(
+            "Vary",
+            "Origin, Access-Control-Request-Method, Access-Control-Request-Headers"
+        );
+        response
+    }
+
+    /// Adds CORS headers to the response of an actual request from `origin`
+    ///
+    /// Responses from disallowed origins, or responses already carrying
+    /// `Access-Control-Allow-Origin`, are left untouched.
+    pub fn decorate(&self, origin: &str, response: &mut HttpResponse) {
+        if !self.is_origin_allowed(origin) || response.has_header("Access-Control-Allow-Origin") {
+            return;
+        }
+
+        self.add_origin_headers(origin, response);
+        if !self.expose_headers.is_empty() {
+            response.add_header("Access-Control-Expose-Headers", &self.expose_headers.join(", "));
+        }
+        if !self.allows_any_origin() {
+            response.add_header("Vary", "Origin");
+        }
+    }
+
+    fn allows_any_origin(&self) -> bool {
+        self.origins.iter().any(|pattern| pattern == "*")
+    }
+
+    fn add_origin_headers(&self, origin: &str, response: &mut HttpResponse) {
+        // the builder ensures that credentials are never combined with the wildcard origin
+        if self.allows_any_origin() {
+            response.add_header("Access-Control-Allow-Origin", "*");
+        } else {
+            response.add_header("Access-Control-Allow-Origin", origin);
+        }
+        if self.credentials {
+            response.add_header("Access-Control-Allow-Credentials", "true");
+        }
+    }
+}
+
+impl Default for CorsPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_host_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'-' || b == b'.'
+}
+
+fn origin_matches(pattern: &[u8], origin: &[u8]) -> bool {
+    match pattern {
+        [] => origin.is_empty(),
+        [b'*', rest @ ..] => {
+            let host_len: usize = origin.iter().take_while(|b| is_host_char(**b)).count();
+            (1..=host_len).any(|taken| origin_matches(rest, &origin[taken..]))
+        },
+        [p, rest @ ..] => match origin {
+            [o, origin_rest @ ..] if o == p => origin_matches(rest, origin_rest),
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http_commons::{HttpResponse, header_value};
+    use crate::minhttpd::CorsPolicy;
+
+    #[test]
+    fn test_origin_patterns() {
+        let policy: CorsPolicy = CorsPolicy::new()
+            .allow_origin("https://*.example.com")
+            .allow_origin("http://localhost:*")
+            .allow_origin("https://exact.org");
+
+        assert!(policy.is_origin_allowed("https://api.example.com"));
+        assert!(policy.is_origin_allowed("https://a.b.EXAMPLE.com"));
+        assert!(policy.is_origin_allowed("http://localhost:8080"));
+        assert!(policy.is_origin_allowed("https://exact.org"));
+        assert!(!policy.is_origin_allowed("https://example.com"));
+        assert!(!policy.is_origin_allowed("https://evil.com/.example.com"));
+        assert!(!policy.is_origin_allowed("http://localhost:"));
+        assert!(!policy.is_origin_allowed("https://exact.org.evil.com"));
+        assert!(!policy.is_origin_allowed("http://exact.org"));
+
+        assert!(CorsPolicy::new().allow_origin("*").is_origin_allowed("null"));
+        assert!(!CorsPolicy::new().is_origin_allowed("https://exact.org"));
+    }
+
+    #[test]
+    fn test_preflight() {
+        let policy: CorsPolicy = CorsPolicy::new()
+            .allow_origin("https://app.example.com")
+            .allow_methods(&["GET", "PUT"])
+            .allow_headers(&["Content-Type"])
+            .allow_credentials(true)
+            .max_age(600);
+
+        let response: HttpResponse = policy.preflight(
+            "https://app.example.com",
+            "PUT",
+            Some("content-type")
+        );
+        assert_eq!(response.code, 204);
+        assert_eq!(
+            header_value(&response.headers, "Access-Control-Allow-Origin"),
+            Some("https://app.example.com")
+        );
+        assert_eq!(header_value(&response.headers, "Access-Control-Allow-Methods"), Some("GET, PUT"));
+        assert_eq!(header_value(&response.headers, "Access-Control-Allow-Headers"), Some("content-type"));
+        assert_eq!(header_value(&response.headers, "Access-Control-Allow-Credentials"), Some("true"));
+        assert_eq!(header_value(&response.headers, "Access-Control-Max-Age"), Some("600"));
+
+        assert_eq!(policy.preflight("https://evil.com", "PUT", None).code, 403);
+        assert_eq!(policy.preflight("https://app.example.com", "DELETE", None).code, 403);
+        assert_eq!(policy.preflight("https://app.example.com", "GET", Some("X-Secret")).code, 403);
+    }
+
+    #[test]
+    fn test_decorate() {
+        let policy: CorsPolicy = CorsPolicy::new()
+            .allow_origin("*")
+            .expose_headers(&["X-Request-Id"]);
+
+        let mut response: HttpResponse = HttpResponse::builder().build();
+        policy.decorate("https://any.org", &mut response);
+        assert_eq!(header_value(&response.headers, "Access-Control-Allow-Origin"), Some("*"));
+        assert_eq!(header_value(&response.headers, "Access-Control-Expose-Headers"), Some("X-Request-Id"));
+        assert!(!response.has_header("Vary"));
+        assert!(!response.has_header("Access-Control-Allow-Credentials"));
+
+        let policy: CorsPolicy = CorsPolicy::new()
+            .allow_credentials(true)
+            .allow_origin("https://*.any.org");
+        let mut response: HttpResponse = HttpResponse::builder().build();
+        policy.decorate("https://www.any.org", &mut response);
+        assert_eq!(
+            header_value(&response.headers, "Access-Control-Allow-Origin"),
+            Some("https://www.any.org")
+        );
+        assert_eq!(header_value(&response.headers, "Access-Control-Allow-Credentials"), Some("true"));
+        assert_eq!(header_value(&response.headers, "Vary"), Some("Origin"));
+
+        let mut response: HttpResponse = HttpResponse::builder().build();
+        CorsPolicy::new().decorate("https://any.org", &mut response);
+        assert!(response.headers.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_any_origin_with_credentials() {
+        let _ = CorsPolicy::new().allow_origin("*").allow_credentials(true);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_credentials_with_any_origin() {
+        let _ = CorsPolicy::new().allow_credentials(true).allow_origin("*");
+    }
+}
@@ -0,0 +1,310 @@
+//! Extraction of typed values from query parameters, route captures and request bodies
+//!
+//! All extractors fail with `400 Bad Request` errors, which the server turns into responses when
+//! they are propagated out of handlers with `?`.
+//!
+//! ```
+//! # use std::str::FromStr;
+//! # use xjbutil::minhttpd::{HttpResponse, MinHttpd, extract};
+//! enum Order { Asc, Desc }
+//!
+//! impl FromStr for Order {
+//!     type Err = String;
+//!
+//!     fn from_str(s: &str) -> Result<Self, Self::Err> {
+//!         match s {
+//!             "asc" => Ok(Order::Asc),
+//!             "desc" => Ok(Order::Desc),
+//!             _ => Err("expected `asc` or `desc`".to_string())
+//!         }
+//!     }
+//! }
+//!
+//! let mut min_httpd: MinHttpd = MinHttpd::new();
+//! min_httpd.route("/users/{id}/posts", Box::new(|_uri, _headers, params, _body| {
+//!     let user_id: u64 = extract::path(&params, "id")?;
+//!     let page: u32 = extract::query_opt(&params, "page")?.unwrap_or(1);
+//!     let order: Order = extract::query_opt(&params, "order")?.unwrap_or(Order::Desc);
+//!     Ok(HttpResponse::builder().set_payload(format!("user {}, page {}", user_id, page)).build())
+//! }));
+//! ```
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::http_commons::{HttpError, HttpParams, percent_decode};
+
+#[cfg(all(feature = "value", feature = "value-serde"))] use crate::http_commons::HttpBody;
+#[cfg(all(feature = "value", feature = "value-serde"))] use crate::value::{Value, from_value};
+#[cfg(feature = "value-serde")] use serde::de::DeserializeOwned;
+
+/// Prefix of the keys under which route captures are stored in `HttpParams`. Route `/users/{id}`
+/// matching `/users/42` produces parameter `:id` with value `42`.
+pub const CAPTURE_PREFIX: &str = ":";
+
+/// Extracts required query parameter `name`
+pub fn query<T>(params: &HttpParams, name: &str) -> Result<T, HttpError>
+    where T: FromStr,
+          T::Err: Display
+{
+    query_opt(params, name)?
+        .ok_or_else(|| HttpError::bad_request(format!("missing query parameter `{}`", name)))
+}
+
+/// Extracts optional query parameter `name`. A parameter present with an invalid value is still
+/// an error.
+pub fn query_opt<T>(params: &HttpParams, name: &str) -> Result<Option<T>, HttpError>
+    where T: FromStr,
+          T::Err: Display
+{
+    match params.get(name) {
+        Some(raw) => parse_value(raw, true, &format!("query parameter `{}`", name)).map(Some),
+        None => Ok(None)
+    }
+}
+
+/// Extracts route capture `name`
+pub fn path<T>(params: &HttpParams, name: &str) -> Result<T, HttpError>
+    where T: FromStr,
+          T::Err: Display
+{
+    let what: String = format!("path parameter `{}`", name);
+    match params.get(&format!("{}{}", CAPTURE_PREFIX, name)) {
+        Some(raw) => parse_value(raw, false, &what),
+        None => Err(HttpError::bad_request(format!("missing {}", what)))
+    }
+}
+
+fn parse_value<T>(raw: &str, plus_as_space: bool, what: &str) -> Result<T, HttpError>
+    where T: FromStr,
+          T::Err: Display
+{
+    let decoded: String = percent_decode(raw, plus_as_space).ok_or_else(|| {
+        HttpError::bad_request(format!("malformed percent-encoding in {}", what))
+    })?;
+    decoded.parse().map_err(|e: T::Err| {
+        HttpError::bad_request(format!("invalid value {:?} for {}: {}", decoded, what, e))
+    })
+}
+
+/// Deserializes all query parameters into `T`, typically a struct with one field per parameter
+#[cfg(feature = "value-serde")]
+pub fn query_struct<T: DeserializeOwned>(params: &HttpParams) -> Result<T, HttpError> {
+    use serde::de::value::{Error, MapDeserializer};
+
+    let mut entries: Vec<(String, serde_impl::ParamValue)> = Vec::new();
+    for (key, raw) in params.iter().filter(|(key, _)| !key.starts_with(CAPTURE_PREFIX)) {
+        let what: String = format!("query parameter `{}`", key);
+        let value: String = parse_value(raw, true, &what)?;
+        entries.push((key.clone(), serde_impl::ParamValue(value)));
+    }
+
+    T::deserialize(MapDeserializer::<_, Error>::new(entries.into_iter()))
+        .map_err(|e| HttpError::bad_request(format!("invalid query parameters: {}", e)))
+}
+
+/// Deserializes a JSON request body into `T`
+#[cfg(all(feature = "value", feature = "value-serde"))]
+pub fn json<T: DeserializeOwned>(body: &HttpBody) -> Result<T, HttpError> {
+    let body: &str = body.as_deref()
+        .ok_or_else(|| HttpError::bad_request("missing request body"))?;
+    let value: Value = Value::from_json(body)
+        .map_err(|e| HttpError::bad_request(format!("invalid JSON body: {}", e)))?;
+    from_value(value).map_err(|e| HttpError::bad_request(format!("invalid JSON body: {}", e)))
+}
+
+#[cfg(feature = "value-serde")]
+mod serde_impl {
+    use std::str::FromStr;
+
+    use serde::de::{Deserializer, Error as _, IntoDeserializer, Visitor};
+    use serde::de::value::Error;
+    use serde::forward_to_deserialize_any;
+
+    /// A single query parameter value, parsed according to the type requested by the visitor
+    pub struct ParamValue(pub String);
+
+    impl ParamValue {
+        fn parse<T: FromStr>(&self, expected: &str) -> Result<T, Error> {
+            self.0.parse().map_err(|_| {
+                Error::custom(format!("invalid value {:?}, expected {}", self.0, expected))
+            })
+        }
+    }
+
+    impl<'de> IntoDeserializer<'de, Error> for ParamValue {
+        type Deserializer = Self;
+
+        fn into_deserializer(self) -> Self::Deserializer {
+            self
+        }
+    }
+
+    macro_rules! deserialize_parsed {
+        ($($method:ident => $visit:ident : $ty:ty, $expected:literal);* $(;)?) => {
+            $(
+                fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                    visitor.$visit(self.parse::<$ty>($expected)?)
+                }
+            )*
+        }
+    }
+
+    impl<'de> Deserializer<'de> for ParamValue {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_string(self.0)
+        }
+
+        deserialize_parsed! {
+            deserialize_bool => visit_bool: bool, "a boolean";
+            deserialize_i8 => visit_i8: i8, "an integer";
+            deserialize_i16 => visit_i16: i16, "an integer";
+            deserialize_i32 => visit_i32: i32, "an integer";
+            deserialize_i64 => visit_i64: i64, "an integer";
+            deserialize_u8 => visit_u8: u8, "an unsigned integer";
+            deserialize_u16 => visit_u16: u16, "an unsigned integer";
+            deserialize_u32 => visit_u32: u32, "an unsigned integer";
+            deserialize_u64 => visit_u64: u64, "an unsigned integer";
+            deserialize_f32 => visit_f32: f32, "a number";
+            deserialize_f64 => visit_f64: f64, "a number";
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_some(self)
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            _variants: &'static [&'static str],
+            visitor: V
+        ) -> Result<V::Value, Error> {
+            visitor.visit_enum(self.0.into_deserializer())
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: V
+        ) -> Result<V::Value, Error> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        forward_to_deserialize_any! {
+            i128 u128 char str string bytes byte_buf unit unit_struct seq tuple tuple_struct map
+            struct identifier ignored_any
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::http_commons::{HttpError, HttpParams};
+    use crate::minhttpd::extract;
+
+    fn params(entries: &[(&str, &str)]) -> HttpParams {
+        entries.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<HashMap<_, _>>()
+    }
+
+    #[test]
+    fn test_query() {
+        let params: HttpParams = params(&[
+            ("page", "3"),
+            ("name", "a%20b+c"),
+            ("verbose", "true"),
+            ("limit", "ten"),
+            ("bad", "%zz"),
+            ("big", "300"),
+            (":id", "42")
+        ]);
+
+        assert_eq!(extract::query::<u32>(&params, "page"), Ok(3));
+        assert_eq!(extract::query::<String>(&params, "name").unwrap(), "a b c");
+        assert_eq!(extract::query::<bool>(&params, "verbose"), Ok(true));
+        assert_eq!(extract::query_opt::<u32>(&params, "offset"), Ok(None));
+        assert_eq!(
+            extract::query::<u32>(&params, "offset"),
+            Err(HttpError::bad_request("missing query parameter `offset`"))
+        );
+        assert_eq!(
+            extract::query_opt::<u32>(&params, "limit"),
+            Err(HttpError::bad_request(
+                "invalid value \"ten\" for query parameter `limit`: invalid digit found in string"
+            ))
+        );
+        assert_eq!(
+            extract::query::<String>(&params, "bad"),
+            Err(HttpError::bad_request("malformed percent-encoding in query parameter `bad`"))
+        );
+        assert_eq!(extract::query::<u8>(&params, "big").unwrap_err().code, 400);
+
+        assert_eq!(extract::path::<u64>(&params, "id"), Ok(42));
+        assert!(extract::path::<u64>(&params, "page").is_err());
+    }
+
+    #[cfg(feature = "value-serde")]
+    #[test]
+    fn test_query_struct() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum Order { Asc, Desc }
+
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Query {
+            page: u32,
+            order: Order,
+            tag: Option<String>,
+            #[serde(default)]
+            verbose: bool
+        }
+
+        let query: Query = extract::query_struct(&params(&[
+            ("page", "2"),
+            ("order", "asc"),
+            ("tag", "rust+lang"),
+            (":id", "ignored")
+        ])).unwrap();
+        assert_eq!(query, Query { page: 2, order: Order::Asc, tag: Some("rust lang".into()), verbose: false });
+
+        let error: HttpError = extract::query_struct::<Query>(&params(&[("order", "asc")])).unwrap_err();
+        assert_eq!(error.message, "invalid query parameters: missing field `page`");
+        let error: HttpError =
+            extract::query_struct::<Query>(&params(&[("page", "-1"), ("order", "asc")])).unwrap_err();
+        assert_eq!(
+            error.message,
+            "invalid query parameters: invalid value \"-1\", expected an unsigned integer"
+        );
+        let error: HttpError =
+            extract::query_struct::<Query>(&params(&[("page", "1"), ("order", "up")])).unwrap_err();
+        assert_eq!(
+            error.message,
+            "invalid query parameters: unknown variant `up`, expected `asc` or `desc`"
+        );
+    }
+
+    #[cfg(all(feature = "value", feature = "value-serde"))]
+    #[test]
+    fn test_json() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Post {
+            title: String,
+            tags: Vec<String>
+        }
+
+        let post: Post = extract::json(&Some(r#"{"title": "hi", "tags": ["a"]}"#.to_string())).unwrap();
+        assert_eq!(post, Post { title: "hi".into(), tags: vec!["a".into()] });
+
+        assert_eq!(
+            extract::json::<Post>(&None),
+            Err(HttpError::bad_request("missing request body"))
+        );
+        assert_eq!(
+            extract::json::<Post>(&Some(r#"{"title": 1}"#.to_string())).unwrap_err().message,
+            "invalid JSON body: invalid type: integer `1`, expected a string at /title"
+        );
+    }
+}
@@ -0,0 +1,1168 @@
+//! HTTP/2 over cleartext TCP, also known as h2c (RFC 7540)
+//!
+//! Connections start either with the client connection preface right away (prior knowledge), or
+//! as an HTTP/1.1 request with `Upgrade: h2c`, which becomes stream 1. Each stream is dispatched
+//! on its own thread to the same routes as HTTP/1.1 requests, so slow handlers do not block other
+//! streams of the connection. Server push is never used. Server-Sent Events routes reset their
+//! streams with `HTTP_1_1_REQUIRED`, telling clients to retry over HTTP/1.1.
+//!
+//! Request bodies are consumed as soon as they arrive, so receive windows get restored right away
+//! and only `MinHttpd::set_max_body_size` bounds them.
+
+mod frame;
+mod hpack;
+mod huffman;
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::error::Error;
+use std::io;
+use std::io::{BufReader, Cursor, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::sync::atomic::Ordering::SeqCst;
+use std::thread;
+use std::thread::Scope;
+use std::time::{Instant, SystemTime};
+
+use crate::http_commons::{HttpError, HttpResponse, base64url_decode};
+use crate::http_commons::cache::format_http_date;
+use crate::http_commons::parser::{HttpRequest, HttpRequestHead, HttpVersion, is_token};
+use crate::minhttpd::{Dispatch, HttpLogLevel, MinHttpd, SERVER_NAME};
+use crate::minhttpd::metrics::Exchange;
+
+use frame::{
+    CONTINUATION,
+    COMPRESSION_ERROR,
+    DATA,
+    ENHANCE_YOUR_CALM,
+    FLAG_ACK,
+    FLAG_END_HEADERS,
+    FLAG_END_STREAM,
+    FLAG_PRIORITY,
+    FLOW_CONTROL_ERROR,
+    FRAME_HEADER_SIZE,
+    FRAME_SIZE_ERROR,
+    Frame,
+    GOAWAY,
+    HEADERS,
+    HTTP_1_1_REQUIRED,
+    INTERNAL_ERROR,
+    MAX_WINDOW_SIZE,
+    NO_ERROR,
+    PING,
+    PRIORITY,
+    PROTOCOL_ERROR,
+    PUSH_PROMISE,
+    REFUSED_STREAM,
+    RST_STREAM,
+    SETTINGS,
+    Settings,
+    WINDOW_UPDATE
+};
+use hpack::Decoder;
+
+/// Connection preface sent by clients (RFC 7540 section 3.5)
+pub(crate) const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Count of streams a client may have open at once
+const MAX_CONCURRENT_STREAMS: u32 = 128;
+/// Maximum size of a header list, both compressed and decoded
+const MAX_HEADER_LIST_SIZE: u32 = 64 * 1024;
+
+/// Header fields specific to HTTP/1.x connections, not allowed in HTTP/2 (RFC 7540 section 8.1.2.2)
+const CONNECTION_SPECIFIC_FIELDS: [&str; 5] =
+    ["connection", "keep-alive", "proxy-connection", "transfer-encoding", "upgrade"];
+
+/// What the first bytes received on a connection turned out to be
+pub(crate) enum Sniffed {
+    /// The HTTP/2 connection preface, followed by these bytes
+    Http2(Vec<u8>),
+    /// Anything else, including all bytes read so far
+    Http1(Vec<u8>)
+}
+
+/// How an HTTP/2 connection got started
+pub(crate) enum Start {
+    /// The client sent the connection preface, followed by these bytes
+    PriorKnowledge(Vec<u8>),
+    /// The client sent an HTTP/1.1 request with `Upgrade: h2c`, along with its settings
+    Upgrade(HttpRequest, Settings)
+}
+
+/// Reads from `stream` until the received bytes either are the connection preface, or cannot be
+pub(crate) fn sniff_preface(mut stream: &TcpStream) -> io::Result<Sniffed> {
+    let mut received: Vec<u8> = Vec::new();
+    let mut buffer: [u8; 4096] = [0; 4096];
+    loop {
+        let size: usize = stream.read(&mut buffer)?;
+        if size == 0 {
+            return Ok(Sniffed::Http1(received));
+        }
+        received.extend_from_slice(&buffer[..size]);
+
+        let len: usize = received.len().min(PREFACE.len());
+        if received[..len] != PREFACE[..len] {
+            return Ok(Sniffed::Http1(received));
+        }
+        if received.len() >= PREFACE.len() {
+            return Ok(Sniffed::Http2(received.split_off(PREFACE.len())));
+        }
+    }
+}
+
+/// Settings of the client, if `head` is a valid request to upgrade to h2c (RFC 7540 section 3.2)
+pub(crate) fn upgrade_settings(head: &HttpRequestHead) -> Option<Settings> {
+    let has_token = |name: &str, token: &str| -> bool {
+        head.headers.iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .flat_map(|(_, value)| value.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    };
+    if head.version != HttpVersion::Http11
+        || !has_token("Upgrade", "h2c")
+        || !has_token("Connection", "Upgrade")
+        || !has_token("Connection", "HTTP2-Settings") {
+        return None;
+    }
+
+    let mut values = head.headers.iter().filter(|(key, _)| key.eq_ignore_ascii_case("HTTP2-Settings"));
+    let (_, value): &(String, String) = values.next()?;
+    if values.next().is_some() {
+        return None;
+    }
+    let mut settings: Settings = Settings::default();
+    settings.apply(&base64url_decode(value.trim())?).ok()?;
+    Some(settings)
+}
+
+/// Serves an HTTP/2 connection until it gets closed
+pub(crate) fn serve(
+    httpd: &MinHttpd,
+    stream: TcpStream,
+    remote_addr: String,
+    request_id: u64,
+    start: Start
+) -> Result<(), Box<dyn Error>> {
+    stream.set_nodelay(true)?;
+    let (received, upgrade, peer): (Vec<u8>, Option<HttpRequest>, Settings) = match start {
+        Start::PriorKnowledge(rest) => (rest, None, Settings::default()),
+        Start::Upgrade(request, peer) => {
+            (&stream).write_all(
+                b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n"
+            )?;
+            (Vec::new(), Some(request), peer)
+        }
+    };
+    httpd.log(
+        HttpLogLevel::Info,
+        &format!("[MIN-HTTPD/{}] HTTP/2 connection established", request_id)
+    );
+
+    let ours: Settings = Settings {
+        max_concurrent_streams: Some(MAX_CONCURRENT_STREAMS),
+        max_header_list_size: Some(MAX_HEADER_LIST_SIZE),
+        ..Settings::default()
+    };
+    let shared: Shared = Shared {
+        state: Mutex::new(SendState {
+            writer: stream.try_clone()?,
+            closed: false,
+            peer,
+            connection_window: Settings::default().initial_window_size as i64,
+            stream_windows: HashMap::new()
+        }),
+        window_updated: Condvar::new()
+    };
+    shared.send(&Frame::new(SETTINGS, 0, 0, ours.encode()))?;
+
+    let mut reader: BufReader<_> = BufReader::new(Cursor::new(received).chain(&stream));
+    if upgrade.is_some() {
+        let mut preface: [u8; 24] = [0; 24];
+        reader.read_exact(&mut preface)?;
+        if preface != PREFACE {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid HTTP/2 connection preface"
+            )));
+        }
+    }
+
+    thread::scope(|scope| {
+        let mut connection: Connection = Connection {
+            httpd,
+            scope,
+            shared: &shared,
+            remote_addr,
+            request_id,
+            decoder: Decoder::new(
+                ours.header_table_size as usize,
+                MAX_HEADER_LIST_SIZE as usize
+            ),
+            receiving: HashMap::new(),
+            last_stream_id: 0,
+            goaway_received: false
+        };
+        if let Some(request) = upgrade {
+            connection.open_upgraded(request);
+        }
+
+        let error: ConnectionError = match connection.run(&mut reader) {
+            Ok(infallible) => match infallible {},
+            Err(error) => error
+        };
+        match error {
+            ConnectionError::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => httpd.log(
+                HttpLogLevel::Info,
+                &format!("[MIN-HTTPD/{}] HTTP/2 connection closed", request_id)
+            ),
+            ConnectionError::Io(e) => httpd.log(
+                HttpLogLevel::Error,
+                &format!("[MIN-HTTPD/{}] HTTP/2 connection broken: {}", request_id, e)
+            ),
+            ConnectionError::Protocol(error_code, reason) => {
+                httpd.log(
+                    HttpLogLevel::Error,
+                    &format!("[MIN-HTTPD/{}] HTTP/2 protocol error: {}", request_id, reason)
+                );
+                let _ = shared.send(&Frame::goaway(connection.last_stream_id, error_code));
+            }
+        }
+        shared.close();
+    });
+    Ok(())
+}
+
+enum ConnectionError {
+    /// The connection got closed or broken
+    Io(io::Error),
+    /// The client violated the protocol, reported to it through `GOAWAY`
+    Protocol(u32, &'static str)
+}
+
+impl From<io::Error> for ConnectionError {
+    fn from(e: io::Error) -> Self {
+        ConnectionError::Io(e)
+    }
+}
+
+fn protocol_error(reason: &'static str) -> ConnectionError {
+    ConnectionError::Protocol(PROTOCOL_ERROR, reason)
+}
+
+/// Sending side of a connection, shared by the connection thread and stream threads
+struct SendState {
+    writer: TcpStream,
+    closed: bool,
+    peer: Settings,
+    connection_window: i64,
+    /// Send windows of streams which have not finished sending their responses yet
+    stream_windows: HashMap<u32, i64>
+}
+
+impl SendState {
+    fn write_frame(&mut self, frame: &Frame) -> io::Result<usize> {
+        if self.closed {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "HTTP/2 connection closed"));
+        }
+
+        let encoded: Vec<u8> = frame.encode();
+        let result: io::Result<()> = self.writer.write_all(&encoded);
+        if result.is_err() {
+            self.closed = true;
+        }
+        result.map(|_| encoded.len())
+    }
+
+    /// Writes a header block, split into `HEADERS` and `CONTINUATION` frames as needed
+    fn write_header_block(
+        &mut self,
+        stream_id: u32,
+        fields: &[(String, String)],
+        end_stream: bool
+    ) -> io::Result<usize> {
+        let block: Vec<u8> = hpack::encode(fields);
+        let max_frame_size: usize = self.peer.max_frame_size as usize;
+        let mut written: usize = 0;
+        let mut offset: usize = 0;
+        loop {
+            let end: usize = (offset + max_frame_size).min(block.len());
+            let (kind, mut flags): (u8, u8) = match (offset, end_stream) {
+                (0, true) => (HEADERS, FLAG_END_STREAM),
+                (0, false) => (HEADERS, 0),
+                _ => (CONTINUATION, 0)
+            };
+            if end == block.len() {
+                flags |= FLAG_END_HEADERS;
+            }
+            let frame: Frame = Frame::new(kind, flags, stream_id, block[offset..end].to_vec());
+            written += self.write_frame(&frame)?;
+            if end == block.len() {
+                return Ok(written);
+            }
+            offset = end;
+        }
+    }
+}
+
+struct Shared {
+    state: Mutex<SendState>,
+    window_updated: Condvar
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, SendState> {
+        self.state.lock().unwrap()
+    }
+
+    fn send(&self, frame: &Frame) -> io::Result<()> {
+        self.lock().write_frame(frame).map(|_| ())
+    }
+
+    fn reset(&self, stream_id: u32, error_code: u32) -> io::Result<()> {
+        let mut state: MutexGuard<SendState> = self.lock();
+        state.stream_windows.remove(&stream_id);
+        state.write_frame(&Frame::rst_stream(stream_id, error_code))?;
+        drop(state);
+        self.window_updated.notify_all();
+        Ok(())
+    }
+
+    /// Marks the connection closed, waking up stream threads waiting for window updates
+    fn close(&self) {
+        self.lock().closed = true;
+        self.window_updated.notify_all();
+    }
+
+    /// Sends `response` on `stream_id`, respecting flow control. Responses of streams reset by
+    /// the client are dropped silently.
+    fn send_response(
+        &self,
+        stream_id: u32,
+        response: HttpResponse,
+        exchange: &mut Exchange
+    ) -> io::Result<()> {
+        exchange.code = Some(response.code.as_u16());
+        let fields: Vec<(String, String)> = response_fields(&response);
+        let payload: Vec<u8> = response.payload.unwrap_or_default();
+
+        let mut state: MutexGuard<SendState> = self.lock();
+        if !state.stream_windows.contains_key(&stream_id) {
+            return Ok(());
+        }
+        exchange.bytes_out += state.write_header_block(stream_id, &fields, payload.is_empty())? as u64;
+        if payload.is_empty() {
+            state.stream_windows.remove(&stream_id);
+            return Ok(());
+        }
+        drop(state);
+
+        let mut sent: usize = 0;
+        while sent < payload.len() {
+            let mut state: MutexGuard<SendState> = self.lock();
+            if state.closed {
+                return Err(io::Error::new(io::ErrorKind::NotConnected, "HTTP/2 connection closed"));
+            }
+            let stream_window: i64 = match state.stream_windows.get(&stream_id) {
+                Some(window) => *window,
+                None => return Ok(())
+            };
+            let window: i64 = stream_window.min(state.connection_window);
+            if window <= 0 {
+                drop(self.window_updated.wait(state).unwrap());
+                continue;
+            }
+
+            let size: usize = (window as usize)
+                .min(state.peer.max_frame_size as usize)
+                .min(payload.len() - sent);
+            let end_stream: bool = sent + size == payload.len();
+            let frame: Frame = Frame::new(
+                DATA,
+                if end_stream { FLAG_END_STREAM } else { 0 },
+                stream_id,
+                payload[sent..sent + size].to_vec()
+            );
+            exchange.bytes_out += state.write_frame(&frame)? as u64;
+            state.connection_window -= size as i64;
+            if end_stream {
+                state.stream_windows.remove(&stream_id);
+            } else {
+                state.stream_windows.insert(stream_id, stream_window - size as i64);
+            }
+            sent += size;
+        }
+        Ok(())
+    }
+}
+
+/// A stream whose request is still being received
+struct Receiving {
+    request_id: u64,
+    head: HttpRequestHead,
+    body: Option<Vec<u8>>,
+    bytes_in: u64
+}
+
+/// Work done on a stream thread
+enum Job {
+    Request(HttpRequest),
+    /// Sends a response rejecting the request before its body was complete, then asks the client
+    /// to stop sending the body
+    Reject(HttpResponse)
+}
+
+/// Receiving side of a connection, run on the connection thread
+struct Connection<'scope, 'env> {
+    httpd: &'env MinHttpd,
+    scope: &'scope Scope<'scope, 'env>,
+    shared: &'env Shared,
+    remote_addr: String,
+    request_id: u64,
+    decoder: Decoder,
+    receiving: HashMap<u32, Receiving>,
+    last_stream_id: u32,
+    goaway_received: bool
+}
+
+impl<'scope, 'env> Connection<'scope, 'env> {
+    fn run(&mut self, reader: &mut impl Read) -> Result<Infallible, ConnectionError> {
+        let first: Frame = self.read_frame(reader)?;
+        if first.kind != SETTINGS || first.has_flag(FLAG_ACK) {
+            return Err(protocol_error("connection preface not followed by SETTINGS"));
+        }
+        self.on_settings(first)?;
+
+        loop {
+            let frame: Frame = self.read_frame(reader)?;
+            match frame.kind {
+                DATA => self.on_data(frame)?,
+                HEADERS => self.on_headers(frame, reader)?,
+                PRIORITY => self.on_priority(frame)?,
+                RST_STREAM => self.on_rst_stream(frame)?,
+                SETTINGS => self.on_settings(frame)?,
+                PUSH_PROMISE => return Err(protocol_error("PUSH_PROMISE sent by client")),
+                PING => self.on_ping(frame)?,
+                GOAWAY => self.on_goaway(frame)?,
+                WINDOW_UPDATE => self.on_window_update(frame)?,
+                CONTINUATION => return Err(protocol_error("unexpected CONTINUATION")),
+                // frames of unknown types must be ignored
+                _ => {}
+            }
+        }
+    }
+
+    fn read_frame(&mut self, reader: &mut impl Read) -> Result<Frame, ConnectionError> {
+        let max_frame_size: usize = Settings::default().max_frame_size as usize;
+        frame::read_frame(reader, max_frame_size)?
+            .map_err(|_| ConnectionError::Protocol(FRAME_SIZE_ERROR, "frame too large"))
+    }
+
+    fn reset(&mut self, stream_id: u32, error_code: u32, reason: &str) -> Result<(), ConnectionError> {
+        self.httpd.log(
+            HttpLogLevel::Warn,
+            &format!("[MIN-HTTPD/{}] Resetting HTTP/2 stream {}: {}", self.request_id, stream_id, reason)
+        );
+        self.receiving.remove(&stream_id);
+        Ok(self.shared.reset(stream_id, error_code)?)
+    }
+
+    fn on_headers(&mut self, frame: Frame, reader: &mut impl Read) -> Result<(), ConnectionError> {
+        let stream_id: u32 = frame.stream_id;
+        if stream_id == 0 {
+            return Err(protocol_error("HEADERS on stream 0"));
+        }
+        let end_stream: bool = frame.has_flag(FLAG_END_STREAM);
+        let mut block: Vec<u8> = frame.unpadded_payload()
+            .ok_or_else(|| protocol_error("invalid padding"))?
+            .to_vec();
+        if frame.has_flag(FLAG_PRIORITY) {
+            if block.len() < 5 {
+                return Err(ConnectionError::Protocol(FRAME_SIZE_ERROR, "truncated priority"));
+            }
+            block.drain(..5);
+        }
+
+        let mut end_headers: bool = frame.has_flag(FLAG_END_HEADERS);
+        while !end_headers {
+            let continuation: Frame = self.read_frame(reader)?;
+            if continuation.kind != CONTINUATION || continuation.stream_id != stream_id {
+                return Err(protocol_error("header block interrupted"));
+            }
+            block.extend_from_slice(&continuation.payload);
+            if block.len() > MAX_HEADER_LIST_SIZE as usize {
+                return Err(ConnectionError::Protocol(ENHANCE_YOUR_CALM, "header block too large"));
+            }
+            end_headers = continuation.has_flag(FLAG_END_HEADERS);
+        }
+        let fields: Vec<(String, String)> = self.decoder.decode(&block)
+            .map_err(|e| ConnectionError::Protocol(COMPRESSION_ERROR, e.0))?;
+
+        if let Some(mut receiving) = self.receiving.remove(&stream_id) {
+            // trailers, which are not passed to handlers
+            if !end_stream {
+                return self.reset(stream_id, PROTOCOL_ERROR, "trailers without END_STREAM");
+            }
+            receiving.bytes_in += block.len() as u64;
+            return self.complete(stream_id, receiving);
+        }
+
+        // clients open streams with odd and increasing identifiers
+        if stream_id & 1 == 0 || stream_id <= self.last_stream_id {
+            return Err(protocol_error("invalid stream identifier"));
+        }
+        self.last_stream_id = stream_id;
+        if self.goaway_received {
+            return Ok(());
+        }
+        if self.shared.lock().stream_windows.len() >= MAX_CONCURRENT_STREAMS as usize {
+            return self.reset(stream_id, REFUSED_STREAM, "too many concurrent streams");
+        }
+        let head: HttpRequestHead = match request_head(fields) {
+            Ok(head) => head,
+            Err(reason) => return self.reset(stream_id, PROTOCOL_ERROR, reason)
+        };
+
+        let request_id: u64 = self.httpd.request_counter.fetch_add(1, SeqCst);
+        self.httpd.log(
+            HttpLogLevel::Info,
+            &format!(
+                "[MIN-HTTPD/{}] HTTP/2 stream {} of connection {}",
+                request_id,
+                stream_id,
+                self.request_id
+            )
+        );
+        self.open(stream_id);
+        match self.httpd.check_head(&head, request_id) {
+            Err(response) => {
+                self.spawn(stream_id, request_id, block.len() as u64, Job::Reject(response));
+                return Ok(());
+            },
+            Ok(true) if !end_stream => {
+                let interim: [(String, String); 1] = [(":status".to_string(), "100".to_string())];
+                self.shared.lock().write_header_block(stream_id, &interim, false)?;
+            },
+            Ok(_) => {}
+        }
+
+        let receiving: Receiving = Receiving {
+            request_id,
+            head,
+            body: None,
+            bytes_in: block.len() as u64
+        };
+        if end_stream {
+            self.complete(stream_id, receiving)
+        } else {
+            self.receiving.insert(stream_id, receiving);
+            Ok(())
+        }
+    }
+
+    fn on_data(&mut self, frame: Frame) -> Result<(), ConnectionError> {
+        let stream_id: u32 = frame.stream_id;
+        if stream_id == 0 {
+            return Err(protocol_error("DATA on stream 0"));
+        }
+        if stream_id > self.last_stream_id {
+            return Err(protocol_error("DATA on idle stream"));
+        }
+        let data: &[u8] = frame.unpadded_payload().ok_or_else(|| protocol_error("invalid padding"))?;
+
+        // padding counts against flow control as well
+        let consumed: u32 = frame.payload.len() as u32;
+        let end_stream: bool = frame.has_flag(FLAG_END_STREAM);
+        if consumed > 0 {
+            self.shared.send(&Frame::window_update(0, consumed))?;
+        }
+
+        // data on streams which are closed, or have been reset by us, is ignored
+        let receiving: &mut Receiving = match self.receiving.get_mut(&stream_id) {
+            Some(receiving) => receiving,
+            None => return Ok(())
+        };
+        let body: &mut Vec<u8> = receiving.body.get_or_insert_with(Vec::new);
+        if self.httpd.max_body_size - body.len() < data.len() {
+            let receiving: Receiving = self.receiving.remove(&stream_id).unwrap();
+            let message: String = format!("request body exceeds {} bytes", self.httpd.max_body_size);
+            let response: HttpResponse = HttpError::new(413, message).into_response();
+            self.spawn(stream_id, receiving.request_id, receiving.bytes_in, Job::Reject(response));
+            return Ok(());
+        }
+        body.extend_from_slice(data);
+        receiving.bytes_in += (FRAME_HEADER_SIZE + frame.payload.len()) as u64;
+
+        if end_stream {
+            let receiving: Receiving = self.receiving.remove(&stream_id).unwrap();
+            self.complete(stream_id, receiving)
+        } else {
+            if consumed > 0 {
+                self.shared.send(&Frame::window_update(stream_id, consumed))?;
+            }
+            Ok(())
+        }
+    }
+
+    fn on_priority(&mut self, frame: Frame) -> Result<(), ConnectionError> {
+        if frame.stream_id == 0 {
+            return Err(protocol_error("PRIORITY on stream 0"));
+        }
+        if frame.payload.len() != 5 {
+            return self.reset(frame.stream_id, FRAME_SIZE_ERROR, "invalid PRIORITY");
+        }
+        Ok(())
+    }
+
+    fn on_rst_stream(&mut self, frame: Frame) -> Result<(), ConnectionError> {
+        if frame.stream_id == 0 || frame.stream_id > self.last_stream_id {
+            return Err(protocol_error("RST_STREAM on idle stream"));
+        }
+        if frame.payload.len() != 4 {
+            return Err(ConnectionError::Protocol(FRAME_SIZE_ERROR, "invalid RST_STREAM"));
+        }
+        self.receiving.remove(&frame.stream_id);
+        self.shared.lock().stream_windows.remove(&frame.stream_id);
+        self.shared.window_updated.notify_all();
+        Ok(())
+    }
+
+    fn on_settings(&mut self, frame: Frame) -> Result<(), ConnectionError> {
+        if frame.stream_id != 0 {
+            return Err(protocol_error("SETTINGS on a stream"));
+        }
+        if frame.has_flag(FLAG_ACK) {
+            return if frame.payload.is_empty() {
+                Ok(())
+            } else {
+                Err(ConnectionError::Protocol(FRAME_SIZE_ERROR, "SETTINGS acknowledgement with payload"))
+            };
+        }
+
+        let mut state: MutexGuard<SendState> = self.shared.lock();
+        let old_window_size: i64 = state.peer.initial_window_size as i64;
+        state.peer.apply(&frame.payload)
+            .map_err(|error_code| ConnectionError::Protocol(error_code, "invalid SETTINGS"))?;
+        let delta: i64 = state.peer.initial_window_size as i64 - old_window_size;
+        for window in state.stream_windows.values_mut() {
+            *window += delta;
+            if *window > MAX_WINDOW_SIZE {
+                return Err(ConnectionError::Protocol(FLOW_CONTROL_ERROR, "window overflow"));
+            }
+        }
+        state.write_frame(&Frame::new(SETTINGS, FLAG_ACK, 0, Vec::new()))?;
+        drop(state);
+        self.shared.window_updated.notify_all();
+        Ok(())
+    }
+
+    fn on_ping(&mut self, frame: Frame) -> Result<(), ConnectionError> {
+        if frame.stream_id != 0 {
+            return Err(protocol_error("PING on a stream"));
+        }
+        if frame.payload.len() != 8 {
+            return Err(ConnectionError::Protocol(FRAME_SIZE_ERROR, "invalid PING"));
+        }
+        if !frame.has_flag(FLAG_ACK) {
+            self.shared.send(&Frame::new(PING, FLAG_ACK, 0, frame.payload))?;
+        }
+        Ok(())
+    }
+
+    fn on_goaway(&mut self, frame: Frame) -> Result<(), ConnectionError> {
+        if frame.stream_id != 0 {
+            return Err(protocol_error("GOAWAY on a stream"));
+        }
+        // streams already open still get their responses, new ones are ignored
+        self.goaway_received = true;
+        Ok(())
+    }
+
+    fn on_window_update(&mut self, frame: Frame) -> Result<(), ConnectionError> {
+        if frame.payload.len() != 4 {
+            return Err(ConnectionError::Protocol(FRAME_SIZE_ERROR, "invalid WINDOW_UPDATE"));
+        }
+        let increment: i64 = (u32::from_be_bytes([
+            frame.payload[0],
+            frame.payload[1],
+            frame.payload[2],
+            frame.payload[3]
+        ]) & 0x7fff_ffff) as i64;
+
+        let mut state: MutexGuard<SendState> = self.shared.lock();
+        if frame.stream_id == 0 {
+            if increment == 0 {
+                return Err(protocol_error("zero window increment"));
+            }
+            state.connection_window += increment;
+            if state.connection_window > MAX_WINDOW_SIZE {
+                return Err(ConnectionError::Protocol(FLOW_CONTROL_ERROR, "window overflow"));
+            }
+        } else if let Some(window) = state.stream_windows.get_mut(&frame.stream_id) {
+            *window += increment;
+            let overflow: bool = *window > MAX_WINDOW_SIZE;
+            drop(state);
+            if increment == 0 {
+                return self.reset(frame.stream_id, PROTOCOL_ERROR, "zero window increment");
+            }
+            if overflow {
+                return self.reset(frame.stream_id, FLOW_CONTROL_ERROR, "window overflow");
+            }
+        } else {
+            drop(state);
+        }
+        self.shared.window_updated.notify_all();
+        Ok(())
+    }
+
+    fn open(&mut self, stream_id: u32) {
+        let mut state: MutexGuard<SendState> = self.shared.lock();
+        let window: i64 = state.peer.initial_window_size as i64;
+        state.stream_windows.insert(stream_id, window);
+    }
+
+    /// Opens stream 1 with the request which upgraded the connection
+    fn open_upgraded(&mut self, request: HttpRequest) {
+        let request_id: u64 = self.httpd.request_counter.fetch_add(1, SeqCst);
+        self.last_stream_id = 1;
+        self.open(1);
+        self.spawn(1, request_id, 0, Job::Request(request));
+    }
+
+    fn complete(&mut self, stream_id: u32, receiving: Receiving) -> Result<(), ConnectionError> {
+        let body_size: usize = receiving.body.as_ref().map_or(0, Vec::len);
+        if let Some(length) = receiving.head.header("content-length") {
+            if length.parse::<usize>() != Ok(body_size) {
+                return self.reset(stream_id, PROTOCOL_ERROR, "content-length mismatch");
+            }
+        }
+
+        let request: HttpRequest = HttpRequest {
+            head: receiving.head,
+            body: receiving.body
+        };
+        self.spawn(stream_id, receiving.request_id, receiving.bytes_in, Job::Request(request));
+        Ok(())
+    }
+
+    fn spawn(&mut self, stream_id: u32, request_id: u64, bytes_in: u64, job: Job) {
+        let httpd: &'env MinHttpd = self.httpd;
+        let shared: &'env Shared = self.shared;
+        let remote_addr: String = self.remote_addr.clone();
+        self.scope.spawn(move || {
+            let start: Instant = Instant::now();
+            let mut exchange: Exchange = Exchange { bytes_in, ..Exchange::default() };
+            let result: io::Result<()> = match job {
+                Job::Request(request) => {
+                    match httpd.dispatch(request, remote_addr, request_id, &mut exchange) {
+                        Dispatch::Response(response) => {
+                            shared.send_response(stream_id, response, &mut exchange)
+                        },
+                        Dispatch::EventStream { .. } => {
+                            httpd.log(
+                                HttpLogLevel::Info,
+                                &format!("[MIN-HTTPD/{}] Event streams require HTTP/1.1", request_id)
+                            );
+                            shared.reset(stream_id, HTTP_1_1_REQUIRED)
+                        },
+                        Dispatch::Abort => shared.reset(stream_id, INTERNAL_ERROR)
+                    }
+                },
+                Job::Reject(response) => shared.send_response(stream_id, response, &mut exchange)
+                    .and_then(|_| shared.reset(stream_id, NO_ERROR))
+            };
+            if let Err(e) = result {
+                httpd.log(
+                    HttpLogLevel::Error,
+                    &format!("[MIN-HTTPD/{}] Error sending response: {}", request_id, e)
+                );
+            }
+            httpd.metrics.record(&exchange, start.elapsed());
+        });
+    }
+}
+
+/// Builds the head of a request from its decoded header fields (RFC 7540 section 8.1.2)
+fn request_head(fields: Vec<(String, String)>) -> Result<HttpRequestHead, &'static str> {
+    let mut method: Option<String> = None;
+    let mut path: Option<String> = None;
+    let mut scheme: Option<String> = None;
+    let mut authority: Option<String> = None;
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut cookies: Vec<String> = Vec::new();
+
+    for (name, value) in fields {
+        // these would end the line of the field when forwarded over HTTP/1.1 (RFC 9113 8.2.1)
+        if name.bytes().chain(value.bytes()).any(|b| matches!(b, b'\r' | b'\n' | b'\0')) {
+            return Err("CR, LF or NUL in header field");
+        }
+        if let Some(pseudo) = name.strip_prefix(':') {
+            if !headers.is_empty() || !cookies.is_empty() {
+                return Err("pseudo-header field after regular fields");
+            }
+            let slot: &mut Option<String> = match pseudo {
+                "method" => &mut method,
+                "path" => &mut path,
+                "scheme" => &mut scheme,
+                "authority" => &mut authority,
+                _ => return Err("unknown pseudo-header field")
+            };
+            if slot.replace(value).is_some() {
+                return Err("duplicate pseudo-header field");
+            }
+        } else if name.bytes().any(|b| b.is_ascii_uppercase()) {
+            return Err("uppercase header field name");
+        } else if CONNECTION_SPECIFIC_FIELDS.contains(&name.as_str())
+            || (name == "te" && value != "trailers") {
+            return Err("connection-specific header field");
+        } else if name == "cookie" {
+            // split cookies get concatenated back for HTTP/1.1 semantics (RFC 7540 section 8.1.2.5)
+            cookies.push(value);
+        } else {
+            headers.push((name, value));
+        }
+    }
+
+    let (method, path): (String, String) = match (method, path, scheme) {
+        (Some(method), Some(path), Some(_)) if !path.is_empty() => (method, path),
+        _ => return Err("missing pseudo-header fields")
+    };
+    // the same as the HTTP/1.1 parser accepts in request lines
+    if !is_token(method.as_bytes()) {
+        return Err("invalid :method");
+    }
+    if !path.bytes().all(|b| (0x21..=0x7e).contains(&b)) {
+        return Err("invalid :path");
+    }
+    if !cookies.is_empty() {
+        headers.push(("cookie".to_string(), cookies.join("; ")));
+    }
+    if let Some(authority) = authority {
+        if !headers.iter().any(|(name, _)| name == "host") {
+            headers.insert(0, ("host".to_string(), authority));
+        }
+    }
+    Ok(HttpRequestHead { method, uri: path, version: HttpVersion::Http2, headers })
+}
+
+/// Header fields of a response, including `:status` and the fields added by the server
+fn response_fields(response: &HttpResponse) -> Vec<(String, String)> {
+    let mut ret: Vec<(String, String)> = vec![
+        (":status".to_string(), response.code.as_u16().to_string())
+    ];
+    for (name, value) in response.headers.iter() {
+        let name: String = name.to_ascii_lowercase();
+        if !CONNECTION_SPECIFIC_FIELDS.contains(&name.as_str()) {
+            ret.push((name, value.clone()));
+        }
+    }
+    if !response.has_header("Server") {
+        ret.push(("server".to_string(), SERVER_NAME.to_string()));
+    }
+    if !response.has_header("Date") {
+        ret.push(("date".to_string(), format_http_date(SystemTime::now())));
+    }
+    if let Some(payload) = &response.payload {
+        ret.push(("content-length".to_string(), payload.len().to_string()));
+    }
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::thread;
+    use std::thread::JoinHandle;
+
+    use crate::http_commons::{HttpResponse, base64url_decode};
+    use crate::http_commons::parser::{HttpRequestHead, HttpVersion};
+    use crate::minhttpd::MinHttpd;
+    use crate::minhttpd::h2::{PREFACE, request_head, upgrade_settings};
+    use crate::minhttpd::h2::frame::{
+        DATA,
+        FLAG_ACK,
+        FLAG_END_HEADERS,
+        FLAG_END_STREAM,
+        Frame,
+        GOAWAY,
+        HEADERS,
+        PING,
+        RST_STREAM,
+        SETTINGS,
+        Settings,
+        read_frame
+    };
+    use crate::minhttpd::h2::hpack::{Decoder, encode};
+    use crate::minhttpd::metrics::Exchange;
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    struct TestClient {
+        stream: TcpStream,
+        decoder: Decoder
+    }
+
+    impl TestClient {
+        fn connect(addr: SocketAddr) -> Self {
+            let stream: TcpStream = TcpStream::connect(addr).unwrap();
+            stream.set_nodelay(true).unwrap();
+            Self {
+                stream,
+                decoder: Decoder::new(4096, usize::MAX)
+            }
+        }
+
+        fn send(&mut self, frame: Frame) {
+            self.stream.write_all(&frame.encode()).unwrap();
+        }
+
+        fn request(&mut self, stream_id: u32, method: &str, path: &str, body: Option<&[u8]>) {
+            let block: Vec<u8> = encode(&fields(&[
+                (":method", method),
+                (":scheme", "http"),
+                (":path", path),
+                (":authority", "localhost")
+            ]));
+            let flags: u8 = FLAG_END_HEADERS | if body.is_none() { FLAG_END_STREAM } else { 0 };
+            self.send(Frame::new(HEADERS, flags, stream_id, block));
+            if let Some(body) = body {
+                // the body is split in two frames, to check it gets reassembled
+                let (first, second): (&[u8], &[u8]) = body.split_at(body.len() / 2);
+                self.send(Frame::new(DATA, 0, stream_id, first.to_vec()));
+                self.send(Frame::new(DATA, FLAG_END_STREAM, stream_id, second.to_vec()));
+            }
+        }
+
+        /// Reads frames until `count` streams have finished, returning their statuses (or reset
+        /// error codes) and bodies. Received data is acknowledged with window updates.
+        fn responses(&mut self, count: usize) -> HashMap<u32, (String, Vec<u8>)> {
+            let mut ret: HashMap<u32, (String, Vec<u8>)> = HashMap::new();
+            let mut finished: usize = 0;
+            while finished < count {
+                let frame: Frame = read_frame(&mut self.stream, 1 << 24).unwrap().unwrap();
+                let response = ret.entry(frame.stream_id).or_default();
+                match frame.kind {
+                    HEADERS => {
+                        let fields: Vec<(String, String)> = self.decoder.decode(&frame.payload).unwrap();
+                        let status: &str = &fields.iter().find(|(k, _)| k == ":status").unwrap().1;
+                        if !status.starts_with('1') {
+                            response.0 = status.to_string();
+                        }
+                    },
+                    DATA => {
+                        response.1.extend_from_slice(&frame.payload);
+                        let size: u32 = frame.payload.len() as u32;
+                        if size > 0 {
+                            self.send(Frame::window_update(0, size));
+                            self.send(Frame::window_update(frame.stream_id, size));
+                        }
+                    },
+                    RST_STREAM => {
+                        response.0 = format!("RST {}", frame.payload[3]);
+                        finished += 1;
+                        continue;
+                    },
+                    _ => continue
+                }
+                if frame.has_flag(FLAG_END_STREAM) {
+                    finished += 1;
+                }
+            }
+            ret
+        }
+    }
+
+    fn test_server(client: impl FnOnce(SocketAddr) + Send + 'static) {
+        let mut min_httpd: MinHttpd = MinHttpd::new();
+        min_httpd.route("/hello", Box::new(|_, headers, params, _| {
+            Ok(HttpResponse::builder()
+                .add_header("Content-Type", "text/plain")
+                .set_payload(format!(
+                    "Hello, {} from {}!",
+                    params.get("name").map(String::as_str).unwrap_or("world"),
+                    headers.get("host").map(String::as_str).unwrap_or("nowhere")
+                ))
+                .build())
+        }));
+        min_httpd.route("/echo", Box::new(|_, _, _, body| {
+            Ok(HttpResponse::builder().set_payload(body.unwrap_or_default()).build())
+        }));
+        min_httpd.route("/big", Box::new(|_, _, _, _| {
+            Ok(HttpResponse::builder().set_payload_raw(vec![b'x'; 100_000]).build())
+        }));
+        min_httpd.route_sse("/events", Box::new(|_, _, _, _| Ok(())));
+
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let client: JoinHandle<()> = thread::spawn(move || client(addr));
+        let (stream, _): (TcpStream, SocketAddr) = listener.accept().unwrap();
+        let mut exchange: Exchange = Exchange::default();
+        min_httpd.handle_connection_impl(stream, "127.0.0.1".to_string(), 0, &mut exchange).unwrap();
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn test_prior_knowledge() {
+        test_server(|addr| {
+            let mut client: TestClient = TestClient::connect(addr);
+            client.stream.write_all(PREFACE).unwrap();
+            // a small window forces the large response to wait for window updates
+            let settings: Settings = Settings { initial_window_size: 1000, ..Settings::default() };
+            client.send(Frame::new(SETTINGS, 0, 0, settings.encode()));
+
+            client.request(1, "GET", "/hello?name=h2", None);
+            client.request(3, "POST", "/echo", Some(b"echoed body"));
+            client.request(5, "GET", "/big", None);
+            client.request(7, "GET", "/missing", None);
+            client.request(9, "GET", "/events", None);
+            client.request(11, "DELETE", "/hello", None);
+            // a header smuggled into an HPACK literal resets the stream with PROTOCOL_ERROR
+            let block: Vec<u8> = encode(&fields(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/hello"),
+                (":authority", "localhost"),
+                ("accept", "*/*\r\nx-injected: 1")
+            ]));
+            client.send(Frame::new(HEADERS, FLAG_END_HEADERS | FLAG_END_STREAM, 13, block));
+            let responses: HashMap<u32, (String, Vec<u8>)> = client.responses(7);
+
+            assert_eq!(responses[&1], ("200".to_string(), b"Hello, h2 from localhost!".to_vec()));
+            assert_eq!(responses[&3], ("200".to_string(), b"echoed body".to_vec()));
+            assert_eq!(responses[&5].0, "200");
+            assert_eq!(responses[&5].1.len(), 100_000);
+            assert_eq!(responses[&7].0, "404");
+            assert_eq!(responses[&9].0, "RST 13");
+            assert_eq!(responses[&11].0, "RST 2");
+            assert_eq!(responses[&13].0, "RST 1");
+        });
+    }
+
+    #[test]
+    fn test_upgrade() {
+        test_server(|addr| {
+            let mut client: TestClient = TestClient::connect(addr);
+            client.stream.write_all(
+                b"GET /hello HTTP/1.1\r\nHost: example.com\r\nConnection: Upgrade, HTTP2-Settings\r\n\
+                  Upgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAoAAAAAIAAAAA\r\n\r\n"
+            ).unwrap();
+
+            let mut head: Vec<u8> = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                let mut byte: [u8; 1] = [0];
+                client.stream.read_exact(&mut byte).unwrap();
+                head.push(byte[0]);
+            }
+            assert!(head.starts_with(b"HTTP/1.1 101 Switching Protocols\r\n"));
+
+            client.stream.write_all(PREFACE).unwrap();
+            client.send(Frame::new(SETTINGS, 0, 0, Vec::new()));
+            let responses: HashMap<u32, (String, Vec<u8>)> = client.responses(1);
+            assert_eq!(responses[&1], ("200".to_string(), b"Hello, world from example.com!".to_vec()));
+        });
+    }
+
+    #[test]
+    fn test_protocol_error() {
+        test_server(|addr| {
+            let mut client: TestClient = TestClient::connect(addr);
+            client.stream.write_all(PREFACE).unwrap();
+            client.send(Frame::new(SETTINGS, 0, 0, Vec::new()));
+            client.send(Frame::new(PING, 0, 0, b"pingpong".to_vec()));
+            // streams initiated by clients must have odd identifiers
+            client.send(Frame::new(HEADERS, FLAG_END_HEADERS | FLAG_END_STREAM, 2, Vec::new()));
+
+            let mut frames: Vec<Frame> = Vec::new();
+            while let Ok(Ok(frame)) = read_frame(&mut client.stream, 1 << 24) {
+                frames.push(frame);
+            }
+            assert!(frames.iter().any(|f| f.kind == SETTINGS && f.has_flag(FLAG_ACK)));
+            assert!(frames.contains(&Frame::new(PING, FLAG_ACK, 0, b"pingpong".to_vec())));
+            assert_eq!(frames.last(), Some(&Frame::goaway(0, 1)));
+            assert_eq!(frames.last().unwrap().kind, GOAWAY);
+        });
+    }
+
+    #[test]
+    fn test_upgrade_settings() {
+        let head = |headers: &[(&str, &str)]| HttpRequestHead {
+            method: "GET".to_string(),
+            uri: "/".to_string(),
+            version: HttpVersion::Http11,
+            headers: fields(headers)
+        };
+
+        let settings: Settings = upgrade_settings(&head(&[
+            ("connection", "Upgrade, HTTP2-Settings"),
+            ("upgrade", "h2c"),
+            ("http2-settings", "AAMAAABkAAQAoAAAAAIAAAAA")
+        ])).unwrap();
+        assert_eq!(settings.max_concurrent_streams, Some(100));
+        assert_eq!(settings.initial_window_size, 10485760);
+        assert!(!settings.enable_push);
+        assert_eq!(base64url_decode("AAMAAABkAAQAoAAAAAIAAAAA").unwrap().len(), 18);
+
+        assert!(upgrade_settings(&head(&[
+            ("connection", "Upgrade"),
+            ("upgrade", "h2c"),
+            ("http2-settings", "")
+        ])).is_none());
+        assert!(upgrade_settings(&head(&[
+            ("connection", "Upgrade, HTTP2-Settings"),
+            ("upgrade", "websocket"),
+            ("http2-settings", "")
+        ])).is_none());
+        assert!(upgrade_settings(&head(&[
+            ("connection", "Upgrade, HTTP2-Settings"),
+            ("upgrade", "h2c"),
+            ("http2-settings", "AAM")
+        ])).is_none());
+    }
+
+    #[test]
+    fn test_request_head() {
+        let head: HttpRequestHead = request_head(fields(&[
+            (":method", "GET"),
+            (":scheme", "http"),
+            (":authority", "example.com"),
+            (":path", "/a?b=c"),
+            ("cookie", "a=1"),
+            ("accept", "*/*"),
+            ("cookie", "b=2")
+        ])).unwrap();
+        assert_eq!(head.method, "GET");
+        assert_eq!(head.uri, "/a?b=c");
+        assert_eq!(head.version, HttpVersion::Http2);
+        assert_eq!(head.headers, fields(&[
+            ("host", "example.com"),
+            ("accept", "*/*"),
+            ("cookie", "a=1; b=2")
+        ]));
+
+        let error = |f: &[(&str, &str)]| request_head(fields(f)).unwrap_err();
+        assert_eq!(error(&[(":method", "GET"), (":path", "/")]), "missing pseudo-header fields");
+        assert_eq!(
+            error(&[(":method", "GET"), ("accept", "*/*"), (":scheme", "http"), (":path", "/")]),
+            "pseudo-header field after regular fields"
+        );
+        assert_eq!(error(&[(":method", "GET"), (":method", "GET")]), "duplicate pseudo-header field");
+        assert_eq!(error(&[(":protocol", "websocket")]), "unknown pseudo-header field");
+        assert_eq!(error(&[("Accept", "*/*")]), "uppercase header field name");
+        assert_eq!(error(&[("connection", "close")]), "connection-specific header field");
+        assert_eq!(error(&[("te", "gzip")]), "connection-specific header field");
+        assert_eq!(error(&[("accept", "*/*\r\nx-evil: 1")]), "CR, LF or NUL in header field");
+        assert_eq!(error(&[(":authority", "a\0b")]), "CR, LF or NUL in header field");
+        assert_eq!(
+            error(&[(":method", "GET /a HTTP/1.1"), (":scheme", "http"), (":path", "/")]),
+            "invalid :method"
+        );
+        assert_eq!(
+            error(&[(":method", "GET"), (":scheme", "http"), (":path", "/a b")]),
+            "invalid :path"
+        );
+    }
+}
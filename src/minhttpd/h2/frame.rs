@@ -0,0 +1,262 @@
+//! HTTP/2 framing layer (RFC 7540 section 4 and 6)
+
+use std::io;
+use std::io::Read;
+use std::slice::ChunksExact;
+
+pub(crate) const FRAME_HEADER_SIZE: usize = 9;
+
+pub(crate) const DATA: u8 = 0x0;
+pub(crate) const HEADERS: u8 = 0x1;
+pub(crate) const PRIORITY: u8 = 0x2;
+pub(crate) const RST_STREAM: u8 = 0x3;
+pub(crate) const SETTINGS: u8 = 0x4;
+pub(crate) const PUSH_PROMISE: u8 = 0x5;
+pub(crate) const PING: u8 = 0x6;
+pub(crate) const GOAWAY: u8 = 0x7;
+pub(crate) const WINDOW_UPDATE: u8 = 0x8;
+pub(crate) const CONTINUATION: u8 = 0x9;
+
+pub(crate) const FLAG_END_STREAM: u8 = 0x1;
+pub(crate) const FLAG_ACK: u8 = 0x1;
+pub(crate) const FLAG_END_HEADERS: u8 = 0x4;
+pub(crate) const FLAG_PADDED: u8 = 0x8;
+pub(crate) const FLAG_PRIORITY: u8 = 0x20;
+
+pub(crate) const NO_ERROR: u32 = 0x0;
+pub(crate) const PROTOCOL_ERROR: u32 = 0x1;
+pub(crate) const INTERNAL_ERROR: u32 = 0x2;
+pub(crate) const FLOW_CONTROL_ERROR: u32 = 0x3;
+pub(crate) const FRAME_SIZE_ERROR: u32 = 0x6;
+pub(crate) const REFUSED_STREAM: u32 = 0x7;
+pub(crate) const COMPRESSION_ERROR: u32 = 0x9;
+pub(crate) const ENHANCE_YOUR_CALM: u32 = 0xb;
+pub(crate) const HTTP_1_1_REQUIRED: u32 = 0xd;
+
+/// Largest flow control window, and largest window increment
+pub(crate) const MAX_WINDOW_SIZE: i64 = (1 << 31) - 1;
+
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Frame {
+    pub(crate) kind: u8,
+    pub(crate) flags: u8,
+    pub(crate) stream_id: u32,
+    pub(crate) payload: Vec<u8>
+}
+
+impl Frame {
+    pub(crate) fn new(kind: u8, flags: u8, stream_id: u32, payload: Vec<u8>) -> Self {
+        Self { kind, flags, stream_id, payload }
+    }
+
+    pub(crate) fn rst_stream(stream_id: u32, error_code: u32) -> Self {
+        Self::new(RST_STREAM, 0, stream_id, error_code.to_be_bytes().to_vec())
+    }
+
+    pub(crate) fn window_update(stream_id: u32, increment: u32) -> Self {
+        Self::new(WINDOW_UPDATE, 0, stream_id, increment.to_be_bytes().to_vec())
+    }
+
+    pub(crate) fn goaway(last_stream_id: u32, error_code: u32) -> Self {
+        let mut payload: Vec<u8> = last_stream_id.to_be_bytes().to_vec();
+        payload.extend_from_slice(&error_code.to_be_bytes());
+        Self::new(GOAWAY, 0, 0, payload)
+    }
+
+    pub(crate) fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut ret: Vec<u8> = Vec::with_capacity(FRAME_HEADER_SIZE + self.payload.len());
+        ret.extend_from_slice(&(self.payload.len() as u32).to_be_bytes()[1..]);
+        ret.push(self.kind);
+        ret.push(self.flags);
+        ret.extend_from_slice(&(self.stream_id & 0x7fff_ffff).to_be_bytes());
+        ret.extend_from_slice(&self.payload);
+        ret
+    }
+
+    /// Payload of a `DATA` or `HEADERS` frame without padding, `None` if the padding length is
+    /// invalid
+    pub(crate) fn unpadded_payload(&self) -> Option<&[u8]> {
+        if !self.has_flag(FLAG_PADDED) {
+            return Some(&self.payload);
+        }
+        let pad_length: usize = *self.payload.first()? as usize;
+        if pad_length >= self.payload.len() {
+            return None;
+        }
+        Some(&self.payload[1..self.payload.len() - pad_length])
+    }
+}
+
+/// Reads a frame. Frames larger than `max_frame_size` are reported as `Ok(Err(size))`, after the
+/// header only, since the connection is going to be closed anyway.
+pub(crate) fn read_frame(
+    reader: &mut impl Read,
+    max_frame_size: usize
+) -> io::Result<Result<Frame, usize>> {
+    let mut header: [u8; FRAME_HEADER_SIZE] = [0; FRAME_HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    let size: usize = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+    if size > max_frame_size {
+        return Ok(Err(size));
+    }
+
+    let mut payload: Vec<u8> = vec![0; size];
+    reader.read_exact(&mut payload)?;
+    let stream_id: u32 = u32::from_be_bytes([header[5], header[6], header[7], header[8]]);
+    Ok(Ok(Frame::new(header[3], header[4], stream_id & 0x7fff_ffff, payload)))
+}
+
+/// Settings of one endpoint, initially the defaults of RFC 7540 section 6.5.2
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Settings {
+    pub(crate) header_table_size: u32,
+    pub(crate) enable_push: bool,
+    pub(crate) max_concurrent_streams: Option<u32>,
+    pub(crate) initial_window_size: u32,
+    pub(crate) max_frame_size: u32,
+    pub(crate) max_header_list_size: Option<u32>
+}
+
+impl Settings {
+    /// Applies the payload of a `SETTINGS` frame, returning the error code of an invalid setting
+    pub(crate) fn apply(&mut self, payload: &[u8]) -> Result<(), u32> {
+        let settings: ChunksExact<'_, u8> = payload.chunks_exact(6);
+        if !settings.remainder().is_empty() {
+            return Err(FRAME_SIZE_ERROR);
+        }
+
+        for setting in settings {
+            let id: u16 = u16::from_be_bytes([setting[0], setting[1]]);
+            let value: u32 = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+            match id {
+                SETTINGS_HEADER_TABLE_SIZE => self.header_table_size = value,
+                SETTINGS_ENABLE_PUSH => match value {
+                    0 | 1 => self.enable_push = value == 1,
+                    _ => return Err(PROTOCOL_ERROR)
+                },
+                SETTINGS_MAX_CONCURRENT_STREAMS => self.max_concurrent_streams = Some(value),
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    if value as i64 > MAX_WINDOW_SIZE {
+                        return Err(FLOW_CONTROL_ERROR);
+                    }
+                    self.initial_window_size = value;
+                },
+                SETTINGS_MAX_FRAME_SIZE => {
+                    if !(16384..=16_777_215).contains(&value) {
+                        return Err(PROTOCOL_ERROR);
+                    }
+                    self.max_frame_size = value;
+                },
+                SETTINGS_MAX_HEADER_LIST_SIZE => self.max_header_list_size = Some(value),
+                // unknown settings must be ignored
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Payload of a `SETTINGS` frame announcing settings differing from the defaults
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let defaults: Self = Self::default();
+        let mut ret: Vec<u8> = Vec::new();
+        let mut push = |id: u16, value: u32| {
+            ret.extend_from_slice(&id.to_be_bytes());
+            ret.extend_from_slice(&value.to_be_bytes());
+        };
+        if self.header_table_size != defaults.header_table_size {
+            push(SETTINGS_HEADER_TABLE_SIZE, self.header_table_size);
+        }
+        if self.enable_push != defaults.enable_push {
+            push(SETTINGS_ENABLE_PUSH, self.enable_push as u32);
+        }
+        if let Some(max_concurrent_streams) = self.max_concurrent_streams {
+            push(SETTINGS_MAX_CONCURRENT_STREAMS, max_concurrent_streams);
+        }
+        if self.initial_window_size != defaults.initial_window_size {
+            push(SETTINGS_INITIAL_WINDOW_SIZE, self.initial_window_size);
+        }
+        if self.max_frame_size != defaults.max_frame_size {
+            push(SETTINGS_MAX_FRAME_SIZE, self.max_frame_size);
+        }
+        if let Some(max_header_list_size) = self.max_header_list_size {
+            push(SETTINGS_MAX_HEADER_LIST_SIZE, max_header_list_size);
+        }
+        ret
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            header_table_size: 4096,
+            enable_push: true,
+            max_concurrent_streams: None,
+            initial_window_size: 65535,
+            max_frame_size: 16384,
+            max_header_list_size: None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::minhttpd::h2::frame::{
+        FLAG_END_STREAM,
+        FLAG_PADDED,
+        FLOW_CONTROL_ERROR,
+        FRAME_SIZE_ERROR,
+        Frame,
+        HEADERS,
+        PROTOCOL_ERROR,
+        Settings,
+        read_frame
+    };
+
+    #[test]
+    fn test_frame() {
+        let frame: Frame = Frame::new(HEADERS, FLAG_END_STREAM | FLAG_PADDED, 3, vec![2, 0xaa, 0, 0]);
+        let encoded: Vec<u8> = frame.encode();
+        assert_eq!(&encoded[..9], &[0, 0, 4, 1, 9, 0, 0, 0, 3]);
+        assert_eq!(frame.unpadded_payload(), Some(&[0xaa][..]));
+
+        let decoded: Frame = read_frame(&mut Cursor::new(&encoded), 16384).unwrap().unwrap();
+        assert_eq!(decoded, frame);
+        assert_eq!(read_frame(&mut Cursor::new(&encoded), 3).unwrap(), Err(4));
+        assert!(read_frame(&mut Cursor::new(&encoded[..10]), 16384).is_err());
+
+        assert_eq!(Frame::new(HEADERS, FLAG_PADDED, 1, vec![1, 0]).unpadded_payload(), Some(&[][..]));
+        assert_eq!(Frame::new(HEADERS, FLAG_PADDED, 1, vec![2, 0]).unpadded_payload(), None);
+        assert_eq!(Frame::new(HEADERS, FLAG_PADDED, 1, vec![]).unpadded_payload(), None);
+        assert_eq!(Frame::rst_stream(1, 8).encode(), vec![0, 0, 4, 3, 0, 0, 0, 0, 1, 0, 0, 0, 8]);
+    }
+
+    #[test]
+    fn test_settings() {
+        let mut settings: Settings = Settings::default();
+        settings.apply(&[0, 3, 0, 0, 0, 100, 0, 4, 0, 1, 0, 0, 0, 0xff, 0, 0, 0, 0]).unwrap();
+        assert_eq!(settings.max_concurrent_streams, Some(100));
+        assert_eq!(settings.initial_window_size, 65536);
+
+        let mut decoded: Settings = Settings::default();
+        decoded.apply(&settings.encode()).unwrap();
+        assert_eq!(decoded, settings);
+
+        assert_eq!(settings.apply(&[0, 3, 0]), Err(FRAME_SIZE_ERROR));
+        assert_eq!(settings.apply(&[0, 2, 0, 0, 0, 2]), Err(PROTOCOL_ERROR));
+        assert_eq!(settings.apply(&[0, 4, 0x80, 0, 0, 0]), Err(FLOW_CONTROL_ERROR));
+        assert_eq!(settings.apply(&[0, 5, 0, 0, 0x10, 0]), Err(PROTOCOL_ERROR));
+    }
+}
@@ -0,0 +1,525 @@
+//! HPACK header compression (RFC 7541)
+//!
+//! The decoder implements the full format, including the dynamic table. The encoder never adds
+//! entries to the dynamic table of the peer, which keeps it stateless: fields are either fully
+//! indexed in the static table, or sent as literals without indexing.
+
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+
+use crate::minhttpd::h2::huffman::HUFFMAN_CODES;
+
+/// Entries of the static table, index 1 is at position 0
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", "")
+];
+
+/// Per-entry overhead counted in the size of the dynamic table
+const ENTRY_OVERHEAD: usize = 32;
+
+/// A malformed header block, always a connection error of type `COMPRESSION_ERROR`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct HpackError(pub(crate) &'static str);
+
+impl Display for HpackError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "malformed header block: {}", self.0)
+    }
+}
+
+/// Decoding context of header blocks received from the peer
+pub(crate) struct Decoder {
+    /// Newest entry first
+    dynamic_table: VecDeque<(String, String)>,
+    table_size: usize,
+    max_table_size: usize,
+    /// `SETTINGS_HEADER_TABLE_SIZE` advertised to the peer, bounding `max_table_size`
+    table_size_limit: usize,
+    /// Maximum size of a decoded header list, counted like entries of the dynamic table
+    header_list_limit: usize,
+    /// Built for each connection, which saves a lazily initialized static
+    huffman: HuffmanDecoder
+}
+
+impl Decoder {
+    pub(crate) fn new(table_size_limit: usize, header_list_limit: usize) -> Self {
+        Self {
+            dynamic_table: VecDeque::new(),
+            table_size: 0,
+            max_table_size: table_size_limit,
+            table_size_limit,
+            header_list_limit,
+            huffman: HuffmanDecoder::new()
+        }
+    }
+
+    /// Decodes a complete header block into a list of fields, in order
+    pub(crate) fn decode(&mut self, block: &[u8]) -> Result<Vec<(String, String)>, HpackError> {
+        let mut ret: Vec<(String, String)> = Vec::new();
+        let mut list_size: usize = 0;
+        let mut pos: usize = 0;
+        while pos < block.len() {
+            let first: u8 = block[pos];
+            let field: (String, String) = if first & 0x80 != 0 {
+                // indexed header field
+                let index: usize = decode_integer(block, &mut pos, 7)?;
+                self.get(index)?
+            } else if first & 0x40 != 0 {
+                // literal header field with incremental indexing
+                let field: (String, String) = self.decode_literal(block, &mut pos, 6)?;
+                self.insert(field.clone());
+                field
+            } else if first & 0x20 != 0 {
+                if !ret.is_empty() {
+                    return Err(HpackError("table size update after header fields"));
+                }
+                let size: usize = decode_integer(block, &mut pos, 5)?;
+                if size > self.table_size_limit {
+                    return Err(HpackError("table size update exceeds the advertised limit"));
+                }
+                self.max_table_size = size;
+                self.evict(0);
+                continue;
+            } else {
+                // literal header field without indexing, or never indexed
+                self.decode_literal(block, &mut pos, 4)?
+            };
+
+            // indexed fields expand a single byte into a whole entry, so the decoded size is
+            // checked as fields are decoded
+            list_size += entry_size(&field);
+            if list_size > self.header_list_limit {
+                return Err(HpackError("header list too large"));
+            }
+            ret.push(field);
+        }
+        Ok(ret)
+    }
+
+    fn decode_literal(
+        &self,
+        block: &[u8],
+        pos: &mut usize,
+        prefix_bits: u8
+    ) -> Result<(String, String), HpackError> {
+        let index: usize = decode_integer(block, pos, prefix_bits)?;
+        let name: String = if index == 0 {
+            self.decode_string(block, pos)?
+        } else {
+            self.get(index)?.0
+        };
+        let value: String = self.decode_string(block, pos)?;
+        Ok((name, value))
+    }
+
+    fn decode_string(&self, input: &[u8], pos: &mut usize) -> Result<String, HpackError> {
+        let huffman: bool = input.get(*pos).ok_or(HpackError("truncated string"))? & 0x80 != 0;
+        let len: usize = decode_integer(input, pos, 7)?;
+        let raw: &[u8] = input.get(*pos..*pos + len).ok_or(HpackError("truncated string"))?;
+        *pos += len;
+
+        let decoded: Vec<u8> = if huffman {
+            self.huffman.decode(raw).ok_or(HpackError("invalid Huffman code"))?
+        } else {
+            raw.to_vec()
+        };
+        Ok(String::from_utf8_lossy(&decoded).into_owned())
+    }
+
+    fn get(&self, index: usize) -> Result<(String, String), HpackError> {
+        if index == 0 {
+            return Err(HpackError("zero index"));
+        }
+        if index <= STATIC_TABLE.len() {
+            let (name, value): (&str, &str) = STATIC_TABLE[index - 1];
+            return Ok((name.to_string(), value.to_string()));
+        }
+        self.dynamic_table.get(index - STATIC_TABLE.len() - 1)
+            .cloned()
+            .ok_or(HpackError("index out of range"))
+    }
+
+    fn insert(&mut self, field: (String, String)) {
+        let size: usize = entry_size(&field);
+        // an entry larger than the whole table empties it without being inserted
+        self.evict(size.min(self.max_table_size));
+        if size <= self.max_table_size {
+            self.table_size += size;
+            self.dynamic_table.push_front(field);
+        }
+    }
+
+    /// Evicts oldest entries until `additional` bytes fit in the table
+    fn evict(&mut self, additional: usize) {
+        while self.table_size + additional > self.max_table_size {
+            match self.dynamic_table.pop_back() {
+                Some(field) => self.table_size -= entry_size(&field),
+                None => break
+            }
+        }
+    }
+}
+
+/// Encodes a list of fields into a header block. Field names must already be lowercase.
+pub(crate) fn encode(fields: &[(String, String)]) -> Vec<u8> {
+    let mut ret: Vec<u8> = Vec::new();
+    for (name, value) in fields {
+        if let Some(index) = STATIC_TABLE.iter().position(|f| f.0 == name && f.1 == value) {
+            encode_integer(index + 1, 7, 0x80, &mut ret);
+            continue;
+        }
+
+        match STATIC_TABLE.iter().position(|f| f.0 == name) {
+            Some(index) => encode_integer(index + 1, 4, 0x00, &mut ret),
+            None => {
+                ret.push(0x00);
+                encode_string(name.as_bytes(), &mut ret);
+            }
+        }
+        encode_string(value.as_bytes(), &mut ret);
+    }
+    ret
+}
+
+fn entry_size(field: &(String, String)) -> usize {
+    field.0.len() + field.1.len() + ENTRY_OVERHEAD
+}
+
+fn encode_integer(value: usize, prefix_bits: u8, first_byte: u8, out: &mut Vec<u8>) {
+    let max_prefix: usize = (1 << prefix_bits) - 1;
+    if value < max_prefix {
+        out.push(first_byte | value as u8);
+        return;
+    }
+
+    out.push(first_byte | max_prefix as u8);
+    let mut rest: usize = value - max_prefix;
+    while rest >= 0x80 {
+        out.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    out.push(rest as u8);
+}
+
+fn decode_integer(input: &[u8], pos: &mut usize, prefix_bits: u8) -> Result<usize, HpackError> {
+    let max_prefix: usize = (1 << prefix_bits) - 1;
+    let mut value: usize = *input.get(*pos).ok_or(HpackError("truncated integer"))? as usize
+        & max_prefix;
+    *pos += 1;
+    if value < max_prefix {
+        return Ok(value);
+    }
+
+    let mut shift: u32 = 0;
+    loop {
+        let byte: u8 = *input.get(*pos).ok_or(HpackError("truncated integer"))?;
+        *pos += 1;
+        // values are bounded by sizes of header blocks in practice, anything beyond 2^28 is
+        // treated as an attack
+        if shift > 21 {
+            return Err(HpackError("integer too large"));
+        }
+        value += ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn encode_string(input: &[u8], out: &mut Vec<u8>) {
+    let huffman_bits: usize = input.iter().map(|b| HUFFMAN_CODES[*b as usize].1 as usize).sum();
+    let huffman_len: usize = (huffman_bits + 7) >> 3;
+    if huffman_len < input.len() {
+        encode_integer(huffman_len, 7, 0x80, out);
+        huffman_encode(input, out);
+    } else {
+        encode_integer(input.len(), 7, 0x00, out);
+        out.extend_from_slice(input);
+    }
+}
+
+fn huffman_encode(input: &[u8], out: &mut Vec<u8>) {
+    // high bits shifted out of `bits` have already been written
+    let mut bits: u64 = 0;
+    let mut pending: u32 = 0;
+    for byte in input {
+        let (code, len): (u32, u8) = HUFFMAN_CODES[*byte as usize];
+        bits = (bits << len) | code as u64;
+        pending += len as u32;
+        while pending >= 8 {
+            pending -= 8;
+            out.push((bits >> pending) as u8);
+        }
+    }
+    if pending > 0 {
+        // padded with the most significant bits of EOS, which are all ones
+        out.push(((bits << (8 - pending)) as u8) | (0xff >> pending));
+    }
+}
+
+/// Decoding tables of the Huffman code. The code is canonical, so codes of each length form a
+/// contiguous range, and are assigned to symbols in ascending order.
+struct HuffmanDecoder {
+    /// Symbols sorted by code
+    symbols: Vec<u16>,
+    /// For each bit length, first code of that length
+    first_code: [u32; 31],
+    /// For each bit length, position of the symbol of `first_code` in `symbols`
+    first_index: [usize; 31],
+    /// For each bit length, count of codes of that length
+    count: [u32; 31]
+}
+
+impl HuffmanDecoder {
+    fn new() -> Self {
+        let mut symbols: Vec<u16> = (0..HUFFMAN_CODES.len() as u16).collect();
+        symbols.sort_by_key(|symbol| (HUFFMAN_CODES[*symbol as usize].1, *symbol));
+
+        let mut ret: Self = Self {
+            symbols: Vec::new(),
+            first_code: [0; 31],
+            first_index: [0; 31],
+            count: [0; 31]
+        };
+        for (index, symbol) in symbols.iter().enumerate() {
+            let (code, len): (u32, u8) = HUFFMAN_CODES[*symbol as usize];
+            let len: usize = len as usize;
+            if ret.count[len] == 0 {
+                ret.first_code[len] = code;
+                ret.first_index[len] = index;
+            }
+            ret.count[len] += 1;
+        }
+        ret.symbols = symbols;
+        ret
+    }
+
+    fn decode(&self, input: &[u8]) -> Option<Vec<u8>> {
+        let mut ret: Vec<u8> = Vec::with_capacity(input.len() * 8 / 5);
+        let mut code: u32 = 0;
+        let mut len: usize = 0;
+        for byte in input {
+            for shift in (0..8).rev() {
+                code = (code << 1) | ((byte >> shift) & 1) as u32;
+                len += 1;
+                if len >= self.count.len() {
+                    return None;
+                }
+
+                let offset: u32 = code.wrapping_sub(self.first_code[len]);
+                if code >= self.first_code[len] && offset < self.count[len] {
+                    let symbol: u16 = self.symbols[self.first_index[len] + offset as usize];
+                    if symbol == 256 {
+                        return None;
+                    }
+                    ret.push(symbol as u8);
+                    code = 0;
+                    len = 0;
+                }
+            }
+        }
+
+        // padding must be shorter than 8 bits, and consist of the most significant bits of EOS
+        if len > 7 || code != (1 << len) - 1 {
+            return None;
+        }
+        Some(ret)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::minhttpd::h2::hpack::{Decoder, HpackError, HuffmanDecoder, encode, huffman_encode};
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_huffman() {
+        // RFC 7541 C.4.1
+        let decoder: HuffmanDecoder = HuffmanDecoder::new();
+        let mut encoded: Vec<u8> = Vec::new();
+        huffman_encode(b"www.example.com", &mut encoded);
+        assert_eq!(encoded, hex("f1e3 c2e5 f23a 6ba0 ab90 f4ff"));
+        assert_eq!(decoder.decode(&encoded).unwrap(), b"www.example.com");
+
+        let all: Vec<u8> = (0..=255).collect();
+        let mut encoded: Vec<u8> = Vec::new();
+        huffman_encode(&all, &mut encoded);
+        assert_eq!(decoder.decode(&encoded).unwrap(), all);
+
+        // EOS, padding of 8 bits or more, and padding with zeros are all invalid
+        assert!(decoder.decode(&hex("ffff fffc")).is_none());
+        assert!(decoder.decode(&hex("1fff")).is_none());
+        // `a` is `00011`
+        assert_eq!(decoder.decode(&[0x1f]).unwrap(), b"a");
+        assert!(decoder.decode(&[0x18]).is_none());
+    }
+
+    #[test]
+    fn test_decode_requests() {
+        // RFC 7541 C.4, requests with Huffman coding sharing one dynamic table
+        let mut decoder: Decoder = Decoder::new(4096, usize::MAX);
+        assert_eq!(
+            decoder.decode(&hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff")).unwrap(),
+            fields(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com")
+            ])
+        );
+        assert_eq!(
+            decoder.decode(&hex("8286 84be 5886 a8eb 1064 9cbf")).unwrap(),
+            fields(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache")
+            ])
+        );
+        assert_eq!(
+            decoder.decode(&hex("8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf"))
+                .unwrap(),
+            fields(&[
+                (":method", "GET"),
+                (":scheme", "https"),
+                (":path", "/index.html"),
+                (":authority", "www.example.com"),
+                ("custom-key", "custom-value")
+            ])
+        );
+        assert_eq!(decoder.table_size, 164);
+    }
+
+    #[test]
+    fn test_decode_eviction() {
+        // RFC 7541 C.5.1 and C.5.2, responses with a 256 bytes table
+        let mut decoder: Decoder = Decoder::new(256, usize::MAX);
+        decoder.decode(&hex(
+            "4803 3330 3258 0770 7269 7661 7465 611d 4d6f 6e2c 2032 3120 4f63 7420 3230 3133
+             2032 303a 3133 3a32 3120 474d 546e 1768 7474 7073 3a2f 2f77 7777 2e65 7861 6d70
+             6c65 2e63 6f6d"
+        )).unwrap();
+        assert_eq!(decoder.table_size, 222);
+        assert_eq!(
+            decoder.decode(&hex("4803 3330 37c1 c0bf")).unwrap(),
+            fields(&[
+                (":status", "307"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                ("location", "https://www.example.com")
+            ])
+        );
+        assert_eq!(decoder.table_size, 222);
+        assert_eq!(decoder.dynamic_table.len(), 4);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut decoder: Decoder = Decoder::new(4096, usize::MAX);
+        assert_eq!(decoder.decode(&[0x80]), Err(HpackError("zero index")));
+        assert_eq!(decoder.decode(&[0xbe]), Err(HpackError("index out of range")));
+        assert_eq!(decoder.decode(&[0x82, 0x20]), Err(HpackError("table size update after header fields")));
+        assert!(decoder.decode(&hex("3fe2 1f")).is_err());
+        assert!(decoder.decode(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(decoder.decode(&[0x04, 0x85, 0x61]).is_err());
+
+        // a table size update to zero empties the table
+        decoder.decode(&hex("400a 6375 7374 6f6d 2d6b 6579 0d63 7573 746f 6d2d 6865 6164 6572")).unwrap();
+        assert_eq!(decoder.decode(&[0xbe]).unwrap(), fields(&[("custom-key", "custom-header")]));
+        decoder.decode(&[0x20]).unwrap();
+        assert!(decoder.decode(&[0xbe]).is_err());
+
+        // each `:method: GET` counts as 42 bytes
+        let mut decoder: Decoder = Decoder::new(4096, 100);
+        assert!(decoder.decode(&[0x82, 0x82]).is_ok());
+        assert_eq!(decoder.decode(&[0x82, 0x82, 0x82]), Err(HpackError("header list too large")));
+    }
+
+    #[test]
+    fn test_encode() {
+        let headers: Vec<(String, String)> = fields(&[
+            (":status", "200"),
+            (":status", "307"),
+            ("content-type", "text/plain"),
+            ("x-custom", "value"),
+            ("content-length", "1234567890")
+        ]);
+        let encoded: Vec<u8> = encode(&headers);
+        assert_eq!(encoded[0], 0x88);
+        assert_eq!(Decoder::new(0, usize::MAX).decode(&encoded).unwrap(), headers);
+
+        let long_value: String = "x".repeat(300);
+        let headers: Vec<(String, String)> = fields(&[("x-long", &long_value)]);
+        assert_eq!(Decoder::new(0, usize::MAX).decode(&encode(&headers)).unwrap(), headers);
+    }
+}
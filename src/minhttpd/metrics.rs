@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::SeqCst;
+use std::time::Duration;
+
+/// Upper bounds of latency histogram buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0
+];
+
+/// Route label of requests not matching any route
+pub const UNMATCHED_ROUTE: &str = "-";
+
+/// Metrics of a single route
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RouteMetrics {
+    /// Count of responses by status class, from `1xx` to `5xx`
+    pub responses_by_class: [u64; 5],
+    /// Count of requests finished within each bucket of `LATENCY_BUCKETS`, not cumulative.
+    /// The last element counts requests slower than all buckets. Event streams are left out,
+    /// since they last as long as the client stays connected.
+    pub latency_buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    pub latency_sum: Duration,
+    /// Count of Server-Sent Events streams opened
+    pub event_streams: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64
+}
+
+impl RouteMetrics {
+    pub fn requests(&self) -> u64 {
+        self.responses_by_class.iter().sum()
+    }
+}
+
+/// Request metrics collected by `MinHttpd`
+///
+/// ```
+/// # use xjbutil::minhttpd::MinHttpd;
+/// let mut min_httpd: MinHttpd = MinHttpd::new();
+/// min_httpd.route_metrics("/metrics");
+/// assert_eq!(min_httpd.metrics().in_flight(), 0);
+/// ```
+#[derive(Default)]
+pub struct HttpMetrics {
+    in_flight: AtomicU64,
+    routes: Mutex<BTreeMap<String, RouteMetrics>>
+}
+
+impl HttpMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count of connections currently being handled
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(SeqCst)
+    }
+
+    pub fn route(&self, route: &str) -> Option<RouteMetrics> {
+        self.routes.lock().unwrap().get(route).cloned()
+    }
+
+    /// Metrics of all routes that have served at least one request, sorted by route
+    pub fn snapshot(&self) -> Vec<(String, RouteMetrics)> {
+        self.routes.lock().unwrap()
+            .iter()
+            .map(|(route, metrics)| (route.clone(), metrics.clone()))
+            .collect()
+    }
+
+    pub(crate) fn enter(&self) -> InFlightGuard<'_> {
+        self.in_flight.fetch_add(1, SeqCst);
+        InFlightGuard { metrics: self }
+    }
+
+    pub(crate) fn record(&self, exchange: &Exchange, latency: Duration) {
+        let code: u16 = match exchange.code {
+            Some(code) => code,
+            None => return
+        };
+
+        let mut routes = self.routes.lock().unwrap();
+        let route: &mut RouteMetrics = routes
+            .entry(exchange.route.clone().unwrap_or_else(|| UNMATCHED_ROUTE.to_string()))
+            .or_default();
+
+        route.responses_by_class[(code as usize / 100).clamp(1, 5) - 1] += 1;
+        route.bytes_in += exchange.bytes_in;
+        route.bytes_out += exchange.bytes_out;
+        if exchange.event_stream {
+            route.event_streams += 1;
+            return;
+        }
+
+        let bucket: usize = LATENCY_BUCKETS.iter()
+            .position(|bound| latency.as_secs_f64() <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        route.latency_buckets[bucket] += 1;
+        route.latency_sum += latency;
+    }
+
+    /// Renders all metrics in Prometheus text exposition format
+    pub fn render_prometheus(&self) -> String {
+        let snapshot: Vec<(String, RouteMetrics)> = self.snapshot();
+        let mut ret: String = String::new();
+
+        ret.push_str("# HELP minhttpd_requests_total Requests handled, by route and status class\n");
+        ret.push_str("# TYPE minhttpd_requests_total counter\n");
+        for (route, metrics) in snapshot.iter() {
+            for (i, count) in metrics.responses_by_class.iter().enumerate() {
+                let _ = writeln!(
+                    ret,
+                    "minhttpd_requests_total{{route=\"{}\",class=\"{}xx\"}} {}",
+                    escape_label(route), i + 1, count
+                );
+            }
+        }
+
+        ret.push_str("# HELP minhttpd_request_duration_seconds Time taken to handle requests\n");
+        ret.push_str("# TYPE minhttpd_request_duration_seconds histogram\n");
+        for (route, metrics) in snapshot.iter() {
+            let route: String = escape_label(route);
+            let count: u64 = metrics.latency_buckets.iter().sum();
+            let mut cumulative: u64 = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(metrics.latency_buckets.iter()) {
+                cumulative += count;
+                let _ = writeln!(
+                    ret,
+                    "minhttpd_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                    route, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                ret,
+                "minhttpd_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}",
+                route, count
+            );
+            let _ = writeln!(
+                ret,
+                "minhttpd_request_duration_seconds_sum{{route=\"{}\"}} {}",
+                route, metrics.latency_sum.as_secs_f64()
+            );
+            let _ = writeln!(
+                ret,
+                "minhttpd_request_duration_seconds_count{{route=\"{}\"}} {}",
+                route, count
+            );
+        }
+
+        ret.push_str("# HELP minhttpd_event_streams_total Server-Sent Events streams opened\n");
+        ret.push_str("# TYPE minhttpd_event_streams_total counter\n");
+        for (route, metrics) in snapshot.iter() {
+            let _ = writeln!(
+                ret,
+                "minhttpd_event_streams_total{{route=\"{}\"}} {}",
+                escape_label(route), metrics.event_streams
+            );
+        }
+
+        ret.push_str("# HELP minhttpd_request_bytes_total Bytes received in requests\n");
+        ret.push_str("# TYPE minhttpd_request_bytes_total counter\n");
+        for (route, metrics) in snapshot.iter() {
+            let _ = writeln!(
+                ret,
+                "minhttpd_request_bytes_total{{route=\"{}\"}} {}",
+                escape_label(route), metrics.bytes_in
+            );
+        }
+
+        ret.push_str("# HELP minhttpd_response_bytes_total Bytes sent in responses\n");
+        ret.push_str("# TYPE minhttpd_response_bytes_total counter\n");
+        for (route, metrics) in snapshot.iter() {
+            let _ = writeln!(
+                ret,
+                "minhttpd_response_bytes_total{{route=\"{}\"}} {}",
+                escape_label(route), metrics.bytes_out
+            );
+        }
+
+        ret.push_str("# HELP minhttpd_connections_in_flight Connections currently being handled\n");
+        ret.push_str("# TYPE minhttpd_connections_in_flight gauge\n");
+        let _ = writeln!(ret, "minhttpd_connections_in_flight {}", self.in_flight());
+        ret
+    }
+}
+
+pub(crate) struct InFlightGuard<'a> {
+    metrics: &'a HttpMetrics
+}
+
+impl<'a> Drop for InFlightGuard<'a> {
+    fn drop(&mut self) {
+        self.metrics.in_flight.fetch_sub(1, SeqCst);
+    }
+}
+
+/// What happened to a single request, filled in while handling the connection
+#[derive(Default)]
+pub(crate) struct Exchange {
+    pub(crate) route: Option<String>,
+    pub(crate) code: Option<u16>,
+    pub(crate) bytes_in: u64,
+    pub(crate) bytes_out: u64,
+    /// Whether the response started an event stream, whose duration is not a latency
+    pub(crate) event_stream: bool
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::minhttpd::{HttpMetrics, RouteMetrics, UNMATCHED_ROUTE};
+    use crate::minhttpd::metrics::Exchange;
+
+    fn exchange(route: Option<&str>, code: Option<u16>) -> Exchange {
+        Exchange {
+            route: route.map(str::to_string),
+            code,
+            bytes_in: 100,
+            bytes_out: 1000,
+            event_stream: false
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let metrics: HttpMetrics = HttpMetrics::new();
+        {
+            let _guard = metrics.enter();
+            let _guard = metrics.enter();
+            assert_eq!(metrics.in_flight(), 2);
+        }
+        assert_eq!(metrics.in_flight(), 0);
+
+        metrics.record(&exchange(Some("/api"), Some(200)), Duration::from_millis(3));
+        metrics.record(&exchange(Some("/api"), Some(204)), Duration::from_millis(30));
+        metrics.record(&exchange(Some("/api"), Some(503)), Duration::from_secs(60));
+        metrics.record(&exchange(None, Some(404)), Duration::from_millis(1));
+        metrics.record(&exchange(Some("/api"), None), Duration::from_millis(1));
+        let stream: Exchange = Exchange { event_stream: true, ..exchange(Some("/api"), Some(200)) };
+        metrics.record(&stream, Duration::from_secs(3600));
+
+        let api: RouteMetrics = metrics.route("/api").unwrap();
+        assert_eq!(api.responses_by_class, [0, 3, 0, 0, 1]);
+        assert_eq!(api.requests(), 4);
+        assert_eq!(api.event_streams, 1);
+        assert_eq!(api.latency_buckets, [1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(api.latency_sum, Duration::from_millis(60033));
+        assert_eq!(api.bytes_in, 400);
+        assert_eq!(api.bytes_out, 4000);
+        assert_eq!(metrics.route(UNMATCHED_ROUTE).unwrap().responses_by_class, [0, 0, 0, 1, 0]);
+        assert_eq!(metrics.snapshot().len(), 2);
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let metrics: HttpMetrics = HttpMetrics::new();
+        metrics.record(&exchange(Some("/a\"b"), Some(200)), Duration::from_millis(20));
+        let rendered: String = metrics.render_prometheus();
+
+        assert!(rendered.contains("minhttpd_requests_total{route=\"/a\\\"b\",class=\"2xx\"} 1\n"));
+        assert!(rendered.contains("minhttpd_requests_total{route=\"/a\\\"b\",class=\"5xx\"} 0\n"));
+        assert!(rendered.contains(
+            "minhttpd_request_duration_seconds_bucket{route=\"/a\\\"b\",le=\"0.01\"} 0\n"
+        ));
+        assert!(rendered.contains(
+            "minhttpd_request_duration_seconds_bucket{route=\"/a\\\"b\",le=\"0.025\"} 1\n"
+        ));
+        assert!(rendered.contains(
+            "minhttpd_request_duration_seconds_bucket{route=\"/a\\\"b\",le=\"+Inf\"} 1\n"
+        ));
+        assert!(rendered.contains("minhttpd_request_duration_seconds_sum{route=\"/a\\\"b\"} 0.02\n"));
+        assert!(rendered.contains("minhttpd_response_bytes_total{route=\"/a\\\"b\"} 1000\n"));
+        assert!(rendered.ends_with("minhttpd_connections_in_flight 0\n"));
+    }
+}
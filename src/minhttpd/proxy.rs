@@ -0,0 +1,426 @@
+use std::io;
+use std::io::{BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::http_commons::HttpResponse;
+use crate::http_commons::parser::{
+    HttpRequest,
+    HttpResponseMessage,
+    HttpResponseParser,
+    ParserLimits,
+    ParseStatus
+};
+
+/// Headers meaningful only for a single transport-level connection (RFC 7230 section 6.1), never
+/// forwarded by proxies
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade"
+];
+
+/// Forwards requests under a route prefix to an upstream HTTP server
+///
+/// The route prefix is replaced with `upstream_path`, hop-by-hop headers are stripped in both
+/// directions, and `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host` are added to the
+/// forwarded request. Unreachable upstreams and malformed upstream responses are answered with
+/// `502 Bad Gateway`, upstream timeouts with `504 Gateway Timeout`.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use xjbutil::minhttpd::{MinHttpd, ReverseProxy};
+/// let mut min_httpd: MinHttpd = MinHttpd::new();
+/// // GET /legacy/users?id=1 gets forwarded as GET /app/users?id=1 to 127.0.0.1:8080
+/// min_httpd.route_proxy(
+///     "/legacy",
+///     ReverseProxy::new("127.0.0.1:8080")
+///         .upstream_path("/app")
+///         .timeout(Duration::from_secs(5))
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct ReverseProxy {
+    upstream: String,
+    upstream_path: String,
+    connect_timeout: Duration,
+    timeout: Duration,
+    limits: ParserLimits
+}
+
+impl ReverseProxy {
+    /// Creates a proxy forwarding to `upstream`, given in `host:port` form
+    pub fn new(upstream: impl Into<String>) -> Self {
+        Self {
+            upstream: upstream.into(),
+            upstream_path: String::new(),
+            connect_timeout: Duration::from_secs(5),
+            timeout: Duration::from_secs(30),
+            limits: ParserLimits::default()
+        }
+    }
+
+    /// Sets the path replacing the route prefix in forwarded requests
+    pub fn upstream_path(mut self, path: impl Into<String>) -> Self {
+        let mut path: String = path.into();
+        if path.ends_with('/') {
+            path.pop();
+        }
+        self.upstream_path = path;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets the timeout of each read from or write to the upstream
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets limits applied when parsing upstream responses
+    pub fn response_limits(mut self, limits: ParserLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Forwards `request`, received on route `prefix` from `remote_addr`. Requests outside of
+    /// `prefix` are answered with `404 Not Found`.
+    pub fn forward(&self, request: &HttpRequest, prefix: &str, remote_addr: &str) -> HttpResponse {
+        let path: String = match self.rewrite_path(&request.head.uri, prefix) {
+            Some(path) => path,
+            None => return gateway_error(404, "not found")
+        };
+        match self.forward_impl(request, &path, remote_addr) {
+            Ok(response) => response,
+            Err(ProxyError::Timeout) => gateway_error(504, "upstream timed out"),
+            Err(ProxyError::BadGateway(reason)) => gateway_error(502, &reason)
+        }
+    }
+
+    /// Maps the request target received on route `prefix` to the request target of the upstream,
+    /// `None` if the target is not under `prefix`, or has `.` or `..` path segments which the
+    /// upstream could resolve to a path outside of `upstream_path`
+    pub fn rewrite_path(&self, target: &str, prefix: &str) -> Option<String> {
+        let rest: &str = strip_path_prefix(target, prefix)?;
+        if has_dot_segment(rest) {
+            return None;
+        }
+        if rest.starts_with('/') {
+            Some(format!("{}{}", self.upstream_path, rest))
+        } else {
+            Some(format!("{}/{}", self.upstream_path, rest))
+        }
+    }
+
+    fn forward_impl(
+        &self,
+        request: &HttpRequest,
+        path: &str,
+        remote_addr: &str
+    ) -> Result<HttpResponse, ProxyError> {
+        let stream: TcpStream = self.connect()?;
+        stream.set_read_timeout(Some(self.timeout)).map_err(ProxyError::from_io)?;
+        stream.set_write_timeout(Some(self.timeout)).map_err(ProxyError::from_io)?;
+
+        let mut writer: BufWriter<&TcpStream> = BufWriter::new(&stream);
+        write!(
+            writer,
+            "{} {} HTTP/1.1\r\n",
+            request.head.method,
+            path
+        ).map_err(ProxyError::from_io)?;
+        for (key, value) in self.forwarded_headers(request, remote_addr) {
+            write!(writer, "{}: {}\r\n", key, value).map_err(ProxyError::from_io)?;
+        }
+        write!(writer, "\r\n").map_err(ProxyError::from_io)?;
+        if let Some(body) = &request.body {
+            writer.write_all(body).map_err(ProxyError::from_io)?;
+        }
+        writer.flush().map_err(ProxyError::from_io)?;
+        drop(writer);
+
+        let mut message: HttpResponseMessage = self.read_response(&stream, &request.head.method)?;
+        let connection_tokens: Vec<String> = connection_tokens(&message.head.headers);
+        message.head.headers.retain(|(key, _)| {
+            !is_hop_by_hop(key, &connection_tokens) && !key.eq_ignore_ascii_case("content-length")
+        });
+        Ok(message.into())
+    }
+
+    fn connect(&self) -> Result<TcpStream, ProxyError> {
+        let addrs: Vec<SocketAddr> = self.upstream.to_socket_addrs()
+            .map_err(|e| ProxyError::BadGateway(format!("cannot resolve upstream: {}", e)))?
+            .collect();
+
+        let mut last_error: ProxyError = ProxyError::BadGateway("upstream has no address".into());
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, self.connect_timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = ProxyError::from_io(e)
+            }
+        }
+        Err(last_error)
+    }
+
+    fn forwarded_headers(&self, request: &HttpRequest, remote_addr: &str) -> Vec<(String, String)> {
+        let connection_tokens: Vec<String> = connection_tokens(&request.head.headers);
+        let mut headers: Vec<(String, String)> = request.head.headers.iter()
+            .filter(|(key, _)| {
+                !is_hop_by_hop(key, &connection_tokens)
+                    && !["host", "content-length", "expect", "x-forwarded-for"]
+                        .iter()
+                        .any(|h| key.eq_ignore_ascii_case(h))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let forwarded_for: String = match request.head.header("X-Forwarded-For") {
+            Some(previous) => format!("{}, {}", previous, remote_addr),
+            None => remote_addr.to_string()
+        };
+        headers.push(("X-Forwarded-For".to_string(), forwarded_for));
+        if request.head.header("X-Forwarded-Proto").is_none() {
+            headers.push(("X-Forwarded-Proto".to_string(), "http".to_string()));
+        }
+        if let (Some(host), None) = (request.head.header("Host"), request.head.header("X-Forwarded-Host")) {
+            headers.push(("X-Forwarded-Host".to_string(), host.to_string()));
+        }
+        headers.push(("Host".to_string(), self.upstream.clone()));
+        if let Some(body) = &request.body {
+            headers.push(("Content-Length".to_string(), body.len().to_string()));
+        }
+        headers.push(("Connection".to_string(), "close".to_string()));
+        headers
+    }
+
+    fn read_response(&self, mut stream: &TcpStream, method: &str) -> Result<HttpResponseMessage, ProxyError> {
+        let mut parser: HttpResponseParser = HttpResponseParser::for_method(method, self.limits);
+        let mut buffer: [u8; 4096] = [0; 4096];
+        loop {
+            let size: usize = stream.read(&mut buffer).map_err(ProxyError::from_io)?;
+            let mut status: ParseStatus<HttpResponseMessage> = if size == 0 {
+                parser.feed_eof()
+            } else {
+                parser.feed(&buffer[..size])
+            };
+
+            loop {
+                match status {
+                    ParseStatus::NeedMore => break,
+                    // interim responses are skipped, the final response follows them
+                    ParseStatus::Complete(message)
+                        if message.head.code.is_informational() && message.head.code != 101 =>
+                    {
+                        status = parser.feed(&[]);
+                    },
+                    ParseStatus::Complete(message) => return Ok(message),
+                    ParseStatus::Error(kind) => {
+                        return Err(ProxyError::BadGateway(
+                            format!("invalid upstream response: {}", kind)
+                        ));
+                    }
+                }
+            }
+        }
+    }
+}
+
+enum ProxyError {
+    Timeout,
+    BadGateway(String)
+}
+
+impl ProxyError {
+    fn from_io(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ProxyError::Timeout,
+            _ => ProxyError::BadGateway(format!("upstream I/O error: {}", e))
+        }
+    }
+}
+
+fn connection_tokens(headers: &[(String, String)]) -> Vec<String> {
+    headers.iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("connection"))
+        .flat_map(|(_, value)| value.split(','))
+        .map(|token| token.trim().to_ascii_lowercase())
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+fn is_hop_by_hop(header: &str, connection_tokens: &[String]) -> bool {
+    HOP_BY_HOP_HEADERS.iter().any(|h| header.eq_ignore_ascii_case(h))
+        || connection_tokens.iter().any(|token| header.eq_ignore_ascii_case(token))
+}
+
+/// The rest of `target` after `prefix`, if `prefix` ends at a path segment boundary of `target`,
+/// so that `/legacy` covers `/legacy`, `/legacy/a` and `/legacy?a` but not `/legacyfoo`. A
+/// trailing `/` of `prefix` is ignored, so `/legacy/` covers the same targets.
+pub(crate) fn strip_path_prefix<'a>(target: &'a str, prefix: &str) -> Option<&'a str> {
+    let prefix: &str = prefix.strip_suffix('/').unwrap_or(prefix);
+    let rest: &str = target.strip_prefix(prefix)?;
+    if rest.is_empty() || rest.starts_with(['/', '?']) {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+/// Whether the path of `target` has `.` or `..` segments, also percent-encoded
+fn has_dot_segment(target: &str) -> bool {
+    let path: &str = target.split('?').next().unwrap_or(target);
+    path.split('/').any(|segment| {
+        let segment: String = segment.to_ascii_lowercase().replace("%2e", ".");
+        segment == "." || segment == ".."
+    })
+}
+
+fn gateway_error(code: u16, reason: &str) -> HttpResponse {
+    HttpResponse::builder()
+        .set_code(code)
+        .add_header("Content-Type", "text/plain")
+        .set_payload(reason.to_string())
+        .build()
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    use crate::http_commons::{HttpResponse, header_value};
+    use crate::http_commons::parser::{HttpRequest, HttpRequestParser, ParseStatus};
+    use crate::minhttpd::ReverseProxy;
+
+    fn parse_request(raw: &[u8]) -> HttpRequest {
+        match HttpRequestParser::new().feed(raw) {
+            ParseStatus::Complete(request) => request,
+            status => panic!("{:?}", status)
+        }
+    }
+
+    /// Spawns an upstream answering a single connection with `response`, returning its address and
+    /// a handle resolving to the raw request received
+    fn spawn_upstream(response: &'static [u8], delay: Duration) -> (String, JoinHandle<HttpRequest>) {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: String = listener.local_addr().unwrap().to_string();
+        let handle: JoinHandle<HttpRequest> = thread::spawn(move || {
+            let (mut stream, _): (TcpStream, _) = listener.accept().unwrap();
+            let mut parser: HttpRequestParser = HttpRequestParser::new();
+            let mut buffer: [u8; 1024] = [0; 1024];
+            let request: HttpRequest = loop {
+                let size: usize = stream.read(&mut buffer).unwrap();
+                if let ParseStatus::Complete(request) = parser.feed(&buffer[..size]) {
+                    break request;
+                }
+            };
+            thread::sleep(delay);
+            let _ = stream.write_all(response);
+            request
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn test_rewrite_path() {
+        let proxy: ReverseProxy = ReverseProxy::new("localhost:1").upstream_path("/app/");
+        assert_eq!(proxy.rewrite_path("/legacy/a/b?c=d", "/legacy").unwrap(), "/app/a/b?c=d");
+        assert_eq!(proxy.rewrite_path("/legacy", "/legacy").unwrap(), "/app/");
+        assert_eq!(proxy.rewrite_path("/legacy?x", "/legacy").unwrap(), "/app/?x");
+
+        let proxy: ReverseProxy = ReverseProxy::new("localhost:1");
+        assert_eq!(proxy.rewrite_path("/legacy/a", "/legacy").unwrap(), "/a");
+        assert_eq!(proxy.rewrite_path("/legacy/a", "/legacy/").unwrap(), "/a");
+        assert_eq!(proxy.rewrite_path("/legacyfoo", "/legacy"), None);
+        assert_eq!(proxy.rewrite_path("/other", "/legacy"), None);
+        assert_eq!(proxy.rewrite_path("/legacy", "/legacy/").unwrap(), "/");
+        assert_eq!(proxy.rewrite_path("/legacy?x", "/legacy/").unwrap(), "/?x");
+        assert_eq!(proxy.rewrite_path("/legacyfoo", "/legacy/"), None);
+
+        assert_eq!(proxy.rewrite_path("/legacy/../admin", "/legacy"), None);
+        assert_eq!(proxy.rewrite_path("/legacy/a/./b", "/legacy"), None);
+        assert_eq!(proxy.rewrite_path("/legacy/%2E%2e/admin", "/legacy"), None);
+        assert_eq!(proxy.rewrite_path("/legacy/a/..", "/legacy"), None);
+        assert_eq!(proxy.rewrite_path("/legacy/a..b/.c", "/legacy").unwrap(), "/a..b/.c");
+        assert_eq!(proxy.rewrite_path("/legacy/a?next=/../b", "/legacy").unwrap(), "/a?next=/../b");
+    }
+
+    #[test]
+    fn test_forward() {
+        let (upstream, handle) = spawn_upstream(
+            b"HTTP/1.1 100 Continue\r\n\r\n\
+              HTTP/1.1 201 Created\r\nConnection: close, X-Internal\r\nX-Internal: 1\r\n\
+              Transfer-Encoding: chunked\r\nX-Result: ok\r\n\r\n3\r\nabc\r\n0\r\n\r\n",
+            Duration::ZERO
+        );
+
+        let request: HttpRequest = parse_request(
+            b"PUT /legacy/item?id=1 HTTP/1.1\r\nHost: front.example.com\r\nConnection: keep-alive, \
+              X-Secret\r\nX-Secret: 1\r\nKeep-Alive: timeout=5\r\nX-Forwarded-For: 10.0.0.1\r\n\
+              Content-Length: 5\r\n\r\nhello"
+        );
+        let response: HttpResponse = ReverseProxy::new(upstream.clone())
+            .upstream_path("/app")
+            .forward(&request, "/legacy", "10.0.0.2");
+        assert_eq!(response.code, 201);
+        assert_eq!(response.payload.unwrap(), b"abc");
+        assert_eq!(header_value(&response.headers, "X-Result"), Some("ok"));
+        assert!(!response.headers.iter().any(|(key, _)| {
+            ["connection", "x-internal", "transfer-encoding", "content-length"]
+                .contains(&key.to_ascii_lowercase().as_str())
+        }));
+
+        let forwarded: HttpRequest = handle.join().unwrap();
+        assert_eq!(forwarded.head.method, "PUT");
+        assert_eq!(forwarded.head.uri, "/app/item?id=1");
+        assert_eq!(forwarded.head.header("Host"), Some(upstream.as_str()));
+        assert_eq!(forwarded.head.header("X-Forwarded-For"), Some("10.0.0.1, 10.0.0.2"));
+        assert_eq!(forwarded.head.header("X-Forwarded-Proto"), Some("http"));
+        assert_eq!(forwarded.head.header("X-Forwarded-Host"), Some("front.example.com"));
+        assert_eq!(forwarded.head.header("Connection"), Some("close"));
+        assert!(forwarded.head.header("X-Secret").is_none());
+        assert!(forwarded.head.header("Keep-Alive").is_none());
+        assert_eq!(forwarded.body.unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_forward_errors() {
+        let request: HttpRequest = parse_request(b"GET /legacy HTTP/1.1\r\n\r\n");
+
+        let (upstream, handle) = spawn_upstream(b"HTTP/1.1 200 OK\r\n\r\n", Duration::from_millis(500));
+        let response: HttpResponse = ReverseProxy::new(upstream)
+            .timeout(Duration::from_millis(50))
+            .forward(&request, "/legacy", "10.0.0.1");
+        assert_eq!(response.code, 504);
+        handle.join().unwrap();
+
+        let (upstream, handle) = spawn_upstream(b"SMTP READY\r\n\r\n", Duration::ZERO);
+        let response: HttpResponse = ReverseProxy::new(upstream).forward(&request, "/legacy", "10.0.0.1");
+        assert_eq!(response.code, 502);
+        handle.join().unwrap();
+
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed: String = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let response: HttpResponse = ReverseProxy::new(closed.clone()).forward(&request, "/legacy", "10.0.0.1");
+        assert_eq!(response.code, 502);
+
+        // rejected before connecting to the upstream
+        let request: HttpRequest = parse_request(b"GET /legacy/../admin HTTP/1.1\r\n\r\n");
+        let response: HttpResponse = ReverseProxy::new(closed).forward(&request, "/legacy", "10.0.0.1");
+        assert_eq!(response.code, 404);
+    }
+}
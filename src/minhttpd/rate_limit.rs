@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::http_commons::{HttpHeaders, HttpResponse};
+use crate::minhttpd::{HttpHandler, REMOTE_ADDR_HEADER};
+
+pub type RateLimitKeyFn = Box<dyn Fn(&HttpHeaders) -> String + Send + Sync + 'static>;
+
+/// Count of buckets above which idle buckets get evicted
+const EVICT_THRESHOLD: usize = 4096;
+
+/// Minimum interval between two eviction sweeps, so that the cost of a sweep is amortized over
+/// all requests in the interval
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Buckets untouched for this long are evicted even if they would not be full yet
+const IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant
+}
+
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    last_sweep: Instant
+}
+
+/// Token bucket rate limiter, keyed by remote address by default
+///
+/// Every client owns a bucket holding at most `capacity` tokens, refilled by `refill_per_second`
+/// tokens per second. Each request takes one token, requests finding the bucket empty are
+/// answered with `429 Too Many Requests`.
+///
+/// ```
+/// # use xjbutil::minhttpd::{HttpResponse, MinHttpd, RateLimiter};
+/// let mut min_httpd: MinHttpd = MinHttpd::new();
+/// // bursts of 10 requests, 2 requests per second in the long run
+/// min_httpd.route("/api", RateLimiter::new(10, 2.0).guard(Box::new(|_uri, _headers, _params, _body| {
+///     Ok(HttpResponse::builder().set_payload("pong").build())
+/// })));
+/// ```
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_second: f64,
+    key_fn: Option<RateLimitKeyFn>,
+    buckets: Mutex<Buckets>
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, refill_per_second: f64) -> Self {
+        assert!(capacity > 0, "capacity of rate limiter must be positive");
+        assert!(refill_per_second > 0.0, "refill rate of rate limiter must be positive");
+
+        Self {
+            capacity: capacity as f64,
+            refill_per_second,
+            key_fn: None,
+            buckets: Mutex::new(Buckets { buckets: HashMap::new(), last_sweep: Instant::now() })
+        }
+    }
+
+    /// Uses `key_fn` instead of remote address to tell clients apart
+    pub fn with_key_fn(mut self, key_fn: RateLimitKeyFn) -> Self {
+        self.key_fn = Some(key_fn);
+        self
+    }
+
+    /// Takes a token from the bucket of `key`. If the bucket is empty, returns how long the client
+    /// should wait before retrying.
+    pub fn try_acquire(&self, key: &str) -> Result<(), Duration> {
+        self.try_acquire_at(key, Instant::now())
+    }
+
+    fn try_acquire_at(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let mut guard = self.buckets.lock().unwrap();
+        let Buckets { buckets, last_sweep } = &mut *guard;
+        if buckets.len() >= EVICT_THRESHOLD
+            && now.saturating_duration_since(*last_sweep) >= SWEEP_INTERVAL
+        {
+            buckets.retain(|_, bucket| !self.is_evictable(bucket, now));
+            *last_sweep = now;
+        }
+
+        let bucket: &mut Bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            last_refill: now
+        });
+        self.refill(bucket, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait: f64 = (1.0 - bucket.tokens) / self.refill_per_second;
+            Err(Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX))
+        }
+    }
+
+    /// Wraps `handler`, so that requests exceeding the rate limit are rejected
+    pub fn guard(self, handler: HttpHandler) -> HttpHandler {
+        Box::new(move |uri, headers, params, body| {
+            let key: String = match &self.key_fn {
+                Some(key_fn) => key_fn(&headers),
+                None => headers.get(REMOTE_ADDR_HEADER).cloned().unwrap_or_default()
+            };
+
+            match self.try_acquire(&key) {
+                Ok(()) => handler(uri, headers, params, body),
+                Err(retry_after) => Ok(too_many_requests(retry_after))
+            }
+        })
+    }
+
+    /// Refills `bucket` according to time elapsed
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        let elapsed: Duration = now.saturating_duration_since(bucket.last_refill);
+        bucket.tokens = f64::min(
+            self.capacity,
+            bucket.tokens + elapsed.as_secs_f64() * self.refill_per_second
+        );
+        bucket.last_refill = now;
+    }
+
+    /// Whether `bucket` has been idle for `IDLE_TIMEOUT`, or would be full by now. A full bucket
+    /// is indistinguishable from a newly created one, so evicting it is lossless.
+    fn is_evictable(&self, bucket: &Bucket, now: Instant) -> bool {
+        let idle: Duration = now.saturating_duration_since(bucket.last_refill);
+        idle >= IDLE_TIMEOUT
+            || bucket.tokens + idle.as_secs_f64() * self.refill_per_second >= self.capacity
+    }
+}
+
+fn too_many_requests(retry_after: Duration) -> HttpResponse {
+    let seconds: u64 = u64::max(1, retry_after.as_secs_f64().ceil() as u64);
+    HttpResponse::builder()
+        .set_code(429)
+        .add_header("Content-Type", "text/plain")
+        .add_header("Retry-After", seconds.to_string())
+        .set_payload("Too Many Requests")
+        .build()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    use crate::http_commons::{HttpHeaders, HttpResponse, header_value};
+    use crate::minhttpd::{HttpHandler, REMOTE_ADDR_HEADER, RateLimiter};
+    use crate::minhttpd::rate_limit::{EVICT_THRESHOLD, IDLE_TIMEOUT, SWEEP_INTERVAL};
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter: RateLimiter = RateLimiter::new(2, 0.5);
+        let start: Instant = Instant::now();
+
+        assert!(limiter.try_acquire_at("a", start).is_ok());
+        assert!(limiter.try_acquire_at("a", start).is_ok());
+        assert_eq!(limiter.try_acquire_at("a", start), Err(Duration::from_secs(2)));
+        assert!(limiter.try_acquire_at("b", start).is_ok());
+
+        let later: Instant = start + Duration::from_secs(1);
+        assert_eq!(limiter.try_acquire_at("a", later), Err(Duration::from_secs(1)));
+        let later: Instant = start + Duration::from_secs(2);
+        assert!(limiter.try_acquire_at("a", later).is_ok());
+        assert!(limiter.try_acquire_at("a", later).is_err());
+
+        let much_later: Instant = start + Duration::from_secs(3600);
+        assert!(limiter.try_acquire_at("a", much_later).is_ok());
+        assert!(limiter.try_acquire_at("a", much_later).is_ok());
+        assert!(limiter.try_acquire_at("a", much_later).is_err());
+    }
+
+    #[test]
+    fn test_eviction() {
+        let limiter: RateLimiter = RateLimiter::new(2, 1e-9);
+        let start: Instant = Instant::now();
+        for i in 0..EVICT_THRESHOLD {
+            assert!(limiter.try_acquire_at(&i.to_string(), start).is_ok());
+        }
+
+        // sweeps are rate limited, and buckets neither full nor idle survive them
+        let soon: Instant = start + SWEEP_INTERVAL / 2;
+        assert!(limiter.try_acquire_at("a", soon).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().buckets.len(), EVICT_THRESHOLD + 1);
+        let later: Instant = start + SWEEP_INTERVAL;
+        assert!(limiter.try_acquire_at("b", later).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().buckets.len(), EVICT_THRESHOLD + 2);
+
+        let idle: Instant = later + IDLE_TIMEOUT;
+        assert!(limiter.try_acquire_at("c", idle).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().buckets.len(), 1);
+    }
+
+    #[test]
+    fn test_tiny_refill_rate() {
+        let limiter: RateLimiter = RateLimiter::new(1, f64::MIN_POSITIVE);
+        let start: Instant = Instant::now();
+        assert!(limiter.try_acquire_at("a", start).is_ok());
+        assert_eq!(limiter.try_acquire_at("a", start), Err(Duration::MAX));
+    }
+
+    #[test]
+    fn test_guard() {
+        let call = |handler: &HttpHandler, remote_addr: &str, api_key: &str| -> HttpResponse {
+            let mut headers: HttpHeaders = HashMap::new();
+            headers.insert(REMOTE_ADDR_HEADER.to_string(), remote_addr.to_string());
+            headers.insert("x-api-key".to_string(), api_key.to_string());
+            handler("/".to_string(), headers, HashMap::new(), None).unwrap()
+        };
+
+        let handler: HttpHandler = RateLimiter::new(1, 0.001)
+            .guard(Box::new(|_, _, _, _| Ok(HttpResponse::builder().build())));
+        assert_eq!(call(&handler, "10.0.0.1", "k1").code, 200);
+        assert_eq!(call(&handler, "10.0.0.2", "k1").code, 200);
+        let response: HttpResponse = call(&handler, "10.0.0.1", "k2");
+        assert_eq!(response.code, 429);
+        assert_eq!(header_value(&response.headers, "Retry-After"), Some("1000"));
+
+        let handler: HttpHandler = RateLimiter::new(1, 0.001)
+            .with_key_fn(Box::new(|headers| headers["x-api-key"].clone()))
+            .guard(Box::new(|_, _, _, _| Ok(HttpResponse::builder().build())));
+        assert_eq!(call(&handler, "10.0.0.1", "k1").code, 200);
+        assert_eq!(call(&handler, "10.0.0.2", "k1").code, 429);
+        assert_eq!(call(&handler, "10.0.0.1", "k2").code, 200);
+    }
+}
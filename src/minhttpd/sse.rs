@@ -0,0 +1,250 @@
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::thread;
+use std::time::Duration;
+
+/// A single Server-Sent Events message
+///
+/// ```
+/// # use std::time::Duration;
+/// # use xjbutil::minhttpd::SseEvent;
+/// let event: SseEvent = SseEvent::new("line 1\nline 2")
+///     .event("update")
+///     .id("42")
+///     .retry(Duration::from_secs(3));
+/// assert_eq!(
+///     event.encode(),
+///     "event: update\nid: 42\nretry: 3000\ndata: line 1\ndata: line 2\n\n"
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SseEvent {
+    event: Option<String>,
+    data: String,
+    id: Option<String>,
+    retry: Option<Duration>
+}
+
+impl SseEvent {
+    pub fn new(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the event type, dispatched to `addEventListener(type)` on the client side
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Sets the event ID, sent back by reconnecting clients as `Last-Event-ID`
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Sets the reconnection delay of the client
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Encodes the event into the `text/event-stream` wire format
+    pub fn encode(&self) -> String {
+        let mut ret: String = String::new();
+        if let Some(event) = &self.event {
+            ret.push_str(&format!("event: {}\n", single_line(event)));
+        }
+        if let Some(id) = &self.id {
+            ret.push_str(&format!("id: {}\n", single_line(id).replace('\0', "")));
+        }
+        if let Some(retry) = self.retry {
+            ret.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        for line in split_lines(&self.data) {
+            ret.push_str(&format!("data: {}\n", line));
+        }
+        ret.push('\n');
+        ret
+    }
+}
+
+struct SinkShared {
+    stream: Mutex<TcpStream>,
+    closed: AtomicBool
+}
+
+impl SinkShared {
+    fn write(&self, data: &[u8]) -> io::Result<()> {
+        if self.closed.load(SeqCst) {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "event stream closed by client"));
+        }
+
+        let mut stream = self.stream.lock().unwrap();
+        let result: io::Result<()> = stream.write_all(data).and_then(|_| stream.flush());
+        if result.is_err() {
+            self.closed.store(true, SeqCst);
+        }
+        result
+    }
+}
+
+/// Sending end of a Server-Sent Events stream
+///
+/// The sink watches the connection in background, so `is_closed` turns `true` soon after the client
+/// goes away, even if nothing is being sent. When configured, heartbeat comments are sent
+/// periodically to keep intermediaries from timing out the connection. Dropping the sink closes
+/// the connection.
+pub struct EventSink {
+    shared: Arc<SinkShared>,
+    // dropping the sender wakes up and stops the heartbeat thread
+    _heartbeat_stop: Option<Sender<()>>
+}
+
+impl EventSink {
+    pub(crate) fn new(stream: TcpStream, heartbeat: Option<Duration>) -> io::Result<Self> {
+        let mut watched_stream: TcpStream = stream.try_clone()?;
+        let shared: Arc<SinkShared> = Arc::new(SinkShared {
+            stream: Mutex::new(stream),
+            closed: AtomicBool::new(false)
+        });
+
+        let watcher_shared: Arc<SinkShared> = shared.clone();
+        thread::spawn(move || {
+            // clients never send anything on an event stream, so reading only returns when the
+            // connection gets closed, either by the client or by dropping the sink
+            let mut buffer: [u8; 256] = [0; 256];
+            while let Ok(size) = watched_stream.read(&mut buffer) {
+                if size == 0 {
+                    break;
+                }
+            }
+            watcher_shared.closed.store(true, SeqCst);
+        });
+
+        let heartbeat_stop: Option<Sender<()>> = heartbeat.map(|interval| {
+            let (sender, receiver): (Sender<()>, Receiver<()>) = channel();
+            let heartbeat_shared: Arc<SinkShared> = shared.clone();
+            thread::spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(interval) {
+                    if heartbeat_shared.write(b": heartbeat\n\n").is_err() {
+                        break;
+                    }
+                }
+            });
+            sender
+        });
+
+        Ok(Self {
+            shared,
+            _heartbeat_stop: heartbeat_stop
+        })
+    }
+
+    pub fn send(&self, event: &SseEvent) -> io::Result<()> {
+        self.shared.write(event.encode().as_bytes())
+    }
+
+    /// Sends an unnamed event carrying only `data`
+    pub fn send_data(&self, data: &str) -> io::Result<()> {
+        self.send(&SseEvent::new(data))
+    }
+
+    /// Sends a comment, which is ignored by clients
+    pub fn comment(&self, comment: &str) -> io::Result<()> {
+        let mut encoded: String = String::new();
+        for line in split_lines(comment) {
+            encoded.push_str(&format!(": {}\n", line));
+        }
+        encoded.push('\n');
+        self.shared.write(encoded.as_bytes())
+    }
+
+    /// Whether the client has disconnected
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(SeqCst)
+    }
+}
+
+impl Drop for EventSink {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, SeqCst);
+        let _ = self.shared.stream.lock().unwrap().shutdown(Shutdown::Both);
+    }
+}
+
+fn single_line(s: &str) -> String {
+    s.replace(['\r', '\n'], "")
+}
+
+/// Splits on `\r\n`, `\r` and `\n`, all of which end a line in an event stream
+fn split_lines(s: &str) -> impl Iterator<Item=&str> {
+    s.split("\r\n").flat_map(|line| line.split(['\r', '\n']))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::minhttpd::{EventSink, SseEvent};
+
+    fn connected_pair() -> (TcpStream, TcpStream) {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client: TcpStream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (server, client)
+    }
+
+    #[test]
+    fn test_encode_event() {
+        assert_eq!(SseEvent::new("").encode(), "data: \n\n");
+        assert_eq!(SseEvent::new("a\r\nb").encode(), "data: a\ndata: b\n\n");
+        assert_eq!(
+            SseEvent::new("x\revent: admin\n\ry").encode(),
+            "data: x\ndata: event: admin\ndata: \ndata: y\n\n"
+        );
+        assert_eq!(
+            SseEvent::new("x").event("evil\nretry: 0").id("1\r\n").encode(),
+            "event: evilretry: 0\nid: 1\ndata: x\n\n"
+        );
+    }
+
+    #[test]
+    fn test_event_sink() {
+        let (server, mut client) = connected_pair();
+        let sink: EventSink = EventSink::new(server, Some(Duration::from_millis(50))).unwrap();
+        sink.send(&SseEvent::new("hello").event("greeting")).unwrap();
+        sink.comment("note").unwrap();
+        thread::sleep(Duration::from_millis(120));
+        drop(sink);
+
+        let mut received: String = String::new();
+        client.read_to_string(&mut received).unwrap();
+        assert!(received.starts_with("event: greeting\ndata: hello\n\n: note\n\n: heartbeat\n\n"));
+    }
+
+    #[test]
+    fn test_event_sink_disconnect() {
+        let (server, client) = connected_pair();
+        let sink: EventSink = EventSink::new(server, None).unwrap();
+        assert!(!sink.is_closed());
+        drop(client);
+
+        let start: Instant = Instant::now();
+        while !sink.is_closed() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(sink.send_data("lost").is_err());
+    }
+}
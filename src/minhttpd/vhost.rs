@@ -0,0 +1,295 @@
+use crate::http_commons::{HttpResponse, HttpUri};
+use crate::minhttpd::{
+    HttpHandler,
+    HttpHandlerFn,
+    ResourceValidator,
+    ReverseProxy,
+    Route,
+    SseHandler,
+    UploadCheck
+};
+use crate::minhttpd::proxy::strip_path_prefix;
+
+/// Values captured by `{name}` segments of a route, in order
+pub(crate) type RouteCaptures = Vec<(String, String)>;
+
+/// An independent route table, selected by the `Host` header of requests
+///
+/// ```
+/// # use xjbutil::minhttpd::{HttpResponse, MinHttpd};
+/// let mut min_httpd: MinHttpd = MinHttpd::new();
+/// min_httpd.virtual_host("wiki.example.com").route_static("/", "text/plain", "wiki".to_string());
+/// // any subdomain of `example.com`, excluding `example.com` itself
+/// min_httpd.virtual_host("*.example.com").route_static("/", "text/plain", "other".to_string());
+/// // requests to unknown hosts go to routes registered on `MinHttpd` directly
+/// min_httpd.route_static("/", "text/plain", "default".to_string());
+/// ```
+pub struct VirtualHost {
+    pub(crate) handlers: Vec<(HttpUri, Route)>,
+    upload_checks: Vec<(HttpUri, UploadCheck)>,
+    validators: Vec<(HttpUri, ResourceValidator)>
+}
+
+impl VirtualHost {
+    pub fn new() -> Self {
+        Self {
+            handlers: Vec::new(),
+            upload_checks: Vec::new(),
+            validators: Vec::new()
+        }
+    }
+
+    pub fn route(&mut self, uri: &str, handler: HttpHandler) {
+        self.handlers.push((uri.to_string(), Route::Handler(handler)));
+    }
+
+    pub fn route_fn(&mut self, uri: &str, handler_fn: HttpHandlerFn) {
+        self.handlers.push((uri.to_string(), Route::Handler(Box::new(handler_fn))));
+    }
+
+    pub fn route_static(&mut self, uri: &str, content_type: &str, content: String) {
+        let content_type: String = content_type.to_string();
+        self.handlers.push((
+            uri.to_string(),
+            Route::Handler(Box::new(move |_, _, _, _| {
+                Ok(HttpResponse::new(
+                    200,
+                    vec![("Content-Type".to_string(), content_type.clone())],
+                    Some(content.clone()))
+                )
+            }))
+        ));
+    }
+
+    /// Registers a Server-Sent Events route, which holds the connection open and streams
+    /// `text/event-stream` to the client
+    pub fn route_sse(&mut self, uri: &str, handler: SseHandler) {
+        self.handlers.push((uri.to_string(), Route::EventStream(handler)));
+    }
+
+    /// Forwards all requests under `uri`, regardless of their method, to an upstream server
+    pub fn route_proxy(&mut self, uri: &str, proxy: ReverseProxy) {
+        self.handlers.push((uri.to_string(), Route::Proxy(proxy)));
+    }
+
+    /// Registers a check run on requests under `uri` as soon as their head arrives, before the
+    /// body is read. Clients sending `Expect: 100-continue` get the rejection instead of
+    /// `100 Continue`, and never transmit the body.
+    pub fn upload_check(&mut self, uri: &str, check: UploadCheck) {
+        self.upload_checks.push((uri.to_string(), check));
+    }
+
+    /// Registers the validators of resources under `uri`. Conditional requests with unsafe methods
+    /// are checked against them before reaching the handler, and rejected with `412` if their
+    /// preconditions fail. The validator returns `None` for resources that do not exist, so that
+    /// `If-None-Match: *` can guard their creation. Without a validator, such requests are checked
+    /// against an existing resource with neither `ETag` nor `Last-Modified`.
+    pub fn validator(&mut self, uri: &str, validator: ResourceValidator) {
+        self.validators.push((uri.to_string(), validator));
+    }
+
+    /// Finds the first route matching `uri`, along with values captured by `{name}` segments
+    pub(crate) fn find_route(
+        &self,
+        uri: &str
+    ) -> Option<(&(HttpUri, Route), RouteCaptures)> {
+        self.handlers.iter().find_map(|h| match &h.1 {
+            // proxies strip their prefix, which must therefore end at a segment boundary
+            Route::Proxy(_) => strip_path_prefix(uri, &h.0).map(|_| (h, Vec::new())),
+            _ => match_route(&h.0, uri).map(|captures| (h, captures))
+        })
+    }
+
+    /// Finds the first upload check matching `uri`
+    pub(crate) fn find_upload_check(&self, uri: &str) -> Option<&UploadCheck> {
+        self.upload_checks.iter()
+            .find(|(pattern, _)| match_route(pattern, uri).is_some())
+            .map(|(_, check)| check)
+    }
+
+    /// Finds the first validator matching `uri`
+    pub(crate) fn find_validator(&self, uri: &str) -> Option<&ResourceValidator> {
+        self.validators.iter()
+            .find(|(pattern, _)| match_route(pattern, uri).is_some())
+            .map(|(_, validator)| validator)
+    }
+}
+
+impl Default for VirtualHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Virtual hosts of a server, along with the default host
+pub(crate) struct HostTable {
+    pub(crate) default_host: VirtualHost,
+    hosts: Vec<(String, VirtualHost)>
+}
+
+impl HostTable {
+    pub(crate) fn new() -> Self {
+        Self {
+            default_host: VirtualHost::new(),
+            hosts: Vec::new()
+        }
+    }
+
+    pub(crate) fn get_or_insert(&mut self, pattern: &str) -> &mut VirtualHost {
+        let pattern: String = pattern.to_ascii_lowercase();
+        let index: usize = match self.hosts.iter().position(|(p, _)| *p == pattern) {
+            Some(index) => index,
+            None => {
+                self.hosts.push((pattern, VirtualHost::new()));
+                self.hosts.len() - 1
+            }
+        };
+        &mut self.hosts[index].1
+    }
+
+    /// Selects the virtual host for the `Host` header `host`. Exact names win over wildcards, and
+    /// longer wildcards win over shorter ones. Returns the pattern of the selected host as well,
+    /// `None` for the default host.
+    pub(crate) fn select(&self, host: Option<&str>) -> (Option<&str>, &VirtualHost) {
+        let host: String = match host {
+            Some(host) => strip_port(host.trim()).trim_end_matches('.').to_ascii_lowercase(),
+            None => return (None, &self.default_host)
+        };
+
+        if let Some((pattern, vhost)) = self.hosts.iter().find(|(pattern, _)| *pattern == host) {
+            return (Some(pattern), vhost);
+        }
+
+        self.hosts.iter()
+            .filter(|(pattern, _)| match pattern.strip_prefix('*') {
+                Some(suffix) => suffix.starts_with('.')
+                    && host.len() > suffix.len()
+                    && host.ends_with(suffix),
+                None => false
+            })
+            .max_by_key(|(pattern, _)| pattern.len())
+            .map(|(pattern, vhost)| (Some(pattern.as_str()), vhost))
+            .unwrap_or((None, &self.default_host))
+    }
+}
+
+/// `pattern` without trailing slash, like the request paths it gets matched against, so that `/`
+/// matches the root path
+fn trim_pattern(pattern: &str) -> &str {
+    pattern.strip_suffix('/').unwrap_or(pattern)
+}
+
+/// Matches `uri` against route `pattern`. Plain patterns match by prefix, while patterns with
+/// `{name}` segments match segment by segment, each capture matching exactly one non-empty segment.
+fn match_route(pattern: &str, uri: &str) -> Option<RouteCaptures> {
+    let pattern: &str = trim_pattern(pattern);
+    if !pattern.contains('{') {
+        return if uri.starts_with(pattern) { Some(Vec::new()) } else { None };
+    }
+
+    let mut captures: RouteCaptures = Vec::new();
+    let mut uri_segments = uri.split('/');
+    for pattern_segment in pattern.split('/') {
+        let uri_segment: &str = uri_segments.next()?;
+        match pattern_segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+            Some(name) if !uri_segment.is_empty() => {
+                captures.push((name.to_string(), uri_segment.to_string()));
+            },
+            Some(_) => return None,
+            None if pattern_segment == uri_segment => {},
+            None => return None
+        }
+    }
+    Some(captures)
+}
+
+fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        // IPv6 literal, like `[::1]:8080`
+        match host.find(']') {
+            Some(end) => &host[..=end],
+            None => host
+        }
+    } else {
+        match host.rfind(':') {
+            Some(colon) => &host[..colon],
+            None => host
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::minhttpd::ReverseProxy;
+    use crate::minhttpd::vhost::{HostTable, VirtualHost, match_route};
+
+    #[test]
+    fn test_select_host() {
+        let mut table: HostTable = HostTable::new();
+        table.get_or_insert("Example.com").route_static("/a", "text/plain", "".to_string());
+        table.get_or_insert("*.example.com");
+        table.get_or_insert("*.api.example.com");
+        table.get_or_insert("[::1]");
+        assert_eq!(table.get_or_insert("example.com").handlers.len(), 1);
+
+        assert_eq!(table.select(None).0, None);
+        assert_eq!(table.select(Some("example.com")).0, Some("example.com"));
+        assert_eq!(table.select(Some("EXAMPLE.com:8080")).0, Some("example.com"));
+        assert_eq!(table.select(Some("example.com.")).0, Some("example.com"));
+        assert_eq!(table.select(Some("www.example.com")).0, Some("*.example.com"));
+        assert_eq!(table.select(Some("a.b.example.com")).0, Some("*.example.com"));
+        assert_eq!(table.select(Some("v1.api.example.com")).0, Some("*.api.example.com"));
+        assert_eq!(table.select(Some("api.example.com")).0, Some("*.example.com"));
+        assert_eq!(table.select(Some("badexample.com")).0, None);
+        assert_eq!(table.select(Some("[::1]:3080")).0, Some("[::1]"));
+        assert_eq!(table.select(Some("localhost")).0, None);
+
+        assert!(table.select(Some("example.com")).1.find_route("/a/b").is_some());
+        assert!(table.select(Some("example.com")).1.find_route("/b").is_none());
+    }
+
+    #[test]
+    fn test_find_proxy_route() {
+        let mut vhost: VirtualHost = VirtualHost::new();
+        vhost.route_proxy("/legacy", ReverseProxy::new("localhost:1"));
+        vhost.route_static("/legacyfoo", "text/plain", "".to_string());
+
+        assert_eq!(vhost.find_route("/legacy").unwrap().0.0, "/legacy");
+        assert_eq!(vhost.find_route("/legacy/a").unwrap().0.0, "/legacy");
+        assert_eq!(vhost.find_route("/legacyfoo").unwrap().0.0, "/legacyfoo");
+        assert!(vhost.find_route("/legacyfo").is_none());
+
+        let mut vhost: VirtualHost = VirtualHost::new();
+        vhost.route_proxy("/legacy/", ReverseProxy::new("localhost:1"));
+        assert_eq!(vhost.find_route("/legacy").unwrap().0.0, "/legacy/");
+        assert_eq!(vhost.find_route("/legacy/a").unwrap().0.0, "/legacy/");
+        assert!(vhost.find_route("/legacyfoo").is_none());
+
+        let mut vhost: VirtualHost = VirtualHost::new();
+        vhost.route_proxy("/", ReverseProxy::new("localhost:1"));
+        assert_eq!(vhost.find_route("").unwrap().0.0, "/");
+        assert_eq!(vhost.find_route("/a").unwrap().0.0, "/");
+    }
+
+    #[test]
+    fn test_match_route() {
+        let captures = match_route;
+        let pair = |k: &str, v: &str| (k.to_string(), v.to_string());
+
+        assert_eq!(captures("/hello", "/hello/world"), Some(vec![]));
+        assert_eq!(captures("/hello", "/hell"), None);
+        assert_eq!(captures("/", ""), Some(vec![]));
+        assert_eq!(captures("/", "/hello"), Some(vec![]));
+        assert_eq!(captures("/hello/", "/hello"), Some(vec![]));
+        assert_eq!(captures("/users/{id}", "/users/42"), Some(vec![pair("id", "42")]));
+        assert_eq!(captures("/users/{id}", "/users/42/posts"), Some(vec![pair("id", "42")]));
+        assert_eq!(captures("/users/{id}", "/users"), None);
+        assert_eq!(captures("/users/{id}", "/users/"), None);
+        assert_eq!(captures("/users/{id}", "/members/42"), None);
+        assert_eq!(
+            captures("/users/{user}/posts/{post}", "/users/a/posts/b"),
+            Some(vec![pair("user", "a"), pair("post", "b")])
+        );
+        assert_eq!(captures("/users/{user}/posts/{post}", "/users/a/comments/b"), None);
+    }
+}
@@ -1,16 +1,53 @@
 use std::borrow::Borrow;
 use std::collections::{BTreeMap, HashMap};
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
+use std::time::SystemTime;
+
+#[cfg(feature = "value-serde")]
+mod de;
+mod binary;
+mod cbor;
+mod convert;
+mod json;
+mod map;
+mod msgpack;
+mod patch;
+mod path;
+mod query;
+mod schema;
+#[cfg(feature = "value-serde")]
+mod ser;
+mod timestamp;
+
+#[cfg(feature = "value-serde")]
+pub use de::{from_value, SerdeError};
+
+pub use binary::{BinaryError, BinaryErrorKind, BinaryReadOptions, BinaryWriteOptions};
+pub use convert::{FromValue, ToValue, ValueError, ValueErrorKind};
+#[cfg(feature = "value-derive")]
+pub use xjbutil_derive::{FromValue, ToValue};
+pub use json::{JsonError, JsonErrorKind, JsonParseOptions, JsonWriteOptions, NonFinitePolicy};
+pub use map::ValueMap;
+pub use patch::{PatchError, PatchErrorKind, PatchOperation};
+pub use query::{Query, QueryError, QueryErrorKind, QueryMatch};
+pub use schema::{Schema, SchemaType, SchemaViolation, ViolationKind};
+#[cfg(feature = "value-serde")]
+pub use ser::{to_value, SerializeArray, SerializeObject, ValueSerializer};
+pub use timestamp::Timestamp;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     Nil,
     Bool(bool),
     Int(i64),
+    /// Integers above `i64::MAX`. Conversions and parsers produce `Int` for anything smaller.
+    UInt(u64),
     Float(f64),
     String(String),
+    Bytes(Vec<u8>),
+    Timestamp(Timestamp),
     Array(Vec<Value>),
-    Object(HashMap<String, Value>),
+    Object(ValueMap),
 }
 
 impl Value {
@@ -38,6 +75,14 @@ impl Value {
         }
     }
 
+    pub fn is_uint(&self) -> bool {
+        if let Value::UInt(_) = self {
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn is_float(&self) -> bool {
         if let Value::Float(_) = self {
             true
@@ -54,6 +99,22 @@ impl Value {
         }
     }
 
+    pub fn is_bytes(&self) -> bool {
+        if let Value::Bytes(_) = self {
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_timestamp(&self) -> bool {
+        if let Value::Timestamp(_) = self {
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn is_array(&self) -> bool {
         if let Value::Array(_) = self {
             true
@@ -83,6 +144,15 @@ impl From<i64> for Value {
     }
 }
 
+impl From<u64> for Value {
+    fn from(u: u64) -> Self {
+        match i64::try_from(u) {
+            Ok(i) => Value::Int(i),
+            Err(_) => Value::UInt(u)
+        }
+    }
+}
+
 impl From<f64> for Value {
     fn from(f: f64) -> Self {
         Value::Float(f)
@@ -101,6 +171,26 @@ impl From<String> for Value {
     }
 }
 
+/// Gives `Value::Bytes`, unlike `ToValue` which converts a `Vec<u8>` like any other sequence, to a
+/// `Value::Array` of integers
+impl From<Vec<u8>> for Value {
+    fn from(b: Vec<u8>) -> Self {
+        Value::Bytes(b)
+    }
+}
+
+impl From<Timestamp> for Value {
+    fn from(t: Timestamp) -> Self {
+        Value::Timestamp(t)
+    }
+}
+
+impl From<SystemTime> for Value {
+    fn from(t: SystemTime) -> Self {
+        Value::Timestamp(t.into())
+    }
+}
+
 impl<T> From<Vec<T>> for Value
     where T: Into<Value>
 {
@@ -125,6 +215,12 @@ impl<T> From<&[T]> for Value
     }
 }
 
+impl From<ValueMap> for Value {
+    fn from(m: ValueMap) -> Self {
+        Value::Object(m)
+    }
+}
+
 impl<S, T> From<HashMap<S, T>> for Value
     where S: Into<String>,
           T: Into<Value>
@@ -225,6 +321,18 @@ impl TryInto<i64> for Value {
     }
 }
 
+impl TryInto<u64> for Value {
+    type Error = String;
+
+    fn try_into(self) -> Result<u64, Self::Error> {
+        match self {
+            Value::Int(i) if i >= 0 => Ok(i as u64),
+            Value::UInt(u) => Ok(u),
+            _ => Err(format!("{:?} is not an unsigned int", self))
+        }
+    }
+}
+
 impl TryInto<f64> for Value {
     type Error = String;
 
@@ -261,6 +369,54 @@ impl<'a> TryInto<&'a str> for &'a Value {
     }
 }
 
+impl TryInto<Vec<u8>> for Value {
+    type Error = String;
+
+    fn try_into(self) -> Result<Vec<u8>, Self::Error> {
+        if let Value::Bytes(b) = self {
+            Ok(b)
+        } else {
+            Err(format!("{:?} is not bytes", self))
+        }
+    }
+}
+
+impl<'a> TryInto<&'a [u8]> for &'a Value {
+    type Error = String;
+
+    fn try_into(self) -> Result<&'a [u8], Self::Error> {
+        if let Value::Bytes(b) = self {
+            Ok(b)
+        } else {
+            Err(format!("{:?} is not bytes", self))
+        }
+    }
+}
+
+impl TryInto<Timestamp> for Value {
+    type Error = String;
+
+    fn try_into(self) -> Result<Timestamp, Self::Error> {
+        if let Value::Timestamp(t) = self {
+            Ok(t)
+        } else {
+            Err(format!("{:?} is not a timestamp", self))
+        }
+    }
+}
+
+impl TryInto<SystemTime> for Value {
+    type Error = String;
+
+    fn try_into(self) -> Result<SystemTime, Self::Error> {
+        if let Value::Timestamp(t) = self {
+            SystemTime::try_from(t).map_err(|_| format!("{} is out of range for SystemTime", t))
+        } else {
+            Err(format!("{:?} is not a timestamp", self))
+        }
+    }
+}
+
 impl TryInto<Vec<Value>> for Value {
     type Error = String;
 
@@ -297,22 +453,34 @@ impl<'a> TryInto<&'a mut [Value]> for &'a mut Value {
     }
 }
 
+impl TryInto<ValueMap> for Value {
+    type Error = String;
+
+    fn try_into(self) -> Result<ValueMap, Self::Error> {
+        if let Value::Object(o) = self {
+            Ok(o)
+        } else {
+            Err(format!("{:?} is not an object", self))
+        }
+    }
+}
+
 impl TryInto<HashMap<String, Value>> for Value {
     type Error = String;
 
     fn try_into(self) -> Result<HashMap<String, Value>, Self::Error> {
         if let Value::Object(o) = self {
-            Ok(o)
+            Ok(o.into())
         } else {
             Err(format!("{:?} is not an object", self))
         }
     }
 }
 
-impl<'a> TryInto<&'a HashMap<String, Value>> for &'a Value {
+impl<'a> TryInto<&'a ValueMap> for &'a Value {
     type Error = String;
 
-    fn try_into(self) -> Result<&'a HashMap<String, Value>, Self::Error> {
+    fn try_into(self) -> Result<&'a ValueMap, Self::Error> {
         if let Value::Object(o) = self {
             Ok(o)
         } else {
@@ -321,10 +489,10 @@ impl<'a> TryInto<&'a HashMap<String, Value>> for &'a Value {
     }
 }
 
-impl<'a> TryInto<&'a mut HashMap<String, Value>> for &'a mut Value {
+impl<'a> TryInto<&'a mut ValueMap> for &'a mut Value {
     type Error = String;
 
-    fn try_into(self) -> Result<&'a mut HashMap<String, Value>, Self::Error> {
+    fn try_into(self) -> Result<&'a mut ValueMap, Self::Error> {
         if let Value::Object(o) = self {
             Ok(o)
         } else {
@@ -337,6 +505,8 @@ impl<'a> TryInto<&'a mut HashMap<String, Value>> for &'a mut Value {
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 #[cfg(feature = "value-serde")]
 use serde::de::{MapAccess, SeqAccess};
+#[cfg(feature = "value-serde")]
+use serde::ser::SerializeMap;
 
 #[cfg(feature = "value-serde")]
 impl Serialize for Value {
@@ -347,10 +517,19 @@ impl Serialize for Value {
             Value::Nil => serializer.serialize_unit(),
             Value::Bool(b) => serializer.serialize_bool(*b),
             Value::Int(i) => serializer.serialize_i64(*i),
+            Value::UInt(u) => serializer.serialize_u64(*u),
             Value::Float(n) => serializer.serialize_f64(*n),
             Value::String(s) => serializer.serialize_str(&s),
+            Value::Bytes(b) => serializer.serialize_bytes(b),
+            Value::Timestamp(t) => serializer.serialize_str(&t.to_rfc3339()),
             Value::Array(a) => a.serialize(serializer),
-            Value::Object(o) => o.serialize(serializer),
+            Value::Object(o) => {
+                let mut map = serializer.serialize_map(Some(o.len()))?;
+                for (key, value) in o {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            },
         }
     }
 }
@@ -387,7 +566,7 @@ impl<'de> Deserialize<'de> for Value {
             fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
                 where E: Error
             {
-                Ok(Value::Int(value as i64))
+                Ok(Value::from(value))
             }
 
             fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E>
@@ -408,6 +587,18 @@ impl<'de> Deserialize<'de> for Value {
                 Ok(Value::String(value))
             }
 
+            fn visit_bytes<E>(self, value: &[u8]) -> Result<Self::Value, E>
+                where E: Error
+            {
+                Ok(Value::Bytes(value.to_vec()))
+            }
+
+            fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<Self::Value, E>
+                where E: Error
+            {
+                Ok(Value::Bytes(value))
+            }
+
             fn visit_unit<E>(self) -> Result<Self::Value, E>
                 where E: Error
             {
@@ -427,7 +618,7 @@ impl<'de> Deserialize<'de> for Value {
             fn visit_map<A>(self, mut map_access: A) -> Result<Self::Value, A::Error>
                 where A: MapAccess<'de>
             {
-                let mut map = HashMap::new();
+                let mut map = ValueMap::with_capacity(map_access.size_hint().unwrap_or(0));
                 while let Some(value) = map_access.next_entry()? {
                     map.insert(value.0, value.1);
                 }
@@ -438,3 +629,54 @@ impl<'de> Deserialize<'de> for Value {
         deserializer.deserialize_any(ValueVisitor)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryInto;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::value::{Timestamp, Value};
+    #[cfg(feature = "value-serde")] use crate::value::{from_value, to_value};
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(Value::from(42u64), Value::Int(42));
+        assert_eq!(Value::from(u64::MAX), Value::UInt(u64::MAX));
+        assert_eq!(TryInto::<u64>::try_into(Value::Int(42)), Ok(42));
+        assert_eq!(TryInto::<u64>::try_into(Value::UInt(u64::MAX)), Ok(u64::MAX));
+        assert!(TryInto::<u64>::try_into(Value::Int(-1)).is_err());
+        assert!(TryInto::<i64>::try_into(Value::UInt(u64::MAX)).is_err());
+
+        let bytes: Value = Value::from(vec![1u8, 2, 3]);
+        assert!(bytes.is_bytes());
+        assert_eq!(TryInto::<&[u8]>::try_into(&bytes), Ok(&[1u8, 2, 3][..]));
+        assert_eq!(TryInto::<Vec<u8>>::try_into(bytes), Ok(vec![1u8, 2, 3]));
+
+        let time: SystemTime = UNIX_EPOCH + Duration::from_millis(1500);
+        let value: Value = Value::from(time);
+        assert_eq!(value, Value::Timestamp(Timestamp::new(1, 500_000_000)));
+        assert_eq!(TryInto::<SystemTime>::try_into(value), Ok(time));
+    }
+
+    #[cfg(feature = "value-serde")]
+    #[test]
+    fn test_serde_lossless() {
+        let value: Value = Value::from(vec![Value::UInt(u64::MAX), Value::Int(i64::MAX)]);
+        assert_eq!(to_value(&value), Ok(value.clone()));
+        assert_eq!(from_value::<Value>(value.clone()), Ok(value));
+
+        let value: Value = Value::from(vec![
+            Value::Bytes(vec![1, 2]),
+            Value::Timestamp(Timestamp::new(0, 0))
+        ]);
+        assert_eq!(
+            to_value(&value),
+            Ok(Value::from(vec![Value::Bytes(vec![1, 2]), Value::from("1970-01-01T00:00:00Z")]))
+        );
+
+        let bytes: Value = serde::de::Deserialize::deserialize(
+            serde::de::value::BytesDeserializer::<serde::de::value::Error>::new(b"\xff\x00")
+        ).unwrap();
+        assert_eq!(bytes, Value::Bytes(vec![0xff, 0x00]));
+    }
+}
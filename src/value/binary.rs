@@ -0,0 +1,229 @@
+//! Error and option types shared by the MessagePack and CBOR codecs, and the input they read from
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read};
+
+use crate::value::{Value, ValueMap};
+
+/// Reasons why an input cannot be decoded
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BinaryErrorKind {
+    /// Reading the input failed
+    Io(io::ErrorKind),
+    /// The input ended before the value was complete
+    UnexpectedEof,
+    /// A type byte which is reserved, or not allowed here
+    UnexpectedByte(u8),
+    /// A string is not valid UTF-8
+    InvalidUtf8,
+    /// A map key is not a string
+    NonStringKey,
+    /// An integer is too small for `i64`
+    NumberOutOfRange,
+    /// A timestamp has out of range nanoseconds, or a malformed or out of range body
+    InvalidTimestamp,
+    /// A MessagePack extension type other than timestamps
+    UnsupportedExtension(i8),
+    /// The value is larger than `BinaryReadOptions::max_size`
+    SizeLimitExceeded,
+    /// Nesting of arrays and maps, and of tags in CBOR, exceeds `BinaryReadOptions::max_depth`
+    DepthLimitExceeded,
+    /// Bytes after the value
+    TrailingBytes
+}
+
+impl Display for BinaryErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BinaryErrorKind::Io(kind) => write!(f, "I/O error: {}", kind),
+            BinaryErrorKind::UnexpectedEof => write!(f, "unexpected end of input"),
+            BinaryErrorKind::UnexpectedByte(byte) => write!(f, "unexpected byte 0x{:02x}", byte),
+            BinaryErrorKind::InvalidUtf8 => write!(f, "invalid UTF-8 in string"),
+            BinaryErrorKind::NonStringKey => write!(f, "map key is not a string"),
+            BinaryErrorKind::NumberOutOfRange => write!(f, "number out of range"),
+            BinaryErrorKind::InvalidTimestamp => write!(f, "invalid timestamp"),
+            BinaryErrorKind::UnsupportedExtension(ty) =>
+                write!(f, "unsupported extension type {}", ty),
+            BinaryErrorKind::SizeLimitExceeded => write!(f, "value too large"),
+            BinaryErrorKind::DepthLimitExceeded => write!(f, "nesting too deep"),
+            BinaryErrorKind::TrailingBytes => write!(f, "trailing bytes")
+        }
+    }
+}
+
+/// Error of decoding MessagePack or CBOR, with the offset of the byte at which it was detected
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BinaryError {
+    pub kind: BinaryErrorKind,
+    pub offset: u64
+}
+
+impl Display for BinaryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.kind, self.offset)
+    }
+}
+
+impl Error for BinaryError {}
+
+/// Options of decoding MessagePack and CBOR
+#[derive(Clone, Copy, Debug)]
+pub struct BinaryReadOptions {
+    /// Maximum nesting depth of arrays, maps and tags
+    pub max_depth: usize,
+    /// Maximum number of bytes read for a value. Length prefixes are checked against the bytes
+    /// left before allocating, so hostile inputs cannot exhaust memory.
+    pub max_size: usize
+}
+
+impl Default for BinaryReadOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 128,
+            max_size: 64 * 1024 * 1024
+        }
+    }
+}
+
+/// Options of encoding MessagePack and CBOR
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BinaryWriteOptions {
+    /// Produce the same bytes for equal values, for hashing and signing: floats use the smallest
+    /// lossless width, NaNs a single bit pattern, and map keys are sorted shorter first, then
+    /// bytewise, which is the bytewise order of the encoded keys that RFC 8949 deterministic
+    /// encoding requires. Integers and lengths always use the smallest encoding.
+    pub canonical: bool
+}
+
+/// Most elements reserved up front for an array or map, which grows past this as elements arrive,
+/// so that a length prefix alone cannot make the decoder allocate much more than the input size
+pub(crate) const MAX_PREALLOCATED: usize = 4096;
+
+/// Members of an object in the order they are written
+pub(crate) fn members<'a>(
+    object: &'a ValueMap,
+    options: &BinaryWriteOptions
+) -> Vec<(&'a String, &'a Value)> {
+    let mut members: Vec<(&String, &Value)> = object.iter().collect();
+    if options.canonical {
+        members.sort_by(|(lhs, _), (rhs, _)| {
+            lhs.len().cmp(&rhs.len()).then_with(|| lhs.as_bytes().cmp(rhs.as_bytes()))
+        });
+    }
+    members
+}
+
+/// Wraps the reader of a decoder, counting bytes read against `BinaryReadOptions::max_size`
+pub(crate) struct Input<'a, R: Read> {
+    reader: R,
+    pub(crate) offset: u64,
+    remaining: usize,
+    options: &'a BinaryReadOptions
+}
+
+impl<'a, R: Read> Input<'a, R> {
+    pub(crate) fn new(reader: R, options: &'a BinaryReadOptions) -> Self {
+        Self { reader, offset: 0, remaining: options.max_size, options }
+    }
+
+    pub(crate) fn error_at(&self, offset: u64, kind: BinaryErrorKind) -> BinaryError {
+        BinaryError { kind, offset }
+    }
+
+    pub(crate) fn error(&self, kind: BinaryErrorKind) -> BinaryError {
+        self.error_at(self.offset, kind)
+    }
+
+    /// Fails if a container starting at `start`, nested in `depth` others, is too deep
+    pub(crate) fn enter(&self, start: u64, depth: usize) -> Result<(), BinaryError> {
+        if depth >= self.options.max_depth {
+            return Err(self.error_at(start, BinaryErrorKind::DepthLimitExceeded));
+        }
+        Ok(())
+    }
+
+    fn reserve(&mut self, len: usize) -> Result<(), BinaryError> {
+        if len > self.remaining {
+            return Err(self.error(BinaryErrorKind::SizeLimitExceeded));
+        }
+        self.remaining -= len;
+        Ok(())
+    }
+
+    /// Fails if `count` items, each at least one byte long, cannot fit into the bytes left
+    pub(crate) fn check_count(&self, count: u64) -> Result<usize, BinaryError> {
+        match usize::try_from(count) {
+            Ok(count) if count <= self.remaining => Ok(count),
+            _ => Err(self.error(BinaryErrorKind::SizeLimitExceeded))
+        }
+    }
+
+    pub(crate) fn read_into(&mut self, buf: &mut [u8]) -> Result<(), BinaryError> {
+        self.reserve(buf.len())?;
+        let mut filled: usize = 0;
+        while filled < buf.len() {
+            match self.reader.read(&mut buf[filled..]) {
+                Ok(0) => {
+                    self.offset += filled as u64;
+                    return Err(self.error(BinaryErrorKind::UnexpectedEof));
+                },
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => {
+                    self.offset += filled as u64;
+                    return Err(self.error(BinaryErrorKind::Io(err.kind())));
+                }
+            }
+        }
+        self.offset += filled as u64;
+        Ok(())
+    }
+
+    pub(crate) fn read_array<const N: usize>(&mut self) -> Result<[u8; N], BinaryError> {
+        let mut buf: [u8; N] = [0; N];
+        self.read_into(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, BinaryError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub(crate) fn read_bytes(&mut self, len: u64) -> Result<Vec<u8>, BinaryError> {
+        let len: usize = self.check_count(len)?;
+        let mut buf: Vec<u8> = vec![0; len];
+        self.read_into(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub(crate) fn read_string(&mut self, len: u64) -> Result<String, BinaryError> {
+        let start: u64 = self.offset;
+        let bytes: Vec<u8> = self.read_bytes(len)?;
+        String::from_utf8(bytes).map_err(|_| self.error_at(start, BinaryErrorKind::InvalidUtf8))
+    }
+
+    /// Fails unless the underlying reader is exhausted
+    pub(crate) fn finish(mut self) -> Result<(), BinaryError> {
+        let mut buf: [u8; 1] = [0];
+        loop {
+            match self.reader.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(_) => return Err(self.error(BinaryErrorKind::TrailingBytes)),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => return Err(self.error(BinaryErrorKind::Io(err.kind())))
+            }
+        }
+    }
+}
+
+/// `value` as `f32`, if that loses nothing
+pub(crate) fn shrink_to_f32(value: f64) -> Option<f32> {
+    let shrunk: f32 = value as f32;
+    if shrunk as f64 == value {
+        Some(shrunk)
+    } else {
+        None
+    }
+}
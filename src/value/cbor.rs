@@ -0,0 +1,500 @@
+//! Dependency-free CBOR (RFC 8949) encoder and decoder for `Value`
+//!
+//! Integers and lengths use the preferred (shortest) serialization. Floats are written as
+//! double precision, or as the shortest lossless one of half, single and double precision when
+//! canonical. `Value::Timestamp` is written as tag 1 over an integer when it has no fraction, and
+//! as tag 0 over an RFC 3339 string otherwise, so nanoseconds are never rounded.
+//!
+//! Decoding accepts indefinite-length strings, arrays and maps, reads `undefined` as `Value::Nil`
+//! and ignores tags other than 0 and 1, returning the tagged value. It fails on map keys which
+//! are not text strings, on negative integers below `i64::MIN` and on simple values other than
+//! booleans, `null` and `undefined`.
+//!
+//! ```
+//! # use xjbutil::value::{BinaryWriteOptions, Value};
+//! let value: Value = Value::from_json(r#"{"b": [1, -1], "a": 1.5}"#).unwrap();
+//! let bytes: Vec<u8> = value.to_cbor_with(&BinaryWriteOptions { canonical: true });
+//! assert_eq!(bytes, [0xa2, 0x61, b'a', 0xf9, 0x3e, 0x00, 0x61, b'b', 0x82, 0x01, 0x20]);
+//! assert_eq!(Value::from_cbor(&bytes).unwrap(), value);
+//! ```
+
+use std::io::{self, Read, Write};
+
+use crate::value::{Timestamp, Value, ValueMap};
+use crate::value::binary::{
+    members,
+    shrink_to_f32,
+    BinaryError,
+    BinaryErrorKind,
+    BinaryReadOptions,
+    BinaryWriteOptions,
+    Input,
+    MAX_PREALLOCATED
+};
+
+const BREAK: u8 = 0xff;
+
+impl Value {
+    /// Encodes to CBOR with default options
+    pub fn to_cbor(&self) -> Vec<u8> {
+        self.to_cbor_with(&BinaryWriteOptions::default())
+    }
+
+    pub fn to_cbor_with(&self, options: &BinaryWriteOptions) -> Vec<u8> {
+        let mut ret: Vec<u8> = Vec::new();
+        write_value(&mut ret, self, options).expect("writing to a Vec cannot fail");
+        ret
+    }
+
+    /// Encodes to CBOR into a writer, which should be buffered
+    pub fn write_cbor(
+        &self,
+        writer: &mut impl Write,
+        options: &BinaryWriteOptions
+    ) -> io::Result<()> {
+        write_value(writer, self, options)
+    }
+
+    /// Decodes a CBOR data item with default options, failing on trailing bytes
+    pub fn from_cbor(input: &[u8]) -> Result<Value, BinaryError> {
+        Self::from_cbor_with(input, &BinaryReadOptions::default())
+    }
+
+    pub fn from_cbor_with(input: &[u8], options: &BinaryReadOptions) -> Result<Value, BinaryError> {
+        let mut input: Input<&[u8]> = Input::new(input, options);
+        let value: Value = read_value(&mut input, 0)?;
+        input.finish()?;
+        Ok(value)
+    }
+
+    /// Decodes one CBOR data item from a reader, which should be buffered. Reads no further than
+    /// the end of the item, so a CBOR sequence can be read by calling this repeatedly.
+    pub fn read_cbor(
+        reader: &mut impl Read,
+        options: &BinaryReadOptions
+    ) -> Result<Value, BinaryError> {
+        read_value(&mut Input::new(reader, options), 0)
+    }
+}
+
+fn write_head(w: &mut impl Write, major: u8, arg: u64) -> io::Result<()> {
+    let major: u8 = major << 5;
+    if arg < 24 {
+        w.write_all(&[major | arg as u8])
+    } else if arg <= u8::MAX as u64 {
+        w.write_all(&[major | 24, arg as u8])
+    } else if arg <= u16::MAX as u64 {
+        w.write_all(&[major | 25])?;
+        w.write_all(&(arg as u16).to_be_bytes())
+    } else if arg <= u32::MAX as u64 {
+        w.write_all(&[major | 26])?;
+        w.write_all(&(arg as u32).to_be_bytes())
+    } else {
+        w.write_all(&[major | 27])?;
+        w.write_all(&arg.to_be_bytes())
+    }
+}
+
+/// `value` as IEEE 754 half precision bits, if that loses nothing. Not for NaNs.
+fn shrink_to_f16(value: f32) -> Option<u16> {
+    let bits: u32 = value.to_bits();
+    let sign: u16 = ((bits >> 16) & 0x8000) as u16;
+    let exponent: i32 = ((bits >> 23) & 0xff) as i32 - 127;
+    let mantissa: u32 = bits & 0x7f_ffff;
+    if value.is_infinite() || value == 0.0 {
+        return Some(sign | if value == 0.0 { 0 } else { 0x7c00 });
+    }
+    if exponent > 15 {
+        None
+    } else if exponent >= -14 {
+        if mantissa & 0x1fff != 0 {
+            return None;
+        }
+        Some(sign | (((exponent + 15) as u16) << 10) | (mantissa >> 13) as u16)
+    } else if exponent >= -24 {
+        let significand: u32 = mantissa | 0x80_0000;
+        let shift: i32 = -(exponent + 1);
+        if significand & ((1 << shift) - 1) != 0 {
+            return None;
+        }
+        Some(sign | (significand >> shift) as u16)
+    } else {
+        None
+    }
+}
+
+fn f16_to_f64(half: u16) -> f64 {
+    let exponent: i32 = ((half >> 10) & 0x1f) as i32;
+    let mantissa: f64 = (half & 0x3ff) as f64;
+    let magnitude: f64 = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15)
+    };
+    if half & 0x8000 != 0 { -magnitude } else { magnitude }
+}
+
+fn write_float(w: &mut impl Write, f: f64, options: &BinaryWriteOptions) -> io::Result<()> {
+    if options.canonical {
+        if f.is_nan() {
+            return w.write_all(&[0xf9, 0x7e, 0x00]);
+        }
+        if let Some(single) = shrink_to_f32(f) {
+            if let Some(half) = shrink_to_f16(single) {
+                w.write_all(&[0xf9])?;
+                return w.write_all(&half.to_be_bytes());
+            }
+            w.write_all(&[0xfa])?;
+            return w.write_all(&single.to_be_bytes());
+        }
+    }
+    w.write_all(&[0xfb])?;
+    w.write_all(&f.to_be_bytes())
+}
+
+fn write_str(w: &mut impl Write, s: &str) -> io::Result<()> {
+    write_head(w, 3, s.len() as u64)?;
+    w.write_all(s.as_bytes())
+}
+
+fn write_value(w: &mut impl Write, value: &Value, options: &BinaryWriteOptions) -> io::Result<()> {
+    match value {
+        Value::Nil => w.write_all(&[0xf6]),
+        Value::Bool(b) => w.write_all(&[if *b { 0xf5 } else { 0xf4 }]),
+        Value::Int(i) if *i >= 0 => write_head(w, 0, *i as u64),
+        Value::Int(i) => write_head(w, 1, !*i as u64),
+        Value::UInt(u) => write_head(w, 0, *u),
+        Value::Float(f) => write_float(w, *f, options),
+        Value::String(s) => write_str(w, s),
+        Value::Bytes(bytes) => {
+            write_head(w, 2, bytes.len() as u64)?;
+            w.write_all(bytes)
+        },
+        Value::Timestamp(timestamp) if timestamp.nanos() == 0 => {
+            write_head(w, 6, 1)?;
+            write_value(w, &Value::Int(timestamp.secs()), options)
+        },
+        Value::Timestamp(timestamp) => {
+            write_head(w, 6, 0)?;
+            write_str(w, &timestamp.to_rfc3339())
+        },
+        Value::Array(array) => {
+            write_head(w, 4, array.len() as u64)?;
+            for element in array {
+                write_value(w, element, options)?;
+            }
+            Ok(())
+        },
+        Value::Object(object) => {
+            write_head(w, 5, object.len() as u64)?;
+            for (key, value) in members(object, options) {
+                write_str(w, key)?;
+                write_value(w, value, options)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Head of a data item: its offset, initial byte and argument, `None` for indefinite lengths
+struct Head {
+    start: u64,
+    initial: u8,
+    arg: Option<u64>
+}
+
+impl Head {
+    fn major(&self) -> u8 {
+        self.initial >> 5
+    }
+}
+
+fn read_head<R: Read>(input: &mut Input<R>) -> Result<Head, BinaryError> {
+    let start: u64 = input.offset;
+    let initial: u8 = input.read_u8()?;
+    let arg: Option<u64> = match initial & 0x1f {
+        info @ 0..=23 => Some(info as u64),
+        24 => Some(input.read_u8()? as u64),
+        25 => Some(u16::from_be_bytes(input.read_array()?) as u64),
+        26 => Some(u32::from_be_bytes(input.read_array()?) as u64),
+        27 => Some(u64::from_be_bytes(input.read_array()?)),
+        31 => None,
+        _ => return Err(input.error_at(start, BinaryErrorKind::UnexpectedByte(initial)))
+    };
+    Ok(Head { start, initial, arg })
+}
+
+/// Reads the bytes of a byte or text string, concatenating the chunks of indefinite-length ones
+fn read_chunks<R: Read>(input: &mut Input<R>, head: &Head) -> Result<Vec<u8>, BinaryError> {
+    if let Some(len) = head.arg {
+        return read_chunk(input, head.major(), len);
+    }
+    let mut ret: Vec<u8> = Vec::new();
+    loop {
+        let chunk: Head = read_head(input)?;
+        match chunk.arg {
+            _ if chunk.initial == BREAK => return Ok(ret),
+            Some(len) if chunk.major() == head.major() => {
+                ret.extend(read_chunk(input, head.major(), len)?)
+            },
+            _ => {
+                let kind: BinaryErrorKind = BinaryErrorKind::UnexpectedByte(chunk.initial);
+                return Err(input.error_at(chunk.start, kind));
+            }
+        }
+    }
+}
+
+fn read_chunk<R: Read>(input: &mut Input<R>, major: u8, len: u64) -> Result<Vec<u8>, BinaryError> {
+    if major == 3 {
+        input.read_string(len).map(String::into_bytes)
+    } else {
+        input.read_bytes(len)
+    }
+}
+
+fn read_timestamp<R: Read>(
+    input: &mut Input<R>,
+    head: &Head,
+    depth: usize
+) -> Result<Value, BinaryError> {
+    let tag: Option<u64> = head.arg;
+    let timestamp: Option<Timestamp> = match (tag, read_value(input, depth + 1)?) {
+        (Some(0), Value::String(s)) => Timestamp::parse_rfc3339(&s),
+        (Some(1), Value::Int(secs)) => Some(Timestamp::new(secs, 0)),
+        (Some(1), Value::Float(secs)) if secs.is_finite() && secs.abs() < 9.2e18 => {
+            let whole: f64 = secs.floor();
+            let nanos: u32 = ((secs - whole) * 1e9).round() as u32;
+            if nanos >= 1_000_000_000 {
+                Some(Timestamp::new(whole as i64 + 1, 0))
+            } else {
+                Some(Timestamp::new(whole as i64, nanos))
+            }
+        },
+        _ => None
+    };
+    timestamp
+        .map(Value::Timestamp)
+        .ok_or_else(|| input.error_at(head.start, BinaryErrorKind::InvalidTimestamp))
+}
+
+fn read_value<R: Read>(input: &mut Input<R>, depth: usize) -> Result<Value, BinaryError> {
+    match read_item(input, depth)? {
+        Some(value) => Ok(value),
+        None => Err(input.error_at(input.offset - 1, BinaryErrorKind::UnexpectedByte(BREAK)))
+    }
+}
+
+/// Reads a data item, or `None` for a break stop code
+fn read_item<R: Read>(input: &mut Input<R>, depth: usize) -> Result<Option<Value>, BinaryError> {
+    let head: Head = read_head(input)?;
+    let unexpected = |input: &Input<R>| {
+        Err(input.error_at(head.start, BinaryErrorKind::UnexpectedByte(head.initial)))
+    };
+    let value: Value = match (head.major(), head.arg) {
+        (0, Some(arg)) => Value::from(arg),
+        (1, Some(arg)) if arg <= i64::MAX as u64 => Value::Int(!(arg as i64)),
+        (1, Some(_)) => return Err(input.error_at(head.start, BinaryErrorKind::NumberOutOfRange)),
+        (2, _) => Value::Bytes(read_chunks(input, &head)?),
+        (3, _) => Value::String(String::from_utf8(read_chunks(input, &head)?).map_err(|_| {
+            input.error_at(head.start, BinaryErrorKind::InvalidUtf8)
+        })?),
+        (4, arg) => {
+            input.enter(head.start, depth)?;
+            let len: usize = input.check_count(arg.unwrap_or(0))?;
+            let mut array: Vec<Value> = Vec::with_capacity(len.min(MAX_PREALLOCATED));
+            match arg {
+                Some(len) => for _ in 0..len {
+                    array.push(read_value(input, depth + 1)?);
+                },
+                None => while let Some(element) = read_item(input, depth + 1)? {
+                    array.push(element);
+                }
+            }
+            Value::Array(array)
+        },
+        (5, arg) => {
+            input.enter(head.start, depth)?;
+            let len: usize = input.check_count(arg.unwrap_or(0).saturating_mul(2))? / 2;
+            let mut object: ValueMap = ValueMap::with_capacity(len.min(MAX_PREALLOCATED));
+            for _ in 0..arg.unwrap_or(u64::MAX) {
+                let start: u64 = input.offset;
+                let key: String = match read_item(input, depth + 1)? {
+                    Some(Value::String(key)) => key,
+                    None if arg.is_none() => break,
+                    None => {
+                        return Err(input.error_at(start, BinaryErrorKind::UnexpectedByte(BREAK)));
+                    },
+                    Some(_) => return Err(input.error_at(start, BinaryErrorKind::NonStringKey))
+                };
+                let value: Value = read_value(input, depth + 1)?;
+                object.insert(key, value);
+            }
+            Value::Object(object)
+        },
+        (6, Some(0 | 1)) => {
+            input.enter(head.start, depth)?;
+            read_timestamp(input, &head, depth)?
+        },
+        (6, Some(_)) => {
+            input.enter(head.start, depth)?;
+            read_value(input, depth + 1)?
+        },
+        (7, _) => match head.initial & 0x1f {
+            20 => Value::Bool(false),
+            21 => Value::Bool(true),
+            22 | 23 => Value::Nil,
+            25 => Value::Float(f16_to_f64(head.arg.unwrap() as u16)),
+            26 => Value::Float(f32::from_bits(head.arg.unwrap() as u32) as f64),
+            27 => Value::Float(f64::from_bits(head.arg.unwrap())),
+            31 => return Ok(None),
+            _ => return unexpected(input)
+        },
+        _ => return unexpected(input)
+    };
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::value::{BinaryErrorKind, BinaryReadOptions, BinaryWriteOptions};
+    use crate::value::{Timestamp, Value, ValueMap};
+    use crate::value::cbor::{f16_to_f64, shrink_to_f16};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_half() {
+        for half in 0..=u16::MAX {
+            let value: f64 = f16_to_f64(half);
+            if !value.is_nan() {
+                assert_eq!(shrink_to_f16(value as f32), Some(half));
+            }
+        }
+        assert_eq!(shrink_to_f16(65536.0), None);
+        assert_eq!(shrink_to_f16(1.0 + 1.0 / 2048.0), None);
+        assert_eq!(shrink_to_f16(2f32.powi(-25)), None);
+        assert_eq!(shrink_to_f16(3.0 * 2f32.powi(-24)), Some(3));
+    }
+
+    /// Examples from RFC 8949 appendix A
+    #[test]
+    fn test_rfc_examples() {
+        let canonical: BinaryWriteOptions = BinaryWriteOptions { canonical: true };
+        let examples: &[(&str, Value)] = &[
+            ("00", Value::Int(0)),
+            ("17", Value::Int(23)),
+            ("1818", Value::Int(24)),
+            ("1903e8", Value::Int(1000)),
+            ("1b000000e8d4a51000", Value::Int(1000000000000)),
+            ("1bffffffffffffffff", Value::UInt(u64::MAX)),
+            ("20", Value::Int(-1)),
+            ("3903e7", Value::Int(-1000)),
+            ("3b7fffffffffffffff", Value::Int(i64::MIN)),
+            ("f90000", Value::Float(0.0)),
+            ("f98000", Value::Float(-0.0)),
+            ("f93c00", Value::Float(1.0)),
+            ("fb3ff199999999999a", Value::Float(1.1)),
+            ("f93e00", Value::Float(1.5)),
+            ("f97bff", Value::Float(65504.0)),
+            ("fa47c35000", Value::Float(100000.0)),
+            ("fa7f7fffff", Value::Float(f32::MAX as f64)),
+            ("f90001", Value::Float(5.960464477539063e-8)),
+            ("f90400", Value::Float(0.00006103515625)),
+            ("f9c400", Value::Float(-4.0)),
+            ("f97c00", Value::Float(f64::INFINITY)),
+            ("f9fc00", Value::Float(f64::NEG_INFINITY)),
+            ("f4", Value::Bool(false)),
+            ("f6", Value::Nil),
+            ("c11a514b67b0", Value::Timestamp(Timestamp::new(1363896240, 0))),
+            ("4401020304", Value::Bytes(vec![1, 2, 3, 4])),
+            ("62225c", Value::from("\"\\")),
+            ("63e6b0b4", Value::from("水")),
+            ("8301820203820405", Value::from_json("[1, [2, 3], [4, 5]]").unwrap()),
+            ("a26161016162820203", Value::from_json(r#"{"a": 1, "b": [2, 3]}"#).unwrap())
+        ];
+        for (encoded, value) in examples {
+            assert_eq!(hex(&value.to_cbor_with(&canonical)), *encoded);
+            assert_eq!(&Value::from_cbor(&unhex(encoded)).unwrap(), value);
+        }
+        assert_eq!(hex(&Value::Float(f64::NAN).to_cbor_with(&canonical)), "f97e00");
+
+        let decode = |encoded: &str| Value::from_cbor(&unhex(encoded)).unwrap();
+        assert!(matches!(decode("fa7fc00000"), Value::Float(f) if f.is_nan()));
+        assert_eq!(decode("f7"), Value::Nil);
+        assert_eq!(decode("fb3ff8000000000000"), Value::Float(1.5));
+        assert_eq!(decode("c074323031332d30332d32315432303a30343a30305a"), decode("c11a514b67b0"));
+        assert_eq!(
+            decode("c1fb41d452d9ec200000"),
+            Value::Timestamp(Timestamp::new(1363896240, 500_000_000))
+        );
+        assert_eq!(decode("d74401020304"), Value::Bytes(vec![1, 2, 3, 4]));
+        assert_eq!(decode("5f42010243030405ff"), Value::Bytes(vec![1, 2, 3, 4, 5]));
+        assert_eq!(decode("7f657374726561646d696e67ff"), Value::from("streaming"));
+        assert_eq!(decode("9f018202039f0405ffff"), decode("8301820203820405"));
+        assert_eq!(decode("bf6346756ef563416d7421ff"), Value::from_json(r#"{"Fun": true, "Amt": -2}"#).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut object: ValueMap = ValueMap::new();
+        object.insert("time".into(), Value::Timestamp(Timestamp::new(-1, 999_999_999)));
+        object.insert("bytes".into(), Value::Bytes(vec![0; 300]));
+        object.insert("text".into(), Value::from("x".repeat(70000).as_str()));
+        object.insert("float".into(), Value::Float(0.1));
+        let value: Value = Value::Array(vec![Value::Object(object), Value::Int(-25), Value::Nil]);
+        for canonical in [false, true] {
+            let options: BinaryWriteOptions = BinaryWriteOptions { canonical };
+            assert_eq!(Value::from_cbor(&value.to_cbor_with(&options)).unwrap(), value);
+        }
+
+        let mut stream: Vec<u8> = Vec::new();
+        value.write_cbor(&mut stream, &BinaryWriteOptions::default()).unwrap();
+        Value::from("next").write_cbor(&mut stream, &BinaryWriteOptions::default()).unwrap();
+        let mut reader: &[u8] = &stream;
+        let options: BinaryReadOptions = BinaryReadOptions::default();
+        assert_eq!(Value::read_cbor(&mut reader, &options).unwrap(), value);
+        assert_eq!(Value::read_cbor(&mut reader, &options).unwrap(), Value::from("next"));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_errors() {
+        let decode = |encoded: &str| Value::from_cbor(&unhex(encoded)).map_err(|e| (e.kind, e.offset));
+        assert_eq!(decode("ff"), Err((BinaryErrorKind::UnexpectedByte(0xff), 0)));
+        assert_eq!(decode("8201ff"), Err((BinaryErrorKind::UnexpectedByte(0xff), 2)));
+        assert_eq!(decode("1c"), Err((BinaryErrorKind::UnexpectedByte(0x1c), 0)));
+        assert_eq!(decode("1f"), Err((BinaryErrorKind::UnexpectedByte(0x1f), 0)));
+        assert_eq!(decode("f0"), Err((BinaryErrorKind::UnexpectedByte(0xf0), 0)));
+        assert_eq!(decode("5f4101610aff"), Err((BinaryErrorKind::UnexpectedByte(0x61), 3)));
+        assert_eq!(decode("3bffffffffffffffff"), Err((BinaryErrorKind::NumberOutOfRange, 0)));
+        assert_eq!(decode("a10101"), Err((BinaryErrorKind::NonStringKey, 1)));
+        assert_eq!(decode("bf6161ff"), Err((BinaryErrorKind::UnexpectedByte(0xff), 3)));
+        assert_eq!(decode("62c328"), Err((BinaryErrorKind::InvalidUtf8, 1)));
+        assert_eq!(decode("c0f6"), Err((BinaryErrorKind::InvalidTimestamp, 0)));
+        assert_eq!(decode("c1fb7ff0000000000000"), Err((BinaryErrorKind::InvalidTimestamp, 0)));
+        assert_eq!(decode("8201"), Err((BinaryErrorKind::UnexpectedEof, 2)));
+        // lengths far beyond the input fail once it runs out, without reserving for them
+        assert_eq!(decode("9a0010000001"), Err((BinaryErrorKind::UnexpectedEof, 6)));
+        assert_eq!(decode("ba00100000"), Err((BinaryErrorKind::UnexpectedEof, 5)));
+        assert_eq!(decode("f6f6"), Err((BinaryErrorKind::TrailingBytes, 1)));
+
+        let options: BinaryReadOptions = BinaryReadOptions { max_depth: 2, max_size: 8 };
+        let decode = |encoded: &str| {
+            Value::from_cbor_with(&unhex(encoded), &options).map_err(|e| (e.kind, e.offset))
+        };
+        assert!(decode("8180").is_ok());
+        assert_eq!(decode("c1818080"), Err((BinaryErrorKind::DepthLimitExceeded, 2)));
+        assert_eq!(decode("9f9f80ffff"), Err((BinaryErrorKind::DepthLimitExceeded, 2)));
+        assert_eq!(decode("48"), Err((BinaryErrorKind::SizeLimitExceeded, 1)));
+        assert_eq!(decode("9affffffff"), Err((BinaryErrorKind::SizeLimitExceeded, 5)));
+        assert_eq!(decode("9f0000000000000000ff"), Err((BinaryErrorKind::SizeLimitExceeded, 8)));
+    }
+}
@@ -0,0 +1,678 @@
+//! Conversions between Rust types and `Value`s without going through serde
+//!
+//! `ToValue` and `FromValue` are implemented for primitives, strings, tuples, arrays and the std
+//! collections. Maps convert to objects and need keys convertible to and from strings. Errors
+//! carry the JSON Pointer of the value which failed to convert. With the `value-derive` feature,
+//! both traits can be derived for structs and enums, with the same layout `serde_json` uses.
+//!
+//! ```
+//! # use std::collections::{BTreeMap, HashSet};
+//! # use xjbutil::value::{FromValue, ToValue, Value, ValueErrorKind};
+//! let mut scores: BTreeMap<String, (u8, f32)> = BTreeMap::new();
+//! scores.insert("alice".into(), (3, 0.5));
+//! let value: Value = scores.to_value();
+//! assert_eq!(value.to_json(), r#"{"alice":[3,0.5]}"#);
+//! assert_eq!(BTreeMap::<String, (u8, f32)>::from_value(&value).unwrap(), scores);
+//!
+//! let value: Value = Value::from_json("[1, 2, 300]").unwrap();
+//! let err = HashSet::<u8>::from_value(&value).unwrap_err();
+//! assert_eq!(err.kind, ValueErrorKind::OutOfRange { expected: "u8" });
+//! assert_eq!(err.path, "/2");
+//! ```
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::value::{Timestamp, Value, ValueMap};
+use crate::value::path::prepend_pointer_token;
+
+/// Reasons why a `Value` cannot be converted to a Rust type
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ValueErrorKind {
+    /// The value has the wrong type, `found` is one of `nil`, `bool`, `int`, `uint`, `float`,
+    /// `string`, `bytes`, `timestamp`, `array` or `object`
+    TypeMismatch { expected: &'static str, found: &'static str },
+    /// A number does not fit into the target type
+    OutOfRange { expected: &'static str },
+    /// An array has the wrong number of elements for a tuple or a fixed-size array
+    InvalidLength { expected: usize, found: usize },
+    /// An object member cannot be converted to a map key
+    InvalidKey(String),
+    /// A required struct field is missing
+    MissingField(&'static str),
+    /// No enum variant has the given name
+    UnknownVariant(String),
+    Custom(String)
+}
+
+impl Display for ValueErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueErrorKind::TypeMismatch { expected, found } =>
+                write!(f, "expected {}, found {}", expected, found),
+            ValueErrorKind::OutOfRange { expected } =>
+                write!(f, "number out of range for {}", expected),
+            ValueErrorKind::InvalidLength { expected, found } =>
+                write!(f, "expected {} elements, found {}", expected, found),
+            ValueErrorKind::InvalidKey(key) => write!(f, "invalid key \"{}\"", key),
+            ValueErrorKind::MissingField(field) => write!(f, "missing field \"{}\"", field),
+            ValueErrorKind::UnknownVariant(variant) =>
+                write!(f, "unknown variant \"{}\"", variant),
+            ValueErrorKind::Custom(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+/// Error of converting a `Value` to a Rust type, with the JSON Pointer of the offending value
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValueError {
+    pub kind: ValueErrorKind,
+    pub path: String
+}
+
+impl ValueError {
+    pub fn new(kind: ValueErrorKind) -> Self {
+        Self { kind, path: String::new() }
+    }
+
+    pub fn type_mismatch(expected: &'static str, found: &Value) -> Self {
+        Self::new(ValueErrorKind::TypeMismatch { expected, found: type_name(found) })
+    }
+
+    pub fn custom(message: impl Display) -> Self {
+        Self::new(ValueErrorKind::Custom(message.to_string()))
+    }
+
+    /// Prefixes the path with a reference token, while the error propagates to the parent
+    pub fn within(mut self, token: &str) -> Self {
+        prepend_pointer_token(&mut self.path, token);
+        self
+    }
+}
+
+impl Display for ValueError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{} at {}", self.kind, self.path)
+        }
+    }
+}
+
+impl Error for ValueError {}
+
+/// Types convertible to a `Value`
+///
+/// Without specialization, `Vec<u8>` and other sequences of `u8` convert like any sequence, to a
+/// `Value::Array` of integers, while `Value::from(Vec<u8>)` gives `Value::Bytes`. `FromValue`
+/// accepts both representations.
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+/// Types convertible from a `Value`
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, ValueError>;
+
+    /// The value of a missing object member, or `None` if the member is required. `Option`s
+    /// default to `None`.
+    fn from_missing() -> Option<Self> {
+        None
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Nil => "nil",
+        Value::Bool(_) => "bool",
+        Value::Int(_) => "int",
+        Value::UInt(_) => "uint",
+        Value::Float(_) => "float",
+        Value::String(_) => "string",
+        Value::Bytes(_) => "bytes",
+        Value::Timestamp(_) => "timestamp",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object"
+    }
+}
+
+fn expect_array<'a>(value: &'a Value, expected: &'static str) -> Result<&'a [Value], ValueError> {
+    match value {
+        Value::Array(array) => Ok(array),
+        _ => Err(ValueError::type_mismatch(expected, value))
+    }
+}
+
+/// Elements of an array, or the bytes of a byte string as integers
+fn expect_sequence(value: &Value) -> Result<Cow<'_, [Value]>, ValueError> {
+    match value {
+        Value::Array(array) => Ok(Cow::Borrowed(array)),
+        Value::Bytes(bytes) => Ok(Cow::Owned(bytes.iter().map(|b| Value::Int(*b as i64)).collect())),
+        _ => Err(ValueError::type_mismatch("array", value))
+    }
+}
+
+fn expect_object<'a>(value: &'a Value, expected: &'static str) -> Result<&'a ValueMap, ValueError> {
+    match value {
+        Value::Object(object) => Ok(object),
+        _ => Err(ValueError::type_mismatch(expected, value))
+    }
+}
+
+fn from_elements<'a, T, C>(
+    elements: impl IntoIterator<Item = &'a Value>
+) -> Result<C, ValueError>
+    where T: FromValue,
+          C: FromIterator<T>
+{
+    elements.into_iter()
+        .enumerate()
+        .map(|(idx, element)| T::from_value(element).map_err(|e| e.within(&idx.to_string())))
+        .collect()
+}
+
+fn from_members<K, V, C>(object: &ValueMap) -> Result<C, ValueError>
+    where K: FromStr,
+          V: FromValue,
+          C: FromIterator<(K, V)>
+{
+    object.iter()
+        .map(|(key, value)| {
+            let k: K = K::from_str(key)
+                .map_err(|_| ValueError::new(ValueErrorKind::InvalidKey(key.clone())))?;
+            let v: V = V::from_value(value).map_err(|e| e.within(key))?;
+            Ok((k, v))
+        })
+        .collect()
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        Ok(value.clone())
+    }
+}
+
+impl ToValue for ValueMap {
+    fn to_value(&self) -> Value {
+        Value::Object(self.clone())
+    }
+}
+
+impl FromValue for ValueMap {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        expect_object(value, "object").cloned()
+    }
+}
+
+impl ToValue for () {
+    fn to_value(&self) -> Value {
+        Value::Nil
+    }
+}
+
+impl FromValue for () {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::Nil => Ok(()),
+            _ => Err(ValueError::type_mismatch("nil", value))
+        }
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(ValueError::type_mismatch("bool", value))
+        }
+    }
+}
+
+macro_rules! impl_integer {
+    ($($t:ident),*) => {
+        $(
+            impl ToValue for $t {
+                fn to_value(&self) -> Value {
+                    match i64::try_from(*self) {
+                        Ok(i) => Value::Int(i),
+                        Err(_) => Value::UInt(*self as u64)
+                    }
+                }
+            }
+
+            impl FromValue for $t {
+                fn from_value(value: &Value) -> Result<Self, ValueError> {
+                    let result: Option<$t> = match value {
+                        Value::Int(i) => $t::try_from(*i).ok(),
+                        Value::UInt(u) => $t::try_from(*u).ok(),
+                        _ => return Err(ValueError::type_mismatch(stringify!($t), value))
+                    };
+                    result.ok_or_else(|| {
+                        ValueError::new(ValueErrorKind::OutOfRange { expected: stringify!($t) })
+                    })
+                }
+            }
+        )*
+    }
+}
+
+impl_integer![i8, i16, i32, i64, isize, u8, u16, u32, u64, usize];
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::Float(*self)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::Int(i) => Ok(*i as f64),
+            Value::UInt(u) => Ok(*u as f64),
+            Value::Float(f) => Ok(*f),
+            _ => Err(ValueError::type_mismatch("f64", value))
+        }
+    }
+}
+
+impl ToValue for f32 {
+    fn to_value(&self) -> Value {
+        Value::Float(*self as f64)
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::Int(i) => Ok(*i as f32),
+            Value::UInt(u) => Ok(*u as f32),
+            Value::Float(f) if f.is_finite() && f.abs() > f32::MAX as f64 => {
+                Err(ValueError::new(ValueErrorKind::OutOfRange { expected: "f32" }))
+            },
+            Value::Float(f) => Ok(*f as f32),
+            _ => Err(ValueError::type_mismatch("f32", value))
+        }
+    }
+}
+
+impl ToValue for char {
+    fn to_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl FromValue for char {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        if let Value::String(s) = value {
+            let mut chars = s.chars();
+            if let (Some(c), None) = (chars.next(), chars.next()) {
+                return Ok(c);
+            }
+        }
+        Err(ValueError::type_mismatch("char", value))
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::String(s) => Ok(s.clone()),
+            _ => Err(ValueError::type_mismatch("string", value))
+        }
+    }
+}
+
+impl ToValue for Timestamp {
+    fn to_value(&self) -> Value {
+        Value::Timestamp(*self)
+    }
+}
+
+/// Also accepts RFC 3339 strings, which is how timestamps come back from JSON
+impl FromValue for Timestamp {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::Timestamp(timestamp) => Ok(*timestamp),
+            Value::String(s) => Timestamp::parse_rfc3339(s)
+                .ok_or_else(|| ValueError::custom(format!("invalid RFC 3339 timestamp \"{}\"", s))),
+            _ => Err(ValueError::type_mismatch("timestamp", value))
+        }
+    }
+}
+
+impl ToValue for SystemTime {
+    fn to_value(&self) -> Value {
+        Value::Timestamp((*self).into())
+    }
+}
+
+impl FromValue for SystemTime {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        SystemTime::try_from(Timestamp::from_value(value)?)
+    }
+}
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self) -> Value {
+        (**self).to_value()
+    }
+}
+
+impl<T: ToValue + ?Sized> ToValue for &mut T {
+    fn to_value(&self) -> Value {
+        (**self).to_value()
+    }
+}
+
+impl<T: ToValue + ?Sized + ToOwned> ToValue for Cow<'_, T> {
+    fn to_value(&self) -> Value {
+        (**self).to_value()
+    }
+}
+
+impl<T: ToOwned + ?Sized> FromValue for Cow<'_, T>
+    where T::Owned: FromValue
+{
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        T::Owned::from_value(value).map(Cow::Owned)
+    }
+}
+
+macro_rules! impl_pointer {
+    ($($p:ident),*) => {
+        $(
+            impl<T: ToValue + ?Sized> ToValue for $p<T> {
+                fn to_value(&self) -> Value {
+                    (**self).to_value()
+                }
+            }
+
+            impl<T: FromValue> FromValue for $p<T> {
+                fn from_value(value: &Value) -> Result<Self, ValueError> {
+                    T::from_value(value).map($p::new)
+                }
+
+                fn from_missing() -> Option<Self> {
+                    T::from_missing().map($p::new)
+                }
+            }
+        )*
+    }
+}
+
+impl_pointer![Box, Rc, Arc];
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        match self {
+            Some(x) => x.to_value(),
+            None => Value::Nil
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::Nil => Ok(None),
+            _ => T::from_value(value).map(Some)
+        }
+    }
+
+    fn from_missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+impl<T: ToValue> ToValue for [T] {
+    fn to_value(&self) -> Value {
+        Value::Array(self.iter().map(ToValue::to_value).collect())
+    }
+}
+
+impl<T: ToValue, const N: usize> ToValue for [T; N] {
+    fn to_value(&self) -> Value {
+        self[..].to_value()
+    }
+}
+
+impl<T: FromValue, const N: usize> FromValue for [T; N] {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        let array: Cow<'_, [Value]> = expect_sequence(value)?;
+        if array.len() != N {
+            return Err(ValueError::new(ValueErrorKind::InvalidLength {
+                expected: N,
+                found: array.len()
+            }));
+        }
+        let elements: Vec<T> = from_elements(array.iter())?;
+        Ok(<[T; N]>::try_from(elements).unwrap_or_else(|_| unreachable!()))
+    }
+}
+
+macro_rules! impl_sequence {
+    ($($c:ident<T $(: $bound:ident $(+ $bounds:ident)*)?>),*) => {
+        $(
+            impl<T: ToValue> ToValue for $c<T> {
+                fn to_value(&self) -> Value {
+                    Value::Array(self.iter().map(ToValue::to_value).collect())
+                }
+            }
+
+            impl<T: FromValue $(+ $bound $(+ $bounds)*)?> FromValue for $c<T> {
+                fn from_value(value: &Value) -> Result<Self, ValueError> {
+                    from_elements(expect_sequence(value)?.iter())
+                }
+            }
+        )*
+    }
+}
+
+impl_sequence![
+    Vec<T>,
+    VecDeque<T>,
+    LinkedList<T>,
+    BinaryHeap<T: Ord>,
+    BTreeSet<T: Ord>
+];
+
+impl<T: ToValue, S> ToValue for HashSet<T, S> {
+    fn to_value(&self) -> Value {
+        Value::Array(self.iter().map(ToValue::to_value).collect())
+    }
+}
+
+impl<T, S> FromValue for HashSet<T, S>
+    where T: FromValue + Eq + Hash,
+          S: BuildHasher + Default
+{
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        from_elements(expect_sequence(value)?.iter())
+    }
+}
+
+impl<K: ToString, V: ToValue, S> ToValue for HashMap<K, V, S> {
+    fn to_value(&self) -> Value {
+        Value::Object(self.iter().map(|(k, v)| (k.to_string(), v.to_value())).collect())
+    }
+}
+
+impl<K, V, S> FromValue for HashMap<K, V, S>
+    where K: FromStr + Eq + Hash,
+          V: FromValue,
+          S: BuildHasher + Default
+{
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        from_members(expect_object(value, "object")?)
+    }
+}
+
+impl<K: ToString, V: ToValue> ToValue for BTreeMap<K, V> {
+    fn to_value(&self) -> Value {
+        Value::Object(self.iter().map(|(k, v)| (k.to_string(), v.to_value())).collect())
+    }
+}
+
+impl<K: FromStr + Ord, V: FromValue> FromValue for BTreeMap<K, V> {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        from_members(expect_object(value, "object")?)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($len:literal => ($($t:ident $idx:tt),+)),*) => {
+        $(
+            impl<$($t: ToValue),+> ToValue for ($($t,)+) {
+                fn to_value(&self) -> Value {
+                    Value::Array(vec![$(self.$idx.to_value()),+])
+                }
+            }
+
+            impl<$($t: FromValue),+> FromValue for ($($t,)+) {
+                fn from_value(value: &Value) -> Result<Self, ValueError> {
+                    let array: &[Value] = expect_array(value, "array")?;
+                    if array.len() != $len {
+                        return Err(ValueError::new(ValueErrorKind::InvalidLength {
+                            expected: $len,
+                            found: array.len()
+                        }));
+                    }
+                    Ok(($(
+                        $t::from_value(&array[$idx])
+                            .map_err(|e| e.within(stringify!($idx)))?,
+                    )+))
+                }
+            }
+        )*
+    }
+}
+
+impl_tuple![
+    1 => (T0 0),
+    2 => (T0 0, T1 1),
+    3 => (T0 0, T1 1, T2 2),
+    4 => (T0 0, T1 1, T2 2, T3 3),
+    5 => (T0 0, T1 1, T2 2, T3 3, T4 4),
+    6 => (T0 0, T1 1, T2 2, T3 3, T4 4, T5 5),
+    7 => (T0 0, T1 1, T2 2, T3 3, T4 4, T5 5, T6 6),
+    8 => (T0 0, T1 1, T2 2, T3 3, T4 4, T5 5, T6 6, T7 7)
+];
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeSet, HashMap, VecDeque};
+    use std::rc::Rc;
+
+    use crate::value::{FromValue, Timestamp, ToValue, Value, ValueError, ValueErrorKind};
+
+    #[test]
+    fn test_primitives() {
+        assert_eq!(300u16.to_value(), Value::Int(300));
+        assert_eq!(u64::MAX.to_value(), Value::UInt(u64::MAX));
+        assert_eq!(u64::from_value(&Value::UInt(u64::MAX)), Ok(u64::MAX));
+        assert_eq!(i32::from_value(&Value::Int(-5)), Ok(-5));
+        assert_eq!(
+            u32::from_value(&Value::Int(-5)).unwrap_err().kind,
+            ValueErrorKind::OutOfRange { expected: "u32" }
+        );
+        assert_eq!(
+            i64::from_value(&Value::UInt(u64::MAX)).unwrap_err().kind,
+            ValueErrorKind::OutOfRange { expected: "i64" }
+        );
+        assert_eq!(
+            usize::from_value(&Value::Float(1.0)).unwrap_err().kind,
+            ValueErrorKind::TypeMismatch { expected: "usize", found: "float" }
+        );
+
+        assert_eq!(f32::from_value(&Value::Int(2)), Ok(2.0));
+        assert_eq!(
+            f32::from_value(&Value::Float(1e39)).unwrap_err().kind,
+            ValueErrorKind::OutOfRange { expected: "f32" }
+        );
+        assert_eq!(f32::from_value(&Value::Float(f64::NEG_INFINITY)), Ok(f32::NEG_INFINITY));
+        assert_eq!(1.5f32.to_value(), Value::Float(1.5));
+        assert_eq!(char::from_value(&'中'.to_value()), Ok('中'));
+        assert!(char::from_value(&Value::from("ab")).is_err());
+        assert_eq!("abc".to_value(), Value::from("abc"));
+        assert_eq!(Option::<bool>::from_value(&Value::Nil), Ok(None));
+        assert_eq!(Rc::<String>::from_value(&Value::from("x")), Ok(Rc::new("x".into())));
+
+        let timestamp: Timestamp = Timestamp::new(1, 500_000_000);
+        assert_eq!(Timestamp::from_value(&timestamp.to_value()), Ok(timestamp));
+        assert_eq!(Timestamp::from_value(&Value::from("1970-01-01T00:00:01.5Z")), Ok(timestamp));
+    }
+
+    #[test]
+    fn test_collections() {
+        let tuple: (i32, String, Option<u8>) = (1, "a".into(), None);
+        assert_eq!(tuple.to_value().to_json(), r#"[1,"a",null]"#);
+        assert_eq!(FromValue::from_value(&tuple.to_value()), Ok(tuple));
+        assert_eq!(
+            <(i32, i32)>::from_value(&Value::from_json("[1]").unwrap()).unwrap_err().kind,
+            ValueErrorKind::InvalidLength { expected: 2, found: 1 }
+        );
+
+        let array: [u8; 3] = [1, 2, 3];
+        assert_eq!(<[u8; 3]>::from_value(&array.to_value()), Ok(array));
+        assert!(<[u8; 2]>::from_value(&array.to_value()).is_err());
+        let bytes: Value = Value::Bytes(vec![1, 200]);
+        assert_eq!(Vec::<u8>::from_value(&bytes), Ok(vec![1, 200]));
+        assert_eq!(<[u16; 2]>::from_value(&bytes), Ok([1, 200]));
+        let err: ValueError = Vec::<i8>::from_value(&bytes).unwrap_err();
+        assert_eq!((err.kind, err.path.as_str()), (ValueErrorKind::OutOfRange { expected: "i8" }, "/1"));
+        assert_eq!(vec![1u8, 200].to_value(), Value::Array(vec![Value::Int(1), Value::Int(200)]));
+        assert_eq!(Value::from(vec![1u8, 200]), bytes);
+        assert_eq!(Vec::<u8>::from_value(&vec![1u8, 200].to_value()), Ok(vec![1, 200]));
+
+        let deque: VecDeque<f64> = vec![1.0, 2.5].into();
+        assert_eq!(VecDeque::<f64>::from_value(&deque.to_value()), Ok(deque));
+        let set: BTreeSet<char> = "hello".chars().collect();
+        assert_eq!(set.to_value().to_json(), r#"["e","h","l","o"]"#);
+
+        let mut map: HashMap<u32, Vec<bool>> = HashMap::new();
+        map.insert(7, vec![true]);
+        assert_eq!(map.to_value().to_json(), r#"{"7":[true]}"#);
+        assert_eq!(HashMap::<u32, Vec<bool>>::from_value(&map.to_value()), Ok(map));
+
+        let value: Value = Value::from_json(r#"{"x": [true, 1]}"#).unwrap();
+        let err: ValueError = HashMap::<String, Vec<bool>>::from_value(&value).unwrap_err();
+        assert_eq!(err.path, "/x/1");
+        assert_eq!(err.to_string(), "expected bool, found int at /x/1");
+        let err: ValueError = HashMap::<u32, Vec<bool>>::from_value(&value).unwrap_err();
+        assert_eq!(err.kind, ValueErrorKind::InvalidKey("x".into()));
+    }
+}
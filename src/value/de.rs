@@ -0,0 +1,418 @@
+//! Deserializing Rust types from `Value`s, treating `Value` as a serde data format
+//!
+//! Enums are externally tagged like in `serde_json`: unit variants are strings, other variants
+//! objects with a single member named after the variant. `None` and `()` are `Value::Nil`.
+//! Errors carry the JSON Pointer of the value which failed to deserialize.
+//!
+//! ```
+//! # use serde::Deserialize;
+//! # use xjbutil::value::{from_value, Value};
+//! #[derive(Debug, Deserialize, PartialEq)]
+//! enum Shape {
+//!     Circle(f64),
+//!     Rect { w: u32, h: u32 }
+//! }
+//!
+//! let value: Value = Value::from_json(r#"[{"Circle": 1.5}, {"Rect": {"w": 2, "h": 3}}]"#).unwrap();
+//! let shapes: Vec<Shape> = from_value(value).unwrap();
+//! assert_eq!(shapes, vec![Shape::Circle(1.5), Shape::Rect { w: 2, h: 3 }]);
+//!
+//! let value: Value = Value::from_json(r#"[{"Rect": {"w": 2, "h": -3}}]"#).unwrap();
+//! let err = from_value::<Vec<Shape>>(value).unwrap_err();
+//! assert_eq!(err.path, "/0/Rect/h");
+//! ```
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::vec;
+
+use serde::de::{
+    self,
+    DeserializeOwned,
+    DeserializeSeed,
+    EnumAccess,
+    IntoDeserializer,
+    MapAccess,
+    SeqAccess,
+    Unexpected,
+    VariantAccess,
+    Visitor
+};
+use serde::forward_to_deserialize_any;
+
+use crate::value::{Value, ValueMap};
+use crate::value::path::prepend_pointer_token;
+
+/// Error of converting between Rust types and `Value`s, with the JSON Pointer of the offending
+/// value
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SerdeError {
+    pub message: String,
+    pub path: String
+}
+
+impl SerdeError {
+    pub(crate) fn within(mut self, token: &str) -> Self {
+        prepend_pointer_token(&mut self.path, token);
+        self
+    }
+}
+
+impl Display for SerdeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{} at {}", self.message, self.path)
+        }
+    }
+}
+
+impl Error for SerdeError {}
+
+impl de::Error for SerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerdeError { message: msg.to_string(), path: String::new() }
+    }
+}
+
+impl serde::ser::Error for SerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerdeError { message: msg.to_string(), path: String::new() }
+    }
+}
+
+/// Deserializes a `T` from a `Value`
+pub fn from_value<T>(value: Value) -> Result<T, SerdeError>
+    where T: DeserializeOwned
+{
+    T::deserialize(value)
+}
+
+impl Value {
+    fn unexpected(&self) -> Unexpected<'_> {
+        match self {
+            Value::Nil => Unexpected::Unit,
+            Value::Bool(b) => Unexpected::Bool(*b),
+            Value::Int(i) => Unexpected::Signed(*i),
+            Value::UInt(u) => Unexpected::Unsigned(*u),
+            Value::Float(f) => Unexpected::Float(*f),
+            Value::String(s) => Unexpected::Str(s),
+            Value::Bytes(b) => Unexpected::Bytes(b),
+            Value::Timestamp(_) => Unexpected::Other("timestamp"),
+            Value::Array(_) => Unexpected::Seq,
+            Value::Object(_) => Unexpected::Map
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = SerdeError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+        where V: Visitor<'de>
+    {
+        match self {
+            Value::Nil => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::Int(i) => visitor.visit_i64(i),
+            Value::UInt(u) => visitor.visit_u64(u),
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::String(s) => visitor.visit_string(s),
+            Value::Bytes(b) => visitor.visit_byte_buf(b),
+            Value::Timestamp(t) => visitor.visit_string(t.to_rfc3339()),
+            Value::Array(a) => visit_array(a, visitor),
+            Value::Object(o) => visit_object(o, visitor)
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+        where V: Visitor<'de>
+    {
+        match self {
+            Value::Nil => visitor.visit_none(),
+            value => visitor.visit_some(value)
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V
+    ) -> Result<V::Value, SerdeError>
+        where V: Visitor<'de>
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, SerdeError>
+        where V: Visitor<'de>
+    {
+        match self {
+            Value::String(variant) => visitor.visit_enum(EnumDeserializer { variant, value: None }),
+            Value::Object(o) if o.len() == 1 => {
+                let (variant, value): (String, Value) = o.into_iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer { variant, value: Some(value) })
+            },
+            other => Err(de::Error::invalid_type(
+                other.unexpected(),
+                &"a string or an object with a single member"
+            ))
+        }
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+        where V: Visitor<'de>
+    {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+impl<'de> IntoDeserializer<'de, SerdeError> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+fn visit_array<'de, V>(array: Vec<Value>, visitor: V) -> Result<V::Value, SerdeError>
+    where V: Visitor<'de>
+{
+    let len: usize = array.len();
+    let mut deserializer: SeqDeserializer = SeqDeserializer { iter: array.into_iter(), index: 0 };
+    let ret: V::Value = visitor.visit_seq(&mut deserializer)?;
+    if deserializer.iter.len() != 0 {
+        return Err(de::Error::invalid_length(len, &"fewer elements in array"));
+    }
+    Ok(ret)
+}
+
+fn visit_object<'de, V>(object: ValueMap, visitor: V) -> Result<V::Value, SerdeError>
+    where V: Visitor<'de>
+{
+    let len: usize = object.len();
+    let mut deserializer: MapDeserializer = MapDeserializer { iter: object.into_iter(), value: None };
+    let ret: V::Value = visitor.visit_map(&mut deserializer)?;
+    if deserializer.iter.len() != 0 {
+        return Err(de::Error::invalid_length(len, &"fewer members in object"));
+    }
+    Ok(ret)
+}
+
+struct SeqDeserializer {
+    iter: vec::IntoIter<Value>,
+    index: usize
+}
+
+impl<'de> SeqAccess<'de> for SeqDeserializer {
+    type Error = SerdeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, SerdeError>
+        where T: DeserializeSeed<'de>
+    {
+        let value: Value = match self.iter.next() {
+            Some(value) => value,
+            None => return Ok(None)
+        };
+        let index: usize = self.index;
+        self.index += 1;
+        seed.deserialize(value)
+            .map(Some)
+            .map_err(|err| err.within(&index.to_string()))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct MapDeserializer {
+    iter: vec::IntoIter<(String, Value)>,
+    value: Option<(String, Value)>
+}
+
+impl<'de> MapAccess<'de> for MapDeserializer {
+    type Error = SerdeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, SerdeError>
+        where K: DeserializeSeed<'de>
+    {
+        let (key, value): (String, Value) = match self.iter.next() {
+            Some(entry) => entry,
+            None => return Ok(None)
+        };
+        let ret: K::Value = seed.deserialize(KeyDeserializer(key.clone()))
+            .map_err(|err| err.within(&key))?;
+        self.value = Some((key, value));
+        Ok(Some(ret))
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, SerdeError>
+        where V: DeserializeSeed<'de>
+    {
+        let (key, value): (String, Value) = self.value.take()
+            .ok_or_else(|| de::Error::custom("value requested before key"))?;
+        seed.deserialize(value).map_err(|err| err.within(&key))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+/// Deserializer of object keys, which parses them as numbers or bools when asked to, so maps
+/// with such keys round trip
+struct KeyDeserializer(String);
+
+macro_rules! deserialize_parsed_key {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+                where V: Visitor<'de>
+            {
+                match self.0.parse() {
+                    Ok(parsed) => visitor.$visit(parsed),
+                    Err(_) => Err(de::Error::invalid_value(Unexpected::Str(&self.0), &visitor))
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+        where V: Visitor<'de>
+    {
+        visitor.visit_string(self.0)
+    }
+
+    deserialize_parsed_key! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+        where V: Visitor<'de>
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V
+    ) -> Result<V::Value, SerdeError>
+        where V: Visitor<'de>
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, SerdeError>
+        where V: Visitor<'de>
+    {
+        visitor.visit_enum(EnumDeserializer { variant: self.0, value: None })
+    }
+
+    forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    value: Option<Value>
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer {
+    type Error = SerdeError;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, VariantDeserializer), SerdeError>
+        where V: DeserializeSeed<'de>
+    {
+        let variant: V::Value = seed.deserialize(Value::String(self.variant.clone()))?;
+        Ok((variant, VariantDeserializer { variant: self.variant, value: self.value }))
+    }
+}
+
+struct VariantDeserializer {
+    variant: String,
+    value: Option<Value>
+}
+
+impl<'de> VariantAccess<'de> for VariantDeserializer {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        match self.value {
+            None | Some(Value::Nil) => Ok(()),
+            Some(other) => Err(de::Error::invalid_type(other.unexpected(), &"unit variant"))
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, SerdeError>
+        where T: DeserializeSeed<'de>
+    {
+        let variant: String = self.variant;
+        match self.value {
+            Some(value) => seed.deserialize(value).map_err(|err| err.within(&variant)),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"newtype variant"))
+        }
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, SerdeError>
+        where V: Visitor<'de>
+    {
+        let variant: String = self.variant;
+        match self.value {
+            Some(Value::Array(a)) => visit_array(a, visitor).map_err(|err| err.within(&variant)),
+            Some(other) => Err(de::Error::invalid_type(other.unexpected(), &"tuple variant")),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"tuple variant"))
+        }
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, SerdeError>
+        where V: Visitor<'de>
+    {
+        let variant: String = self.variant;
+        match self.value {
+            Some(Value::Object(o)) => visit_object(o, visitor).map_err(|err| err.within(&variant)),
+            Some(other) => Err(de::Error::invalid_type(other.unexpected(), &"struct variant")),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"struct variant"))
+        }
+    }
+}
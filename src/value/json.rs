@@ -0,0 +1,702 @@
+//! Dependency-free JSON (RFC 8259) parser and serializer for `Value`
+//!
+//! Integers without fraction or exponent are parsed as `Value::Int` when they fit into `i64`, as
+//! `Value::UInt` when they fit into `u64`, everything else as `Value::Float`. Floats are always
+//! written with a fraction or exponent, so `Value::from_json(&value.to_json())` preserves the
+//! distinction. Object keys are written in the order of the `ValueMap`, which is the document
+//! order for parsed values.
+//!
+//! JSON has no binary or date types, so `Value::Bytes` is written as a padded base64 string and
+//! `Value::Timestamp` as an RFC 3339 string, both of which are parsed back as `Value::String`.
+//!
+//! ```
+//! # use xjbutil::value::Value;
+//! let value: Value = Value::from_json(r#"{"a": [1, 2.0, "\ud83d\ude00"]}"#).unwrap();
+//! assert_eq!(value.to_json(), r#"{"a":[1,2.0,"😀"]}"#);
+//!
+//! let err = Value::from_json("[1,\n  2,]").unwrap_err();
+//! assert_eq!((err.line, err.column), (2, 5));
+//! ```
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Write};
+use std::str::FromStr;
+
+use crate::value::{Value, ValueMap};
+
+/// Reasons why an input is not valid JSON
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JsonErrorKind {
+    /// The input ended before the value was complete
+    UnexpectedEof,
+    /// A character that cannot start or continue a value here
+    UnexpectedCharacter(char),
+    /// A number does not follow the JSON number grammar
+    InvalidNumber,
+    /// A number is too large to be represented as a finite `f64`
+    NumberOutOfRange,
+    /// An unknown escape sequence in a string
+    InvalidEscape,
+    /// A `\u` escape produces an unpaired surrogate
+    LoneSurrogate,
+    /// An unescaped control character in a string
+    ControlCharacter,
+    /// Nesting of arrays and objects exceeds `JsonParseOptions::max_depth`
+    DepthLimitExceeded,
+    /// Non-whitespace characters after the value
+    TrailingCharacters
+}
+
+impl Display for JsonErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonErrorKind::UnexpectedEof => write!(f, "unexpected end of input"),
+            JsonErrorKind::UnexpectedCharacter(ch) => write!(f, "unexpected character {:?}", ch),
+            JsonErrorKind::InvalidNumber => write!(f, "invalid number"),
+            JsonErrorKind::NumberOutOfRange => write!(f, "number out of range"),
+            JsonErrorKind::InvalidEscape => write!(f, "invalid escape sequence"),
+            JsonErrorKind::LoneSurrogate => write!(f, "unpaired surrogate in unicode escape"),
+            JsonErrorKind::ControlCharacter => write!(f, "control character in string"),
+            JsonErrorKind::DepthLimitExceeded => write!(f, "nesting too deep"),
+            JsonErrorKind::TrailingCharacters => write!(f, "trailing characters")
+        }
+    }
+}
+
+/// Error of parsing malformed JSON, with 1-based line and column (in characters) of the error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct JsonError {
+    pub kind: JsonErrorKind,
+    pub line: usize,
+    pub column: usize
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at line {} column {}", self.kind, self.line, self.column)
+    }
+}
+
+impl Error for JsonError {}
+
+/// Options of `Value::from_json_with`
+#[derive(Clone, Copy, Debug)]
+pub struct JsonParseOptions {
+    /// Maximum nesting depth of arrays and objects
+    pub max_depth: usize,
+    /// Accept `NaN`, `Infinity` and `-Infinity` literals, and parse numbers too large for `f64`
+    /// as infinity
+    pub allow_non_finite: bool
+}
+
+impl Default for JsonParseOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 128,
+            allow_non_finite: false
+        }
+    }
+}
+
+/// How `NaN` and infinite floats are serialized, since JSON has no representation for them
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NonFinitePolicy {
+    /// Write `null`, like `JSON.stringify` does
+    Null,
+    /// Write `NaN`, `Infinity` and `-Infinity` literals, which are not valid JSON but accepted
+    /// with `JsonParseOptions::allow_non_finite`
+    Literal
+}
+
+/// Options of `Value::to_json_with`
+#[derive(Clone, Copy, Debug)]
+pub struct JsonWriteOptions {
+    /// Spaces per indentation level, `None` for compact output
+    pub indent: Option<usize>,
+    pub non_finite: NonFinitePolicy
+}
+
+impl Default for JsonWriteOptions {
+    fn default() -> Self {
+        Self {
+            indent: None,
+            non_finite: NonFinitePolicy::Null
+        }
+    }
+}
+
+impl Value {
+    /// Parses a JSON document with default options
+    pub fn from_json(input: &str) -> Result<Value, JsonError> {
+        Self::from_json_with(input, &JsonParseOptions::default())
+    }
+
+    pub fn from_json_with(input: &str, options: &JsonParseOptions) -> Result<Value, JsonError> {
+        let mut parser: Parser = Parser { input, pos: 0, options };
+        let value: Value = parser.parse_value(0)?;
+        parser.skip_whitespace();
+        if parser.pos != input.len() {
+            return Err(parser.error_at(parser.pos, JsonErrorKind::TrailingCharacters));
+        }
+        Ok(value)
+    }
+
+    /// Serializes to compact JSON, writing non-finite floats as `null`
+    pub fn to_json(&self) -> String {
+        self.to_json_with(&JsonWriteOptions::default())
+    }
+
+    /// Serializes to JSON indented by two spaces, writing non-finite floats as `null`
+    pub fn to_json_pretty(&self) -> String {
+        self.to_json_with(&JsonWriteOptions { indent: Some(2), ..JsonWriteOptions::default() })
+    }
+
+    pub fn to_json_with(&self, options: &JsonWriteOptions) -> String {
+        let mut ret: String = String::new();
+        write_value(&mut ret, self, options, 0);
+        ret
+    }
+}
+
+impl FromStr for Value {
+    type Err = JsonError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Value::from_json(s)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    options: &'a JsonParseOptions
+}
+
+impl<'a> Parser<'a> {
+    fn error_at(&self, pos: usize, kind: JsonErrorKind) -> JsonError {
+        let before: &str = &self.input[..pos];
+        let line_start: usize = before.rfind('\n').map_or(0, |idx| idx + 1);
+        JsonError {
+            kind,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1
+        }
+    }
+
+    /// Error about the character at the current position, or `UnexpectedEof`
+    fn unexpected(&self) -> JsonError {
+        match self.input[self.pos..].chars().next() {
+            Some(ch) => self.error_at(self.pos, JsonErrorKind::UnexpectedCharacter(ch)),
+            None => self.error_at(self.pos, JsonErrorKind::UnexpectedEof)
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+        if self.peek() != Some(byte) {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn eat_literal(&mut self, literal: &str) -> bool {
+        if self.input[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.parse_object(depth + 1),
+            Some(b'[') => self.parse_array(depth + 1),
+            Some(b'"') => Ok(Value::String(self.parse_string()?)),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            _ => {
+                let literals: &[(&str, Value)] = &[
+                    ("null", Value::Nil),
+                    ("true", Value::Bool(true)),
+                    ("false", Value::Bool(false))
+                ];
+                for (literal, value) in literals {
+                    if self.eat_literal(literal) {
+                        return Ok(value.clone());
+                    }
+                }
+                if self.options.allow_non_finite {
+                    if self.eat_literal("NaN") {
+                        return Ok(Value::Float(f64::NAN));
+                    }
+                    if self.eat_literal("Infinity") {
+                        return Ok(Value::Float(f64::INFINITY));
+                    }
+                }
+                Err(self.unexpected())
+            }
+        }
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<Value, JsonError> {
+        if depth > self.options.max_depth {
+            return Err(self.error_at(self.pos, JsonErrorKind::DepthLimitExceeded));
+        }
+        self.pos += 1;
+
+        let mut ret: Vec<Value> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(ret));
+        }
+        loop {
+            ret.push(self.parse_value(depth)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(ret));
+                },
+                _ => return Err(self.unexpected())
+            }
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<Value, JsonError> {
+        if depth > self.options.max_depth {
+            return Err(self.error_at(self.pos, JsonErrorKind::DepthLimitExceeded));
+        }
+        self.pos += 1;
+
+        let mut ret: ValueMap = ValueMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(ret));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.unexpected());
+            }
+            let key: String = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            let value: Value = self.parse_value(depth)?;
+            ret.insert(key, value);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(ret));
+                },
+                _ => return Err(self.unexpected())
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1;
+        let mut ret: String = String::new();
+        let mut run_start: usize = self.pos;
+        loop {
+            match self.peek() {
+                None => return Err(self.error_at(self.pos, JsonErrorKind::UnexpectedEof)),
+                Some(b'"') => {
+                    ret.push_str(&self.input[run_start..self.pos]);
+                    self.pos += 1;
+                    return Ok(ret);
+                },
+                Some(b'\\') => {
+                    ret.push_str(&self.input[run_start..self.pos]);
+                    ret.push(self.parse_escape()?);
+                    run_start = self.pos;
+                },
+                Some(0x00..=0x1f) => {
+                    return Err(self.error_at(self.pos, JsonErrorKind::ControlCharacter));
+                },
+                Some(_) => self.pos += 1
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, JsonError> {
+        let escape_start: usize = self.pos;
+        self.pos += 1;
+        let ch: char = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.pos += 1;
+                let high: u32 = self.parse_hex4()?;
+                let code_point: u32 = if (0xd800..0xdc00).contains(&high) {
+                    if !self.eat_literal("\\u") {
+                        return Err(self.error_at(escape_start, JsonErrorKind::LoneSurrogate));
+                    }
+                    let low: u32 = self.parse_hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error_at(escape_start, JsonErrorKind::LoneSurrogate));
+                    }
+                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                } else if (0xdc00..0xe000).contains(&high) {
+                    return Err(self.error_at(escape_start, JsonErrorKind::LoneSurrogate));
+                } else {
+                    high
+                };
+                return Ok(char::from_u32(code_point).unwrap());
+            },
+            None => return Err(self.error_at(self.pos, JsonErrorKind::UnexpectedEof)),
+            Some(_) => return Err(self.error_at(escape_start, JsonErrorKind::InvalidEscape))
+        };
+        self.pos += 1;
+        Ok(ch)
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonError> {
+        let digits: &str = self.input.get(self.pos..self.pos + 4)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error_at(self.pos, JsonErrorKind::InvalidEscape))?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(digits, 16).unwrap())
+    }
+
+    fn parse_number(&mut self) -> Result<Value, JsonError> {
+        let start: usize = self.pos;
+        let bytes: &[u8] = self.input.as_bytes();
+        let digits = |mut pos: usize| {
+            while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                pos += 1;
+            }
+            pos
+        };
+
+        let mut pos: usize = start;
+        if bytes[pos] == b'-' {
+            pos += 1;
+            if self.options.allow_non_finite && self.input[pos..].starts_with("Infinity") {
+                self.pos = pos + "Infinity".len();
+                return Ok(Value::Float(f64::NEG_INFINITY));
+            }
+        }
+        let int_start: usize = pos;
+        pos = digits(pos);
+        if pos == int_start || (bytes[int_start] == b'0' && pos - int_start > 1) {
+            return Err(self.error_at(start, JsonErrorKind::InvalidNumber));
+        }
+
+        let mut is_float: bool = false;
+        if pos < bytes.len() && bytes[pos] == b'.' {
+            let frac_start: usize = pos + 1;
+            pos = digits(frac_start);
+            if pos == frac_start {
+                return Err(self.error_at(start, JsonErrorKind::InvalidNumber));
+            }
+            is_float = true;
+        }
+        if pos < bytes.len() && (bytes[pos] == b'e' || bytes[pos] == b'E') {
+            pos += 1;
+            if pos < bytes.len() && (bytes[pos] == b'+' || bytes[pos] == b'-') {
+                pos += 1;
+            }
+            let exp_start: usize = pos;
+            pos = digits(exp_start);
+            if pos == exp_start {
+                return Err(self.error_at(start, JsonErrorKind::InvalidNumber));
+            }
+            is_float = true;
+        }
+
+        let literal: &str = &self.input[start..pos];
+        if !is_float {
+            if let Ok(i) = literal.parse::<i64>() {
+                self.pos = pos;
+                return Ok(Value::Int(i));
+            }
+            if let Ok(u) = literal.parse::<u64>() {
+                self.pos = pos;
+                return Ok(Value::UInt(u));
+            }
+        }
+        let f: f64 = literal.parse::<f64>().unwrap();
+        if f.is_infinite() && !self.options.allow_non_finite {
+            return Err(self.error_at(start, JsonErrorKind::NumberOutOfRange));
+        }
+        self.pos = pos;
+        Ok(Value::Float(f))
+    }
+}
+
+fn write_value(out: &mut String, value: &Value, options: &JsonWriteOptions, level: usize) {
+    match value {
+        Value::Nil => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Int(i) => write!(out, "{}", i).unwrap(),
+        Value::UInt(u) => write!(out, "{}", u).unwrap(),
+        Value::Float(f) => write_float(out, *f, options.non_finite),
+        Value::String(s) => write_string(out, s),
+        Value::Bytes(b) => write_string(out, &encode_base64(b)),
+        Value::Timestamp(t) => write_string(out, &t.to_rfc3339()),
+        Value::Array(array) => {
+            if array.is_empty() {
+                out.push_str("[]");
+                return;
+            }
+            out.push('[');
+            for (idx, item) in array.iter().enumerate() {
+                if idx != 0 {
+                    out.push(',');
+                }
+                write_newline(out, options, level + 1);
+                write_value(out, item, options, level + 1);
+            }
+            write_newline(out, options, level);
+            out.push(']');
+        },
+        Value::Object(object) => {
+            if object.is_empty() {
+                out.push_str("{}");
+                return;
+            }
+            out.push('{');
+            for (idx, (key, item)) in object.iter().enumerate() {
+                if idx != 0 {
+                    out.push(',');
+                }
+                write_newline(out, options, level + 1);
+                write_string(out, key);
+                out.push(':');
+                if options.indent.is_some() {
+                    out.push(' ');
+                }
+                write_value(out, item, options, level + 1);
+            }
+            write_newline(out, options, level);
+            out.push('}');
+        }
+    }
+}
+
+fn write_newline(out: &mut String, options: &JsonWriteOptions, level: usize) {
+    if let Some(indent) = options.indent {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent * level));
+    }
+}
+
+fn write_float(out: &mut String, f: f64, policy: NonFinitePolicy) {
+    if f.is_finite() {
+        // `Debug` gives the shortest round-trip representation, always with fraction or exponent
+        write!(out, "{:?}", f).unwrap();
+        return;
+    }
+    match policy {
+        NonFinitePolicy::Null => out.push_str("null"),
+        NonFinitePolicy::Literal => out.push_str(if f.is_nan() {
+            "NaN"
+        } else if f > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        })
+    }
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut ret: String = String::with_capacity(bytes.len() / 3 * 4 + 4);
+    for chunk in bytes.chunks(3) {
+        let group: u32 = chunk.iter()
+            .enumerate()
+            .fold(0, |acc, (idx, b)| acc | (*b as u32) << (16 - 8 * idx));
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                ret.push(ALPHABET[(group >> (18 - 6 * idx) & 0x3f) as usize] as char);
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+    ret
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '\u{0}'..='\u{1f}' | '\u{7f}' => write!(out, "\\u{:04x}", ch as u32).unwrap(),
+            _ => out.push(ch)
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryInto;
+
+    use crate::value::{Timestamp, Value, ValueMap};
+    use crate::value::json::{
+        JsonError,
+        JsonErrorKind,
+        JsonParseOptions,
+        JsonWriteOptions,
+        NonFinitePolicy
+    };
+
+    fn parse_error(input: &str) -> (JsonErrorKind, usize, usize) {
+        let err: JsonError = Value::from_json(input).unwrap_err();
+        (err.kind, err.line, err.column)
+    }
+
+    #[test]
+    fn test_parse() {
+        let value: Value = Value::from_json(
+            " {\"a\": [1, -2, 3.5, 1e2, -0.25E-1], \"b\": {\"c\": null}, \"d\": true, \"e\": \"x\"} "
+        ).unwrap();
+        let mut b: ValueMap = ValueMap::new();
+        b.insert("c".into(), Value::Nil);
+        let mut expected: ValueMap = ValueMap::new();
+        expected.insert("a".into(), Value::Array(vec![
+            Value::Int(1), Value::Int(-2), Value::Float(3.5), Value::Float(100.0), Value::Float(-0.025)
+        ]));
+        expected.insert("b".into(), Value::Object(b));
+        expected.insert("d".into(), Value::Bool(true));
+        expected.insert("e".into(), Value::from("x"));
+        assert_eq!(value, Value::Object(expected));
+
+        assert_eq!(Value::from_json("9223372036854775807").unwrap(), Value::Int(i64::MAX));
+        assert_eq!(Value::from_json("-9223372036854775808").unwrap(), Value::Int(i64::MIN));
+        assert_eq!(Value::from_json("9223372036854775808").unwrap(), Value::UInt(1 << 63));
+        assert_eq!(Value::from_json("18446744073709551615").unwrap(), Value::UInt(u64::MAX));
+        assert_eq!(Value::from_json("18446744073709551616").unwrap(), Value::Float(18446744073709551616.0));
+        assert_eq!("[]".parse::<Value>().unwrap(), Value::Array(vec![]));
+        assert_eq!(
+            Value::from_json(r#""\"\\\/\b\f\n\r\t\u00e9\uD83D\uDE00""#).unwrap(),
+            Value::from("\"\\/\u{8}\u{c}\n\r\té😀")
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert_eq!(parse_error(""), (JsonErrorKind::UnexpectedEof, 1, 1));
+        assert_eq!(parse_error("[1,\n  2,]"), (JsonErrorKind::UnexpectedCharacter(']'), 2, 5));
+        assert_eq!(parse_error("{\"é\": tru}"), (JsonErrorKind::UnexpectedCharacter('t'), 1, 7));
+        assert_eq!(parse_error("{1: 2}"), (JsonErrorKind::UnexpectedCharacter('1'), 1, 2));
+        assert_eq!(parse_error("[1] 2"), (JsonErrorKind::TrailingCharacters, 1, 5));
+        assert_eq!(parse_error("01"), (JsonErrorKind::InvalidNumber, 1, 1));
+        assert_eq!(parse_error("[1.]"), (JsonErrorKind::InvalidNumber, 1, 2));
+        assert_eq!(parse_error("-"), (JsonErrorKind::InvalidNumber, 1, 1));
+        assert_eq!(parse_error("1e400"), (JsonErrorKind::NumberOutOfRange, 1, 1));
+        assert_eq!(parse_error("\"\\x\""), (JsonErrorKind::InvalidEscape, 1, 2));
+        assert_eq!(parse_error("\"\\u12\""), (JsonErrorKind::InvalidEscape, 1, 4));
+        assert_eq!(parse_error("\"\\ud83d\""), (JsonErrorKind::LoneSurrogate, 1, 2));
+        assert_eq!(parse_error("\"\\ude00\""), (JsonErrorKind::LoneSurrogate, 1, 2));
+        assert_eq!(parse_error("\"a\nb\""), (JsonErrorKind::ControlCharacter, 1, 3));
+        assert_eq!(parse_error("\"abc"), (JsonErrorKind::UnexpectedEof, 1, 5));
+        assert_eq!(parse_error("NaN"), (JsonErrorKind::UnexpectedCharacter('N'), 1, 1));
+
+        let deep: String = "[".repeat(129) + &"]".repeat(129);
+        assert_eq!(parse_error(&deep), (JsonErrorKind::DepthLimitExceeded, 1, 129));
+        let options: JsonParseOptions = JsonParseOptions { max_depth: 200, ..JsonParseOptions::default() };
+        assert!(Value::from_json_with(&deep, &options).is_ok());
+        assert_eq!(
+            Value::from_json("[1,\n  2,]").unwrap_err().to_string(),
+            "unexpected character ']' at line 2 column 5"
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        let value: Value = Value::from(vec![
+            Value::Nil,
+            Value::Bool(false),
+            Value::Int(-3),
+            Value::Float(1.0),
+            Value::Float(1e20),
+            Value::Float(0.1),
+            Value::from("\"\\\n\u{1}\u{7f}é😀"),
+            Value::Array(vec![]),
+            Value::Object(ValueMap::new())
+        ]);
+        let json: String = value.to_json();
+        assert_eq!(json, r#"[null,false,-3,1.0,1e20,0.1,"\"\\\n\u0001\u007fé😀",[],{}]"#);
+        assert_eq!(Value::from_json(&json).unwrap(), value);
+
+        let mut object: ValueMap = ValueMap::new();
+        object.insert("b".into(), Value::from(vec![1i64, 2]));
+        object.insert("a".into(), Value::Object(ValueMap::new()));
+        assert_eq!(
+            Value::Object(object).to_json_pretty(),
+            "{\n  \"b\": [\n    1,\n    2\n  ],\n  \"a\": {}\n}"
+        );
+
+        let json: &str = r#"{"z":1,"a":{"y":2,"b":3},"m":4}"#;
+        assert_eq!(Value::from_json(json).unwrap().to_json(), json);
+
+        let value: Value = Value::from(vec![
+            Value::UInt(u64::MAX),
+            Value::Bytes(vec![]),
+            Value::Bytes(b"f".to_vec()),
+            Value::Bytes(b"fo".to_vec()),
+            Value::Bytes(b"foo".to_vec()),
+            Value::Bytes(vec![0xfb, 0xff, 0xbf, 0x00]),
+            Value::Timestamp(Timestamp::new(1614805567, 0))
+        ]);
+        assert_eq!(
+            value.to_json(),
+            r#"[18446744073709551615,"","Zg==","Zm8=","Zm9v","+/+/AA==","2021-03-03T21:06:07Z"]"#
+        );
+    }
+
+    #[test]
+    fn test_non_finite() {
+        let value: Value = Value::from(vec![f64::NAN, f64::INFINITY, f64::NEG_INFINITY]);
+        assert_eq!(value.to_json(), "[null,null,null]");
+
+        let write_options: JsonWriteOptions = JsonWriteOptions {
+            non_finite: NonFinitePolicy::Literal,
+            ..JsonWriteOptions::default()
+        };
+        let json: String = value.to_json_with(&write_options);
+        assert_eq!(json, "[NaN,Infinity,-Infinity]");
+
+        let parse_options: JsonParseOptions = JsonParseOptions {
+            allow_non_finite: true,
+            ..JsonParseOptions::default()
+        };
+        let parsed: Vec<Value> = Value::from_json_with(&json, &parse_options).unwrap().try_into().unwrap();
+        assert!(matches!(parsed[0], Value::Float(f) if f.is_nan()));
+        assert_eq!(parsed[1..], [Value::Float(f64::INFINITY), Value::Float(f64::NEG_INFINITY)]);
+        assert_eq!(
+            Value::from_json_with("-1e400", &parse_options).unwrap(),
+            Value::Float(f64::NEG_INFINITY)
+        );
+    }
+}
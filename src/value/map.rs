@@ -0,0 +1,288 @@
+//! Insertion-ordered string-keyed map backing `Value::Object`
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::iter::{FromIterator, Map};
+use std::ops::{Index, IndexMut};
+use std::slice;
+use std::vec;
+
+use crate::value::Value;
+
+/// A map from `String` to `Value` which iterates in insertion order
+///
+/// Replacing the value of an existing key keeps its position, removing a key keeps the order of
+/// the remaining ones. Two maps compare equal if they have the same entries, regardless of order.
+///
+/// ```
+/// # use xjbutil::value::{Value, ValueMap};
+/// let mut map: ValueMap = ValueMap::new();
+/// map.insert("b".into(), Value::Int(1));
+/// map.insert("a".into(), Value::Int(2));
+/// map.insert("b".into(), Value::Int(3));
+/// assert_eq!(map.keys().collect::<Vec<_>>(), vec!["b", "a"]);
+/// assert_eq!(map["b"], Value::Int(3));
+/// ```
+#[derive(Clone, Default)]
+pub struct ValueMap {
+    entries: Vec<(String, Value)>,
+    index: HashMap<String, usize>
+}
+
+pub type Iter<'a> = Map<
+    slice::Iter<'a, (String, Value)>,
+    fn(&'a (String, Value)) -> (&'a String, &'a Value)
+>;
+
+pub type IterMut<'a> = Map<
+    slice::IterMut<'a, (String, Value)>,
+    fn(&'a mut (String, Value)) -> (&'a String, &'a mut Value)
+>;
+
+pub type IntoIter = vec::IntoIter<(String, Value)>;
+
+impl ValueMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.index.contains_key(key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.index.get(key).map(|idx| &self.entries[*idx].1)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        let idx: usize = *self.index.get(key)?;
+        Some(&mut self.entries[idx].1)
+    }
+
+    /// The entry at position `idx` in insertion order
+    pub fn get_index(&self, idx: usize) -> Option<(&String, &Value)> {
+        self.entries.get(idx).map(|(key, value)| (key, value))
+    }
+
+    /// Inserts a key-value pair, returning the old value if the key was present. An existing key
+    /// keeps its position.
+    pub fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+        if let Some(idx) = self.index.get(&key) {
+            return Some(std::mem::replace(&mut self.entries[*idx].1, value));
+        }
+        self.index.insert(key.clone(), self.entries.len());
+        self.entries.push((key, value));
+        None
+    }
+
+    /// Value of `key`, inserting the result of `f` at the end if the key is not present
+    pub fn get_or_insert_with(&mut self, key: &str, f: impl FnOnce() -> Value) -> &mut Value {
+        let idx: usize = match self.index.get(key) {
+            Some(idx) => *idx,
+            None => {
+                self.index.insert(key.to_string(), self.entries.len());
+                self.entries.push((key.to_string(), f()));
+                self.entries.len() - 1
+            }
+        };
+        &mut self.entries[idx].1
+    }
+
+    /// Removes a key, keeping the order of remaining entries. Takes linear time.
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let idx: usize = self.index.remove(key)?;
+        let (_, value) = self.entries.remove(idx);
+        for (key, _) in &self.entries[idx..] {
+            *self.index.get_mut(key).unwrap() -= 1;
+        }
+        Some(value)
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&String, &mut Value) -> bool) {
+        let entries: Vec<(String, Value)> = std::mem::take(&mut self.entries);
+        self.entries = entries.into_iter()
+            .filter_map(|(key, mut value)| if f(&key, &mut value) { Some((key, value)) } else { None })
+            .collect();
+        self.rebuild_index();
+    }
+
+    /// Sorts entries by key, for output independent of insertion order
+    pub fn sort_keys(&mut self) {
+        self.entries.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+        self.rebuild_index();
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        self.entries.iter_mut().map(|(key, value)| (&*key, value))
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &String> + ExactSizeIterator {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &Value> + ExactSizeIterator {
+        self.entries.iter().map(|(_, value)| value)
+    }
+
+    pub fn values_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut Value> + ExactSizeIterator {
+        self.entries.iter_mut().map(|(_, value)| value)
+    }
+
+    fn rebuild_index(&mut self) {
+        self.index.clear();
+        for (idx, (key, _)) in self.entries.iter().enumerate() {
+            self.index.insert(key.clone(), idx);
+        }
+    }
+}
+
+impl PartialEq for ValueMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.iter().all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl Debug for ValueMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl Index<&str> for ValueMap {
+    type Output = Value;
+
+    /// # Panics
+    ///
+    /// Panics if `key` is not present
+    fn index(&self, key: &str) -> &Value {
+        self.get(key).unwrap_or_else(|| panic!("key {:?} not present in map", key))
+    }
+}
+
+impl IndexMut<&str> for ValueMap {
+    fn index_mut(&mut self, key: &str) -> &mut Value {
+        self.get_mut(key).unwrap_or_else(|| panic!("key {:?} not present in map", key))
+    }
+}
+
+impl FromIterator<(String, Value)> for ValueMap {
+    fn from_iter<I: IntoIterator<Item = (String, Value)>>(iter: I) -> Self {
+        let mut ret: ValueMap = ValueMap::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+impl Extend<(String, Value)> for ValueMap {
+    fn extend<I: IntoIterator<Item = (String, Value)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl IntoIterator for ValueMap {
+    type Item = (String, Value);
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ValueMap {
+    type Item = (&'a String, &'a Value);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut ValueMap {
+    type Item = (&'a String, &'a mut Value);
+    type IntoIter = IterMut<'a>;
+
+    fn into_iter(self) -> IterMut<'a> {
+        self.iter_mut()
+    }
+}
+
+impl From<ValueMap> for HashMap<String, Value> {
+    fn from(map: ValueMap) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::value::Value;
+    use crate::value::map::ValueMap;
+    #[cfg(feature = "value-serde")] use crate::value::{from_value, to_value};
+
+    #[test]
+    fn test_value_map() {
+        let mut map: ValueMap = vec!["c", "a", "b", "d"].into_iter()
+            .enumerate()
+            .map(|(idx, key)| (key.to_string(), Value::Int(idx as i64)))
+            .collect();
+        assert_eq!(map.insert("a".into(), Value::Nil), Some(Value::Int(1)));
+        assert_eq!(map.remove("c"), Some(Value::Int(0)));
+        assert_eq!(map.remove("c"), None);
+        *map.get_or_insert_with("e", || Value::Int(4)) = Value::Int(5);
+        assert_eq!(
+            map.iter().map(|(key, value)| (key.as_str(), value.clone())).collect::<Vec<_>>(),
+            vec![("a", Value::Nil), ("b", Value::Int(2)), ("d", Value::Int(3)), ("e", Value::Int(5))]
+        );
+        assert_eq!(map.get("d"), Some(&Value::Int(3)));
+        assert_eq!(map.get_index(1), Some((&"b".to_string(), &Value::Int(2))));
+
+        let mut reversed: ValueMap = map.clone().into_iter().rev().collect();
+        assert_eq!(reversed, map);
+        assert_eq!(format!("{:?}", reversed), r#"{"e": Int(5), "d": Int(3), "b": Int(2), "a": Nil}"#);
+        reversed.sort_keys();
+        assert_eq!(reversed.keys().collect::<Vec<_>>(), vec!["a", "b", "d", "e"]);
+        reversed.retain(|key, value| {
+            *value = Value::from(key.as_str());
+            key != "b"
+        });
+        assert_eq!(reversed.keys().collect::<Vec<_>>(), vec!["a", "d", "e"]);
+        assert_eq!(reversed.get("d"), Some(&Value::from("d")));
+        assert!(!reversed.contains_key("b"));
+        assert_ne!(reversed, map);
+    }
+
+    #[cfg(feature = "value-serde")]
+    #[test]
+    fn test_serde_order() {
+        let json: &str = r#"{"z":1,"a":[{"y":2,"b":3}],"m":null}"#;
+        let value: Value = Value::from_json(json).unwrap();
+        assert_eq!(to_value(&value).unwrap().to_json(), json);
+        assert_eq!(from_value::<Value>(value).unwrap().to_json(), json);
+    }
+}
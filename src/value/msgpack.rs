@@ -0,0 +1,426 @@
+//! Dependency-free MessagePack encoder and decoder for `Value`
+//!
+//! Integers are written in the smallest format, non-negative ones as unsigned. Floats are written
+//! as `float 64`, or as `float 32` when canonical and lossless. `Value::Bytes` maps to the `bin`
+//! family and `Value::Timestamp` to the timestamp extension type -1, in its smallest layout.
+//! Decoding reads integers as `Value::Int` when they fit into `i64`, and fails on map keys which
+//! are not strings and on other extension types.
+//!
+//! ```
+//! # use xjbutil::value::{BinaryErrorKind, Value};
+//! let value: Value = Value::from_json(r#"{"a": [1, -1, 1.5, "x"]}"#).unwrap();
+//! let bytes: Vec<u8> = value.to_msgpack();
+//! assert_eq!(bytes[..4], [0x81, 0xa1, b'a', 0x94]);
+//! assert_eq!(Value::from_msgpack(&bytes).unwrap(), value);
+//!
+//! let err = Value::from_msgpack(&bytes[..bytes.len() - 1]).unwrap_err();
+//! assert_eq!((err.kind, err.offset), (BinaryErrorKind::UnexpectedEof, 16));
+//! ```
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+use crate::value::{Timestamp, Value, ValueMap};
+use crate::value::binary::{
+    members,
+    shrink_to_f32,
+    BinaryError,
+    BinaryErrorKind,
+    BinaryReadOptions,
+    BinaryWriteOptions,
+    Input,
+    MAX_PREALLOCATED
+};
+
+const TIMESTAMP_EXT: i8 = -1;
+
+impl Value {
+    /// Encodes to MessagePack with default options
+    pub fn to_msgpack(&self) -> Vec<u8> {
+        self.to_msgpack_with(&BinaryWriteOptions::default())
+    }
+
+    pub fn to_msgpack_with(&self, options: &BinaryWriteOptions) -> Vec<u8> {
+        let mut ret: Vec<u8> = Vec::new();
+        write_value(&mut ret, self, options).expect("writing to a Vec cannot fail");
+        ret
+    }
+
+    /// Encodes to MessagePack into a writer, which should be buffered
+    pub fn write_msgpack(
+        &self,
+        writer: &mut impl Write,
+        options: &BinaryWriteOptions
+    ) -> io::Result<()> {
+        write_value(writer, self, options)
+    }
+
+    /// Decodes a MessagePack document with default options, failing on trailing bytes
+    pub fn from_msgpack(input: &[u8]) -> Result<Value, BinaryError> {
+        Self::from_msgpack_with(input, &BinaryReadOptions::default())
+    }
+
+    pub fn from_msgpack_with(
+        input: &[u8],
+        options: &BinaryReadOptions
+    ) -> Result<Value, BinaryError> {
+        let mut input: Input<&[u8]> = Input::new(input, options);
+        let value: Value = read_value(&mut input, 0)?;
+        input.finish()?;
+        Ok(value)
+    }
+
+    /// Decodes one MessagePack value from a reader, which should be buffered. Reads no further
+    /// than the end of the value, so a stream of values can be read by calling this repeatedly.
+    pub fn read_msgpack(
+        reader: &mut impl Read,
+        options: &BinaryReadOptions
+    ) -> Result<Value, BinaryError> {
+        read_value(&mut Input::new(reader, options), 0)
+    }
+}
+
+fn write_uint(w: &mut impl Write, u: u64) -> io::Result<()> {
+    if u < 0x80 {
+        w.write_all(&[u as u8])
+    } else if u <= u8::MAX as u64 {
+        w.write_all(&[0xcc, u as u8])
+    } else if u <= u16::MAX as u64 {
+        w.write_all(&[0xcd])?;
+        w.write_all(&(u as u16).to_be_bytes())
+    } else if u <= u32::MAX as u64 {
+        w.write_all(&[0xce])?;
+        w.write_all(&(u as u32).to_be_bytes())
+    } else {
+        w.write_all(&[0xcf])?;
+        w.write_all(&u.to_be_bytes())
+    }
+}
+
+fn write_int(w: &mut impl Write, i: i64) -> io::Result<()> {
+    if i >= 0 {
+        write_uint(w, i as u64)
+    } else if i >= -32 {
+        w.write_all(&[i as u8])
+    } else if i >= i8::MIN as i64 {
+        w.write_all(&[0xd0, i as u8])
+    } else if i >= i16::MIN as i64 {
+        w.write_all(&[0xd1])?;
+        w.write_all(&(i as i16).to_be_bytes())
+    } else if i >= i32::MIN as i64 {
+        w.write_all(&[0xd2])?;
+        w.write_all(&(i as i32).to_be_bytes())
+    } else {
+        w.write_all(&[0xd3])?;
+        w.write_all(&i.to_be_bytes())
+    }
+}
+
+fn write_float(w: &mut impl Write, f: f64, options: &BinaryWriteOptions) -> io::Result<()> {
+    if options.canonical {
+        let shrunk: Option<f32> = if f.is_nan() { Some(f32::NAN) } else { shrink_to_f32(f) };
+        if let Some(shrunk) = shrunk {
+            w.write_all(&[0xca])?;
+            return w.write_all(&shrunk.to_be_bytes());
+        }
+    }
+    w.write_all(&[0xcb])?;
+    w.write_all(&f.to_be_bytes())
+}
+
+/// Writes the header of a `str`, `bin`, `array` or `map`, given the fix format marker and its
+/// maximum length, and the 8-bit (if any), 16-bit and 32-bit markers
+fn write_header(
+    w: &mut impl Write,
+    len: usize,
+    fix: Option<(u8, usize)>,
+    markers: (Option<u8>, u8, u8)
+) -> io::Result<()> {
+    match (fix, markers.0) {
+        (Some((marker, max)), _) if len <= max => w.write_all(&[marker | len as u8]),
+        (_, Some(marker)) if len <= u8::MAX as usize => w.write_all(&[marker, len as u8]),
+        _ if len <= u16::MAX as usize => {
+            w.write_all(&[markers.1])?;
+            w.write_all(&(len as u16).to_be_bytes())
+        },
+        _ => {
+            let len: u32 = u32::try_from(len).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "MessagePack length exceeds u32")
+            })?;
+            w.write_all(&[markers.2])?;
+            w.write_all(&len.to_be_bytes())
+        }
+    }
+}
+
+fn write_str(w: &mut impl Write, s: &str) -> io::Result<()> {
+    write_header(w, s.len(), Some((0xa0, 31)), (Some(0xd9), 0xda, 0xdb))?;
+    w.write_all(s.as_bytes())
+}
+
+fn write_timestamp(w: &mut impl Write, timestamp: &Timestamp) -> io::Result<()> {
+    let (secs, nanos): (i64, u32) = (timestamp.secs(), timestamp.nanos());
+    if secs >> 34 == 0 {
+        if nanos == 0 && secs >> 32 == 0 {
+            w.write_all(&[0xd6, TIMESTAMP_EXT as u8])?;
+            w.write_all(&(secs as u32).to_be_bytes())
+        } else {
+            w.write_all(&[0xd7, TIMESTAMP_EXT as u8])?;
+            w.write_all(&(((nanos as u64) << 34) | secs as u64).to_be_bytes())
+        }
+    } else {
+        w.write_all(&[0xc7, 12, TIMESTAMP_EXT as u8])?;
+        w.write_all(&nanos.to_be_bytes())?;
+        w.write_all(&secs.to_be_bytes())
+    }
+}
+
+fn write_value(w: &mut impl Write, value: &Value, options: &BinaryWriteOptions) -> io::Result<()> {
+    match value {
+        Value::Nil => w.write_all(&[0xc0]),
+        Value::Bool(b) => w.write_all(&[if *b { 0xc3 } else { 0xc2 }]),
+        Value::Int(i) => write_int(w, *i),
+        Value::UInt(u) => write_uint(w, *u),
+        Value::Float(f) => write_float(w, *f, options),
+        Value::String(s) => write_str(w, s),
+        Value::Bytes(bytes) => {
+            write_header(w, bytes.len(), None, (Some(0xc4), 0xc5, 0xc6))?;
+            w.write_all(bytes)
+        },
+        Value::Timestamp(timestamp) => write_timestamp(w, timestamp),
+        Value::Array(array) => {
+            write_header(w, array.len(), Some((0x90, 15)), (None, 0xdc, 0xdd))?;
+            for element in array {
+                write_value(w, element, options)?;
+            }
+            Ok(())
+        },
+        Value::Object(object) => {
+            write_header(w, object.len(), Some((0x80, 15)), (None, 0xde, 0xdf))?;
+            for (key, value) in members(object, options) {
+                write_str(w, key)?;
+                write_value(w, value, options)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn read_len<R: Read>(input: &mut Input<R>, width: usize) -> Result<u64, BinaryError> {
+    Ok(match width {
+        1 => input.read_u8()? as u64,
+        2 => u16::from_be_bytes(input.read_array()?) as u64,
+        _ => u32::from_be_bytes(input.read_array()?) as u64
+    })
+}
+
+fn read_ext<R: Read>(input: &mut Input<R>, len: u64) -> Result<Value, BinaryError> {
+    let start: u64 = input.offset;
+    let ty: i8 = input.read_u8()? as i8;
+    if ty != TIMESTAMP_EXT {
+        return Err(input.error_at(start, BinaryErrorKind::UnsupportedExtension(ty)));
+    }
+    let (secs, nanos): (i64, u32) = match len {
+        4 => (u32::from_be_bytes(input.read_array()?) as i64, 0),
+        8 => {
+            let data: u64 = u64::from_be_bytes(input.read_array()?);
+            ((data & ((1 << 34) - 1)) as i64, (data >> 34) as u32)
+        },
+        12 => {
+            let nanos: u32 = u32::from_be_bytes(input.read_array()?);
+            (i64::from_be_bytes(input.read_array()?), nanos)
+        },
+        _ => return Err(input.error_at(start, BinaryErrorKind::InvalidTimestamp))
+    };
+    if nanos >= 1_000_000_000 {
+        return Err(input.error_at(start, BinaryErrorKind::InvalidTimestamp));
+    }
+    Ok(Value::Timestamp(Timestamp::new(secs, nanos)))
+}
+
+fn read_array<R: Read>(
+    input: &mut Input<R>,
+    start: u64,
+    len: u64,
+    depth: usize
+) -> Result<Value, BinaryError> {
+    input.enter(start, depth)?;
+    let len: usize = input.check_count(len)?;
+    let mut array: Vec<Value> = Vec::with_capacity(len.min(MAX_PREALLOCATED));
+    for _ in 0..len {
+        array.push(read_value(input, depth + 1)?);
+    }
+    Ok(Value::Array(array))
+}
+
+fn read_map<R: Read>(
+    input: &mut Input<R>,
+    start: u64,
+    len: u64,
+    depth: usize
+) -> Result<Value, BinaryError> {
+    input.enter(start, depth)?;
+    let len: usize = input.check_count(len.saturating_mul(2))? / 2;
+    let mut object: ValueMap = ValueMap::with_capacity(len.min(MAX_PREALLOCATED));
+    for _ in 0..len {
+        let start: u64 = input.offset;
+        let key: String = match read_value(input, depth + 1)? {
+            Value::String(key) => key,
+            _ => return Err(input.error_at(start, BinaryErrorKind::NonStringKey))
+        };
+        let value: Value = read_value(input, depth + 1)?;
+        object.insert(key, value);
+    }
+    Ok(Value::Object(object))
+}
+
+fn read_value<R: Read>(input: &mut Input<R>, depth: usize) -> Result<Value, BinaryError> {
+    let start: u64 = input.offset;
+    let marker: u8 = input.read_u8()?;
+    Ok(match marker {
+        0x00..=0x7f => Value::Int(marker as i64),
+        0x80..=0x8f => return read_map(input, start, (marker & 0x0f) as u64, depth),
+        0x90..=0x9f => return read_array(input, start, (marker & 0x0f) as u64, depth),
+        0xa0..=0xbf => Value::String(input.read_string((marker & 0x1f) as u64)?),
+        0xc0 => Value::Nil,
+        0xc2 => Value::Bool(false),
+        0xc3 => Value::Bool(true),
+        0xc4..=0xc6 => {
+            let len: u64 = read_len(input, 1 << (marker - 0xc4))?;
+            Value::Bytes(input.read_bytes(len)?)
+        },
+        0xc7..=0xc9 => {
+            let len: u64 = read_len(input, 1 << (marker - 0xc7))?;
+            return read_ext(input, len);
+        },
+        0xca => Value::Float(f32::from_be_bytes(input.read_array()?) as f64),
+        0xcb => Value::Float(f64::from_be_bytes(input.read_array()?)),
+        0xcc => Value::Int(input.read_u8()? as i64),
+        0xcd => Value::Int(u16::from_be_bytes(input.read_array()?) as i64),
+        0xce => Value::Int(u32::from_be_bytes(input.read_array()?) as i64),
+        0xcf => Value::from(u64::from_be_bytes(input.read_array()?)),
+        0xd0 => Value::Int(input.read_u8()? as i8 as i64),
+        0xd1 => Value::Int(i16::from_be_bytes(input.read_array()?) as i64),
+        0xd2 => Value::Int(i32::from_be_bytes(input.read_array()?) as i64),
+        0xd3 => Value::Int(i64::from_be_bytes(input.read_array()?)),
+        0xd4..=0xd8 => return read_ext(input, 1 << (marker - 0xd4)),
+        0xd9..=0xdb => {
+            let len: u64 = read_len(input, 1 << (marker - 0xd9))?;
+            Value::String(input.read_string(len)?)
+        },
+        0xdc | 0xdd => {
+            let len: u64 = read_len(input, if marker == 0xdc { 2 } else { 4 })?;
+            return read_array(input, start, len, depth);
+        },
+        0xde | 0xdf => {
+            let len: u64 = read_len(input, if marker == 0xde { 2 } else { 4 })?;
+            return read_map(input, start, len, depth);
+        },
+        0xe0..=0xff => Value::Int(marker as i8 as i64),
+        0xc1 => return Err(input.error_at(start, BinaryErrorKind::UnexpectedByte(marker)))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::value::{BinaryErrorKind, BinaryReadOptions, BinaryWriteOptions};
+    use crate::value::{Timestamp, Value, ValueMap};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_encode() {
+        let encode = |value: Value| hex(&value.to_msgpack());
+        assert_eq!(encode(Value::Nil), "c0");
+        assert_eq!(encode(Value::Int(127)), "7f");
+        assert_eq!(encode(Value::Int(128)), "cc80");
+        assert_eq!(encode(Value::Int(-32)), "e0");
+        assert_eq!(encode(Value::Int(-33)), "d0df");
+        assert_eq!(encode(Value::Int(65536)), "ce00010000");
+        assert_eq!(encode(Value::Int(i64::MIN)), "d38000000000000000");
+        assert_eq!(encode(Value::UInt(u64::MAX)), "cfffffffffffffffff");
+        assert_eq!(encode(Value::Float(1.5)), "cb3ff8000000000000");
+        assert_eq!(encode(Value::from("a".repeat(32).as_str()))[..4], *"d920");
+        assert_eq!(encode(Value::Bytes(vec![1, 2])), "c4020102");
+        assert_eq!(encode(Value::Array(vec![Value::Nil; 16]))[..6], *"dc0010");
+        assert_eq!(encode(Value::Timestamp(Timestamp::new(1, 0))), "d6ff00000001");
+        assert_eq!(encode(Value::Timestamp(Timestamp::new(1, 1))), "d7ff0000000400000001");
+        assert_eq!(encode(Value::Timestamp(Timestamp::new(-1, 0))), "c70cff00000000ffffffffffffffff");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut value: Value = Value::from_json(
+            r#"{"int": [0, -1, 200, -200, 70000, -70000, 5000000000, -5000000000],
+                "float": [0.5, 1e300, -0.0], "str": ["", "中文"], "nested": [[{}], {"a": null}]}"#
+        ).unwrap();
+        value["uint"] = Value::UInt(u64::MAX);
+        value["bytes"] = Value::Bytes((0..=255).collect());
+        value["time"] = Value::Array(vec![
+            Value::Timestamp(Timestamp::new(0x3_ffff_ffff, 999_999_999)),
+            Value::Timestamp(Timestamp::new(-62135596800, 5))
+        ]);
+        assert_eq!(Value::from_msgpack(&value.to_msgpack()).unwrap(), value);
+
+        let mut stream: Vec<u8> = Vec::new();
+        value.write_msgpack(&mut stream, &BinaryWriteOptions::default()).unwrap();
+        Value::Int(1).write_msgpack(&mut stream, &BinaryWriteOptions::default()).unwrap();
+        let mut reader: &[u8] = &stream;
+        let options: BinaryReadOptions = BinaryReadOptions::default();
+        assert_eq!(Value::read_msgpack(&mut reader, &options).unwrap(), value);
+        assert_eq!(Value::read_msgpack(&mut reader, &options).unwrap(), Value::Int(1));
+        assert_eq!(
+            Value::read_msgpack(&mut reader, &options).unwrap_err().kind,
+            BinaryErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_canonical() {
+        let mut lhs: ValueMap = ValueMap::new();
+        lhs.insert("bb".into(), Value::Float(1.5));
+        lhs.insert("a".into(), Value::Array(vec![Value::Float(0.1), Value::Float(-f64::NAN)]));
+        let mut rhs: ValueMap = ValueMap::new();
+        rhs.insert("a".into(), Value::Array(vec![Value::Float(0.1), Value::Float(f64::NAN)]));
+        rhs.insert("bb".into(), Value::Float(1.5));
+
+        let options: BinaryWriteOptions = BinaryWriteOptions { canonical: true };
+        let encoded: Vec<u8> = Value::Object(lhs).to_msgpack_with(&options);
+        assert_eq!(encoded, Value::Object(rhs).to_msgpack_with(&options));
+        assert_eq!(hex(&encoded), "82a16192cb3fb999999999999aca7fc00000a26262ca3fc00000");
+    }
+
+    #[test]
+    fn test_errors() {
+        let decode = |bytes: &[u8]| Value::from_msgpack(bytes).map_err(|e| (e.kind, e.offset));
+        assert_eq!(decode(&[0x92, 0x01, 0xc1]), Err((BinaryErrorKind::UnexpectedByte(0xc1), 2)));
+        assert_eq!(decode(&[0x81, 0x01, 0x01]), Err((BinaryErrorKind::NonStringKey, 1)));
+        assert_eq!(decode(&[0xa2, 0xff, 0xfe]), Err((BinaryErrorKind::InvalidUtf8, 1)));
+        assert_eq!(decode(&[0xd4, 0x05, 0x00]), Err((BinaryErrorKind::UnsupportedExtension(5), 1)));
+        assert_eq!(decode(&[0xc0, 0xc0]), Err((BinaryErrorKind::TrailingBytes, 1)));
+        assert_eq!(
+            decode(&[0xd7, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]),
+            Err((BinaryErrorKind::InvalidTimestamp, 1))
+        );
+        assert_eq!(decode(&[0x92, 0x01]), Err((BinaryErrorKind::UnexpectedEof, 2)));
+        // lengths far beyond the input fail once it runs out, without reserving for them
+        assert_eq!(decode(&[0xdd, 0x00, 0x10, 0x00, 0x00, 0x01]), Err((BinaryErrorKind::UnexpectedEof, 6)));
+        assert_eq!(decode(&[0xdf, 0x00, 0x10, 0x00, 0x00]), Err((BinaryErrorKind::UnexpectedEof, 5)));
+        assert_eq!(
+            decode(&[0xdd, 0xff, 0xff, 0xff, 0xff]),
+            Err((BinaryErrorKind::SizeLimitExceeded, 5))
+        );
+
+        let options: BinaryReadOptions = BinaryReadOptions { max_depth: 2, max_size: 8 };
+        let decode = |bytes: &[u8]| {
+            Value::from_msgpack_with(bytes, &options).map_err(|e| (e.kind, e.offset))
+        };
+        assert!(decode(&[0x91, 0x90]).is_ok());
+        assert!(decode(&[0x91, 0x91, 0x01]).is_ok());
+        assert_eq!(decode(&[0x91, 0x91, 0x90]), Err((BinaryErrorKind::DepthLimitExceeded, 2)));
+        assert_eq!(decode(&[0xc4, 0x08]), Err((BinaryErrorKind::SizeLimitExceeded, 2)));
+        assert_eq!(decode(&[0xdd, 0xff, 0xff, 0xff, 0xff]), Err((BinaryErrorKind::SizeLimitExceeded, 5)));
+    }
+}
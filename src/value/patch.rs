@@ -0,0 +1,559 @@
+//! Structural diff and patching: JSON Patch (RFC 6902) and JSON Merge Patch (RFC 7386)
+//!
+//! ```
+//! # use xjbutil::value::{PatchOperation, Value};
+//! let old: Value = Value::from_json(r#"{"a": 1, "b": [1, 2, 3], "c": true}"#).unwrap();
+//! let new: Value = Value::from_json(r#"{"a": 2, "b": [1, 3], "d": null}"#).unwrap();
+//!
+//! let patch: Vec<PatchOperation> = old.diff(&new);
+//! assert_eq!(
+//!     Value::from(patch.clone()).to_json(),
+//!     r#"[{"op":"replace","path":"/a","value":2},{"op":"remove","path":"/b/1"},"#.to_string()
+//!         + r#"{"op":"remove","path":"/c"},{"op":"add","path":"/d","value":null}]"#
+//! );
+//!
+//! let mut value: Value = old.clone();
+//! value.apply_patch(&patch).unwrap();
+//! assert_eq!(value, new);
+//!
+//! let mut value: Value = old;
+//! value.merge_patch(&Value::from_json(r#"{"a": 2, "c": null}"#).unwrap());
+//! assert_eq!(value.to_json(), r#"{"a":2,"b":[1,2,3]}"#);
+//! ```
+
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::value::{Value, ValueMap};
+use crate::value::path::{parse_index, parse_pointer, push_pointer_token};
+use crate::value::query::compare_values;
+
+/// A single JSON Patch operation. Paths are JSON Pointers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchOperation {
+    /// Inserts into an array, or adds or replaces an object member. The last token may be `-` to
+    /// append to an array.
+    Add { path: String, value: Value },
+    Remove { path: String },
+    /// Replaces an existing value
+    Replace { path: String, value: Value },
+    /// Removes the value at `from` and adds it at `path`
+    Move { from: String, path: String },
+    /// Adds a copy of the value at `from` at `path`
+    Copy { from: String, path: String },
+    /// Fails the patch unless the value at `path` equals `value`, comparing numbers numerically
+    Test { path: String, value: Value }
+}
+
+/// Reasons why a patch cannot be read or applied
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PatchErrorKind {
+    /// An operation object has an unknown `op`, or lacks a required member
+    InvalidOperation,
+    /// A path is not a valid JSON Pointer
+    InvalidPointer,
+    /// The value at a path, or the container of a path to add at, does not exist
+    PathNotFound,
+    /// A `move` operation moves a value into one of its own children
+    MoveIntoChild,
+    /// A `test` operation found an unequal value
+    TestFailed
+}
+
+impl Display for PatchErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchErrorKind::InvalidOperation => write!(f, "invalid operation"),
+            PatchErrorKind::InvalidPointer => write!(f, "invalid JSON pointer"),
+            PatchErrorKind::PathNotFound => write!(f, "path not found"),
+            PatchErrorKind::MoveIntoChild => write!(f, "cannot move a value into its own child"),
+            PatchErrorKind::TestFailed => write!(f, "test failed")
+        }
+    }
+}
+
+/// Error of reading or applying a patch, with the 0-based index of the failing operation
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PatchError {
+    pub kind: PatchErrorKind,
+    pub index: usize
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} in operation {}", self.kind, self.index)
+    }
+}
+
+impl Error for PatchError {}
+
+impl Value {
+    /// Computes a JSON Patch turning `self` into `target`
+    ///
+    /// Objects are compared member by member and arrays element by element, after skipping their
+    /// common prefix and suffix, so an insertion or removal in the middle of an array is a single
+    /// operation. Only `add`, `remove` and `replace` operations are generated.
+    pub fn diff(&self, target: &Value) -> Vec<PatchOperation> {
+        let mut ret: Vec<PatchOperation> = Vec::new();
+        diff_values(&mut String::new(), self, target, &mut ret);
+        ret
+    }
+
+    /// Applies a JSON Patch. If any operation fails, `self` is left unchanged.
+    ///
+    /// Removing the empty path, i.e. the whole value, leaves `Value::Nil`.
+    pub fn apply_patch(&mut self, patch: &[PatchOperation]) -> Result<(), PatchError> {
+        let mut patched: Value = self.clone();
+        for (index, operation) in patch.iter().enumerate() {
+            apply_operation(&mut patched, operation).map_err(|kind| PatchError { kind, index })?;
+        }
+        *self = patched;
+        Ok(())
+    }
+
+    /// Reads a JSON Patch document, an array of operation objects
+    pub fn to_patch(&self) -> Result<Vec<PatchOperation>, PatchError> {
+        match self {
+            Value::Array(a) => a.iter()
+                .enumerate()
+                .map(|(index, operation)| {
+                    read_operation(operation).ok_or(PatchError {
+                        kind: PatchErrorKind::InvalidOperation,
+                        index
+                    })
+                })
+                .collect(),
+            _ => Err(PatchError { kind: PatchErrorKind::InvalidOperation, index: 0 })
+        }
+    }
+
+    /// Applies a JSON Merge Patch: objects are merged recursively, `Nil` members remove keys,
+    /// and everything else replaces the current value
+    pub fn merge_patch(&mut self, patch: &Value) {
+        let patch: &ValueMap = match patch {
+            Value::Object(patch) => patch,
+            _ => {
+                *self = patch.clone();
+                return;
+            }
+        };
+        if !self.is_object() {
+            *self = Value::Object(ValueMap::new());
+        }
+        if let Value::Object(o) = self {
+            for (key, value) in patch {
+                if value.is_nil() {
+                    o.remove(key);
+                } else {
+                    o.get_or_insert_with(key, || Value::Nil).merge_patch(value);
+                }
+            }
+        }
+    }
+
+    /// Computes a JSON Merge Patch turning `self` into `target`
+    ///
+    /// Merge patches cannot set a member to `Nil`, so such members of `target` are removed
+    /// instead when the patch is applied.
+    pub fn merge_diff(&self, target: &Value) -> Value {
+        let (source, target_map): (&ValueMap, &ValueMap) = match (self, target) {
+            (Value::Object(source), Value::Object(target)) => (source, target),
+            _ => return target.clone()
+        };
+        let mut ret: ValueMap = ValueMap::new();
+        for key in source.keys() {
+            if !target_map.contains_key(key) {
+                ret.insert(key.clone(), Value::Nil);
+            }
+        }
+        for (key, value) in target_map {
+            match source.get(key) {
+                Some(old) if old == value => {},
+                Some(old) => {
+                    ret.insert(key.clone(), old.merge_diff(value));
+                },
+                None => {
+                    ret.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        Value::Object(ret)
+    }
+}
+
+impl From<PatchOperation> for Value {
+    /// The operation object of a JSON Patch document
+    fn from(operation: PatchOperation) -> Self {
+        let mut ret: ValueMap = ValueMap::new();
+        let (op, from, path, value): (&str, Option<String>, String, Option<Value>) = match operation {
+            PatchOperation::Add { path, value } => ("add", None, path, Some(value)),
+            PatchOperation::Remove { path } => ("remove", None, path, None),
+            PatchOperation::Replace { path, value } => ("replace", None, path, Some(value)),
+            PatchOperation::Move { from, path } => ("move", Some(from), path, None),
+            PatchOperation::Copy { from, path } => ("copy", Some(from), path, None),
+            PatchOperation::Test { path, value } => ("test", None, path, Some(value))
+        };
+        ret.insert("op".into(), Value::from(op));
+        if let Some(from) = from {
+            ret.insert("from".into(), Value::String(from));
+        }
+        ret.insert("path".into(), Value::String(path));
+        if let Some(value) = value {
+            ret.insert("value".into(), value);
+        }
+        Value::Object(ret)
+    }
+}
+
+fn read_operation(operation: &Value) -> Option<PatchOperation> {
+    let string = |key: &str| match &operation[key] {
+        Value::String(s) => Some(s.clone()),
+        _ => None
+    };
+    let value = || match operation {
+        Value::Object(o) => o.get("value").cloned(),
+        _ => None
+    };
+    let path: String = string("path")?;
+    Some(match string("op")?.as_str() {
+        "add" => PatchOperation::Add { path, value: value()? },
+        "remove" => PatchOperation::Remove { path },
+        "replace" => PatchOperation::Replace { path, value: value()? },
+        "move" => PatchOperation::Move { from: string("from")?, path },
+        "copy" => PatchOperation::Copy { from: string("from")?, path },
+        "test" => PatchOperation::Test { path, value: value()? },
+        _ => return None
+    })
+}
+
+fn diff_values(path: &mut String, source: &Value, target: &Value, out: &mut Vec<PatchOperation>) {
+    match (source, target) {
+        (Value::Object(source), Value::Object(target)) => {
+            for (key, value) in source {
+                let len: usize = path.len();
+                push_pointer_token(path, key);
+                match target.get(key) {
+                    Some(new) => diff_values(path, value, new, out),
+                    None => out.push(PatchOperation::Remove { path: path.clone() })
+                }
+                path.truncate(len);
+            }
+            for (key, value) in target {
+                if !source.contains_key(key) {
+                    let mut path: String = path.clone();
+                    push_pointer_token(&mut path, key);
+                    out.push(PatchOperation::Add { path, value: value.clone() });
+                }
+            }
+        },
+        (Value::Array(source), Value::Array(target)) => diff_arrays(path, source, target, out),
+        (source, target) if source == target => {},
+        (_, target) => out.push(PatchOperation::Replace { path: path.clone(), value: target.clone() })
+    }
+}
+
+fn diff_arrays(path: &mut String, source: &[Value], target: &[Value], out: &mut Vec<PatchOperation>) {
+    let prefix: usize = source.iter().zip(target).take_while(|(lhs, rhs)| lhs == rhs).count();
+    let suffix: usize = source[prefix..].iter().rev()
+        .zip(target[prefix..].iter().rev())
+        .take_while(|(lhs, rhs)| lhs == rhs)
+        .count();
+    let source: &[Value] = &source[prefix..source.len() - suffix];
+    let target: &[Value] = &target[prefix..target.len() - suffix];
+    let element_path = |path: &String, idx: usize| {
+        let mut path: String = path.clone();
+        push_pointer_token(&mut path, &(prefix + idx).to_string());
+        path
+    };
+
+    let common: usize = source.len().min(target.len());
+    for idx in 0..common {
+        let mut path: String = element_path(path, idx);
+        diff_values(&mut path, &source[idx], &target[idx], out);
+    }
+    for idx in (common..source.len()).rev() {
+        out.push(PatchOperation::Remove { path: element_path(path, idx) });
+    }
+    for (idx, value) in target.iter().enumerate().skip(common) {
+        out.push(PatchOperation::Add { path: element_path(path, idx), value: value.clone() });
+    }
+}
+
+fn apply_operation(value: &mut Value, operation: &PatchOperation) -> Result<(), PatchErrorKind> {
+    match operation {
+        PatchOperation::Add { path, value: new } => add(value, path, new.clone()),
+        PatchOperation::Remove { path } => remove(value, path).map(drop),
+        PatchOperation::Replace { path, value: new } => {
+            *lookup_mut(value, path)? = new.clone();
+            Ok(())
+        },
+        PatchOperation::Move { from, path } => {
+            if path == from {
+                return lookup(value, from).map(drop);
+            }
+            if path.starts_with(from.as_str()) && path[from.len()..].starts_with('/') {
+                return Err(PatchErrorKind::MoveIntoChild);
+            }
+            let moved: Value = remove(value, from)?;
+            add(value, path, moved)
+        },
+        PatchOperation::Copy { from, path } => {
+            let copied: Value = lookup(value, from)?.clone();
+            add(value, path, copied)
+        },
+        PatchOperation::Test { path, value: expected } => {
+            if compare_values(lookup(value, path)?, expected) == Some(Ordering::Equal) {
+                Ok(())
+            } else {
+                Err(PatchErrorKind::TestFailed)
+            }
+        }
+    }
+}
+
+fn lookup<'a>(value: &'a Value, pointer: &str) -> Result<&'a Value, PatchErrorKind> {
+    parse_pointer(pointer).ok_or(PatchErrorKind::InvalidPointer)?;
+    value.pointer(pointer).ok_or(PatchErrorKind::PathNotFound)
+}
+
+fn lookup_mut<'a>(value: &'a mut Value, pointer: &str) -> Result<&'a mut Value, PatchErrorKind> {
+    parse_pointer(pointer).ok_or(PatchErrorKind::InvalidPointer)?;
+    value.pointer_mut(pointer).ok_or(PatchErrorKind::PathNotFound)
+}
+
+/// Splits a pointer into the container it points into and the last unescaped token, `None` for
+/// the empty pointer
+fn split_last<'a>(
+    value: &'a mut Value,
+    pointer: &str
+) -> Result<Option<(&'a mut Value, String)>, PatchErrorKind> {
+    let last: String = match parse_pointer(pointer).ok_or(PatchErrorKind::InvalidPointer)?.pop() {
+        Some(last) => last,
+        None => return Ok(None)
+    };
+    let parent: &str = &pointer[..pointer.rfind('/').unwrap()];
+    Ok(Some((lookup_mut(value, parent)?, last)))
+}
+
+fn add(value: &mut Value, pointer: &str, new: Value) -> Result<(), PatchErrorKind> {
+    let (parent, last): (&mut Value, String) = match split_last(value, pointer)? {
+        Some(split) => split,
+        None => {
+            *value = new;
+            return Ok(());
+        }
+    };
+    match parent {
+        Value::Object(o) => {
+            o.insert(last, new);
+            Ok(())
+        },
+        Value::Array(a) => {
+            let idx: usize = if last == "-" {
+                a.len()
+            } else {
+                parse_index(&last)
+                    .filter(|idx| *idx <= a.len())
+                    .ok_or(PatchErrorKind::PathNotFound)?
+            };
+            a.insert(idx, new);
+            Ok(())
+        },
+        _ => Err(PatchErrorKind::PathNotFound)
+    }
+}
+
+fn remove(value: &mut Value, pointer: &str) -> Result<Value, PatchErrorKind> {
+    let (parent, last): (&mut Value, String) = match split_last(value, pointer)? {
+        Some(split) => split,
+        None => return Ok(std::mem::replace(value, Value::Nil))
+    };
+    match parent {
+        Value::Object(o) => o.remove(&last).ok_or(PatchErrorKind::PathNotFound),
+        Value::Array(a) => {
+            let idx: usize = parse_index(&last)
+                .filter(|idx| *idx < a.len())
+                .ok_or(PatchErrorKind::PathNotFound)?;
+            Ok(a.remove(idx))
+        },
+        _ => Err(PatchErrorKind::PathNotFound)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::value::Value;
+    use crate::value::patch::{PatchError, PatchErrorKind, PatchOperation};
+
+    fn json(input: &str) -> Value {
+        Value::from_json(input).unwrap()
+    }
+
+    fn apply(value: &str, patch: &str) -> Result<Value, PatchError> {
+        let mut value: Value = json(value);
+        value.apply_patch(&json(patch).to_patch()?)?;
+        Ok(value)
+    }
+
+    #[test]
+    fn test_apply() {
+        // examples of RFC 6902 appendix A
+        assert_eq!(
+            apply(r#"{"foo": "bar"}"#, r#"[{"op": "add", "path": "/baz", "value": "qux"}]"#),
+            Ok(json(r#"{"baz": "qux", "foo": "bar"}"#))
+        );
+        assert_eq!(
+            apply(r#"{"foo": ["bar", "baz"]}"#, r#"[{"op": "add", "path": "/foo/1", "value": "qux"}]"#),
+            Ok(json(r#"{"foo": ["bar", "qux", "baz"]}"#))
+        );
+        assert_eq!(
+            apply(r#"{"baz": "qux", "foo": "bar"}"#, r#"[{"op": "remove", "path": "/baz"}]"#),
+            Ok(json(r#"{"foo": "bar"}"#))
+        );
+        assert_eq!(
+            apply(r#"{"foo": ["bar", "qux", "baz"]}"#, r#"[{"op": "remove", "path": "/foo/1"}]"#),
+            Ok(json(r#"{"foo": ["bar", "baz"]}"#))
+        );
+        assert_eq!(
+            apply(r#"{"baz": "qux"}"#, r#"[{"op": "replace", "path": "/baz", "value": "boo"}]"#),
+            Ok(json(r#"{"baz": "boo"}"#))
+        );
+        assert_eq!(
+            apply(
+                r#"{"foo": {"bar": "baz", "waldo": "fred"}, "qux": {"corge": "grault"}}"#,
+                r#"[{"op": "move", "from": "/foo/waldo", "path": "/qux/thud"}]"#
+            ),
+            Ok(json(r#"{"foo": {"bar": "baz"}, "qux": {"corge": "grault", "thud": "fred"}}"#))
+        );
+        assert_eq!(
+            apply(
+                r#"{"foo": ["all", "grass", "cows", "eat"]}"#,
+                r#"[{"op": "move", "from": "/foo/1", "path": "/foo/3"}]"#
+            ),
+            Ok(json(r#"{"foo": ["all", "cows", "eat", "grass"]}"#))
+        );
+        assert_eq!(
+            apply(
+                r#"{"baz": "qux", "foo": ["a", 2, "c"]}"#,
+                r#"[{"op": "test", "path": "/baz", "value": "qux"}, {"op": "test", "path": "/foo/1", "value": 2.0}]"#
+            ),
+            Ok(json(r#"{"baz": "qux", "foo": ["a", 2, "c"]}"#))
+        );
+        assert_eq!(
+            apply(r#"{"foo": ["bar"]}"#, r#"[{"op": "add", "path": "/foo/-", "value": ["abc", "def"]}]"#),
+            Ok(json(r#"{"foo": ["bar", ["abc", "def"]]}"#))
+        );
+        assert_eq!(
+            apply(r#"{"a/b": {"~": 1}}"#, r#"[{"op": "copy", "from": "/a~1b/~0", "path": "/c"}]"#),
+            Ok(json(r#"{"a/b": {"~": 1}, "c": 1}"#))
+        );
+        assert_eq!(apply(r#"{"a": 1}"#, r#"[{"op": "add", "path": "", "value": 2}]"#), Ok(Value::Int(2)));
+        assert_eq!(apply(r#"{"a": 1}"#, r#"[{"op": "remove", "path": ""}]"#), Ok(Value::Nil));
+    }
+
+    #[test]
+    fn test_apply_error() {
+        let error = |kind: PatchErrorKind, index: usize| Err(PatchError { kind, index });
+        assert_eq!(
+            apply(r#"{"baz": "qux"}"#, r#"[{"op": "test", "path": "/baz", "value": "bar"}]"#),
+            error(PatchErrorKind::TestFailed, 0)
+        );
+        assert_eq!(
+            apply(r#"{"foo": "bar"}"#, r#"[{"op": "add", "path": "/baz/bat", "value": "qux"}]"#),
+            error(PatchErrorKind::PathNotFound, 0)
+        );
+        assert_eq!(
+            apply(r#"{"a": [1]}"#, r#"[{"op": "remove", "path": "/a/0"}, {"op": "add", "path": "/a/1", "value": 2}]"#),
+            error(PatchErrorKind::PathNotFound, 1)
+        );
+        assert_eq!(
+            apply(r#"{"a": {"b": 1}}"#, r#"[{"op": "move", "from": "/a", "path": "/a/b/c"}]"#),
+            error(PatchErrorKind::MoveIntoChild, 0)
+        );
+        assert_eq!(
+            apply(r#"{"a": 1}"#, r#"[{"op": "replace", "path": "a", "value": 2}]"#),
+            error(PatchErrorKind::InvalidPointer, 0)
+        );
+        assert_eq!(
+            apply(r#"{"a": 1}"#, r#"[{"op": "remove", "path": "/a"}, {"op": "copy", "path": "/b"}]"#),
+            error(PatchErrorKind::InvalidOperation, 1)
+        );
+        assert_eq!(
+            apply(r#"{"a": 1}"#, r#"[{"op": "frobnicate", "path": "/a"}]"#),
+            error(PatchErrorKind::InvalidOperation, 0)
+        );
+
+        let mut value: Value = json(r#"{"a": 1}"#);
+        let patch: Vec<PatchOperation> = vec![
+            PatchOperation::Replace { path: "/a".into(), value: Value::Int(2) },
+            PatchOperation::Remove { path: "/b".into() }
+        ];
+        assert!(value.apply_patch(&patch).is_err());
+        assert_eq!(value, json(r#"{"a": 1}"#));
+    }
+
+    #[test]
+    fn test_diff() {
+        let pairs: &[(&str, &str)] = &[
+            (r#"{"a": 1, "b": {"c": [1, 2]}}"#, r#"{"b": {"c": [1, 2, 3]}, "d": "x"}"#),
+            (r#"[1, 2, 3, 4, 5]"#, r#"[1, 9, 8, 5]"#),
+            (r#"[1, 2, 3]"#, r#"[0, 1, 2, 3]"#),
+            (r#"[{"a": 1}, {"a": 2}]"#, r#"[{"a": 1}, {"a": 3}, {"a": 4}]"#),
+            (r#"{"a/b": {"~": 1}}"#, r#"{"a/b": {"~": 2}}"#),
+            (r#"{"a": 1}"#, r#"[1]"#),
+            (r#"{"a": 1, "b": 2}"#, r#"{"b": 2, "a": 1}"#)
+        ];
+        for (source, target) in pairs {
+            let mut value: Value = json(source);
+            let patch: Vec<PatchOperation> = value.diff(&json(target));
+            let document: Value = Value::from(patch.clone());
+            assert_eq!(Value::from_json(&document.to_json()).unwrap().to_patch().as_ref(), Ok(&patch));
+            value.apply_patch(&patch).unwrap();
+            assert_eq!(value, json(target), "{} -> {}", source, target);
+        }
+
+        assert_eq!(json(r#"[1, 2, 3]"#).diff(&json(r#"[0, 1, 2, 3]"#)), vec![
+            PatchOperation::Add { path: "/0".into(), value: Value::Int(0) }
+        ]);
+        assert_eq!(json(r#"{"a/b": {"~": 1}}"#).diff(&json(r#"{"a/b": {"~": 2}}"#)), vec![
+            PatchOperation::Replace { path: "/a~1b/~0".into(), value: Value::Int(2) }
+        ]);
+        assert!(json(r#"{"a": 1, "b": 2}"#).diff(&json(r#"{"b": 2, "a": 1}"#)).is_empty());
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        // examples of RFC 7386 appendix A
+        let cases: &[(&str, &str, &str)] = &[
+            (r#"{"a":"b"}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+            (r#"{"a":"b"}"#, r#"{"b":"c"}"#, r#"{"a":"b","b":"c"}"#),
+            (r#"{"a":"b"}"#, r#"{"a":null}"#, r#"{}"#),
+            (r#"{"a":"b","b":"c"}"#, r#"{"a":null}"#, r#"{"b":"c"}"#),
+            (r#"{"a":["b"]}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+            (r#"{"a":"c"}"#, r#"{"a":["b"]}"#, r#"{"a":["b"]}"#),
+            (r#"{"a":{"b":"c"}}"#, r#"{"a":{"b":"d","c":null}}"#, r#"{"a":{"b":"d"}}"#),
+            (r#"{"a":[{"b":"c"}]}"#, r#"{"a":[1]}"#, r#"{"a":[1]}"#),
+            (r#"["a","b"]"#, r#"["c","d"]"#, r#"["c","d"]"#),
+            (r#"{"a":"b"}"#, r#"["c"]"#, r#"["c"]"#),
+            (r#"{"a":"foo"}"#, r#"null"#, r#"null"#),
+            (r#"{"a":"foo"}"#, r#""bar""#, r#""bar""#),
+            (r#"{"e":null}"#, r#"{"a":1}"#, r#"{"e":null,"a":1}"#),
+            (r#"[1,2]"#, r#"{"a":"b","c":null}"#, r#"{"a":"b"}"#),
+            (r#"{}"#, r#"{"a":{"bb":{"ccc":null}}}"#, r#"{"a":{"bb":{}}}"#)
+        ];
+        for (original, patch, result) in cases {
+            let mut value: Value = json(original);
+            value.merge_patch(&json(patch));
+            assert_eq!(value.to_json(), *result, "{} + {}", original, patch);
+        }
+
+        let source: Value = json(r#"{"a": 1, "b": {"c": 2, "d": 3}, "e": [1]}"#);
+        let target: Value = json(r#"{"a": 1, "b": {"c": 4}, "e": [1, 2], "f": true}"#);
+        let patch: Value = source.merge_diff(&target);
+        assert_eq!(patch.to_json(), r#"{"b":{"d":null,"c":4},"e":[1,2],"f":true}"#);
+        let mut value: Value = source;
+        value.merge_patch(&patch);
+        assert_eq!(value, target);
+    }
+}
@@ -0,0 +1,334 @@
+//! Navigating nested `Value`s: JSON Pointer (RFC 6901), indexing and dotted paths
+//!
+//! ```
+//! # use xjbutil::value::Value;
+//! let mut value: Value = Value::from_json(r#"{"a": [{"b": 1}]}"#).unwrap();
+//! assert_eq!(value.pointer("/a/0/b"), Some(&Value::Int(1)));
+//! assert_eq!(value["a"][0]["b"], Value::Int(1));
+//! assert_eq!(value["x"][3], Value::Nil);
+//!
+//! value.set_path("c.d", Value::Bool(true)).unwrap();
+//! assert_eq!(value.to_json(), r#"{"a":[{"b":1}],"c":{"d":true}}"#);
+//! ```
+
+use std::ops::{Index, IndexMut};
+
+use crate::value::{Value, ValueMap};
+
+static NIL: Value = Value::Nil;
+
+/// Splits a JSON Pointer into unescaped reference tokens, `None` if it is malformed
+pub(crate) fn parse_pointer(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(vec![]);
+    }
+    if !pointer.starts_with('/') {
+        return None;
+    }
+    pointer[1..].split('/')
+        .map(|token| {
+            let mut ret: String = String::with_capacity(token.len());
+            let mut chars = token.chars();
+            while let Some(ch) = chars.next() {
+                if ch != '~' {
+                    ret.push(ch);
+                    continue;
+                }
+                match chars.next() {
+                    Some('0') => ret.push('~'),
+                    Some('1') => ret.push('/'),
+                    _ => return None
+                }
+            }
+            Some(ret)
+        })
+        .collect()
+}
+
+/// Appends a reference token to a JSON Pointer, escaping `~` and `/`
+pub(crate) fn push_pointer_token(pointer: &mut String, token: &str) {
+    pointer.push('/');
+    for ch in token.chars() {
+        match ch {
+            '~' => pointer.push_str("~0"),
+            '/' => pointer.push_str("~1"),
+            _ => pointer.push(ch)
+        }
+    }
+}
+
+/// Prepends a reference token to a JSON Pointer, locating it inside the child named by `token`
+pub(crate) fn prepend_pointer_token(pointer: &mut String, token: &str) {
+    let mut prefix: String = String::new();
+    push_pointer_token(&mut prefix, token);
+    pointer.insert_str(0, &prefix);
+}
+
+/// Array index of a reference token: decimal digits without leading zeros
+pub(crate) fn parse_index(token: &str) -> Option<usize> {
+    if token.is_empty()
+        || !token.bytes().all(|b| b.is_ascii_digit())
+        || (token.len() > 1 && token.starts_with('0'))
+    {
+        return None;
+    }
+    token.parse().ok()
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    if path.is_empty() {
+        vec![]
+    } else {
+        path.split('.').collect()
+    }
+}
+
+impl Value {
+    fn child(&self, token: &str) -> Option<&Value> {
+        match self {
+            Value::Object(o) => o.get(token),
+            Value::Array(a) => a.get(parse_index(token)?),
+            _ => None
+        }
+    }
+
+    fn child_mut(&mut self, token: &str) -> Option<&mut Value> {
+        match self {
+            Value::Object(o) => o.get_mut(token),
+            Value::Array(a) => a.get_mut(parse_index(token)?),
+            _ => None
+        }
+    }
+
+    /// Looks up a value by JSON Pointer, like `/a/0/b`. The empty pointer refers to the value
+    /// itself, `~1` and `~0` in tokens stand for `/` and `~`.
+    pub fn pointer(&self, pointer: &str) -> Option<&Value> {
+        parse_pointer(pointer)?.iter()
+            .try_fold(self, |value, token| value.child(token))
+    }
+
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Value> {
+        parse_pointer(pointer)?.iter()
+            .try_fold(self, |value, token| value.child_mut(token))
+    }
+
+    /// Looks up a value by dot-separated path, like `a.0.b`. Segments index arrays when they are
+    /// decimal numbers. Keys containing dots can only be reached with `pointer`.
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        split_path(path).into_iter()
+            .try_fold(self, |value, segment| value.child(segment))
+    }
+
+    pub fn get_path_mut(&mut self, path: &str) -> Option<&mut Value> {
+        split_path(path).into_iter()
+            .try_fold(self, |value, segment| value.child_mut(segment))
+    }
+
+    /// Sets the value at a dot-separated path, returning the old value if there was one
+    ///
+    /// Missing keys and `Nil` values along the path are replaced by objects. The last segment may
+    /// also be the length of an array, to append to it. Fails if the path runs into a scalar or
+    /// past the end of an array.
+    pub fn set_path(&mut self, path: &str, value: Value) -> Result<Option<Value>, String> {
+        let segments: Vec<&str> = split_path(path);
+        let (last, parents) = match segments.split_last() {
+            Some(split) => split,
+            None => return Ok(Some(std::mem::replace(self, value)))
+        };
+
+        let mut current: &mut Value = self;
+        for segment in parents {
+            if current.is_nil() {
+                *current = Value::Object(ValueMap::new());
+            }
+            current = match current {
+                Value::Object(o) => o.get_or_insert_with(segment, || Value::Object(ValueMap::new())),
+                Value::Array(a) => {
+                    let len: usize = a.len();
+                    parse_index(segment)
+                        .and_then(move |idx| a.get_mut(idx))
+                        .ok_or_else(|| format!("index {:?} out of bounds of array of length {}", segment, len))?
+                },
+                other => return Err(format!("cannot index {:?} by {:?}", other, segment))
+            };
+            if current.is_nil() {
+                *current = Value::Object(ValueMap::new());
+            }
+        }
+
+        if current.is_nil() {
+            *current = Value::Object(ValueMap::new());
+        }
+        match current {
+            Value::Object(o) => Ok(o.insert(last.to_string(), value)),
+            Value::Array(a) => match parse_index(last) {
+                Some(idx) if idx < a.len() => Ok(Some(std::mem::replace(&mut a[idx], value))),
+                Some(idx) if idx == a.len() => {
+                    a.push(value);
+                    Ok(None)
+                },
+                _ => Err(format!("index {:?} out of bounds of array of length {}", last, a.len()))
+            },
+            other => Err(format!("cannot index {:?} by {:?}", other, last))
+        }
+    }
+
+    /// Removes the value at a dot-separated path, shifting later array elements
+    pub fn remove_path(&mut self, path: &str) -> Option<Value> {
+        let segments: Vec<&str> = split_path(path);
+        let (last, parents) = segments.split_last()?;
+        let parent: &mut Value = parents.iter()
+            .try_fold(self, |value, segment| value.child_mut(segment))?;
+        match parent {
+            Value::Object(o) => o.remove(last),
+            Value::Array(a) => {
+                let idx: usize = parse_index(last).filter(|idx| *idx < a.len())?;
+                Some(a.remove(idx))
+            },
+            _ => None
+        }
+    }
+}
+
+impl Index<&str> for Value {
+    type Output = Value;
+
+    /// Value of the key, `Value::Nil` if the key is not present or this is not an object
+    fn index(&self, key: &str) -> &Value {
+        match self {
+            Value::Object(o) => o.get(key).unwrap_or(&NIL),
+            _ => &NIL
+        }
+    }
+}
+
+impl IndexMut<&str> for Value {
+    /// Value of the key, inserting `Value::Nil` if the key is not present. `Value::Nil` is
+    /// turned into an empty object first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is neither an object nor `Value::Nil`
+    fn index_mut(&mut self, key: &str) -> &mut Value {
+        if self.is_nil() {
+            *self = Value::Object(ValueMap::new());
+        }
+        match self {
+            Value::Object(o) => o.get_or_insert_with(key, || Value::Nil),
+            other => panic!("cannot index {:?} by key {:?}", other, key)
+        }
+    }
+}
+
+impl Index<usize> for Value {
+    type Output = Value;
+
+    /// Element at the index, `Value::Nil` if out of bounds or this is not an array
+    fn index(&self, idx: usize) -> &Value {
+        match self {
+            Value::Array(a) => a.get(idx).unwrap_or(&NIL),
+            _ => &NIL
+        }
+    }
+}
+
+impl IndexMut<usize> for Value {
+    /// # Panics
+    ///
+    /// Panics if this is not an array, or the index is out of bounds
+    fn index_mut(&mut self, idx: usize) -> &mut Value {
+        match self {
+            Value::Array(a) => {
+                let len: usize = a.len();
+                a.get_mut(idx)
+                    .unwrap_or_else(|| panic!("index {} out of bounds of array of length {}", idx, len))
+            },
+            other => panic!("cannot index {:?} by index {}", other, idx)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::value::Value;
+    use crate::value::path::parse_pointer;
+
+    #[test]
+    fn test_pointer() {
+        // examples of RFC 6901 section 5
+        let value: Value = Value::from_json(r#"{
+            "foo": ["bar", "baz"], "": 0, "a/b": 1, "c%d": 2, "e^f": 3, "g|h": 4, "i\\j": 5,
+            "k\"l": 6, " ": 7, "m~n": 8
+        }"#).unwrap();
+        assert_eq!(value.pointer(""), Some(&value));
+        assert_eq!(value.pointer("/foo"), Some(&Value::from(vec!["bar", "baz"])));
+        assert_eq!(value.pointer("/foo/0"), Some(&Value::from("bar")));
+        let expected: Vec<(&str, i64)> = vec![
+            ("/", 0), ("/a~1b", 1), ("/c%d", 2), ("/e^f", 3), ("/g|h", 4), ("/i\\j", 5),
+            ("/k\"l", 6), ("/ ", 7), ("/m~0n", 8)
+        ];
+        for (pointer, i) in expected {
+            assert_eq!(value.pointer(pointer), Some(&Value::Int(i)), "{}", pointer);
+        }
+
+        assert_eq!(value.pointer("foo"), None);
+        assert_eq!(value.pointer("/foo/2"), None);
+        assert_eq!(value.pointer("/foo/01"), None);
+        assert_eq!(value.pointer("/foo/-"), None);
+        assert_eq!(value.pointer("/foo/0/x"), None);
+        assert_eq!(parse_pointer("/a~2"), None);
+        assert_eq!(parse_pointer("/a~"), None);
+        assert_eq!(parse_pointer("/~01/").unwrap(), vec!["~1", ""]);
+
+        let mut value: Value = value;
+        *value.pointer_mut("/foo/1").unwrap() = Value::Int(42);
+        assert_eq!(value["foo"][1], Value::Int(42));
+    }
+
+    #[test]
+    fn test_index() {
+        let mut value: Value = Value::Nil;
+        value["a"]["b"] = Value::from(vec![1i64, 2]);
+        value["a"]["b"][1] = Value::Int(3);
+        assert_eq!(value.to_json(), r#"{"a":{"b":[1,3]}}"#);
+        assert_eq!(value["a"]["b"][1], Value::Int(3));
+        assert_eq!(value["a"]["b"][2], Value::Nil);
+        assert_eq!(value["a"]["c"]["d"], Value::Nil);
+        assert_eq!(value[0], Value::Nil);
+
+        assert!(std::panic::catch_unwind(move || {
+            let mut value: Value = Value::Int(1);
+            value["a"] = Value::Nil;
+        }).is_err());
+    }
+
+    #[test]
+    fn test_path() {
+        let mut value: Value = Value::from_json(r#"{"a": [{"b": 1}], "n": null}"#).unwrap();
+        assert_eq!(value.get_path("a.0.b"), Some(&Value::Int(1)));
+        assert_eq!(value.get_path(""), Some(&value));
+        assert_eq!(value.get_path("a.1"), None);
+
+        assert_eq!(value.set_path("a.0.b", Value::Int(2)), Ok(Some(Value::Int(1))));
+        assert_eq!(value.set_path("a.1", Value::Int(3)), Ok(None));
+        assert_eq!(value.set_path("x.y.z", Value::Int(4)), Ok(None));
+        assert_eq!(value.set_path("n.m", Value::Int(5)), Ok(None));
+        assert_eq!(
+            value.to_json(),
+            r#"{"a":[{"b":2},3],"n":{"m":5},"x":{"y":{"z":4}}}"#
+        );
+        assert!(value.set_path("a.3", Value::Nil).is_err());
+        assert!(value.set_path("a.1.c", Value::Nil).is_err());
+        assert!(value.set_path("a.q.c", Value::Nil).is_err());
+
+        assert_eq!(value.remove_path("a.0"), Some(Value::from_json(r#"{"b":2}"#).unwrap()));
+        assert_eq!(value.remove_path("x.y.z"), Some(Value::Int(4)));
+        assert_eq!(value.remove_path("x.y.z"), None);
+        assert_eq!(value.remove_path("a.5"), None);
+        *value.get_path_mut("n.m").unwrap() = Value::Bool(true);
+        assert_eq!(value.to_json(), r#"{"a":[3],"n":{"m":true},"x":{"y":{}}}"#);
+
+        assert_eq!(value.set_path("", Value::Nil).unwrap().unwrap()["a"][0], Value::Int(3));
+        assert_eq!(value, Value::Nil);
+    }
+}
@@ -0,0 +1,929 @@
+//! JSONPath-style queries selecting many nodes of a `Value` at once
+//!
+//! A query starts with `$` for the root, followed by segments:
+//!
+//! - `.name`, `['name']`: member of an object
+//! - `[0]`, `[-1]`: element of an array, negative indices count from the end
+//! - `.*`, `[*]`: all members or elements
+//! - `[start:end:step]`: array slice with Python semantics, every part is optional
+//! - `[?(filter)]`: members or elements for which the filter holds
+//! - `[a, b]`: union of several selectors
+//! - `..name`, `..*`, `..[selectors]`: like the above, applied to the node and all its
+//!   descendants
+//!
+//! Filters compare paths relative to the current node (`@.price`) or the root (`$.limit`) with
+//! each other or with literals using `==`, `!=`, `<`, `<=`, `>`, `>=`, and combine comparisons
+//! with `&&`, `||`, `!` and parentheses. A path on its own tests for existence. As in RFC 9535,
+//! ordering comparisons involving a missing node are false, two missing nodes are equal, and `!=`
+//! is always the negation of `==`, so a missing node is unequal to any value.
+//!
+//! Matches are returned in document order together with their JSON Pointer, so they can be
+//! looked up again with `Value::pointer_mut`.
+//!
+//! ```
+//! # use xjbutil::value::Value;
+//! let value: Value = Value::from_json(r#"{"books": [
+//!     {"title": "A", "price": 8}, {"title": "B", "price": 12.5}, {"title": "C", "price": 5}
+//! ]}"#).unwrap();
+//! let titles: Vec<&Value> = value.query("$.books[?(@.price < 10)].title").unwrap()
+//!     .into_iter()
+//!     .map(|m| m.value)
+//!     .collect();
+//! assert_eq!(titles, vec![&Value::from("A"), &Value::from("C")]);
+//!
+//! let prices: Vec<String> = value.query("$..price").unwrap()
+//!     .into_iter()
+//!     .map(|m| m.value.to_json())
+//!     .collect();
+//! assert_eq!(prices, vec!["8", "12.5", "5"]);
+//! ```
+
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::value::Value;
+use crate::value::path::push_pointer_token;
+
+/// Maximum nesting of filter expressions, through `!`, parentheses and nested filters
+const MAX_FILTER_DEPTH: usize = 128;
+
+/// Reasons why a query is malformed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QueryErrorKind {
+    /// The query ended before a segment or filter was complete
+    UnexpectedEof,
+    /// A character that cannot start or continue a segment or filter here
+    UnexpectedCharacter(char),
+    /// An index, slice bound or number literal is malformed or out of range
+    InvalidNumber,
+    /// An unknown escape sequence in a quoted name or string literal
+    InvalidEscape,
+    /// Filter expressions are nested more than 128 levels deep
+    DepthLimitExceeded
+}
+
+impl Display for QueryErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryErrorKind::UnexpectedEof => write!(f, "unexpected end of query"),
+            QueryErrorKind::UnexpectedCharacter(ch) => write!(f, "unexpected character {:?}", ch),
+            QueryErrorKind::InvalidNumber => write!(f, "invalid number"),
+            QueryErrorKind::InvalidEscape => write!(f, "invalid escape sequence"),
+            QueryErrorKind::DepthLimitExceeded => write!(f, "filter nesting too deep")
+        }
+    }
+}
+
+/// Error of parsing a malformed query, with the 1-based column (in characters) of the error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct QueryError {
+    pub kind: QueryErrorKind,
+    pub column: usize
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at column {}", self.kind, self.column)
+    }
+}
+
+impl Error for QueryError {}
+
+/// A node selected by a query
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryMatch<'a> {
+    /// JSON Pointer of the node, relative to the queried value
+    pub path: String,
+    pub value: &'a Value
+}
+
+/// A parsed query, reusable against many values
+#[derive(Clone, Debug)]
+pub struct Query {
+    segments: Vec<Segment>
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Child(Vec<Selector>),
+    Descendant(Vec<Selector>)
+}
+
+#[derive(Clone, Debug)]
+enum Selector {
+    Name(String),
+    Index(i64),
+    Wildcard,
+    Slice(Option<i64>, Option<i64>, Option<i64>),
+    Filter(Filter)
+}
+
+#[derive(Clone, Debug)]
+enum Filter {
+    Or(Box<Filter>, Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Exists(Operand),
+    Compare(Operand, CompareOp, Operand)
+}
+
+#[derive(Clone, Copy, Debug)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Current(Vec<Step>),
+    Root(Vec<Step>),
+    Literal(Value)
+}
+
+#[derive(Clone, Debug)]
+enum Step {
+    Name(String),
+    Index(i64)
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, QueryError> {
+        let mut parser: Parser = Parser { input: query, pos: 0, depth: 0 };
+        let query: Query = parser.parse_query()?;
+        parser.skip_whitespace();
+        if parser.pos != parser.input.len() {
+            return Err(parser.unexpected());
+        }
+        Ok(query)
+    }
+
+    /// Selects all nodes of `root` matching the query, in document order. Nodes reached in
+    /// several ways, like with `[0, 0]`, are returned as many times.
+    pub fn select<'a>(&self, root: &'a Value) -> Vec<QueryMatch<'a>> {
+        let mut current: Vec<QueryMatch<'a>> = vec![QueryMatch { path: String::new(), value: root }];
+        for segment in &self.segments {
+            let mut next: Vec<QueryMatch<'a>> = Vec::new();
+            for node in current {
+                match segment {
+                    Segment::Child(selectors) => {
+                        for selector in selectors {
+                            selector.select(root, &node, &mut next);
+                        }
+                    },
+                    Segment::Descendant(selectors) => {
+                        let mut descendants: Vec<QueryMatch<'a>> = Vec::new();
+                        collect_descendants(node, &mut descendants);
+                        for descendant in &descendants {
+                            for selector in selectors {
+                                selector.select(root, descendant, &mut next);
+                            }
+                        }
+                    }
+                }
+            }
+            current = next;
+        }
+        current
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Query::parse(s)
+    }
+}
+
+impl Value {
+    /// Parses `query` and selects all matching nodes, see `Query::select`
+    pub fn query(&self, query: &str) -> Result<Vec<QueryMatch<'_>>, QueryError> {
+        Ok(Query::parse(query)?.select(self))
+    }
+}
+
+/// The node itself followed by all its descendants, in document order
+fn collect_descendants<'a>(node: QueryMatch<'a>, out: &mut Vec<QueryMatch<'a>>) {
+    let value: &'a Value = node.value;
+    let path: String = node.path.clone();
+    out.push(node);
+    for_each_child(value, &path, |child| collect_descendants(child, out));
+}
+
+fn for_each_child<'a>(value: &'a Value, path: &str, mut f: impl FnMut(QueryMatch<'a>)) {
+    match value {
+        Value::Object(o) => for (key, child) in o {
+            f(child_match(path, key, child));
+        },
+        Value::Array(a) => for (idx, child) in a.iter().enumerate() {
+            f(child_match(path, &idx.to_string(), child));
+        },
+        _ => {}
+    }
+}
+
+fn child_match<'a>(path: &str, token: &str, value: &'a Value) -> QueryMatch<'a> {
+    let mut path: String = path.to_string();
+    push_pointer_token(&mut path, token);
+    QueryMatch { path, value }
+}
+
+/// Non-negative index of `idx` into an array of length `len`, counting from the end if negative
+fn normalize_index(idx: i64, len: usize) -> Option<usize> {
+    let idx: i64 = if idx < 0 { idx + len as i64 } else { idx };
+    if idx >= 0 && (idx as usize) < len {
+        Some(idx as usize)
+    } else {
+        None
+    }
+}
+
+/// Indices selected by a slice, in selection order
+fn slice_indices(start: Option<i64>, end: Option<i64>, step: Option<i64>, len: usize) -> Vec<usize> {
+    let len: i64 = len as i64;
+    let step: i64 = step.unwrap_or(1);
+    let normalize = |idx: i64| if idx < 0 { idx + len } else { idx };
+
+    let mut ret: Vec<usize> = Vec::new();
+    if step > 0 {
+        let lower: i64 = start.map_or(0, normalize).clamp(0, len);
+        let upper: i64 = end.map_or(len, normalize).clamp(0, len);
+        let mut idx: i64 = lower;
+        while idx < upper {
+            ret.push(idx as usize);
+            idx = match idx.checked_add(step) {
+                Some(idx) => idx,
+                None => break
+            };
+        }
+    } else if step < 0 {
+        let upper: i64 = start.map_or(len - 1, normalize).clamp(-1, len - 1);
+        let lower: i64 = end.map_or(-1, normalize).clamp(-1, len - 1);
+        let mut idx: i64 = upper;
+        while idx > lower {
+            ret.push(idx as usize);
+            idx = match idx.checked_add(step) {
+                Some(idx) => idx,
+                None => break
+            };
+        }
+    }
+    ret
+}
+
+impl Selector {
+    fn select<'a>(&self, root: &'a Value, node: &QueryMatch<'a>, out: &mut Vec<QueryMatch<'a>>) {
+        match (self, node.value) {
+            (Selector::Name(name), Value::Object(o)) => {
+                if let Some(child) = o.get(name) {
+                    out.push(child_match(&node.path, name, child));
+                }
+            },
+            (Selector::Wildcard, value) => for_each_child(value, &node.path, |child| out.push(child)),
+            (Selector::Index(idx), Value::Array(a)) => {
+                if let Some(idx) = normalize_index(*idx, a.len()) {
+                    out.push(child_match(&node.path, &idx.to_string(), &a[idx]));
+                }
+            },
+            (Selector::Slice(start, end, step), Value::Array(a)) => {
+                for idx in slice_indices(*start, *end, *step, a.len()) {
+                    out.push(child_match(&node.path, &idx.to_string(), &a[idx]));
+                }
+            },
+            (Selector::Filter(filter), value) => for_each_child(value, &node.path, |child| {
+                if filter.test(root, child.value) {
+                    out.push(child);
+                }
+            }),
+            _ => {}
+        }
+    }
+}
+
+impl Filter {
+    fn test(&self, root: &Value, current: &Value) -> bool {
+        match self {
+            Filter::Or(lhs, rhs) => lhs.test(root, current) || rhs.test(root, current),
+            Filter::And(lhs, rhs) => lhs.test(root, current) && rhs.test(root, current),
+            Filter::Not(filter) => !filter.test(root, current),
+            Filter::Exists(operand) => operand.eval(root, current).is_some(),
+            Filter::Compare(lhs, op, rhs) => {
+                let lhs: Option<&Value> = lhs.eval(root, current);
+                let rhs: Option<&Value> = rhs.eval(root, current);
+                let ordering: Option<Ordering> = match (lhs, rhs) {
+                    (None, None) => Some(Ordering::Equal),
+                    (Some(lhs), Some(rhs)) => compare_values(lhs, rhs),
+                    _ => None
+                };
+                match op {
+                    CompareOp::Eq => ordering == Some(Ordering::Equal),
+                    CompareOp::Ne => ordering != Some(Ordering::Equal),
+                    CompareOp::Lt => ordering == Some(Ordering::Less),
+                    CompareOp::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                    CompareOp::Gt => ordering == Some(Ordering::Greater),
+                    CompareOp::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+                }
+            }
+        }
+    }
+}
+
+impl Operand {
+    fn eval<'a>(&'a self, root: &'a Value, current: &'a Value) -> Option<&'a Value> {
+        let (start, steps): (&Value, &[Step]) = match self {
+            Operand::Current(steps) => (current, steps),
+            Operand::Root(steps) => (root, steps),
+            Operand::Literal(value) => return Some(value)
+        };
+        steps.iter().try_fold(start, |value, step| match (step, value) {
+            (Step::Name(name), Value::Object(o)) => o.get(name),
+            (Step::Index(idx), Value::Array(a)) => a.get(normalize_index(*idx, a.len())?),
+            _ => None
+        })
+    }
+}
+
+/// Ordering of two values for filters: numbers, strings and timestamps are ordered, other values
+/// are only equal or unequal, and values of different types are incomparable
+pub(crate) fn compare_values(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Value::Int(lhs), Value::Int(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::Int(lhs), Value::Float(rhs)) => compare_int_float(*lhs as i128, *rhs),
+        (Value::Float(lhs), Value::Int(rhs)) => compare_int_float(*rhs as i128, *lhs).map(Ordering::reverse),
+        (Value::Float(lhs), Value::Float(rhs)) => lhs.partial_cmp(rhs),
+        (Value::UInt(lhs), Value::UInt(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::Int(lhs), Value::UInt(rhs)) => Some((*lhs as i128).cmp(&(*rhs as i128))),
+        (Value::UInt(lhs), Value::Int(rhs)) => Some((*lhs as i128).cmp(&(*rhs as i128))),
+        (Value::UInt(lhs), Value::Float(rhs)) => compare_int_float(*lhs as i128, *rhs),
+        (Value::Float(lhs), Value::UInt(rhs)) => compare_int_float(*rhs as i128, *lhs).map(Ordering::reverse),
+        (Value::String(lhs), Value::String(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::Timestamp(lhs), Value::Timestamp(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::Array(lhs), Value::Array(rhs)) => {
+            let equal: bool = lhs.len() == rhs.len() && lhs.iter().zip(rhs)
+                .all(|(lhs, rhs)| compare_values(lhs, rhs) == Some(Ordering::Equal));
+            Some(Ordering::Equal).filter(|_| equal)
+        },
+        (Value::Object(lhs), Value::Object(rhs)) => {
+            let equal: bool = lhs.len() == rhs.len() && lhs.iter().all(|(key, lhs)| {
+                rhs.get(key).and_then(|rhs| compare_values(lhs, rhs)) == Some(Ordering::Equal)
+            });
+            Some(Ordering::Equal).filter(|_| equal)
+        },
+        (lhs, rhs) => Some(Ordering::Equal).filter(|_| lhs == rhs)
+    }
+}
+
+/// Exact ordering of an integer and a float, where casting the integer could round it
+fn compare_int_float(lhs: i128, rhs: f64) -> Option<Ordering> {
+    const TWO_POW_64: f64 = 18446744073709551616.0;
+    if rhs.is_nan() {
+        None
+    } else if rhs >= TWO_POW_64 {
+        Some(Ordering::Less)
+    } else if rhs <= -TWO_POW_64 {
+        Some(Ordering::Greater)
+    } else {
+        // integral part is exact in `i128`, the fraction only matters when the integers are equal
+        let integral: f64 = rhs.trunc();
+        Some(lhs.cmp(&(integral as i128)).then(integral.partial_cmp(&rhs)?))
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    /// Count of filter expressions being parsed, each nested in the previous one
+    depth: usize
+}
+
+impl<'a> Parser<'a> {
+    fn error_at(&self, pos: usize, kind: QueryErrorKind) -> QueryError {
+        QueryError {
+            kind,
+            column: self.input[..pos].chars().count() + 1
+        }
+    }
+
+    /// Error about the character at the current position, or `UnexpectedEof`
+    fn unexpected(&self) -> QueryError {
+        match self.input[self.pos..].chars().next() {
+            Some(ch) => self.error_at(self.pos, QueryErrorKind::UnexpectedCharacter(ch)),
+            None => self.error_at(self.pos, QueryErrorKind::UnexpectedEof)
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), QueryError> {
+        if self.peek() != Some(byte) {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn eat_literal(&mut self, literal: &str) -> bool {
+        if self.input[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_query(&mut self) -> Result<Query, QueryError> {
+        self.skip_whitespace();
+        self.expect(b'$')?;
+        let mut segments: Vec<Segment> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.eat_literal("..") {
+                segments.push(Segment::Descendant(self.parse_selectors_after_dot(true)?));
+            } else if self.eat_literal(".") {
+                segments.push(Segment::Child(self.parse_selectors_after_dot(false)?));
+            } else if self.peek() == Some(b'[') {
+                segments.push(Segment::Child(self.parse_bracketed()?));
+            } else {
+                return Ok(Query { segments });
+            }
+        }
+    }
+
+    /// Selectors after `.` or `..`: `*`, a member name or, after `..` only, a bracketed list
+    fn parse_selectors_after_dot(&mut self, descendant: bool) -> Result<Vec<Selector>, QueryError> {
+        match self.peek() {
+            Some(b'*') => {
+                self.pos += 1;
+                Ok(vec![Selector::Wildcard])
+            },
+            Some(b'[') if descendant => self.parse_bracketed(),
+            _ => Ok(vec![Selector::Name(self.parse_name()?)])
+        }
+    }
+
+    /// Member name shorthand: letters, digits, `_` and non-ASCII characters, not starting with
+    /// a digit
+    fn parse_name(&mut self) -> Result<String, QueryError> {
+        let start: usize = self.pos;
+        for (offset, ch) in self.input[start..].char_indices() {
+            let valid: bool = ch == '_'
+                || ch.is_ascii_alphabetic()
+                || !ch.is_ascii()
+                || (offset != 0 && ch.is_ascii_digit());
+            if !valid {
+                break;
+            }
+            self.pos = start + offset + ch.len_utf8();
+        }
+        if self.pos == start {
+            return Err(self.unexpected());
+        }
+        Ok(self.input[start..self.pos].to_string())
+    }
+
+    fn parse_bracketed(&mut self) -> Result<Vec<Selector>, QueryError> {
+        self.pos += 1;
+        let mut ret: Vec<Selector> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            ret.push(self.parse_selector()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(ret);
+                },
+                _ => return Err(self.unexpected())
+            }
+        }
+    }
+
+    fn parse_selector(&mut self) -> Result<Selector, QueryError> {
+        match self.peek() {
+            Some(b'*') => {
+                self.pos += 1;
+                Ok(Selector::Wildcard)
+            },
+            Some(b'\'' | b'"') => Ok(Selector::Name(self.parse_string()?)),
+            Some(b'?') => {
+                self.pos += 1;
+                Ok(Selector::Filter(self.parse_or()?))
+            },
+            _ => {
+                let start: Option<i64> = self.parse_optional_int()?;
+                self.skip_whitespace();
+                if self.peek() != Some(b':') {
+                    return match start {
+                        Some(idx) => Ok(Selector::Index(idx)),
+                        None => Err(self.unexpected())
+                    };
+                }
+                self.pos += 1;
+                self.skip_whitespace();
+                let end: Option<i64> = self.parse_optional_int()?;
+                self.skip_whitespace();
+                let step: Option<i64> = if self.peek() == Some(b':') {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    self.parse_optional_int()?
+                } else {
+                    None
+                };
+                Ok(Selector::Slice(start, end, step))
+            }
+        }
+    }
+
+    fn parse_optional_int(&mut self) -> Result<Option<i64>, QueryError> {
+        let start: usize = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        let digits_start: usize = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        if self.pos == digits_start {
+            if self.pos == start {
+                return Ok(None);
+            }
+            return Err(self.error_at(start, QueryErrorKind::InvalidNumber));
+        }
+        self.input[start..self.pos].parse()
+            .map(Some)
+            .map_err(|_| self.error_at(start, QueryErrorKind::InvalidNumber))
+    }
+
+    /// A string in single or double quotes, with JSON escapes plus `\'`
+    fn parse_string(&mut self) -> Result<String, QueryError> {
+        let quote: u8 = self.peek().unwrap();
+        self.pos += 1;
+        let mut ret: String = String::new();
+        let mut run_start: usize = self.pos;
+        loop {
+            match self.peek() {
+                None => return Err(self.error_at(self.pos, QueryErrorKind::UnexpectedEof)),
+                Some(b) if b == quote => {
+                    ret.push_str(&self.input[run_start..self.pos]);
+                    self.pos += 1;
+                    return Ok(ret);
+                },
+                Some(b'\\') => {
+                    ret.push_str(&self.input[run_start..self.pos]);
+                    ret.push(self.parse_escape()?);
+                    run_start = self.pos;
+                },
+                Some(_) => self.pos += 1
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, QueryError> {
+        let escape_start: usize = self.pos;
+        self.pos += 1;
+        let ch: char = match self.peek() {
+            Some(b'\'') => '\'',
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.pos += 1;
+                let high: u32 = self.parse_hex4()?;
+                let code_point: u32 = if (0xd800..0xdc00).contains(&high) {
+                    if !self.eat_literal("\\u") {
+                        return Err(self.error_at(escape_start, QueryErrorKind::InvalidEscape));
+                    }
+                    let low: u32 = self.parse_hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error_at(escape_start, QueryErrorKind::InvalidEscape));
+                    }
+                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                } else {
+                    high
+                };
+                return char::from_u32(code_point)
+                    .ok_or_else(|| self.error_at(escape_start, QueryErrorKind::InvalidEscape));
+            },
+            None => return Err(self.error_at(self.pos, QueryErrorKind::UnexpectedEof)),
+            Some(_) => return Err(self.error_at(escape_start, QueryErrorKind::InvalidEscape))
+        };
+        self.pos += 1;
+        Ok(ch)
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, QueryError> {
+        let digits: &str = self.input.get(self.pos..self.pos + 4)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error_at(self.pos, QueryErrorKind::InvalidEscape))?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(digits, 16).unwrap())
+    }
+
+    fn parse_or(&mut self) -> Result<Filter, QueryError> {
+        let mut ret: Filter = self.parse_and()?;
+        loop {
+            self.skip_whitespace();
+            if !self.eat_literal("||") {
+                return Ok(ret);
+            }
+            ret = Filter::Or(Box::new(ret), Box::new(self.parse_and()?));
+        }
+    }
+
+    fn parse_and(&mut self) -> Result<Filter, QueryError> {
+        let mut ret: Filter = self.parse_unary()?;
+        loop {
+            self.skip_whitespace();
+            if !self.eat_literal("&&") {
+                return Ok(ret);
+            }
+            ret = Filter::And(Box::new(ret), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Filter, QueryError> {
+        self.skip_whitespace();
+        if self.depth >= MAX_FILTER_DEPTH {
+            return Err(self.error_at(self.pos, QueryErrorKind::DepthLimitExceeded));
+        }
+        self.depth += 1;
+        let ret: Result<Filter, QueryError> = self.parse_unary_impl();
+        self.depth -= 1;
+        ret
+    }
+
+    fn parse_unary_impl(&mut self) -> Result<Filter, QueryError> {
+        match self.peek() {
+            Some(b'!') if !self.input[self.pos..].starts_with("!=") => {
+                self.pos += 1;
+                Ok(Filter::Not(Box::new(self.parse_unary()?)))
+            },
+            Some(b'(') => {
+                self.pos += 1;
+                let ret: Filter = self.parse_or()?;
+                self.skip_whitespace();
+                self.expect(b')')?;
+                Ok(ret)
+            },
+            _ => self.parse_comparison()
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<Filter, QueryError> {
+        let lhs_start: usize = self.pos;
+        let lhs: Operand = self.parse_operand()?;
+        self.skip_whitespace();
+        let operators: &[(&str, CompareOp)] = &[
+            ("==", CompareOp::Eq),
+            ("!=", CompareOp::Ne),
+            ("<=", CompareOp::Le),
+            (">=", CompareOp::Ge),
+            ("<", CompareOp::Lt),
+            (">", CompareOp::Gt)
+        ];
+        for (literal, op) in operators {
+            if self.eat_literal(literal) {
+                self.skip_whitespace();
+                let rhs: Operand = self.parse_operand()?;
+                return Ok(Filter::Compare(lhs, *op, rhs));
+            }
+        }
+        match lhs {
+            Operand::Literal(_) => {
+                self.pos = lhs_start;
+                Err(self.unexpected())
+            },
+            path => Ok(Filter::Exists(path))
+        }
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand, QueryError> {
+        match self.peek() {
+            Some(b'@') => {
+                self.pos += 1;
+                Ok(Operand::Current(self.parse_steps()?))
+            },
+            Some(b'$') => {
+                self.pos += 1;
+                Ok(Operand::Root(self.parse_steps()?))
+            },
+            Some(b'\'' | b'"') => Ok(Operand::Literal(Value::String(self.parse_string()?))),
+            Some(b'-' | b'0'..=b'9') => {
+                let start: usize = self.pos;
+                while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+                    self.pos += 1;
+                }
+                Value::from_json(&self.input[start..self.pos])
+                    .map(Operand::Literal)
+                    .map_err(|_| self.error_at(start, QueryErrorKind::InvalidNumber))
+            },
+            _ => {
+                let literals: &[(&str, Value)] = &[
+                    ("null", Value::Nil),
+                    ("true", Value::Bool(true)),
+                    ("false", Value::Bool(false))
+                ];
+                for (literal, value) in literals {
+                    if self.eat_literal(literal) {
+                        return Ok(Operand::Literal(value.clone()));
+                    }
+                }
+                Err(self.unexpected())
+            }
+        }
+    }
+
+    /// Steps of a path in a filter, which must select a single node
+    fn parse_steps(&mut self) -> Result<Vec<Step>, QueryError> {
+        let mut ret: Vec<Step> = Vec::new();
+        loop {
+            match self.peek() {
+                Some(b'.') => {
+                    self.pos += 1;
+                    ret.push(Step::Name(self.parse_name()?));
+                },
+                Some(b'[') => {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    let step: Step = match self.peek() {
+                        Some(b'\'' | b'"') => Step::Name(self.parse_string()?),
+                        _ => match self.parse_optional_int()? {
+                            Some(idx) => Step::Index(idx),
+                            None => return Err(self.unexpected())
+                        }
+                    };
+                    self.skip_whitespace();
+                    self.expect(b']')?;
+                    ret.push(step);
+                },
+                _ => return Ok(ret)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::value::Value;
+    use crate::value::query::{MAX_FILTER_DEPTH, Query, QueryError, QueryErrorKind};
+
+    fn store() -> Value {
+        Value::from_json(r#"{"store": {
+            "book": [
+                {"category": "reference", "author": "Nigel Rees", "price": 8.95},
+                {"category": "fiction", "author": "Evelyn Waugh", "price": 12.99},
+                {"category": "fiction", "author": "Herman Melville", "isbn": "0-553-21311-3", "price": 8.99},
+                {"category": "fiction", "author": "J. R. R. Tolkien", "isbn": "0-395-19395-8", "price": 22.99}
+            ],
+            "bicycle": {"color": "red", "price": 19.95}
+        }, "limit": 10}"#).unwrap()
+    }
+
+    fn paths(value: &Value, query: &str) -> Vec<String> {
+        value.query(query).unwrap().into_iter().map(|m| m.path).collect()
+    }
+
+    fn query_error(query: &str) -> (QueryErrorKind, usize) {
+        let err: QueryError = Query::parse(query).unwrap_err();
+        (err.kind, err.column)
+    }
+
+    #[test]
+    fn test_select() {
+        let value: Value = store();
+        assert_eq!(paths(&value, "$"), vec![""]);
+        assert_eq!(paths(&value, "$.store.bicycle.color"), vec!["/store/bicycle/color"]);
+        assert_eq!(paths(&value, "$['store'][\"bicycle\"]"), vec!["/store/bicycle"]);
+        assert_eq!(paths(&value, "$.store.*"), vec!["/store/book", "/store/bicycle"]);
+        assert_eq!(paths(&value, "$.store.book[-1].author"), vec!["/store/book/3/author"]);
+        assert_eq!(paths(&value, "$.store.book[0, 2].price"), vec![
+            "/store/book/0/price", "/store/book/2/price"
+        ]);
+        assert_eq!(paths(&value, "$.store.book[4]"), Vec::<String>::new());
+        assert_eq!(paths(&value, "$.limit.x[*]"), Vec::<String>::new());
+
+        let authors: Vec<Value> = value.query("$..author").unwrap()
+            .into_iter()
+            .map(|m| m.value.clone())
+            .collect();
+        assert_eq!(authors, vec![
+            Value::from("Nigel Rees"),
+            Value::from("Evelyn Waugh"),
+            Value::from("Herman Melville"),
+            Value::from("J. R. R. Tolkien")
+        ]);
+        assert_eq!(paths(&value, "$..price").len(), 5);
+        assert_eq!(paths(&value, "$.store..[0]"), vec!["/store/book/0"]);
+        assert_eq!(paths(&value, "$..*").len(), 24);
+
+        let value: Value = Value::from_json(r#"{"a/b": {"~": 1}}"#).unwrap();
+        assert_eq!(paths(&value, "$['a/b']['~']"), vec!["/a~1b/~0"]);
+    }
+
+    #[test]
+    fn test_slice() {
+        let value: Value = Value::from((0..6i64).collect::<Vec<_>>());
+        let ints = |query: &str| -> Vec<Value> {
+            value.query(query).unwrap().into_iter().map(|m| m.value.clone()).collect()
+        };
+        let expected = |ints: &[i64]| -> Vec<Value> { ints.iter().map(|i| Value::Int(*i)).collect() };
+        assert_eq!(ints("$[1:3]"), expected(&[1, 2]));
+        assert_eq!(ints("$[:2]"), expected(&[0, 1]));
+        assert_eq!(ints("$[4:]"), expected(&[4, 5]));
+        assert_eq!(ints("$[-2:]"), expected(&[4, 5]));
+        assert_eq!(ints("$[::2]"), expected(&[0, 2, 4]));
+        assert_eq!(ints("$[::-1]"), expected(&[5, 4, 3, 2, 1, 0]));
+        assert_eq!(ints("$[4:1:-2]"), expected(&[4, 2]));
+        assert_eq!(ints("$[-100:100]"), expected(&[0, 1, 2, 3, 4, 5]));
+        assert_eq!(ints("$[::0]"), expected(&[]));
+        assert_eq!(ints("$[1::9223372036854775807]"), expected(&[1]));
+        assert_eq!(ints("$[4::-9223372036854775808]"), expected(&[4]));
+    }
+
+    #[test]
+    fn test_filter() {
+        let value: Value = store();
+        assert_eq!(paths(&value, "$.store.book[?(@.price < 10)]"), vec![
+            "/store/book/0", "/store/book/2"
+        ]);
+        assert_eq!(paths(&value, "$.store.book[?(@.price < $.limit)]"), vec![
+            "/store/book/0", "/store/book/2"
+        ]);
+        assert_eq!(paths(&value, "$.store.book[?@.isbn].author"), vec![
+            "/store/book/2/author", "/store/book/3/author"
+        ]);
+        assert_eq!(paths(&value, "$.store.book[?(!@.isbn)]"), vec![
+            "/store/book/0", "/store/book/1"
+        ]);
+        assert_eq!(
+            paths(&value, "$.store.book[?(@.category == 'fiction' && (@.price < 10 || @.price > 20))]"),
+            vec!["/store/book/2", "/store/book/3"]
+        );
+        assert_eq!(paths(&value, "$..[?(@.color == \"red\")]"), vec!["/store/bicycle"]);
+        assert_eq!(paths(&value, "$.store.book[?(@.price >= 22.99)]"), vec!["/store/book/3"]);
+        assert_eq!(paths(&value, "$.store.book[?(@.author > 'I')]"), vec![
+            "/store/book/0", "/store/book/3"
+        ]);
+
+        let value: Value = Value::from_json(r#"[1, 2.0, "2", [2], {"a": [1, 2]}, null]"#).unwrap();
+        assert_eq!(paths(&value, "$[?(@ == 2)]"), vec!["/1"]);
+        assert_eq!(paths(&value, "$[?(@ >= 1)]"), vec!["/0", "/1"]);
+        assert_eq!(paths(&value, "$[?(@ == null)]"), vec!["/5"]);
+        assert_eq!(paths(&value, "$[?(@.a[-1] == 2)]"), vec!["/4"]);
+        assert_eq!(paths(&value, "$[?(@.x == @.y)]").len(), 6);
+        // `!=` negates `==`, so a missing member is unequal to anything but another missing one
+        assert_eq!(paths(&value, "$[?(@.x != 1)]").len(), 6);
+        assert_eq!(paths(&value, "$[?(@.x != @.y)]").len(), 0);
+        assert_eq!(paths(&value, "$[?(@.x < 1 || @.x >= 1)]").len(), 0);
+
+        // integers are compared exactly with floats, beyond the 53 bits of precision of the latter
+        let value: Value = Value::from_json("[9007199254740993, 18446744073709551615, -1]").unwrap();
+        assert_eq!(paths(&value, "$[?(@ == 9007199254740992.0)]"), Vec::<String>::new());
+        assert_eq!(paths(&value, "$[?(@ > 9007199254740992.0)]"), vec!["/0", "/1"]);
+        assert_eq!(paths(&value, "$[?(@ < 18446744073709551616.0)]"), vec!["/0", "/1", "/2"]);
+        assert_eq!(paths(&value, "$[?(@ > -1.5 && @ < -0.5)]"), vec!["/2"]);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert_eq!(query_error(""), (QueryErrorKind::UnexpectedEof, 1));
+        assert_eq!(query_error("a"), (QueryErrorKind::UnexpectedCharacter('a'), 1));
+        assert_eq!(query_error("$.a["), (QueryErrorKind::UnexpectedEof, 5));
+        assert_eq!(query_error("$.1"), (QueryErrorKind::UnexpectedCharacter('1'), 3));
+        assert_eq!(query_error("$[1 2]"), (QueryErrorKind::UnexpectedCharacter('2'), 5));
+        assert_eq!(query_error("$[99999999999999999999]"), (QueryErrorKind::InvalidNumber, 3));
+        assert_eq!(query_error("$['a\\x']"), (QueryErrorKind::InvalidEscape, 5));
+        assert_eq!(query_error("$['a"), (QueryErrorKind::UnexpectedEof, 5));
+        assert_eq!(query_error("$[?(@.a < )]"), (QueryErrorKind::UnexpectedCharacter(')'), 11));
+        assert_eq!(query_error("$[?(1)]"), (QueryErrorKind::UnexpectedCharacter('1'), 5));
+        assert_eq!(query_error("$[?(@.a == 1.)]"), (QueryErrorKind::InvalidNumber, 12));
+        assert_eq!(query_error("$.a b"), (QueryErrorKind::UnexpectedCharacter('b'), 5));
+
+        let nested: String = format!("$[?{}@.a]", "!".repeat(MAX_FILTER_DEPTH));
+        assert_eq!(query_error(&nested), (QueryErrorKind::DepthLimitExceeded, MAX_FILTER_DEPTH + 4));
+        let nested: String = format!("$[?{}@.a{}]", "(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(query_error(&nested).0, QueryErrorKind::DepthLimitExceeded);
+        let nested: String = format!("$[?{}@.a]", "!".repeat(MAX_FILTER_DEPTH - 1));
+        assert!(Query::parse(&nested).is_ok());
+    }
+}
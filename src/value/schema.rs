@@ -0,0 +1,625 @@
+//! Validating `Value`s against schemas, a practical subset of JSON Schema
+//!
+//! Supported keywords are `type`, `enum`, `minimum`, `maximum`, `exclusiveMinimum`,
+//! `exclusiveMaximum`, `minLength`, `maxLength`, `minItems`, `maxItems`, `items`, `properties`,
+//! `required` and `additionalProperties`. Schemas can be built in code or read from a JSON Schema
+//! document with `Schema::from_value`, which ignores other keywords.
+//!
+//! ```
+//! # use xjbutil::value::{Schema, SchemaType, Value, ViolationKind};
+//! let schema: Schema = Schema::new()
+//!     .of_type(SchemaType::Object)
+//!     .property("name", Schema::new().of_type(SchemaType::String).min_length(1))
+//!     .property("port", Schema::new().of_type(SchemaType::Integer).minimum(1).maximum(65535))
+//!     .required(&["name", "port"])
+//!     .additional_properties(false);
+//!
+//! let value: Value = Value::from_json(r#"{"name": "", "port": 70000, "debug": true}"#).unwrap();
+//! let violations: Vec<(String, ViolationKind)> = schema.validate(&value).unwrap_err()
+//!     .into_iter()
+//!     .map(|violation| (violation.path, violation.kind))
+//!     .collect();
+//! assert_eq!(violations, vec![
+//!     ("/name".to_string(), ViolationKind::TooShort),
+//!     ("/port".to_string(), ViolationKind::AboveMaximum),
+//!     ("/debug".to_string(), ViolationKind::AdditionalProperty)
+//! ]);
+//! ```
+
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::value::{ToValue, Value, ValueMap};
+use crate::value::path::push_pointer_token;
+use crate::value::query::compare_values;
+
+/// Types a schema can require, named as in JSON Schema
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SchemaType {
+    Null,
+    Boolean,
+    /// `Value::Int`, `Value::UInt`, or a `Value::Float` without fractional part
+    Integer,
+    /// `Value::Int`, `Value::UInt` or `Value::Float`
+    Number,
+    String,
+    Array,
+    Object
+}
+
+impl SchemaType {
+    pub fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (SchemaType::Null, Value::Nil) => true,
+            (SchemaType::Boolean, Value::Bool(_)) => true,
+            (SchemaType::Integer, Value::Int(_) | Value::UInt(_)) => true,
+            (SchemaType::Integer, Value::Float(f)) => f.is_finite() && f.fract() == 0.0,
+            (SchemaType::Number, Value::Int(_) | Value::UInt(_) | Value::Float(_)) => true,
+            (SchemaType::String, Value::String(_)) => true,
+            (SchemaType::Array, Value::Array(_)) => true,
+            (SchemaType::Object, Value::Object(_)) => true,
+            _ => false
+        }
+    }
+
+    fn from_name(name: &str) -> Option<SchemaType> {
+        Some(match name {
+            "null" => SchemaType::Null,
+            "boolean" => SchemaType::Boolean,
+            "integer" => SchemaType::Integer,
+            "number" => SchemaType::Number,
+            "string" => SchemaType::String,
+            "array" => SchemaType::Array,
+            "object" => SchemaType::Object,
+            _ => return None
+        })
+    }
+}
+
+/// Ways in which a value violates a schema
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ViolationKind {
+    /// The value has none of the allowed types
+    TypeMismatch,
+    /// The value is not one of the allowed values
+    NotInEnum,
+    /// A number is smaller than `minimum`, or not greater than `exclusiveMinimum`
+    BelowMinimum,
+    /// A number is greater than `maximum`, or not smaller than `exclusiveMaximum`
+    AboveMaximum,
+    /// A string has fewer characters than `minLength`
+    TooShort,
+    /// A string has more characters than `maxLength`
+    TooLong,
+    /// An array has fewer elements than `minItems`
+    TooFewItems,
+    /// An array has more elements than `maxItems`
+    TooManyItems,
+    /// An object lacks a required property
+    MissingProperty(String),
+    /// An object has a property not allowed by `additionalProperties`
+    AdditionalProperty,
+    /// The schema is `false`, which no value satisfies
+    Rejected
+}
+
+impl Display for ViolationKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ViolationKind::TypeMismatch => write!(f, "unexpected type"),
+            ViolationKind::NotInEnum => write!(f, "not one of the allowed values"),
+            ViolationKind::BelowMinimum => write!(f, "below minimum"),
+            ViolationKind::AboveMaximum => write!(f, "above maximum"),
+            ViolationKind::TooShort => write!(f, "string too short"),
+            ViolationKind::TooLong => write!(f, "string too long"),
+            ViolationKind::TooFewItems => write!(f, "too few items"),
+            ViolationKind::TooManyItems => write!(f, "too many items"),
+            ViolationKind::MissingProperty(name) => write!(f, "missing property {:?}", name),
+            ViolationKind::AdditionalProperty => write!(f, "property not allowed"),
+            ViolationKind::Rejected => write!(f, "no value allowed")
+        }
+    }
+}
+
+/// A violation of a schema, with the JSON Pointer of the offending value
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SchemaViolation {
+    pub path: String,
+    pub kind: ViolationKind
+}
+
+impl Display for SchemaViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{} at {}", self.kind, self.path)
+        }
+    }
+}
+
+impl Error for SchemaViolation {}
+
+#[derive(Clone, Debug)]
+enum AdditionalProperties {
+    Allow,
+    Deny,
+    Schema(Box<Schema>)
+}
+
+/// A schema for `Value`s. Keywords which do not apply to the type of a value are ignored, so
+/// `min_length` only constrains strings.
+#[derive(Clone, Debug)]
+pub struct Schema {
+    reject_all: bool,
+    types: Vec<SchemaType>,
+    enum_values: Option<Vec<Value>>,
+    minimum: Option<Value>,
+    exclusive_minimum: Option<Value>,
+    maximum: Option<Value>,
+    exclusive_maximum: Option<Value>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+    items: Option<Box<Schema>>,
+    properties: Vec<(String, Schema)>,
+    required: Vec<String>,
+    additional_properties: AdditionalProperties
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Schema {
+    /// Creates a schema accepting every value
+    pub fn new() -> Self {
+        Self {
+            reject_all: false,
+            types: Vec::new(),
+            enum_values: None,
+            minimum: None,
+            exclusive_minimum: None,
+            maximum: None,
+            exclusive_maximum: None,
+            min_length: None,
+            max_length: None,
+            min_items: None,
+            max_items: None,
+            items: None,
+            properties: Vec::new(),
+            required: Vec::new(),
+            additional_properties: AdditionalProperties::Allow
+        }
+    }
+
+    /// Creates a schema rejecting every value, like `false` in JSON Schema
+    pub fn reject_all() -> Self {
+        Self { reject_all: true, ..Self::new() }
+    }
+
+    /// Allows values of the type. Calling this several times allows any of the types.
+    pub fn of_type(mut self, ty: SchemaType) -> Self {
+        self.types.push(ty);
+        self
+    }
+
+    /// Allows only the given values, comparing numbers numerically
+    pub fn enum_values(mut self, values: Vec<Value>) -> Self {
+        self.enum_values = Some(values);
+        self
+    }
+
+    /// Smallest allowed number. Integer bounds are compared exactly, also beyond the precision of
+    /// `f64`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `minimum` is not a number. The other bounds behave alike.
+    pub fn minimum(mut self, minimum: impl ToValue) -> Self {
+        self.minimum = Some(number_bound(minimum));
+        self
+    }
+
+    pub fn exclusive_minimum(mut self, minimum: impl ToValue) -> Self {
+        self.exclusive_minimum = Some(number_bound(minimum));
+        self
+    }
+
+    pub fn maximum(mut self, maximum: impl ToValue) -> Self {
+        self.maximum = Some(number_bound(maximum));
+        self
+    }
+
+    pub fn exclusive_maximum(mut self, maximum: impl ToValue) -> Self {
+        self.exclusive_maximum = Some(number_bound(maximum));
+        self
+    }
+
+    /// Minimum length of strings, in characters
+    pub fn min_length(mut self, length: usize) -> Self {
+        self.min_length = Some(length);
+        self
+    }
+
+    /// Maximum length of strings, in characters
+    pub fn max_length(mut self, length: usize) -> Self {
+        self.max_length = Some(length);
+        self
+    }
+
+    pub fn min_items(mut self, count: usize) -> Self {
+        self.min_items = Some(count);
+        self
+    }
+
+    pub fn max_items(mut self, count: usize) -> Self {
+        self.max_items = Some(count);
+        self
+    }
+
+    /// Schema of every array element
+    pub fn items(mut self, schema: Schema) -> Self {
+        self.items = Some(Box::new(schema));
+        self
+    }
+
+    /// Schema of an object property, if present
+    pub fn property(mut self, name: impl Into<String>, schema: Schema) -> Self {
+        self.properties.push((name.into(), schema));
+        self
+    }
+
+    pub fn required(mut self, names: &[&str]) -> Self {
+        self.required.extend(names.iter().map(|name| name.to_string()));
+        self
+    }
+
+    /// Whether objects may have properties not declared with `property`, allowed by default
+    pub fn additional_properties(mut self, allow: bool) -> Self {
+        self.additional_properties = if allow {
+            AdditionalProperties::Allow
+        } else {
+            AdditionalProperties::Deny
+        };
+        self
+    }
+
+    /// Schema of object properties not declared with `property`
+    pub fn additional_properties_schema(mut self, schema: Schema) -> Self {
+        self.additional_properties = AdditionalProperties::Schema(Box::new(schema));
+        self
+    }
+
+    /// Reads a schema from a JSON Schema document, failing with a description of the first
+    /// malformed keyword
+    pub fn from_value(value: &Value) -> Result<Schema, String> {
+        read_schema(value, &mut String::new())
+    }
+
+    pub fn is_valid(&self, value: &Value) -> bool {
+        let mut violations: Vec<SchemaViolation> = Vec::new();
+        self.check(value, &mut String::new(), &mut violations);
+        violations.is_empty()
+    }
+
+    /// Validates a value, returning all violations in document order if there are any
+    pub fn validate(&self, value: &Value) -> Result<(), Vec<SchemaViolation>> {
+        let mut violations: Vec<SchemaViolation> = Vec::new();
+        self.check(value, &mut String::new(), &mut violations);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    fn check(&self, value: &Value, path: &mut String, out: &mut Vec<SchemaViolation>) {
+        let mut violation = |kind: ViolationKind| out.push(SchemaViolation { path: path.clone(), kind });
+        if self.reject_all {
+            violation(ViolationKind::Rejected);
+            return;
+        }
+        if !self.types.is_empty() && !self.types.iter().any(|ty| ty.matches(value)) {
+            violation(ViolationKind::TypeMismatch);
+        }
+        if let Some(values) = &self.enum_values {
+            if !values.iter().any(|allowed| compare_values(value, allowed) == Some(Ordering::Equal)) {
+                violation(ViolationKind::NotInEnum);
+            }
+        }
+
+        match value {
+            Value::Int(_) | Value::UInt(_) | Value::Float(_) => {
+                // integers are compared exactly with integer bounds, not through `f64`
+                let compare = |bound: &Option<Value>| {
+                    bound.as_ref().and_then(|bound| compare_values(value, bound))
+                };
+                let below_minimum: bool = compare(&self.minimum) == Some(Ordering::Less)
+                    || matches!(compare(&self.exclusive_minimum), Some(Ordering::Less | Ordering::Equal));
+                if below_minimum {
+                    violation(ViolationKind::BelowMinimum);
+                }
+                let above_maximum: bool = compare(&self.maximum) == Some(Ordering::Greater)
+                    || matches!(compare(&self.exclusive_maximum), Some(Ordering::Greater | Ordering::Equal));
+                if above_maximum {
+                    violation(ViolationKind::AboveMaximum);
+                }
+            },
+            Value::String(s) => {
+                let length: usize = s.chars().count();
+                if matches!(self.min_length, Some(min) if length < min) {
+                    violation(ViolationKind::TooShort);
+                }
+                if matches!(self.max_length, Some(max) if length > max) {
+                    violation(ViolationKind::TooLong);
+                }
+            },
+            Value::Array(a) => {
+                if matches!(self.min_items, Some(min) if a.len() < min) {
+                    violation(ViolationKind::TooFewItems);
+                }
+                if matches!(self.max_items, Some(max) if a.len() > max) {
+                    violation(ViolationKind::TooManyItems);
+                }
+                if let Some(items) = &self.items {
+                    for (idx, item) in a.iter().enumerate() {
+                        let len: usize = path.len();
+                        push_pointer_token(path, &idx.to_string());
+                        items.check(item, path, out);
+                        path.truncate(len);
+                    }
+                }
+            },
+            Value::Object(o) => self.check_object(o, path, out),
+            _ => {}
+        }
+    }
+
+    fn check_object(&self, object: &ValueMap, path: &mut String, out: &mut Vec<SchemaViolation>) {
+        for name in &self.required {
+            if !object.contains_key(name) {
+                out.push(SchemaViolation {
+                    path: path.clone(),
+                    kind: ViolationKind::MissingProperty(name.clone())
+                });
+            }
+        }
+        for (key, value) in object {
+            let len: usize = path.len();
+            push_pointer_token(path, key);
+            match self.properties.iter().find(|(name, _)| name == key) {
+                Some((_, schema)) => schema.check(value, path, out),
+                None => match &self.additional_properties {
+                    AdditionalProperties::Allow => {},
+                    AdditionalProperties::Deny => out.push(SchemaViolation {
+                        path: path.clone(),
+                        kind: ViolationKind::AdditionalProperty
+                    }),
+                    AdditionalProperties::Schema(schema) => schema.check(value, path, out)
+                }
+            }
+            path.truncate(len);
+        }
+    }
+}
+
+fn number_bound(bound: impl ToValue) -> Value {
+    let bound: Value = bound.to_value();
+    assert!(
+        matches!(bound, Value::Int(_) | Value::UInt(_) | Value::Float(_)),
+        "bound {:?} is not a number", bound
+    );
+    bound
+}
+
+fn read_schema(value: &Value, path: &mut String) -> Result<Schema, String> {
+    let object: &ValueMap = match value {
+        Value::Bool(true) => return Ok(Schema::new()),
+        Value::Bool(false) => return Ok(Schema::reject_all()),
+        Value::Object(o) => o,
+        _ => return Err(format!("{}: schema must be an object or a bool", display_path(path)))
+    };
+
+    let mut ret: Schema = Schema::new();
+    for (key, value) in object {
+        let len: usize = path.len();
+        push_pointer_token(path, key);
+        let error = |expected: &str| format!("{}: expected {}", display_path(path), expected);
+        match key.as_str() {
+            "type" => {
+                let names: Vec<&Value> = match value {
+                    Value::Array(a) => a.iter().collect(),
+                    name => vec![name]
+                };
+                for name in names {
+                    let ty: SchemaType = match name {
+                        Value::String(name) => SchemaType::from_name(name),
+                        _ => None
+                    }.ok_or_else(|| error("a type name or an array of type names"))?;
+                    ret.types.push(ty);
+                }
+            },
+            "enum" => match value {
+                Value::Array(a) => ret.enum_values = Some(a.clone()),
+                _ => return Err(error("an array"))
+            },
+            "minimum" | "exclusiveMinimum" | "maximum" | "exclusiveMaximum" => {
+                let bound: Value = match value {
+                    Value::Int(_) | Value::UInt(_) | Value::Float(_) => value.clone(),
+                    _ => return Err(error("a number"))
+                };
+                match key.as_str() {
+                    "minimum" => ret.minimum = Some(bound),
+                    "exclusiveMinimum" => ret.exclusive_minimum = Some(bound),
+                    "maximum" => ret.maximum = Some(bound),
+                    _ => ret.exclusive_maximum = Some(bound)
+                }
+            },
+            "minLength" | "maxLength" | "minItems" | "maxItems" => {
+                let count: usize = match value {
+                    Value::Int(i) if *i >= 0 => *i as usize,
+                    _ => return Err(error("a non-negative integer"))
+                };
+                match key.as_str() {
+                    "minLength" => ret.min_length = Some(count),
+                    "maxLength" => ret.max_length = Some(count),
+                    "minItems" => ret.min_items = Some(count),
+                    _ => ret.max_items = Some(count)
+                }
+            },
+            "items" => ret.items = Some(Box::new(read_schema(value, path)?)),
+            "properties" => match value {
+                Value::Object(properties) => for (name, schema) in properties {
+                    let len: usize = path.len();
+                    push_pointer_token(path, name);
+                    ret.properties.push((name.clone(), read_schema(schema, path)?));
+                    path.truncate(len);
+                },
+                _ => return Err(error("an object"))
+            },
+            "required" => match value {
+                Value::Array(a) => for name in a {
+                    match name {
+                        Value::String(name) => ret.required.push(name.clone()),
+                        _ => return Err(error("an array of strings"))
+                    }
+                },
+                _ => return Err(error("an array of strings"))
+            },
+            "additionalProperties" => ret.additional_properties = match value {
+                Value::Bool(true) => AdditionalProperties::Allow,
+                Value::Bool(false) => AdditionalProperties::Deny,
+                schema => AdditionalProperties::Schema(Box::new(read_schema(schema, path)?))
+            },
+            _ => {}
+        }
+        path.truncate(len);
+    }
+    Ok(ret)
+}
+
+fn display_path(path: &str) -> &str {
+    if path.is_empty() { "/" } else { path }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::value::Value;
+    use crate::value::schema::{Schema, SchemaType, SchemaViolation, ViolationKind};
+
+    fn violations(schema: &Schema, value: &str) -> Vec<(String, ViolationKind)> {
+        match schema.validate(&Value::from_json(value).unwrap()) {
+            Ok(()) => vec![],
+            Err(violations) => violations.into_iter()
+                .map(|SchemaViolation { path, kind }| (path, kind))
+                .collect()
+        }
+    }
+
+    fn at(path: &str, kind: ViolationKind) -> (String, ViolationKind) {
+        (path.to_string(), kind)
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema: Schema = Schema::new()
+            .of_type(SchemaType::Object)
+            .property("id", Schema::new().of_type(SchemaType::Integer).exclusive_minimum(0))
+            .property("tags", Schema::new()
+                .of_type(SchemaType::Array)
+                .max_items(2)
+                .items(Schema::new().of_type(SchemaType::String).max_length(3)))
+            .property("level", Schema::new().enum_values(vec![Value::Int(1), Value::from("max")]))
+            .property("ratio", Schema::new().of_type(SchemaType::Number).of_type(SchemaType::Null).maximum(1.0))
+            .required(&["id", "name"])
+            .additional_properties_schema(Schema::new().of_type(SchemaType::Boolean));
+
+        assert!(schema.is_valid(&Value::from_json(
+            r#"{"id": 1.0, "name": true, "tags": ["ab", "ŝŝŝ"], "level": 1.0, "ratio": null}"#
+        ).unwrap()));
+        assert_eq!(violations(&schema, "[]"), vec![at("", ViolationKind::TypeMismatch)]);
+        assert_eq!(
+            violations(&schema, r#"{"id": 0, "tags": ["a", "abcd", 3], "level": 2, "ratio": 1.5, "x~": 1}"#),
+            vec![
+                at("", ViolationKind::MissingProperty("name".into())),
+                at("/id", ViolationKind::BelowMinimum),
+                at("/tags", ViolationKind::TooManyItems),
+                at("/tags/1", ViolationKind::TooLong),
+                at("/tags/2", ViolationKind::TypeMismatch),
+                at("/level", ViolationKind::NotInEnum),
+                at("/ratio", ViolationKind::AboveMaximum),
+                at("/x~0", ViolationKind::TypeMismatch)
+            ]
+        );
+        assert_eq!(
+            violations(&schema, r#"{"id": 2.5, "name": false}"#),
+            vec![at("/id", ViolationKind::TypeMismatch)]
+        );
+        assert!(Schema::new().is_valid(&Value::Nil));
+        assert_eq!(violations(&Schema::reject_all(), "null"), vec![at("", ViolationKind::Rejected)]);
+
+        let schema: Schema = Schema::new().minimum(-9007199254740993i64).exclusive_maximum(u64::MAX);
+        assert_eq!(violations(&schema, "-9007199254740992"), vec![]);
+        assert_eq!(violations(&schema, "-9007199254740993"), vec![]);
+        assert_eq!(violations(&schema, "-9007199254740994"), vec![at("", ViolationKind::BelowMinimum)]);
+        assert_eq!(violations(&schema, "18446744073709551614"), vec![]);
+        assert_eq!(violations(&schema, "18446744073709551615"), vec![at("", ViolationKind::AboveMaximum)]);
+    }
+
+    #[test]
+    fn test_from_value() {
+        let schema: Schema = Schema::from_value(&Value::from_json(r#"{
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "minLength": 1},
+                "ports": {"type": "array", "minItems": 1, "items": {"type": "integer", "minimum": 1, "maximum": 65535}},
+                "mode": {"enum": ["a", "b"]}
+            },
+            "required": ["name"],
+            "additionalProperties": false
+        }"#).unwrap()).unwrap();
+        assert_eq!(violations(&schema, r#"{"name": "x", "ports": [80, 443], "mode": "a"}"#), vec![]);
+        assert_eq!(
+            violations(&schema, r#"{"ports": [0], "mode": "c", "extra": 1}"#),
+            vec![
+                at("", ViolationKind::MissingProperty("name".into())),
+                at("/ports/0", ViolationKind::BelowMinimum),
+                at("/mode", ViolationKind::NotInEnum),
+                at("/extra", ViolationKind::AdditionalProperty)
+            ]
+        );
+        assert_eq!(violations(&schema, r#"{"name": "", "ports": []}"#), vec![
+            at("/name", ViolationKind::TooShort),
+            at("/ports", ViolationKind::TooFewItems)
+        ]);
+
+        let schema: Schema = Schema::from_value(&Value::from_json(
+            r#"{"minimum": 0, "exclusiveMinimum": 1, "exclusiveMaximum": 10, "maximum": 5}"#
+        ).unwrap()).unwrap();
+        assert_eq!(violations(&schema, "1"), vec![at("", ViolationKind::BelowMinimum)]);
+        assert_eq!(violations(&schema, "5"), vec![]);
+        assert_eq!(violations(&schema, "5.5"), vec![at("", ViolationKind::AboveMaximum)]);
+
+        let schema: Schema = Schema::from_value(&Value::from_json(
+            r#"{"maximum": 9007199254740992, "exclusiveMinimum": -9007199254740993}"#
+        ).unwrap()).unwrap();
+        assert_eq!(violations(&schema, "9007199254740992"), vec![]);
+        assert_eq!(violations(&schema, "9007199254740993"), vec![at("", ViolationKind::AboveMaximum)]);
+        assert_eq!(violations(&schema, "-9007199254740992"), vec![]);
+        assert_eq!(violations(&schema, "-9007199254740993"), vec![at("", ViolationKind::BelowMinimum)]);
+
+        let schema: Schema = Schema::from_value(&Value::from_json(r#"{"items": false}"#).unwrap()).unwrap();
+        assert_eq!(violations(&schema, "[1]"), vec![at("/0", ViolationKind::Rejected)]);
+
+        let error = |input: &str| Schema::from_value(&Value::from_json(input).unwrap()).unwrap_err();
+        assert_eq!(error(r#"{"type": "float"}"#), "/type: expected a type name or an array of type names");
+        assert_eq!(error(r#"{"properties": {"a": {"minLength": -1}}}"#), "/properties/a/minLength: expected a non-negative integer");
+        assert_eq!(error("1"), "/: schema must be an object or a bool");
+    }
+}
@@ -0,0 +1,700 @@
+//! Serializing Rust types into `Value`s, treating `Value` as a serde data format
+//!
+//! The mapping is the inverse of `from_value`: structs and maps become objects, sequences and
+//! tuples arrays, enums are externally tagged. Map keys must serialize as strings, numbers, bools
+//! or unit variants, and are stored as strings.
+//!
+//! ```
+//! # use serde::Serialize;
+//! # use xjbutil::value::{to_value, Value};
+//! #[derive(Serialize)]
+//! struct Config {
+//!     name: &'static str,
+//!     port: Option<u16>,
+//!     tags: (bool, char)
+//! }
+//!
+//! let value: Value = to_value(&Config { name: "x", port: None, tags: (true, 'y') }).unwrap();
+//! assert_eq!(value.to_json(), r#"{"name":"x","port":null,"tags":[true,"y"]}"#);
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt::Display;
+
+use serde::ser::{self, Impossible, Serialize};
+
+use crate::value::{Value, ValueMap};
+use crate::value::de::SerdeError;
+
+/// Serializes a `T` into a `Value`
+pub fn to_value<T>(value: &T) -> Result<Value, SerdeError>
+    where T: Serialize + ?Sized
+{
+    value.serialize(ValueSerializer)
+}
+
+/// A serde `Serializer` producing `Value`s
+pub struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeArray;
+    type SerializeMap = SerializeObject;
+    type SerializeStruct = SerializeObject;
+    type SerializeStructVariant = SerializeObject;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, SerdeError> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, SerdeError> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, SerdeError> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, SerdeError> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, SerdeError> {
+        Ok(Value::Int(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, SerdeError> {
+        if let Ok(i) = i64::try_from(v) {
+            Ok(Value::Int(i))
+        } else if let Ok(u) = u64::try_from(v) {
+            Ok(Value::UInt(u))
+        } else {
+            Err(ser::Error::custom(format!("integer {} out of range", v)))
+        }
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, SerdeError> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, SerdeError> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, SerdeError> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, SerdeError> {
+        match u64::try_from(v) {
+            Ok(u) => Ok(Value::from(u)),
+            Err(_) => Err(ser::Error::custom(format!("integer {} out of range", v)))
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, SerdeError> {
+        Ok(Value::Float(v as f64))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, SerdeError> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, SerdeError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, SerdeError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, SerdeError> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Value, SerdeError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Value, SerdeError>
+        where T: Serialize + ?Sized
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, SerdeError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, SerdeError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str
+    ) -> Result<Value, SerdeError> {
+        Ok(Value::from(variant))
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Value, SerdeError>
+        where T: Serialize + ?Sized
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T
+    ) -> Result<Value, SerdeError>
+        where T: Serialize + ?Sized
+    {
+        let value: Value = to_value(value).map_err(|err| err.within(variant))?;
+        Ok(tagged(variant, value))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray, SerdeError> {
+        Ok(SerializeArray { variant: None, array: Vec::with_capacity(len.unwrap_or(0)) })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize
+    ) -> Result<SerializeArray, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize
+    ) -> Result<SerializeArray, SerdeError> {
+        Ok(SerializeArray { variant: Some(variant), array: Vec::with_capacity(len) })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeObject, SerdeError> {
+        Ok(SerializeObject {
+            variant: None,
+            object: ValueMap::with_capacity(len.unwrap_or(0)),
+            next_key: None
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeObject, SerdeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize
+    ) -> Result<SerializeObject, SerdeError> {
+        Ok(SerializeObject {
+            variant: Some(variant),
+            object: ValueMap::with_capacity(len),
+            next_key: None
+        })
+    }
+
+    fn collect_str<T>(self, value: &T) -> Result<Value, SerdeError>
+        where T: Display + ?Sized
+    {
+        Ok(Value::String(value.to_string()))
+    }
+}
+
+/// An object with the variant name as its only key, the representation of non-unit variants
+fn tagged(variant: &str, value: Value) -> Value {
+    let mut ret: ValueMap = ValueMap::with_capacity(1);
+    ret.insert(variant.to_string(), value);
+    Value::Object(ret)
+}
+
+/// Serializer of sequences, tuples and tuple variants
+pub struct SerializeArray {
+    variant: Option<&'static str>,
+    array: Vec<Value>
+}
+
+impl SerializeArray {
+    fn push<T>(&mut self, value: &T) -> Result<(), SerdeError>
+        where T: Serialize + ?Sized
+    {
+        let index: usize = self.array.len();
+        let value: Value = to_value(value).map_err(|err| {
+            let err: SerdeError = err.within(&index.to_string());
+            match self.variant {
+                Some(variant) => err.within(variant),
+                None => err
+            }
+        })?;
+        self.array.push(value);
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        match self.variant {
+            Some(variant) => tagged(variant, Value::Array(self.array)),
+            None => Value::Array(self.array)
+        }
+    }
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), SerdeError>
+        where T: Serialize + ?Sized
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), SerdeError>
+        where T: Serialize + ?Sized
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), SerdeError>
+        where T: Serialize + ?Sized
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeArray {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), SerdeError>
+        where T: Serialize + ?Sized
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.finish())
+    }
+}
+
+/// Serializer of maps, structs and struct variants
+pub struct SerializeObject {
+    variant: Option<&'static str>,
+    object: ValueMap,
+    next_key: Option<String>
+}
+
+impl SerializeObject {
+    fn insert<T>(&mut self, key: String, value: &T) -> Result<(), SerdeError>
+        where T: Serialize + ?Sized
+    {
+        let value: Value = to_value(value).map_err(|err| {
+            let err: SerdeError = err.within(&key);
+            match self.variant {
+                Some(variant) => err.within(variant),
+                None => err
+            }
+        })?;
+        self.object.insert(key, value);
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        match self.variant {
+            Some(variant) => tagged(variant, Value::Object(self.object)),
+            None => Value::Object(self.object)
+        }
+    }
+}
+
+impl ser::SerializeMap for SerializeObject {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), SerdeError>
+        where T: Serialize + ?Sized
+    {
+        self.next_key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), SerdeError>
+        where T: Serialize + ?Sized
+    {
+        let key: String = self.next_key.take()
+            .ok_or_else(|| ser::Error::custom("value serialized before key"))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStruct for SerializeObject {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), SerdeError>
+        where T: Serialize + ?Sized
+    {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeObject {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), SerdeError>
+        where T: Serialize + ?Sized
+    {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.finish())
+    }
+}
+
+/// Serializer of map keys into strings
+struct KeySerializer;
+
+fn key_must_be_a_string() -> SerdeError {
+    ser::Error::custom("map key must be a string, number, bool or unit variant")
+}
+
+macro_rules! serialize_key_to_string {
+    ($($method:ident($ty:ty)),*) => {
+        $(
+            fn $method(self, v: $ty) -> Result<String, SerdeError> {
+                Ok(v.to_string())
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = SerdeError;
+
+    type SerializeSeq = Impossible<String, SerdeError>;
+    type SerializeTuple = Impossible<String, SerdeError>;
+    type SerializeTupleStruct = Impossible<String, SerdeError>;
+    type SerializeTupleVariant = Impossible<String, SerdeError>;
+    type SerializeMap = Impossible<String, SerdeError>;
+    type SerializeStruct = Impossible<String, SerdeError>;
+    type SerializeStructVariant = Impossible<String, SerdeError>;
+
+    serialize_key_to_string! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, SerdeError> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_none(self) -> Result<String, SerdeError> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<String, SerdeError>
+        where T: Serialize + ?Sized
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<String, SerdeError> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, SerdeError> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str
+    ) -> Result<String, SerdeError> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<String, SerdeError>
+        where T: Serialize + ?Sized
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T
+    ) -> Result<String, SerdeError>
+        where T: Serialize + ?Sized
+    {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SerdeError> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SerdeError> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize
+    ) -> Result<Self::SerializeTupleStruct, SerdeError> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize
+    ) -> Result<Self::SerializeTupleVariant, SerdeError> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SerdeError> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, SerdeError> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize
+    ) -> Result<Self::SerializeStructVariant, SerdeError> {
+        Err(key_must_be_a_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::value::{from_value, to_value, Value};
+    use crate::value::de::SerdeError;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Unit;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Meters(f64);
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Pair(i32, String);
+
+    #[derive(Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+    enum Key {
+        A,
+        B
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Line(i32, i32),
+        Rect { w: u32, h: u32 }
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Everything {
+        unit: (),
+        unit_struct: Unit,
+        newtype: Meters,
+        tuple_struct: Pair,
+        tuple: (u8, char, bool),
+        option: Option<i64>,
+        nested_option: Option<Option<i64>>,
+        big: u64,
+        small: i8,
+        shapes: Vec<Shape>,
+        by_key: BTreeMap<Key, u16>,
+        by_number: HashMap<u32, String>,
+        #[serde(with = "bytes")]
+        bytes: Vec<u8>
+    }
+
+    mod bytes {
+        use serde::{Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(bytes)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+            struct BytesVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+                type Value = Vec<u8>;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    formatter.write_str("bytes")
+                }
+
+                fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+                    Ok(v)
+                }
+            }
+
+            deserializer.deserialize_byte_buf(BytesVisitor)
+        }
+    }
+
+    fn everything() -> Everything {
+        Everything {
+            unit: (),
+            unit_struct: Unit,
+            newtype: Meters(1.5),
+            tuple_struct: Pair(-1, "x".into()),
+            tuple: (255, 'c', true),
+            option: None,
+            nested_option: Some(None),
+            big: u64::MAX,
+            small: -128,
+            shapes: vec![Shape::Empty, Shape::Circle(2.0), Shape::Line(1, 2), Shape::Rect { w: 3, h: 4 }],
+            by_key: vec![(Key::B, 2), (Key::A, 1)].into_iter().collect(),
+            by_number: vec![(7, "seven".to_string())].into_iter().collect(),
+            bytes: vec![0, 255]
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value: Value = to_value(&everything()).unwrap();
+        let mut expected: Value = Value::from_json(r#"{
+            "unit": null, "unit_struct": null, "newtype": 1.5, "tuple_struct": [-1, "x"],
+            "tuple": [255, "c", true], "option": null, "nested_option": null,
+            "big": 18446744073709551615, "small": -128,
+            "shapes": ["Empty", {"Circle": 2.0}, {"Line": [1, 2]}, {"Rect": {"w": 3, "h": 4}}],
+            "by_key": {"A": 1, "B": 2}, "by_number": {"7": "seven"}
+        }"#).unwrap();
+        expected["bytes"] = Value::Bytes(vec![0, 255]);
+        assert_eq!(value, expected);
+
+        let mut decoded: Everything = from_value(value).unwrap();
+        assert_eq!(decoded.nested_option, None);
+        decoded.nested_option = Some(None);
+        assert_eq!(decoded, everything());
+
+        assert_eq!(from_value::<Value>(expected.clone()), Ok(expected.clone()));
+        assert_eq!(to_value(&expected), Ok(expected));
+    }
+
+    #[test]
+    fn test_error_path() {
+        let error = |json: &str| -> SerdeError {
+            from_value::<Vec<Everything>>(Value::from_json(json).unwrap()).unwrap_err()
+        };
+        let mut value: Value = to_value(&everything()).unwrap();
+        value["shapes"][3]["Rect"]["h"] = Value::Int(-1);
+        let err: SerdeError = from_value::<Vec<Everything>>(Value::from(vec![value])).unwrap_err();
+        assert_eq!(err.path, "/0/shapes/3/Rect/h");
+        assert_eq!(err.to_string(), format!("{} at /0/shapes/3/Rect/h", err.message));
+
+        let mut value: Value = to_value(&everything()).unwrap();
+        value["by_number"] = Value::from_json(r#"{"x": "y"}"#).unwrap();
+        let err: SerdeError = from_value::<Everything>(value).unwrap_err();
+        assert_eq!(err.path, "/by_number/x");
+
+        let mut value: Value = to_value(&everything()).unwrap();
+        value["shapes"][1] = Value::from("Hexagon");
+        assert_eq!(from_value::<Everything>(value).unwrap_err().path, "/shapes/1");
+
+        let mut value: Value = to_value(&everything()).unwrap();
+        value["tuple"] = Value::from(vec![1i64, 2]);
+        assert_eq!(from_value::<Everything>(value).unwrap_err().path, "/tuple/1");
+
+        assert_eq!(error(r#"[{}]"#).path, "/0");
+        assert_eq!(error(r#"{}"#).path, "");
+
+        let mut map: HashMap<Vec<u8>, i64> = HashMap::new();
+        map.insert(vec![1], 1);
+        assert_eq!(to_value(&vec![map]).unwrap_err().path, "/0");
+        assert_eq!(to_value(&Shape::Circle(f64::NAN)).map(|value| value.is_object()), Ok(true));
+        assert!(to_value(&u128::MAX).is_err());
+    }
+}
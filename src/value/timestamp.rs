@@ -0,0 +1,271 @@
+//! Points in time stored in `Value::Timestamp`, convertible to and from RFC 3339 strings
+
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::value::{ValueError, ValueErrorKind};
+
+/// A point in time, as seconds and nanoseconds since the Unix epoch, ignoring leap seconds
+///
+/// ```
+/// # use xjbutil::value::Timestamp;
+/// let timestamp: Timestamp = Timestamp::parse_rfc3339("2021-03-04T05:06:07.5+08:00").unwrap();
+/// assert_eq!(timestamp.secs(), 1614805567);
+/// assert_eq!(timestamp.to_rfc3339(), "2021-03-03T21:06:07.500Z");
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Timestamp {
+    secs: i64,
+    nanos: u32
+}
+
+impl Timestamp {
+    /// # Panics
+    ///
+    /// Panics if `nanos` is not less than one billion
+    pub fn new(secs: i64, nanos: u32) -> Self {
+        assert!(nanos < 1_000_000_000, "nanoseconds {} out of range", nanos);
+        Self { secs, nanos }
+    }
+
+    pub fn now() -> Self {
+        SystemTime::now().into()
+    }
+
+    /// Seconds since the Unix epoch, negative for earlier times
+    pub fn secs(&self) -> i64 {
+        self.secs
+    }
+
+    /// Nanoseconds after `secs`
+    pub fn nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    /// Formats in UTC like `2021-03-03T21:06:07Z`, with 3, 6 or 9 fraction digits if needed
+    pub fn to_rfc3339(&self) -> String {
+        let days: i64 = self.secs.div_euclid(86400);
+        let secs_of_day: i64 = self.secs.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        let mut ret: String = format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year, month, day, secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60
+        );
+        if self.nanos != 0 {
+            let (digits, fraction): (usize, u32) = match (self.nanos % 1_000_000, self.nanos % 1000) {
+                (0, _) => (3, self.nanos / 1_000_000),
+                (_, 0) => (6, self.nanos / 1000),
+                _ => (9, self.nanos)
+            };
+            ret.push_str(&format!(".{:0width$}", fraction, width = digits));
+        }
+        ret.push('Z');
+        ret
+    }
+
+    /// Parses an RFC 3339 date-time like `2021-03-04T05:06:07.5+08:00`. Fractions beyond
+    /// nanoseconds are truncated, a leap second `60` is read as the following second.
+    pub fn parse_rfc3339(input: &str) -> Option<Timestamp> {
+        let bytes: &[u8] = input.as_bytes();
+        let number = |start: usize, len: usize| -> Option<i64> {
+            let digits: &[u8] = bytes.get(start..start + len)?;
+            if !digits.iter().all(u8::is_ascii_digit) {
+                return None;
+            }
+            Some(digits.iter().fold(0, |acc, digit| acc * 10 + (digit - b'0') as i64))
+        };
+        let separator = |pos: usize, allowed: &[u8]| {
+            matches!(bytes.get(pos), Some(b) if allowed.contains(&b.to_ascii_uppercase()))
+        };
+
+        if !separator(4, b"-") || !separator(7, b"-") || !separator(10, b"T ")
+            || !separator(13, b":") || !separator(16, b":")
+        {
+            return None;
+        }
+        let (year, month, day) = (number(0, 4)?, number(5, 2)?, number(8, 2)?);
+        let (hour, minute, second) = (number(11, 2)?, number(14, 2)?, number(17, 2)?);
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month)
+            || hour > 23 || minute > 59 || second > 60
+        {
+            return None;
+        }
+
+        let mut pos: usize = 19;
+        let mut nanos: u32 = 0;
+        if bytes.get(pos) == Some(&b'.') {
+            pos += 1;
+            let frac_start: usize = pos;
+            while matches!(bytes.get(pos), Some(b) if b.is_ascii_digit()) {
+                if pos - frac_start < 9 {
+                    nanos = nanos * 10 + (bytes[pos] - b'0') as u32;
+                }
+                pos += 1;
+            }
+            let digits: usize = pos - frac_start;
+            if digits == 0 {
+                return None;
+            }
+            if digits < 9 {
+                nanos *= 10u32.pow((9 - digits) as u32);
+            }
+        }
+
+        let offset: i64 = match bytes.get(pos)?.to_ascii_uppercase() {
+            b'Z' if pos + 1 == bytes.len() => 0,
+            sign @ (b'+' | b'-') if pos + 6 == bytes.len() && separator(pos + 3, b":") => {
+                let (hours, minutes) = (number(pos + 1, 2)?, number(pos + 4, 2)?);
+                if hours > 23 || minutes > 59 {
+                    return None;
+                }
+                let offset: i64 = hours * 3600 + minutes * 60;
+                if sign == b'-' { -offset } else { offset }
+            },
+            _ => return None
+        };
+
+        let secs: i64 = days_from_civil(year, month, day) * 86400
+            + hour * 3600 + minute * 60 + second
+            - offset;
+        Some(Timestamp { secs, nanos })
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_rfc3339())
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Self {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => Timestamp {
+                secs: duration.as_secs() as i64,
+                nanos: duration.subsec_nanos()
+            },
+            Err(err) => {
+                let duration: Duration = err.duration();
+                // wraps for exactly 2^63 seconds before the epoch, giving `i64::MIN`
+                let secs: i64 = (duration.as_secs() as i64).wrapping_neg();
+                match duration.subsec_nanos() {
+                    0 => Timestamp { secs, nanos: 0 },
+                    nanos => Timestamp { secs: secs - 1, nanos: 1_000_000_000 - nanos }
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<Timestamp> for SystemTime {
+    type Error = ValueError;
+
+    /// Fails if the timestamp is beyond the range of `SystemTime` on this platform
+    fn try_from(timestamp: Timestamp) -> Result<Self, Self::Error> {
+        let whole_secs: Duration = Duration::from_secs(timestamp.secs.unsigned_abs());
+        let time: Option<SystemTime> = if timestamp.secs >= 0 {
+            UNIX_EPOCH.checked_add(whole_secs)
+        } else {
+            UNIX_EPOCH.checked_sub(whole_secs)
+        };
+        time.and_then(|time| time.checked_add(Duration::from_nanos(timestamp.nanos as u64)))
+            .ok_or_else(|| ValueError::new(ValueErrorKind::OutOfRange { expected: "SystemTime" }))
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date, after Howard Hinnant's `days_from_civil`
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year: i64 = if month <= 2 { year - 1 } else { year };
+    let era: i64 = year.div_euclid(400);
+    let year_of_era: i64 = year - era * 400;
+    let day_of_year: i64 = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era: i64 = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days: i64 = days + 719468;
+    let era: i64 = days.div_euclid(146097);
+    let day_of_era: i64 = days - era * 146097;
+    let year_of_era: i64 =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year: i64 = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp: i64 = (5 * day_of_year + 2) / 153;
+    let day: i64 = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month: i64 = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year: i64 = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryFrom;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::value::ValueErrorKind;
+    use crate::value::timestamp::{civil_from_days, days_from_civil, Timestamp};
+
+    #[test]
+    fn test_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        for days in (-800_000..800_000).step_by(997) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        let parse = |input: &str| Timestamp::parse_rfc3339(input).map(|t| (t.secs(), t.nanos()));
+        assert_eq!(parse("1970-01-01T00:00:00Z"), Some((0, 0)));
+        assert_eq!(parse("2000-02-29t12:00:00.123456789123z"), Some((951825600, 123456789)));
+        assert_eq!(parse("1969-12-31 23:59:59.25-00:30"), Some((1799, 250_000_000)));
+        assert_eq!(parse("2016-12-31T23:59:60Z"), Some((1483228800, 0)));
+        assert_eq!(parse("2021-02-29T00:00:00Z"), None);
+        assert_eq!(parse("2021-01-01T00:00:00"), None);
+        assert_eq!(parse("2021-01-01T00:00:00.Z"), None);
+        assert_eq!(parse("2021-01-01T24:00:00Z"), None);
+        assert_eq!(parse("2021-01-01T00:00:00+0800"), None);
+        assert_eq!(parse("2021-1-01T00:00:00Z"), None);
+
+        assert_eq!(Timestamp::new(0, 0).to_rfc3339(), "1970-01-01T00:00:00Z");
+        assert_eq!(Timestamp::new(-1, 1000).to_rfc3339(), "1969-12-31T23:59:59.000001Z");
+        assert_eq!(Timestamp::new(951825600, 123456789).to_string(), "2000-02-29T12:00:00.123456789Z");
+    }
+
+    #[test]
+    fn test_system_time() {
+        let before_epoch: SystemTime = UNIX_EPOCH - Duration::from_millis(1500);
+        let timestamp: Timestamp = before_epoch.into();
+        assert_eq!((timestamp.secs(), timestamp.nanos()), (-2, 500_000_000));
+        assert_eq!(SystemTime::try_from(timestamp), Ok(before_epoch));
+
+        let now: SystemTime = SystemTime::now();
+        assert_eq!(SystemTime::try_from(Timestamp::from(now)), Ok(now));
+
+        // the range of `SystemTime` depends on the platform, but converting never panics
+        for timestamp in &[Timestamp::new(i64::MAX, 999_999_999), Timestamp::new(i64::MIN, 0)] {
+            match SystemTime::try_from(*timestamp) {
+                Ok(time) => assert_eq!(Timestamp::from(time), *timestamp),
+                Err(err) => assert_eq!(err.kind, ValueErrorKind::OutOfRange { expected: "SystemTime" })
+            }
+        }
+    }
+}
@@ -0,0 +1,407 @@
+//! Derive macros for `xjbutil::value::ToValue` and `xjbutil::value::FromValue`
+//!
+//! Use these through the `value-derive` feature of `xjbutil` rather than directly. Structs with
+//! named fields become objects, tuple structs arrays, newtype structs their only field and unit
+//! structs `Value::Nil`. Enums are externally tagged like in `serde_json`: unit variants are
+//! strings, other variants objects with a single member named after the variant.
+//!
+//! Fields and variants accept `#[value(rename = "name")]`, fields also `#[value(default)]` to
+//! fall back to `Default::default()` when missing. Missing `Option` fields are always `None`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input,
+    parse_quote,
+    Attribute,
+    Data,
+    DeriveInput,
+    Error,
+    Fields,
+    Generics,
+    LitStr
+};
+
+/// Derives `xjbutil::value::ToValue`
+///
+/// ```
+/// # use xjbutil::value::{ToValue, Value};
+/// #[derive(ToValue)]
+/// struct Point {
+///     x: i32,
+///     #[value(rename = "Y")]
+///     y: i32
+/// }
+///
+/// #[derive(ToValue)]
+/// enum Shape {
+///     Empty,
+///     Circle(Point, u32)
+/// }
+///
+/// let shape: Shape = Shape::Circle(Point { x: 1, y: 2 }, 3);
+/// assert_eq!(shape.to_value().to_json(), r#"{"Circle":[{"x":1,"Y":2},3]}"#);
+/// assert_eq!(Shape::Empty.to_value(), Value::from("Empty"));
+/// ```
+#[proc_macro_derive(ToValue, attributes(value))]
+pub fn derive_to_value(input: TokenStream) -> TokenStream {
+    let input: DeriveInput = parse_macro_input!(input as DeriveInput);
+    expand_to_value(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// Derives `xjbutil::value::FromValue`
+///
+/// ```
+/// # use xjbutil::value::{FromValue, Value, ValueErrorKind};
+/// #[derive(Debug, FromValue, PartialEq)]
+/// struct Config {
+///     name: String,
+///     port: Option<u16>,
+///     #[value(default)]
+///     verbose: bool
+/// }
+///
+/// let value: Value = Value::from_json(r#"{"name": "xjb"}"#).unwrap();
+/// let config: Config = Config::from_value(&value).unwrap();
+/// assert_eq!(config, Config { name: "xjb".into(), port: None, verbose: false });
+///
+/// let value: Value = Value::from_json(r#"{"port": 80}"#).unwrap();
+/// let err = Config::from_value(&value).unwrap_err();
+/// assert_eq!(err.kind, ValueErrorKind::MissingField("name"));
+/// ```
+#[proc_macro_derive(FromValue, attributes(value))]
+pub fn derive_from_value(input: TokenStream) -> TokenStream {
+    let input: DeriveInput = parse_macro_input!(input as DeriveInput);
+    expand_from_value(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// Options read from `#[value(...)]` attributes
+#[derive(Default)]
+struct Options {
+    rename: Option<String>,
+    default: bool
+}
+
+fn parse_options(attrs: &[Attribute], allow_default: bool) -> syn::Result<Options> {
+    let mut options: Options = Options::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("value")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                let name: LitStr = meta.value()?.parse()?;
+                options.rename = Some(name.value());
+                Ok(())
+            } else if allow_default && meta.path.is_ident("default") {
+                options.default = true;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported value attribute"))
+            }
+        })?;
+    }
+    Ok(options)
+}
+
+fn add_bounds(generics: &Generics, bound: TokenStream2) -> Generics {
+    let mut generics: Generics = generics.clone();
+    let type_params: Vec<Ident> = generics.type_params().map(|param| param.ident.clone()).collect();
+    let where_clause = generics.make_where_clause();
+    for ident in type_params {
+        where_clause.predicates.push(parse_quote!(#ident: #bound));
+    }
+    generics
+}
+
+/// Names and bindings of fields, in declaration order
+struct FieldInfo {
+    name: String,
+    binding: Ident,
+    default: bool
+}
+
+fn field_infos(fields: &Fields) -> syn::Result<Vec<FieldInfo>> {
+    fields.iter()
+        .enumerate()
+        .map(|(idx, field)| {
+            let options: Options = parse_options(&field.attrs, true)?;
+            let (name, binding): (String, Ident) = match &field.ident {
+                Some(ident) => {
+                    let ident: String = ident.to_string().trim_start_matches("r#").to_string();
+                    let binding: Ident = format_ident!("__field_{}", ident);
+                    (options.rename.unwrap_or(ident), binding)
+                },
+                None => (idx.to_string(), format_ident!("__field_{}", idx))
+            };
+            Ok(FieldInfo { name, binding, default: options.default })
+        })
+        .collect()
+}
+
+/// Pattern destructuring `path` into the bindings of `infos`
+fn pattern(path: TokenStream2, fields: &Fields, infos: &[FieldInfo]) -> TokenStream2 {
+    let bindings = infos.iter().map(|info| &info.binding);
+    match fields {
+        Fields::Named(named) => {
+            let idents = named.named.iter().map(|field| &field.ident);
+            quote!(#path { #(#idents: #bindings),* })
+        },
+        Fields::Unnamed(_) => quote!(#path(#(#bindings),*)),
+        Fields::Unit => quote!(#path)
+    }
+}
+
+fn fields_to_value(fields: &Fields, infos: &[FieldInfo]) -> TokenStream2 {
+    match fields {
+        Fields::Named(_) => {
+            let len: usize = infos.len();
+            let inserts = infos.iter().map(|FieldInfo { name, binding, .. }| quote! {
+                __object.insert(
+                    ::std::string::String::from(#name),
+                    ::xjbutil::value::ToValue::to_value(#binding)
+                );
+            });
+            quote! {{
+                let mut __object: ::xjbutil::value::ValueMap =
+                    ::xjbutil::value::ValueMap::with_capacity(#len);
+                #(#inserts)*
+                ::xjbutil::value::Value::Object(__object)
+            }}
+        },
+        Fields::Unnamed(_) if infos.len() == 1 => {
+            let binding: &Ident = &infos[0].binding;
+            quote!(::xjbutil::value::ToValue::to_value(#binding))
+        },
+        Fields::Unnamed(_) => {
+            let bindings = infos.iter().map(|info| &info.binding);
+            quote! {
+                ::xjbutil::value::Value::Array(::std::vec![
+                    #(::xjbutil::value::ToValue::to_value(#bindings)),*
+                ])
+            }
+        },
+        Fields::Unit => quote!(::xjbutil::value::Value::Nil)
+    }
+}
+
+/// Expression converting `__value` to `constructor`, returning errors suffixed with `within`
+fn fields_from_value(
+    constructor: TokenStream2,
+    fields: &Fields,
+    infos: &[FieldInfo],
+    within: TokenStream2
+) -> TokenStream2 {
+    let construct: TokenStream2 = pattern(constructor, fields, infos);
+    match fields {
+        Fields::Named(_) => {
+            let lets = infos.iter().map(|FieldInfo { name, binding, default }| {
+                let missing: TokenStream2 = if *default {
+                    quote!(::std::default::Default::default())
+                } else {
+                    quote! {
+                        ::xjbutil::value::FromValue::from_missing().ok_or_else(|| {
+                            ::xjbutil::value::ValueError::new(
+                                ::xjbutil::value::ValueErrorKind::MissingField(#name)
+                            ) #within
+                        })?
+                    }
+                };
+                quote! {
+                    let #binding = match __object.get(#name) {
+                        ::std::option::Option::Some(__member) =>
+                            ::xjbutil::value::FromValue::from_value(__member)
+                                .map_err(|__e| __e.within(#name) #within)?,
+                        ::std::option::Option::None => #missing
+                    };
+                }
+            });
+            quote! {{
+                let __object: &::xjbutil::value::ValueMap = match __value {
+                    ::xjbutil::value::Value::Object(__object) => __object,
+                    _ => return ::std::result::Result::Err(
+                        ::xjbutil::value::ValueError::type_mismatch("object", __value) #within
+                    )
+                };
+                #(#lets)*
+                ::std::result::Result::Ok(#construct)
+            }}
+        },
+        Fields::Unnamed(_) if infos.len() == 1 => {
+            let binding: &Ident = &infos[0].binding;
+            quote! {{
+                let #binding = ::xjbutil::value::FromValue::from_value(__value)
+                    .map_err(|__e| __e #within)?;
+                ::std::result::Result::Ok(#construct)
+            }}
+        },
+        Fields::Unnamed(_) => {
+            let len: usize = infos.len();
+            let lets = infos.iter().enumerate().map(|(idx, FieldInfo { name, binding, .. })| quote! {
+                let #binding = ::xjbutil::value::FromValue::from_value(&__array[#idx])
+                    .map_err(|__e| __e.within(#name) #within)?;
+            });
+            quote! {{
+                let __array: &[::xjbutil::value::Value] = match __value {
+                    ::xjbutil::value::Value::Array(__array) => __array,
+                    _ => return ::std::result::Result::Err(
+                        ::xjbutil::value::ValueError::type_mismatch("array", __value) #within
+                    )
+                };
+                if __array.len() != #len {
+                    return ::std::result::Result::Err(::xjbutil::value::ValueError::new(
+                        ::xjbutil::value::ValueErrorKind::InvalidLength {
+                            expected: #len,
+                            found: __array.len()
+                        }
+                    ) #within);
+                }
+                #(#lets)*
+                ::std::result::Result::Ok(#construct)
+            }}
+        },
+        Fields::Unit => quote! {{
+            match __value {
+                ::xjbutil::value::Value::Nil => ::std::result::Result::Ok(#construct),
+                _ => ::std::result::Result::Err(
+                    ::xjbutil::value::ValueError::type_mismatch("nil", __value) #within
+                )
+            }
+        }}
+    }
+}
+
+fn variant_name(ident: &Ident, attrs: &[Attribute]) -> syn::Result<String> {
+    Ok(parse_options(attrs, false)?.rename.unwrap_or_else(|| ident.to_string()))
+}
+
+fn expand_to_value(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let body: TokenStream2 = match &input.data {
+        Data::Struct(data) => {
+            let infos: Vec<FieldInfo> = field_infos(&data.fields)?;
+            let pattern: TokenStream2 = pattern(quote!(Self), &data.fields, &infos);
+            let to_value: TokenStream2 = fields_to_value(&data.fields, &infos);
+            quote! {
+                let #pattern = self;
+                #to_value
+            }
+        },
+        Data::Enum(data) => {
+            let mut arms: Vec<TokenStream2> = Vec::new();
+            for variant in &data.variants {
+                let ident: &Ident = &variant.ident;
+                let name: String = variant_name(ident, &variant.attrs)?;
+                let infos: Vec<FieldInfo> = field_infos(&variant.fields)?;
+                let pattern: TokenStream2 = pattern(quote!(Self::#ident), &variant.fields, &infos);
+                let arm: TokenStream2 = if let Fields::Unit = variant.fields {
+                    quote! {
+                        #pattern => ::xjbutil::value::Value::String(::std::string::String::from(#name))
+                    }
+                } else {
+                    let to_value: TokenStream2 = fields_to_value(&variant.fields, &infos);
+                    quote! {
+                        #pattern => {
+                            let mut __object: ::xjbutil::value::ValueMap =
+                                ::xjbutil::value::ValueMap::with_capacity(1);
+                            __object.insert(::std::string::String::from(#name), #to_value);
+                            ::xjbutil::value::Value::Object(__object)
+                        }
+                    }
+                };
+                arms.push(arm);
+            }
+            quote! {
+                match self {
+                    #(#arms,)*
+                }
+            }
+        },
+        Data::Union(data) => {
+            return Err(Error::new(data.union_token.span, "unions cannot derive ToValue"));
+        }
+    };
+
+    let ident: &Ident = &input.ident;
+    let generics: Generics = add_bounds(&input.generics, quote!(::xjbutil::value::ToValue));
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::xjbutil::value::ToValue for #ident #type_generics #where_clause {
+            fn to_value(&self) -> ::xjbutil::value::Value {
+                #body
+            }
+        }
+    })
+}
+
+fn expand_from_value(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let body: TokenStream2 = match &input.data {
+        Data::Struct(data) => {
+            let infos: Vec<FieldInfo> = field_infos(&data.fields)?;
+            fields_from_value(quote!(Self), &data.fields, &infos, TokenStream2::new())
+        },
+        Data::Enum(data) => {
+            let mut unit_arms: Vec<TokenStream2> = Vec::new();
+            let mut object_arms: Vec<TokenStream2> = Vec::new();
+            for variant in &data.variants {
+                let ident: &Ident = &variant.ident;
+                let name: String = variant_name(ident, &variant.attrs)?;
+                if let Fields::Unit = variant.fields {
+                    unit_arms.push(quote!(#name => ::std::result::Result::Ok(Self::#ident)));
+                } else {
+                    let infos: Vec<FieldInfo> = field_infos(&variant.fields)?;
+                    let from_value: TokenStream2 = fields_from_value(
+                        quote!(Self::#ident),
+                        &variant.fields,
+                        &infos,
+                        quote!(.within(#name))
+                    );
+                    object_arms.push(quote! {
+                        #name => {
+                            let __value: &::xjbutil::value::Value = __member;
+                            #from_value
+                        }
+                    });
+                }
+            }
+            let unknown_variant: TokenStream2 = quote! {
+                __name => ::std::result::Result::Err(::xjbutil::value::ValueError::new(
+                    ::xjbutil::value::ValueErrorKind::UnknownVariant(
+                        ::std::string::ToString::to_string(__name)
+                    )
+                ))
+            };
+            quote! {
+                match __value {
+                    ::xjbutil::value::Value::String(__name) => match __name.as_str() {
+                        #(#unit_arms,)*
+                        #unknown_variant
+                    },
+                    ::xjbutil::value::Value::Object(__object) if __object.len() == 1 => {
+                        let (__name, __member) = __object.get_index(0).unwrap();
+                        match __name.as_str() {
+                            #(#object_arms,)*
+                            #unknown_variant
+                        }
+                    },
+                    _ => ::std::result::Result::Err(
+                        ::xjbutil::value::ValueError::type_mismatch("string or object", __value)
+                    )
+                }
+            }
+        },
+        Data::Union(data) => {
+            return Err(Error::new(data.union_token.span, "unions cannot derive FromValue"));
+        }
+    };
+
+    let ident: &Ident = &input.ident;
+    let generics: Generics = add_bounds(&input.generics, quote!(::xjbutil::value::FromValue));
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::xjbutil::value::FromValue for #ident #type_generics #where_clause {
+            fn from_value(
+                __value: &::xjbutil::value::Value
+            ) -> ::std::result::Result<Self, ::xjbutil::value::ValueError> {
+                #body
+            }
+        }
+    })
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+use xjbutil::value::{FromValue, ToValue, Value, ValueError, ValueErrorKind};
+
+#[derive(Debug, FromValue, PartialEq, ToValue)]
+struct Unit;
+
+#[derive(Debug, FromValue, PartialEq, ToValue)]
+struct Meters(f64);
+
+#[derive(Debug, FromValue, PartialEq, ToValue)]
+struct Pair(i32, String);
+
+#[derive(Debug, FromValue, PartialEq, ToValue)]
+struct Wrapper<T> {
+    inner: T,
+    tags: Option<Vec<String>>
+}
+
+#[derive(Debug, FromValue, PartialEq, ToValue)]
+enum Event {
+    Started,
+    #[value(rename = "stop")]
+    Stopped,
+    Moved(Meters),
+    Resized(u32, u32),
+    Renamed {
+        r#from: String,
+        #[value(rename = "to")]
+        into: String,
+        #[value(default)]
+        history: BTreeMap<String, u8>
+    }
+}
+
+fn round_trip<T: Debug + FromValue + PartialEq + ToValue>(x: T, json: &str) {
+    let value: Value = x.to_value();
+    assert_eq!(value.to_json(), json);
+    assert_eq!(T::from_value(&value), Ok(x));
+}
+
+#[test]
+fn test_round_trip() {
+    round_trip(Unit, "null");
+    round_trip(Meters(1.5), "1.5");
+    round_trip(Pair(1, "a".into()), r#"[1,"a"]"#);
+    round_trip(
+        Wrapper { inner: Event::Started, tags: Some(vec!["x".into()]) },
+        r#"{"inner":"Started","tags":["x"]}"#
+    );
+    round_trip(Event::Stopped, r#""stop""#);
+    round_trip(Event::Moved(Meters(2.0)), r#"{"Moved":2.0}"#);
+    round_trip(Event::Resized(3, 4), r#"{"Resized":[3,4]}"#);
+    round_trip(
+        Event::Renamed { from: "a".into(), into: "b".into(), history: BTreeMap::new() },
+        r#"{"Renamed":{"from":"a","to":"b","history":{}}}"#
+    );
+}
+
+#[test]
+fn test_missing_and_errors() {
+    let value: Value = Value::from_json(r#"{"inner": 1}"#).unwrap();
+    assert_eq!(Wrapper::<u8>::from_value(&value), Ok(Wrapper { inner: 1, tags: None }));
+
+    let value: Value = Value::from_json(r#"{"Renamed": {"from": "a", "to": "b"}}"#).unwrap();
+    assert_eq!(
+        Event::from_value(&value),
+        Ok(Event::Renamed { from: "a".into(), into: "b".into(), history: BTreeMap::new() })
+    );
+
+    let from_json = |json: &str| -> ValueError {
+        Wrapper::<Vec<Event>>::from_value(&Value::from_json(json).unwrap()).unwrap_err()
+    };
+    let err: ValueError = from_json(r#"{"tags": []}"#);
+    assert_eq!((err.kind, err.path.as_str()), (ValueErrorKind::MissingField("inner"), ""));
+    let err: ValueError = from_json(r#"{"inner": ["Started", {"Resized": [1, -2]}]}"#);
+    assert_eq!(err.kind, ValueErrorKind::OutOfRange { expected: "u32" });
+    assert_eq!(err.path, "/inner/1/Resized/1");
+    let err: ValueError = from_json(r#"{"inner": [{"Renamed": {"from": "a"}}]}"#);
+    assert_eq!((err.kind, err.path.as_str()), (ValueErrorKind::MissingField("to"), "/inner/0/Renamed"));
+    let err: ValueError = from_json(r#"{"inner": ["Paused"]}"#);
+    assert_eq!(err.kind, ValueErrorKind::UnknownVariant("Paused".into()));
+    assert_eq!(err.path, "/inner/0");
+    let err: ValueError = from_json(r#"{"inner": [{"Resized": [1]}]}"#);
+    assert_eq!(err.kind, ValueErrorKind::InvalidLength { expected: 2, found: 1 });
+    assert_eq!(err.path, "/inner/0/Resized");
+}